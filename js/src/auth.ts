@@ -1,4 +1,5 @@
 import { AuthResponse, CodeCallbacks, CodeResponse, LoginCallbacks, LoginCredentials, MagicLinkCallbacks, MagicLinkCredentials, RegisterCallbacks, RegisterCredentials, SessionResponse } from "./types/auth";
+import { CollectEmailCallbacks, CollectEmailCredentials } from "./types/auth/collectEmail";
 import { ForgetPasswordCallbacks, ForgetPasswordCredentials, ForgetPasswordResponse } from "./types/auth/forgetPassword";
 import { LogoutCallbacks } from "./types/auth/logout";
 import { OAuthCallbacks, OAuthOptions, OAuthResponse } from "./types/auth/oauth";
@@ -92,12 +93,24 @@ export class Auth extends BaseClient {
           case 'amazon':
             requiredScopes = ['profile', 'profile:user_id'];
             break;
+          case 'apple':
+            requiredScopes = ['name', 'email'];
+            break;
+          case 'bitbucket':
+            requiredScopes = ['account', 'email'];
+            break;
+          case 'discord':
+            requiredScopes = ['identify', 'email'];
+            break;
           case 'facebook':
             requiredScopes = ['email', 'public_profile'];
             break;
           case 'github':
             requiredScopes = ['public_repo', 'user:email'];
             break;
+          case 'gitlab':
+            requiredScopes = ['read_user'];
+            break;
           case 'google':
             requiredScopes = ['email', 'profile', 'openid'];
             break;
@@ -113,6 +126,9 @@ export class Auth extends BaseClient {
           case 'reddit':
             requiredScopes = ['identity', 'read'];
             break;
+          case 'slack':
+            requiredScopes = ['openid', 'profile', 'email'];
+            break;
           case 'tiktok':
             requiredScopes = ['user.info.basic'];
             break;
@@ -245,6 +261,32 @@ export class Auth extends BaseClient {
     }
   };
 
+  // Social accounts signed in without a verified email are redirected with ?collect_email=true
+  public collectEmail = async (
+    credentials: CollectEmailCredentials,
+    callbacks?: CollectEmailCallbacks
+  ): Promise<{ error?: Error }> => {
+    try {
+      const response = await this.fetchWithAuth('/collect_email', {
+        method: 'POST',
+        body: JSON.stringify(credentials),
+      });
+
+      if (!response.ok) {
+        const error = new Error(`Collect email request failed: ${response.statusText}`);
+        callbacks?.onError?.(error);
+        return { error };
+      }
+
+      callbacks?.onSuccess?.();
+      return {};
+    } catch (error) {
+      const err = error instanceof Error ? error : new Error('Collect email request failed');
+      callbacks?.onError?.(err);
+      return { error: err };
+    }
+  };
+
   public resetPassword = async (
     credentials: ResetPasswordCredentials,
     callbacks?: ResetPasswordCallbacks
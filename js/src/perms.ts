@@ -1,7 +1,7 @@
 import { BaseClient } from "./lib";
 import { SchemaWriteBody, SchemaWriteResponse } from "./types/perms/schema";
 import * as tenants from "./types/perms/tenant";
-import { CheckBody, PermissionCheckResponse} from "./types/perms/permissions";
+import { CheckBody, ExpandBody, LookupSubjectsBody, LookupSubjectsResponse, PermissionCheckResponse, PermissionExpandResponse, RelationshipDeleteBody, RelationshipWriteBody } from "./types/perms/permissions";
 
 export class Perms extends BaseClient {
   constructor(config: { baseURL: string }) {
@@ -36,6 +36,53 @@ export class Perms extends BaseClient {
           method: 'GET',
         });
         return response.json();
+      },
+      // Pass null to leave the active tenant
+      switch: async (tenantId: string | null): Promise<tenants.TenantSwitchResponse> => {
+        const response = await this.fetchWithAuth('/tenants/switch', {
+          method: 'POST',
+          body: JSON.stringify({ tenantId }),
+        });
+        const data: tenants.TenantSwitchResponse = await response.json();
+        this.accessToken = data.access_token;
+        return data;
+      },
+      members: {
+        list: async (tenantId: string): Promise<tenants.TenantMemberListResponse> => {
+          const response = await this.fetchWithAuth(`/tenants/${tenantId}/members`, {
+            method: 'GET',
+          });
+          return response.json();
+        },
+        put: async (tenantId: string, body: tenants.TenantMemberRequest): Promise<{ status: string }> => {
+          const response = await this.fetchWithAuth(`/tenants/${tenantId}/members`, {
+            method: 'POST',
+            body: JSON.stringify(body),
+          });
+          return response.json();
+        },
+        remove: async (tenantId: string, userId: string): Promise<{ status: string }> => {
+          const response = await this.fetchWithAuth(`/tenants/${tenantId}/members/${userId}`, {
+            method: 'DELETE',
+          });
+          return response.json();
+        }
+      },
+      settings: {
+        get: async (tenantId: string): Promise<tenants.TenantSettingsResponse> => {
+          const response = await this.fetchWithAuth(`/tenants/${tenantId}/settings`, {
+            method: 'GET',
+          });
+          return response.json();
+        },
+        // Replaces every override, leave a field out to fall back to the server default
+        put: async (tenantId: string, body: tenants.TenantSettings): Promise<tenants.TenantSettingsResponse> => {
+          const response = await this.fetchWithAuth(`/tenants/${tenantId}/settings`, {
+            method: 'PUT',
+            body: JSON.stringify(body),
+          });
+          return response.json();
+        }
       }
   };
 
@@ -50,6 +97,50 @@ export class Perms extends BaseClient {
     return response.json();
   }
 
+  async expandPermissions(tenantId: string, body: ExpandBody): Promise<PermissionExpandResponse> {
+    const response = await this.fetchWithAuth('/permissions/expand', {
+      method: 'POST',
+      body: JSON.stringify({
+        tenantId,
+        ...body
+      }),
+    });
+    return response.json();
+  }
+
+  async lookupSubjects(tenantId: string, body: LookupSubjectsBody): Promise<LookupSubjectsResponse> {
+    const response = await this.fetchWithAuth('/permissions/lookup-subjects', {
+      method: 'POST',
+      body: JSON.stringify({
+        tenantId,
+        ...body
+      }),
+    });
+    return response.json();
+  }
+
+  async writeRelationships(tenantId: string, body: RelationshipWriteBody): Promise<{ status: string }> {
+    const response = await this.fetchWithAuth('/relationships/write', {
+      method: 'POST',
+      body: JSON.stringify({
+        tenantId,
+        ...body
+      }),
+    });
+    return response.json();
+  }
+
+  async deleteRelationships(tenantId: string, body: RelationshipDeleteBody): Promise<{ status: string }> {
+    const response = await this.fetchWithAuth('/relationships/delete', {
+      method: 'POST',
+      body: JSON.stringify({
+        tenantId,
+        ...body
+      }),
+    });
+    return response.json();
+  }
+
   async writeSchema(tenantId: string, body: SchemaWriteBody): Promise<SchemaWriteResponse> {
     const response = await this.fetchWithAuth(`/schemas/${tenantId}`, {
       method: 'POST', // or GET, PUT, DELETE, etc.
@@ -0,0 +1,9 @@
+export interface CollectEmailCredentials {
+  email: string;
+  redirectTo: string;
+}
+
+export interface CollectEmailCallbacks {
+  onSuccess?: () => void;
+  onError?: (error: Error) => void;
+}
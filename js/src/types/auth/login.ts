@@ -6,6 +6,8 @@ export interface LoginCallbacks {
 export interface LoginCredentials {
   email: string;
   password: string;
+  // Sign in to a tenant, otherwise it is picked from the host
+  tenantId?: string;
 }
 
 export interface MagicLinkCallbacks {
@@ -16,4 +18,5 @@ export interface MagicLinkCallbacks {
 export interface MagicLinkCredentials {
   email: string;
   redirectTo: string;
+  tenantId?: string;
 }
\ No newline at end of file
@@ -1,24 +1,34 @@
 import { AmazonScopes } from './social/amazon';
+import { AppleScopes } from './social/apple';
+import { BitbucketScopes } from './social/bitbucket';
+import { DiscordScopes } from './social/discord';
 import { FacebookScopes } from './social/facebook';
 import { GitHubScopes } from './social/github';
+import { GitLabScopes } from './social/gitlab';
 import { GoogleScopes } from './social/google';
 import { InstagramScopes } from './social/instagram';
 import { LinkedInScopes } from './social/linkedin';
 import { MicrosoftScopes } from './social/microsoft';
 import { RedditScopes } from './social/reddit';
+import { SlackScopes } from './social/slack';
 import { TikTokScopes } from './social/tiktok';
 import { TwitchScopes } from './social/twitch';
 import { TwitterScopes } from './social/twitter';
 
 export type OAuthProvider =
   | 'amazon'
+  | 'apple'
+  | 'bitbucket'
+  | 'discord'
   | 'github'
+  | 'gitlab'
   | 'google'
   | 'facebook'
   | 'instagram'
   | 'linkedin'
   | 'microsoft'
   | 'reddit'
+  | 'slack'
   | 'tiktok'
   | 'twitter'
   | 'twitch';
@@ -35,6 +45,27 @@ interface AmazonOAuthOptions extends BaseOAuthOptions {
   scopes?: AmazonScopes[];
 }
 
+// Apple specific options
+interface AppleOAuthOptions extends BaseOAuthOptions {
+  provider: 'apple';
+  callback_url: string;
+  scopes?: AppleScopes[];
+}
+
+// Bitbucket specific options
+interface BitbucketOAuthOptions extends BaseOAuthOptions {
+  provider: 'bitbucket';
+  callback_url: string;
+  scopes?: BitbucketScopes[];
+}
+
+// Discord specific options
+interface DiscordOAuthOptions extends BaseOAuthOptions {
+  provider: 'discord';
+  callback_url: string;
+  scopes?: DiscordScopes[];
+}
+
 // Facebook specific options
 interface FacebookOAuthOptions extends BaseOAuthOptions {
   provider: 'facebook';
@@ -49,6 +80,13 @@ interface GitHubOAuthOptions extends BaseOAuthOptions {
   scopes?: GitHubScopes[];
 }
 
+// GitLab specific options
+interface GitLabOAuthOptions extends BaseOAuthOptions {
+  provider: 'gitlab';
+  callback_url: string;
+  scopes?: GitLabScopes[];
+}
+
 // Google specific options
 interface GoogleOAuthOptions extends BaseOAuthOptions {
   provider: 'google';
@@ -84,6 +122,13 @@ interface RedditOAuthOptions extends BaseOAuthOptions {
   scopes?: RedditScopes[];
 }
 
+// Slack specific options
+interface SlackOAuthOptions extends BaseOAuthOptions {
+  provider: 'slack';
+  callback_url: string;
+  scopes?: SlackScopes[];
+}
+
 // Tiktok specific options
 interface TiktokOAuthOptions extends BaseOAuthOptions {
   provider: 'tiktok';
@@ -107,13 +152,18 @@ interface TwitterOAuthOptions extends BaseOAuthOptions {
 
 export type OAuthOptions = 
 | AmazonOAuthOptions 
+| AppleOAuthOptions
+| BitbucketOAuthOptions
+| DiscordOAuthOptions
 | GitHubOAuthOptions 
+| GitLabOAuthOptions
 | GoogleOAuthOptions
 | FacebookOAuthOptions 
 | LinkedInOAuthOptions 
 | InstagramOAuthOptions
 | MicrosoftOAuthOptions
 | RedditOAuthOptions 
+| SlackOAuthOptions
 | TiktokOAuthOptions
 | TwitchOAuthOptions 
 | TwitterOAuthOptions;
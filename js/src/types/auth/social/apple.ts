@@ -0,0 +1,3 @@
+export type AppleScopes =
+  | "name" // The user's name, only returned on first authorization
+  | "email"; // The user's email, which may be a private relay address
@@ -0,0 +1,7 @@
+export type BitbucketScopes =
+  | "account" // Read the user's account information
+  | "email" // Read the user's email addresses
+  | "repository" // Read repositories
+  | "pullrequest" // Read pull requests
+  | "issue" // Read issues
+  | "webhook"; // Manage webhooks
@@ -0,0 +1,7 @@
+export type DiscordScopes =
+  | "identify" // Basic user information without email
+  | "email" // The user's email and verification status
+  | "guilds" // The guilds the user is in
+  | "guilds.members.read" // The user's member information in guilds
+  | "connections" // Linked third-party accounts
+  | "openid"; // OpenID Connect id_token
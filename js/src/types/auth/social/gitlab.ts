@@ -0,0 +1,9 @@
+export type GitLabScopes =
+  | "read_user" // Read the authenticated user's profile, including email
+  | "openid" // OpenID Connect authentication
+  | "profile" // Read-only access to the user's profile via OpenID Connect
+  | "email" // Read-only access to the user's primary email via OpenID Connect
+  | "read_api" // Read access to the API
+  | "api" // Full API access
+  | "read_repository" // Read access to repositories
+  | "write_repository"; // Write access to repositories
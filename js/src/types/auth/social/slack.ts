@@ -0,0 +1,4 @@
+export type SlackScopes =
+  | "openid" // Required for Sign in with Slack
+  | "profile" // The user's name and picture
+  | "email"; // The user's email address
@@ -55,4 +55,47 @@ export interface PermissionCheckRequestMetadata {
     // Add the response type based on your API requirements
     allowed: boolean;
     [key: string]: any;
-  }
\ No newline at end of file
+  }
+
+  export interface ExpandBody {
+    metadata?: PermissionCheckRequestMetadata;
+    entity?: Entity;
+    permission?: string;
+    context?: Context;
+  }
+
+  export interface PermissionExpandResponse {
+    tree: any;
+    metadata?: { schemaVersion?: string };
+  }
+
+  export interface LookupSubjectsBody {
+    metadata?: PermissionCheckRequestMetadata;
+    entity?: Entity;
+    permission?: string;
+    subjectReference?: {
+      type?: string;
+      relation?: string;
+    };
+    context?: Context;
+  }
+
+  export interface LookupSubjectsResponse {
+    subjectIds: string[];
+    metadata?: { schemaVersion?: string };
+  }
+
+  export interface Tuple {
+    entity: Entity;
+    relation: string;
+    subject: Subject;
+  }
+
+  export interface RelationshipWriteBody {
+    schemaVersion?: string;
+    tuples: Tuple[];
+  }
+
+  export interface RelationshipDeleteBody {
+    tuples: Tuple[];
+  }
@@ -25,4 +25,45 @@ export interface TenantCreateRequest {
   export interface TenantListResponse {
     tenants: Tenant[];
     continuousToken?: string;
-  }
\ No newline at end of file
+  }
+
+  export type TenantRole = 'owner' | 'admin' | 'member';
+
+  export interface TenantMember {
+    userId: string;
+    role: TenantRole;
+    createdAt?: Date;
+  }
+
+  export interface TenantMemberListResponse {
+    members: TenantMember[];
+  }
+
+  export interface TenantMemberRequest {
+    userId: string;
+    role: TenantRole;
+  }
+
+  export interface TenantSwitchResponse {
+    access_token: string;
+    tenantId?: string | null;
+  }
+
+  export interface TenantSettings {
+    host?: string | null;
+    enabledProviders?: string[] | null;
+    passwordMinLength?: number | null;
+    passwordRequireDigit?: boolean | null;
+    passwordRequireSymbol?: boolean | null;
+    mfaRequired?: boolean | null;
+    // Minutes
+    accessTokenMaxAge?: number | null;
+    refreshTokenMaxAge?: number | null;
+    mailerFrom?: string | null;
+    mailerFromName?: string | null;
+  }
+
+  export interface TenantSettingsResponse {
+    status: string;
+    settings: TenantSettings & { tenantId: string };
+  }
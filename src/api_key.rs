@@ -0,0 +1,41 @@
+use chrono::Utc;
+use diesel::{query_dsl::methods::FilterDsl, BoolExpressionMethods, ExpressionMethods, OptionalExtension, PgConnection, RunQueryDsl};
+
+use crate::{schema::{api_keys, ApiKey, ApiKeyUsage}, smtp::generate_random_string, utils::hash_token};
+
+// Recognizable so keys can be told apart from session tokens and caught by secret scanners
+pub const API_KEY_PREFIX: &str = "hmd_";
+
+// Returns the full key, shown to the user once, and the part kept for display
+pub fn generate_api_key() -> (String, String) {
+  let key = format!("{}{}", API_KEY_PREFIX, generate_random_string());
+  let prefix = key[..API_KEY_PREFIX.len() + 6].to_string();
+  (key, prefix)
+}
+
+pub fn is_api_key(value: &str) -> bool {
+  value.starts_with(API_KEY_PREFIX)
+}
+
+// Keys are random enough that a plain hash is safe to look up by
+pub fn find_active_key(conn: &mut PgConnection, key: &str) -> Option<ApiKey> {
+  let now = Utc::now().naive_utc();
+
+  api_keys::table
+    .filter(api_keys::key_hash.eq(hash_token(key)))
+    .filter(api_keys::revoked_at.is_null())
+    .filter(api_keys::expires.is_null().or(api_keys::expires.gt(now)))
+    .first::<ApiKey>(conn)
+    .optional()
+    .unwrap_or(None)
+}
+
+pub fn record_usage(conn: &mut PgConnection, id: &str, ip: Option<String>) {
+  let _ = diesel::update(api_keys::table)
+    .filter(api_keys::id.eq(id))
+    .set(&ApiKeyUsage {
+      last_used_at: Some(Utc::now().naive_utc()),
+      last_used_ip: ip,
+    })
+    .execute(conn);
+}
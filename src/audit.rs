@@ -0,0 +1,128 @@
+use std::{convert::Infallible, time::Duration as StdDuration};
+
+use axum::{extract::FromRequestParts, http::{header, request::Parts, Extensions, HeaderMap}};
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use ulid::Ulid;
+
+use crate::{schema::{audit_events, AuditEvent}, utils::forwarded_ip, DbPool};
+
+const PURGE_INTERVAL: StdDuration = StdDuration::from_secs(60 * 60);
+
+pub const OUTCOME_SUCCESS: &str = "success";
+pub const OUTCOME_FAILURE: &str = "failure";
+
+// Where a request came from, taken from the headers so handlers can record it without the raw request
+#[derive(Debug, Clone, Default)]
+pub struct RequestContext {
+  pub ip: Option<String>,
+  pub user_agent: Option<String>,
+  // Not stored with events, emails sent during the request use it to pick a language
+  pub accept_language: Option<String>,
+}
+
+impl RequestContext {
+  pub fn new(headers: &HeaderMap, extensions: &Extensions) -> Self {
+    RequestContext {
+      ip: forwarded_ip(headers, extensions),
+      user_agent: headers
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string()),
+      accept_language: headers
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string()),
+    }
+  }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for RequestContext {
+  type Rejection = Infallible;
+
+  async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+    Ok(RequestContext::new(&parts.headers, &parts.extensions))
+  }
+}
+
+pub struct AuditRecord<'a> {
+  pub event_type: &'a str,
+  pub actor_id: Option<&'a str>,
+  pub subject_id: Option<&'a str>,
+  pub outcome: &'a str,
+  pub metadata: serde_json::Value,
+}
+
+// A failed write is logged rather than failing the request it describes
+pub fn record(conn: &mut PgConnection, context: &RequestContext, record: AuditRecord) {
+  let statement = diesel::insert_into(audit_events::table)
+    .values(&AuditEvent {
+      id: Ulid::new().to_string(),
+      event_type: record.event_type.to_string(),
+      actor_id: record.actor_id.map(|id| id.to_string()),
+      subject_id: record.subject_id.map(|id| id.to_string()),
+      outcome: record.outcome.to_string(),
+      ip: context.ip.clone(),
+      user_agent: context.user_agent.clone(),
+      metadata: record.metadata,
+      created_at: Utc::now().naive_utc(),
+    })
+    .execute(conn);
+
+  if let Err(e) = statement {
+    tracing::error!("Failed to record audit event {}: {}", record.event_type, e);
+  }
+}
+
+// Most events are a user acting on their own account, or an attempt to before we know who they are
+pub fn record_user_event(
+  conn: &mut PgConnection,
+  context: &RequestContext,
+  event_type: &str,
+  user_id: Option<&str>,
+  outcome: &str,
+  metadata: serde_json::Value,
+) {
+  record(conn, context, AuditRecord {
+    event_type,
+    actor_id: user_id,
+    subject_id: user_id,
+    outcome,
+    metadata,
+  });
+}
+
+// Drops events older than the retention window, zero days keeps everything
+pub fn purge_expired(conn: &mut PgConnection, retention_days: i64) -> QueryResult<usize> {
+  if retention_days <= 0 {
+    return Ok(0);
+  }
+
+  let cutoff = (Utc::now() - Duration::days(retention_days)).naive_utc();
+  diesel::delete(audit_events::table)
+    .filter(audit_events::created_at.lt(cutoff))
+    .execute(conn)
+}
+
+// Runs for the life of the server, purging once an hour
+pub fn spawn_retention_task(pool: DbPool, retention_days: i64) {
+  if retention_days <= 0 {
+    return;
+  }
+
+  tokio::spawn(async move {
+    let mut interval = tokio::time::interval(PURGE_INTERVAL);
+    loop {
+      interval.tick().await;
+      let purged = pool
+        .get()
+        .map_err(|e| e.to_string())
+        .and_then(|mut conn| purge_expired(&mut conn, retention_days).map_err(|e| e.to_string()));
+      match purged {
+        Ok(0) => {}
+        Ok(count) => tracing::info!("Purged {} expired audit events", count),
+        Err(e) => tracing::error!("Failed to purge audit events: {}", e),
+      }
+    }
+  });
+}
@@ -1,27 +1,95 @@
+use std::net::IpAddr;
+
 fn get_env_var(var_name: &str) -> String {
   std::env::var(var_name).unwrap_or_else(|_| panic!("{} must be set", var_name))
 }
 
+fn get_env_var_or(var_name: &str, default: &str) -> String {
+  std::env::var(var_name).unwrap_or_else(|_| default.to_string())
+}
+
+fn get_optional_env_var(var_name: &str) -> Option<String> {
+  std::env::var(var_name).ok().filter(|value| !value.trim().is_empty())
+}
+
 #[derive(Debug, Clone)]
 pub struct Config {  
   pub client_origin: String,
   pub database_url: String,    
   pub server_url: String,
 
+  pub redirect_allowlist: Vec<String>,
+  pub default_redirect_url: String,
+  // Proxies whose X-Forwarded-For is believed, with none the peer address is used
+  pub trusted_proxies: Vec<IpAddr>,
+
+  pub oauth_login_url: String,
+  pub oauth_consent_url: String,
+  pub oauth_device_url: String,
+  // Where a magic link opened on another device goes to approve the sign-in
+  pub magiclink_approval_url: String,
+  // Where the "this wasn't me" link from security notices asks the user to confirm
+  pub not_me_url: String,
+  pub oidc_private_key: String,
+
+  // Empty means every provider. Besides the social providers, "password" and "magiclink" name the email sign-ins
+  pub enabled_providers: Vec<String>,
+  pub password_min_length: usize,
+  pub password_require_digit: bool,
+  pub password_require_symbol: bool,
+  pub mfa_required: bool,
+
   pub mailer_server: String,
   pub mailer_port: u16,
   pub mailer_from: String,
   pub mailer_from_name: String,
+  // Queued emails are given up on after this many failed attempts
+  pub mailer_max_attempts: i32,
+  // "smtp", "file" (a maildir under mailer_file_dir), "log" or "memory"
+  pub mailer_transport: String,
+  // "none", "starttls" or "tls"
+  pub mailer_security: String,
+  pub mailer_username: Option<String>,
+  pub mailer_password: Option<String>,
+  pub mailer_pool_size: usize,
+  pub mailer_file_dir: String,
+  // Templates here replace the built-in ones, EMAIL_TEMPLATE_WATCH reloads them when a file changes
+  pub email_template_dir: Option<String>,
+  pub email_template_watch: bool,
+  // Emails users about new sign-ins and changes to their account
+  pub security_notifications: bool,
+  // Codes emailed with a magic link when the client asks for one, expiry is in seconds
+  pub magiclink_otp_length: usize,
+  pub magiclink_otp_expires_in: i64,
+  pub magiclink_otp_max_attempts: i32,
+  pub magiclink_otp_lockout: i64,
+  // Only the browser that asked for a magic link may sign in with it, others get an approval page
+  pub magiclink_device_binding: bool,
 
   pub amazon_client_id: String,
   pub amazon_client_secret: String,
   pub amazon_redirect_url: String,
+  pub apple_client_id: String,
+  pub apple_team_id: String,
+  pub apple_key_id: String,
+  pub apple_private_key: String,
+  pub apple_redirect_url: String,
+  pub bitbucket_client_id: String,
+  pub bitbucket_client_secret: String,
+  pub bitbucket_redirect_url: String,
+  pub discord_client_id: String,
+  pub discord_client_secret: String,
+  pub discord_redirect_url: String,
   pub facebook_client_id: String,
   pub facebook_client_secret: String,
   pub facebook_redirect_url: String,
   pub github_client_id: String,
   pub github_client_secret: String,
   pub github_redirect_url: String,
+  pub gitlab_client_id: String,
+  pub gitlab_client_secret: String,
+  pub gitlab_redirect_url: String,
+  pub gitlab_base_url: String,
   pub google_client_id: String,
   pub google_client_secret: String,
   pub google_redirect_url: String,
@@ -37,6 +105,9 @@ pub struct Config {
   pub reddit_client_id: String,
   pub reddit_client_secret: String,
   pub reddit_redirect_url: String,
+  pub slack_client_id: String,
+  pub slack_client_secret: String,
+  pub slack_redirect_url: String,
   pub tiktok_client_id: String,
   pub tiktok_client_secret: String,
   pub tiktok_redirect_url: String,
@@ -52,6 +123,22 @@ pub struct Config {
   pub access_token_max_age: i64,
   pub refresh_token_expires_in: String,
   pub refresh_token_max_age: i64,
+  // Minutes, impersonation sessions get no refresh token
+  pub impersonation_max_age: i64,
+  // Days to keep audit events, zero keeps them forever
+  pub audit_retention_days: i64,
+  // Deliveries are given up on after this many failed attempts
+  pub webhook_max_attempts: i32,
+
+  // Auth hooks, each one is off until its url is set
+  pub hook_before_register_url: Option<String>,
+  pub hook_before_login_url: Option<String>,
+  pub hook_access_token_claims_url: Option<String>,
+  pub hook_oauth_profile_url: Option<String>,
+  pub hook_secret: String,
+  pub hook_timeout_ms: u64,
+  // Whether to carry on when a hook can't be reached or answers nonsense
+  pub hook_fail_open: bool,
 }
 
 impl Config {
@@ -60,26 +147,120 @@ impl Config {
     let client_origin = get_env_var("AUTH_CLIENT_ORIGIN");        
     let server_url = get_env_var("AUTH_SERVER_URL");
 
+    let redirect_allowlist = get_env_var_or("AUTH_REDIRECT_ALLOWLIST", &client_origin)
+      .split(',')
+      .map(|entry| entry.trim().to_string())
+      .filter(|entry| !entry.is_empty())
+      .collect::<Vec<String>>();
+    let default_redirect_url = get_env_var_or("AUTH_DEFAULT_REDIRECT_URL", &client_origin);
+    let trusted_proxies = get_env_var_or("AUTH_TRUSTED_PROXIES", "")
+      .split(',')
+      .map(|entry| entry.trim())
+      .filter(|entry| !entry.is_empty())
+      .map(|entry| entry.parse::<IpAddr>().expect("AUTH_TRUSTED_PROXIES must be a comma separated list of IP addresses"))
+      .collect::<Vec<IpAddr>>();
+
+    let oauth_login_url = get_env_var_or("AUTH_OAUTH_LOGIN_URL", &format!("{}/login", client_origin));
+    let oauth_consent_url = get_env_var_or("AUTH_OAUTH_CONSENT_URL", &format!("{}/consent", client_origin));
+    let oauth_device_url = get_env_var_or("AUTH_OAUTH_DEVICE_URL", &format!("{}/device", client_origin));
+    let magiclink_approval_url = get_env_var_or("AUTH_MAGICLINK_APPROVAL_URL", &format!("{}/magiclink/approve", client_origin));
+    let not_me_url = get_env_var_or("AUTH_NOT_ME_URL", &format!("{}/not_me", client_origin));
+    let oidc_private_key = get_env_var("AUTH_OIDC_PRIVATE_KEY");
+
+    let enabled_providers = get_env_var_or("AUTH_ENABLED_PROVIDERS", "")
+      .split(',')
+      .map(|entry| entry.trim().to_lowercase())
+      .filter(|entry| !entry.is_empty())
+      .collect::<Vec<String>>();
+    let password_min_length = get_env_var_or("AUTH_PASSWORD_MIN_LENGTH", "8").parse::<usize>().unwrap();
+    let password_require_digit = get_env_var_or("AUTH_PASSWORD_REQUIRE_DIGIT", "false").parse::<bool>().unwrap();
+    let password_require_symbol = get_env_var_or("AUTH_PASSWORD_REQUIRE_SYMBOL", "false").parse::<bool>().unwrap();
+    let mfa_required = get_env_var_or("AUTH_MFA_REQUIRED", "false").parse::<bool>().unwrap();
+
     let auth_key = get_env_var("AUTH_KEY");
     let access_token_expires_in = get_env_var("AUTH_ACCESS_TOKEN_EXPIRED_IN");
     let access_token_max_age = get_env_var("AUTH_ACCESS_TOKEN_MAXAGE");
     let refresh_token_expires_in = get_env_var("AUTH_REFRESH_TOKEN_EXPIRED_IN");
     let refresh_token_max_age = get_env_var("AUTH_REFRESH_TOKEN_MAXAGE");
+    let impersonation_max_age = get_env_var_or("AUTH_IMPERSONATION_MAXAGE", "15");
+    let audit_retention_days = get_env_var_or("AUDIT_RETENTION_DAYS", "90");
+    let webhook_max_attempts = get_env_var_or("WEBHOOK_MAX_ATTEMPTS", "8");
+
+    let hook_before_register_url = get_optional_env_var("AUTH_HOOK_BEFORE_REGISTER_URL");
+    let hook_before_login_url = get_optional_env_var("AUTH_HOOK_BEFORE_LOGIN_URL");
+    let hook_access_token_claims_url = get_optional_env_var("AUTH_HOOK_ACCESS_TOKEN_CLAIMS_URL");
+    let hook_oauth_profile_url = get_optional_env_var("AUTH_HOOK_OAUTH_PROFILE_URL");
+    let hook_secret = get_env_var_or("AUTH_HOOK_SECRET", "");
+    let hook_timeout_ms = get_env_var_or("AUTH_HOOK_TIMEOUT_MS", "2000").parse::<u64>().unwrap();
+    let hook_fail_open = get_env_var_or("AUTH_HOOK_FAIL_OPEN", "false").parse::<bool>().unwrap();
+    let hooks_enabled = [&hook_before_register_url, &hook_before_login_url, &hook_access_token_claims_url, &hook_oauth_profile_url]
+      .iter()
+      .any(|url| url.is_some());
+    if hooks_enabled && hook_secret.is_empty() {
+      panic!("AUTH_HOOK_SECRET must be set when an auth hook is configured");
+    }
 
     let mailer_server = get_env_var("SMTP_SERVER_URL");
     let mailer_port = get_env_var("SMTP_PORT").parse::<u16>().unwrap();
     let mailer_from = get_env_var("SMTP_FROM");
     let mailer_from_name = get_env_var("SMTP_FROM_NAME");
+    let mailer_max_attempts = get_env_var_or("SMTP_MAX_ATTEMPTS", "8").parse::<i32>().unwrap();
+    let mailer_transport = get_env_var_or("MAIL_TRANSPORT", "smtp").to_lowercase();
+    let mailer_security = get_env_var_or("SMTP_SECURITY", "none").to_lowercase();
+    let mailer_username = get_optional_env_var("SMTP_USERNAME");
+    let mailer_password = get_optional_env_var("SMTP_PASSWORD");
+    let mailer_pool_size = get_env_var_or("SMTP_POOL_SIZE", "4").parse::<usize>().unwrap();
+    let mailer_file_dir = get_env_var_or("MAIL_FILE_DIR", "./mail");
+    let email_template_dir = get_optional_env_var("EMAIL_TEMPLATE_DIR");
+    let email_template_watch = get_env_var_or("EMAIL_TEMPLATE_WATCH", "false").parse::<bool>().unwrap();
+    let security_notifications = get_env_var_or("SECURITY_NOTIFICATIONS", "true").parse::<bool>().unwrap();
+    let magiclink_otp_length = get_env_var_or("MAGICLINK_OTP_LENGTH", "6").parse::<usize>().unwrap().clamp(6, 10);
+    let magiclink_otp_expires_in = crate::utils::parse_duration(&get_env_var_or("MAGICLINK_OTP_EXPIRES_IN", "10m"))
+      .expect("MAGICLINK_OTP_EXPIRES_IN must be a duration such as 10m");
+    let magiclink_otp_max_attempts = get_env_var_or("MAGICLINK_OTP_MAX_ATTEMPTS", "5").parse::<i32>().unwrap();
+    let magiclink_otp_lockout = crate::utils::parse_duration(&get_env_var_or("MAGICLINK_OTP_LOCKOUT", "15m"))
+      .expect("MAGICLINK_OTP_LOCKOUT must be a duration such as 15m");
+    let magiclink_device_binding = get_env_var_or("MAGICLINK_DEVICE_BINDING", "true").parse::<bool>().unwrap();
+    if !["smtp", "file", "log", "memory"].contains(&mailer_transport.as_str()) {
+      panic!("MAIL_TRANSPORT must be one of smtp, file, log or memory");
+    }
+    if !["none", "starttls", "tls"].contains(&mailer_security.as_str()) {
+      panic!("SMTP_SECURITY must be one of none, starttls or tls");
+    }
+    if mailer_username.is_some() != mailer_password.is_some() {
+      panic!("SMTP_USERNAME and SMTP_PASSWORD must be set together");
+    }
+    // Never send the password over a plain connection
+    if mailer_username.is_some() && mailer_security == "none" {
+      panic!("SMTP_USERNAME and SMTP_PASSWORD require SMTP_SECURITY=starttls or tls");
+    }
 
     let amazon_client_id = get_env_var("AUTH_AMAZON_CLIENT_ID");
     let amazon_client_secret = get_env_var("AUTH_AMAZON_CLIENT_SECRET");
     let amazon_redirect_url = get_env_var("AUTH_AMAZON_REDIRECT_URI");
+    let apple_client_id = get_env_var("AUTH_APPLE_CLIENT_ID");
+    let apple_team_id = get_env_var("AUTH_APPLE_TEAM_ID");
+    let apple_key_id = get_env_var("AUTH_APPLE_KEY_ID");
+    let apple_private_key = get_env_var("AUTH_APPLE_PRIVATE_KEY");
+    let apple_redirect_url = get_env_var("AUTH_APPLE_REDIRECT_URI");
+    let bitbucket_client_id = get_env_var("AUTH_BITBUCKET_CLIENT_ID");
+    let bitbucket_client_secret = get_env_var("AUTH_BITBUCKET_CLIENT_SECRET");
+    let bitbucket_redirect_url = get_env_var("AUTH_BITBUCKET_REDIRECT_URI");
+    let discord_client_id = get_env_var("AUTH_DISCORD_CLIENT_ID");
+    let discord_client_secret = get_env_var("AUTH_DISCORD_CLIENT_SECRET");
+    let discord_redirect_url = get_env_var("AUTH_DISCORD_REDIRECT_URI");
     let facebook_client_id = get_env_var("AUTH_FACEBOOK_CLIENT_ID");
     let facebook_client_secret = get_env_var("AUTH_FACEBOOK_CLIENT_SECRET");
     let facebook_redirect_url = get_env_var("AUTH_FACEBOOK_REDIRECT_URI");
     let github_client_id = get_env_var("AUTH_GITHUB_CLIENT_ID");
     let github_client_secret = get_env_var("AUTH_GITHUB_CLIENT_SECRET");
     let github_redirect_url = get_env_var("AUTH_GITHUB_REDIRECT_URI");
+    let gitlab_client_id = get_env_var("AUTH_GITLAB_CLIENT_ID");
+    let gitlab_client_secret = get_env_var("AUTH_GITLAB_CLIENT_SECRET");
+    let gitlab_redirect_url = get_env_var("AUTH_GITLAB_REDIRECT_URI");
+    let gitlab_base_url = get_env_var_or("AUTH_GITLAB_BASE_URL", "https://gitlab.com")
+      .trim_end_matches('/')
+      .to_string();
     let google_client_id = get_env_var("AUTH_GOOGLE_CLIENT_ID");
     let google_client_secret = get_env_var("AUTH_GOOGLE_CLIENT_SECRET");
     let google_redirect_url = get_env_var("AUTH_GOOGLE_REDIRECT_URI");
@@ -95,6 +276,9 @@ impl Config {
     let reddit_client_id = get_env_var("AUTH_REDDIT_CLIENT_ID");
     let reddit_client_secret = get_env_var("AUTH_REDDIT_CLIENT_SECRET");
     let reddit_redirect_url = get_env_var("AUTH_REDDIT_REDIRECT_URI");
+    let slack_client_id = get_env_var("AUTH_SLACK_CLIENT_ID");
+    let slack_client_secret = get_env_var("AUTH_SLACK_CLIENT_SECRET");
+    let slack_redirect_url = get_env_var("AUTH_SLACK_REDIRECT_URI");
     let tiktok_client_id = get_env_var("AUTH_TIKTOK_CLIENT_ID");
     let tiktok_client_secret = get_env_var("AUTH_TIKTOK_CLIENT_SECRET");
     let tiktok_redirect_url = get_env_var("AUTH_TIKTOK_REDIRECT_URI");
@@ -109,19 +293,63 @@ impl Config {
       client_origin,
       database_url,
       server_url,
+      redirect_allowlist,
+      default_redirect_url,
+      trusted_proxies,
+      oauth_login_url,
+      oauth_consent_url,
+      oauth_device_url,
+      magiclink_approval_url,
+      not_me_url,
+      oidc_private_key,
+      enabled_providers,
+      password_min_length,
+      password_require_digit,
+      password_require_symbol,
+      mfa_required,
       mailer_server,
       mailer_port,
       mailer_from,
       mailer_from_name,  
+      mailer_max_attempts,
+      mailer_transport,
+      mailer_security,
+      mailer_username,
+      mailer_password,
+      mailer_pool_size,
+      mailer_file_dir,
+      email_template_dir,
+      email_template_watch,
+      security_notifications,
+      magiclink_otp_length,
+      magiclink_otp_expires_in,
+      magiclink_otp_max_attempts,
+      magiclink_otp_lockout,
+      magiclink_device_binding,
       amazon_client_id,
       amazon_client_secret,
       amazon_redirect_url,    
+      apple_client_id,
+      apple_team_id,
+      apple_key_id,
+      apple_private_key,
+      apple_redirect_url,
+      bitbucket_client_id,
+      bitbucket_client_secret,
+      bitbucket_redirect_url,
+      discord_client_id,
+      discord_client_secret,
+      discord_redirect_url,
       facebook_client_id,
       facebook_client_secret,
       facebook_redirect_url,
       github_client_id,
       github_client_secret,
       github_redirect_url,
+      gitlab_client_id,
+      gitlab_client_secret,
+      gitlab_redirect_url,
+      gitlab_base_url,
       google_client_id,
       google_client_secret,
       google_redirect_url,
@@ -137,6 +365,9 @@ impl Config {
       reddit_client_id,
       reddit_client_secret,
       reddit_redirect_url,
+      slack_client_id,
+      slack_client_secret,
+      slack_redirect_url,
       tiktok_client_id,
       tiktok_client_secret,
       tiktok_redirect_url,
@@ -151,6 +382,35 @@ impl Config {
       access_token_max_age: access_token_max_age.parse::<i64>().unwrap(),
       refresh_token_expires_in,
       refresh_token_max_age: refresh_token_max_age.parse::<i64>().unwrap(),
+      impersonation_max_age: impersonation_max_age.parse::<i64>().unwrap(),
+      audit_retention_days: audit_retention_days.parse::<i64>().unwrap(),
+      webhook_max_attempts: webhook_max_attempts.parse::<i32>().unwrap(),
+      hook_before_register_url,
+      hook_before_login_url,
+      hook_access_token_claims_url,
+      hook_oauth_profile_url,
+      hook_secret,
+      hook_timeout_ms,
+      hook_fail_open,
+    }
+  }
+}
+
+impl Config {
+  pub fn provider_enabled(&self, provider: &str) -> bool {
+    self.enabled_providers.is_empty() || self.enabled_providers.iter().any(|enabled| enabled == &provider.to_lowercase())
+  }
+
+  pub fn check_password(&self, password: &str) -> Result<(), String> {
+    if password.chars().count() < self.password_min_length {
+      return Err(format!("Password must be at least {} characters", self.password_min_length));
+    }
+    if self.password_require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+      return Err("Password must contain a digit".to_string());
+    }
+    if self.password_require_symbol && password.chars().all(|c| c.is_alphanumeric()) {
+      return Err("Password must contain a symbol".to_string());
     }
+    Ok(())
   }
 }
@@ -0,0 +1,141 @@
+use chrono::{Duration, Utc};
+use diesel::{dsl::sum, prelude::*};
+use rand::Rng;
+use ulid::Ulid;
+
+use crate::{
+  config::Config,
+  schema::{email_otps, user, EmailOtp, EmailOtpUse},
+  utils::hash_token
+};
+
+#[derive(Debug, PartialEq)]
+pub enum OtpError {
+  Invalid,
+  TooManyAttempts,
+}
+
+// Salted with the row id, so equal codes never share a hash
+fn hash_code(id: &str, code: &str) -> String {
+  hash_token(&format!("{}.{}", id, code))
+}
+
+fn generate_code(length: usize) -> String {
+  let mut rng = rand::thread_rng();
+  (0..length).map(|_| char::from(b'0' + rng.gen_range(0..10))).collect()
+}
+
+// Only the newest code of a user works, requesting another retires the rest along with their attempts
+pub fn create(conn: &mut PgConnection, env: &Config, confirmation_id: &str, user_id: &str) -> QueryResult<String> {
+  let timestamp = Utc::now().naive_utc();
+  let id = Ulid::new().to_string();
+  let code = generate_code(env.magiclink_otp_length);
+
+  conn.transaction(|conn| {
+    diesel::update(email_otps::table)
+      .filter(email_otps::user_id.eq(user_id))
+      .filter(email_otps::used_at.is_null())
+      .set(&EmailOtpUse {
+        used_at: Some(timestamp),
+        updated_at: Some(timestamp),
+      })
+      .execute(conn)?;
+
+    diesel::insert_into(email_otps::table)
+      .values(&EmailOtp {
+        id: id.clone(),
+        confirmation_id: confirmation_id.to_string(),
+        user_id: user_id.to_string(),
+        code_hash: hash_code(&id, &code),
+        attempts: 0,
+        expires: timestamp + Duration::seconds(env.magiclink_otp_expires_in),
+        used_at: None,
+        created_at: timestamp,
+        updated_at: None,
+      })
+      .execute(conn)
+  })?;
+
+  Ok(code)
+}
+
+// Called when the magic link itself was used
+pub fn invalidate(conn: &mut PgConnection, confirmation_id: &str) {
+  let timestamp = Utc::now().naive_utc();
+  let _ = diesel::update(email_otps::table)
+    .filter(email_otps::confirmation_id.eq(confirmation_id))
+    .filter(email_otps::used_at.is_null())
+    .set(&EmailOtpUse {
+      used_at: Some(timestamp),
+      updated_at: Some(timestamp),
+    })
+    .execute(conn)
+    .inspect_err(|e| tracing::error!("Failed to invalidate codes for {}: {}", confirmation_id, e));
+}
+
+fn find_current(conn: &mut PgConnection, user_id: &str) -> QueryResult<Option<EmailOtp>> {
+  email_otps::table
+    .filter(email_otps::user_id.eq(user_id))
+    .filter(email_otps::used_at.is_null())
+    .filter(email_otps::expires.gt(Utc::now().naive_utc()))
+    .order(email_otps::id.desc())
+    .first::<EmailOtp>(conn)
+    .optional()
+}
+
+// Every guess spends an attempt before it is compared. Attempts count per user across every code in
+// the lockout window, so requesting a new code does not buy more guesses. A matching code is marked
+// used and returned
+pub fn redeem(conn: &mut PgConnection, env: &Config, user_id: &str, code: &str) -> Result<EmailOtp, OtpError> {
+  let timestamp = Utc::now().naive_utc();
+  let lockout_start = timestamp - Duration::seconds(env.magiclink_otp_lockout);
+
+  // The spent attempt is committed whether or not the guess matches
+  let outcome = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+    // Guesses for one user are taken one at a time, so parallel guesses cannot exceed the limit
+    user::table
+      .filter(user::id.eq(user_id))
+      .select(user::id)
+      .for_update()
+      .first::<String>(conn)?;
+
+    let recent_attempts = email_otps::table
+      .filter(email_otps::user_id.eq(user_id))
+      .filter(email_otps::updated_at.gt(lockout_start))
+      .select(sum(email_otps::attempts))
+      .first::<Option<i64>>(conn)?
+      .unwrap_or(0);
+
+    if recent_attempts >= i64::from(env.magiclink_otp_max_attempts) {
+      return Ok(Err(OtpError::TooManyAttempts));
+    }
+
+    let Some(otp) = find_current(conn, user_id)? else {
+      return Ok(Err(OtpError::Invalid));
+    };
+
+    let otp = diesel::update(email_otps::table)
+      .filter(email_otps::id.eq(otp.id))
+      .set((email_otps::attempts.eq(email_otps::attempts + 1), email_otps::updated_at.eq(Some(timestamp))))
+      .get_result::<EmailOtp>(conn)?;
+
+    if hash_code(&otp.id, code.trim()) != otp.code_hash {
+      return Ok(Err(OtpError::Invalid));
+    }
+
+    diesel::update(email_otps::table)
+      .filter(email_otps::id.eq(otp.id.clone()))
+      .set(&EmailOtpUse {
+        used_at: Some(timestamp),
+        updated_at: Some(timestamp),
+      })
+      .execute(conn)?;
+
+    Ok(Ok(otp))
+  });
+
+  outcome.unwrap_or_else(|e| {
+    tracing::error!("Failed to check code for {}: {}", user_id, e);
+    Err(OtpError::Invalid)
+  })
+}
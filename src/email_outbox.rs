@@ -0,0 +1,147 @@
+use std::{sync::Arc, time::Duration as StdDuration};
+
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use ulid::Ulid;
+
+use crate::{
+  config::Config,
+  mail_transport::MailTransport,
+  schema::{email_outbox, OutboxEmail, OutboxEmailClaim, OutboxEmailResult},
+  smtp::EmailBaseParams, DbPool
+};
+
+pub const STATUS_PENDING: &str = "pending";
+pub const STATUS_SENT: &str = "sent";
+pub const STATUS_FAILED: &str = "failed";
+
+const POLL_INTERVAL: StdDuration = StdDuration::from_secs(2);
+const BATCH_SIZE: i64 = 20;
+// Each send is cut off after this, whatever the transport does
+const SEND_TIMEOUT: StdDuration = StdDuration::from_secs(30);
+// Outlasts a batch in which every send runs into the timeout, so another worker never picks up an
+// email that is still being sent
+const CLAIM_SECONDS: i64 = BATCH_SIZE * SEND_TIMEOUT.as_secs() as i64 + 60;
+const FIRST_RETRY_SECONDS: i64 = 30;
+const MAX_RETRY_SECONDS: i64 = 60 * 60;
+
+// Saves a rendered email for the worker, the request that caused it no longer waits on SMTP
+pub fn enqueue(
+  conn: &mut PgConnection,
+  template: &str,
+  base: EmailBaseParams,
+  html: String,
+  text: String,
+) -> QueryResult<String> {
+  let timestamp = Utc::now().naive_utc();
+  let email = OutboxEmail {
+    id: Ulid::new().to_string(),
+    template: template.to_string(),
+    from_address: base.from,
+    from_name: base.from_name,
+    to_address: base.to,
+    subject: base.subject,
+    html_body: html,
+    text_body: text,
+    tenant_id: base.tenant_id,
+    status: STATUS_PENDING.into(),
+    attempts: 0,
+    next_attempt_at: timestamp,
+    last_error: None,
+    sent_at: None,
+    created_at: timestamp,
+    updated_at: None,
+  };
+
+  diesel::insert_into(email_outbox::table)
+    .values(&email)
+    .execute(conn)?;
+
+  Ok(email.id)
+}
+
+// 30s, 1m, 2m, 4m... capped at an hour
+fn retry_delay(attempts: i32) -> Duration {
+  let exponent = attempts.clamp(1, 20) as u32 - 1;
+  Duration::seconds(FIRST_RETRY_SECONDS.saturating_mul(2_i64.pow(exponent)).min(MAX_RETRY_SECONDS))
+}
+
+// Skips rows another worker has locked, then pushes them back so they are not picked up twice
+fn claim_due(conn: &mut PgConnection) -> QueryResult<Vec<OutboxEmail>> {
+  conn.transaction(|conn| {
+    let now = Utc::now().naive_utc();
+    let due = email_outbox::table
+      .filter(email_outbox::status.eq(STATUS_PENDING))
+      .filter(email_outbox::next_attempt_at.le(now))
+      .order(email_outbox::next_attempt_at.asc())
+      .limit(BATCH_SIZE)
+      .for_update()
+      .skip_locked()
+      .load::<OutboxEmail>(conn)?;
+
+    diesel::update(email_outbox::table)
+      .filter(email_outbox::id.eq_any(due.iter().map(|email| email.id.clone()).collect::<Vec<_>>()))
+      .set(&OutboxEmailClaim {
+        next_attempt_at: now + Duration::seconds(CLAIM_SECONDS),
+      })
+      .execute(conn)?;
+
+    Ok(due)
+  })
+}
+
+fn record_result(conn: &mut PgConnection, email: &OutboxEmail, error: Option<String>, max_attempts: i32) {
+  let now = Utc::now().naive_utc();
+  let attempts = email.attempts + 1;
+
+  let (status, next_attempt_at, sent_at) = match &error {
+    None => (STATUS_SENT, now, Some(now)),
+    Some(_) if attempts >= max_attempts => (STATUS_FAILED, now, None),
+    Some(_) => (STATUS_PENDING, now + retry_delay(attempts), None),
+  };
+
+  if let Some(e) = &error {
+    tracing::warn!("Email {} to {} failed on attempt {}: {}", email.id, email.to_address, attempts, e);
+  }
+
+  let _ = diesel::update(email_outbox::table)
+    .filter(email_outbox::id.eq(email.id.clone()))
+    .set(&OutboxEmailResult {
+      status: status.into(),
+      attempts,
+      next_attempt_at,
+      last_error: error,
+      sent_at,
+      updated_at: Some(now),
+    })
+    .execute(conn)
+    .inspect_err(|e| tracing::error!("Failed to update email {}: {}", email.id, e));
+}
+
+async fn send_due(pool: &DbPool, transport: &dyn MailTransport, max_attempts: i32) -> Result<(), String> {
+  let mut conn = pool.get().map_err(|e| e.to_string())?;
+  let due = claim_due(&mut conn).map_err(|e| e.to_string())?;
+
+  for email in due {
+    let result = tokio::time::timeout(SEND_TIMEOUT, transport.send(&email))
+      .await
+      .unwrap_or_else(|_| Err(format!("Timed out after {}s", SEND_TIMEOUT.as_secs())));
+    record_result(&mut conn, &email, result.err(), max_attempts);
+  }
+
+  Ok(())
+}
+
+// Runs for the life of the server, draining the outbox every couple of seconds
+pub fn spawn_worker(pool: DbPool, env: &Config, transport: Arc<dyn MailTransport>) {
+  let max_attempts = env.mailer_max_attempts;
+  tokio::spawn(async move {
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    loop {
+      interval.tick().await;
+      if let Err(e) = send_due(&pool, transport.as_ref(), max_attempts).await {
+        tracing::error!("Failed to send queued emails: {}", e);
+      }
+    }
+  });
+}
@@ -0,0 +1,437 @@
+use std::{collections::HashMap, sync::Arc};
+use axum::{
+  extract::{Path, Query, State}, http::StatusCode, response::IntoResponse, Extension, Json
+};
+use anyhow::Result;
+use chrono::Utc;
+use diesel::prelude::*;
+use serde_json::json;
+use crate::{
+  audit::{self, AuditRecord, RequestContext, OUTCOME_FAILURE, OUTCOME_SUCCESS},
+  handlers::forgot_password_handler::send_password_reset,
+  jwt_auth::JWTAuthMiddleware,
+  model::{AdminPasswordResetSchema, AdminUpdateUserSchema, AdminUserSearchSchema},
+  redirect::validate_redirect,
+  response::{FilteredAdminUser, FilteredIdentity, FilteredSession},
+  schema::{identities, social_provider, tokens, user, Identity, SocialProvider, Token, User, UserAdminUpdate, UserDelete, UserDisable},
+  security_notice::{self, SecurityNotice},
+  template::{normalize_locale, preferred_locales},
+  token::revoke_user_tokens, utils::escape_like, webhook, AppState
+};
+
+const DEFAULT_PAGE_SIZE: i64 = 20;
+const MAX_PAGE_SIZE: i64 = 100;
+
+fn filter_admin_user(user: &User) -> FilteredAdminUser {
+  FilteredAdminUser {
+    id: user.id.to_owned(),
+    name: user.name.to_owned(),
+    email: user.email.to_owned(),
+    verified: user.verified,
+    locale: user.locale.to_owned(),
+    disabledAt: user.disabled_at,
+    createdAt: user.created_at,
+    updatedAt: user.updated_at,
+  }
+}
+
+pub fn admin_error(status: StatusCode, message: &str) -> (StatusCode, Json<serde_json::Value>) {
+  let error_response = serde_json::json!({
+      "status": "fail",
+      "message": message
+  });
+  (status, Json(error_response))
+}
+
+// Deleted accounts are gone as far as the admin API is concerned
+pub fn find_admin_user(conn: &mut PgConnection, user_id: &str) -> Result<User, (StatusCode, Json<serde_json::Value>)> {
+  user::table
+    .filter(user::id.eq(user_id))
+    .filter(user::deleted_at.is_null())
+    .first::<User>(conn)
+    .optional()
+    .unwrap_or(None)
+    .ok_or_else(|| admin_error(StatusCode::NOT_FOUND, "User not found"))
+}
+
+// Admins cannot lock themselves out
+pub fn not_self(admin: &User, user_id: &str) -> Result<(), (StatusCode, Json<serde_json::Value>)> {
+  if admin.id == user_id {
+    return Err(admin_error(StatusCode::BAD_REQUEST, "Admins cannot do this to their own account"));
+  }
+  Ok(())
+}
+
+pub fn audit_admin_action(
+  conn: &mut PgConnection,
+  context: &RequestContext,
+  event_type: &str,
+  admin: &User,
+  user_id: &str,
+  metadata: serde_json::Value,
+) {
+  audit_admin_event(conn, context, event_type, &admin.id, Some(user_id), OUTCOME_SUCCESS, metadata);
+}
+
+// Reads of user data are audited too, including the ones that fail
+pub fn audit_admin_event(
+  conn: &mut PgConnection,
+  context: &RequestContext,
+  event_type: &str,
+  actor_id: &str,
+  user_id: Option<&str>,
+  outcome: &str,
+  metadata: serde_json::Value,
+) {
+  audit::record(conn, context, AuditRecord {
+    event_type,
+    actor_id: Some(actor_id),
+    subject_id: user_id,
+    outcome,
+    metadata,
+  });
+}
+
+// Users matching every given filter, paged by id
+pub async fn search_users_handler(
+    Extension(jwtauth): Extension<JWTAuthMiddleware>,
+    State(data): State<Arc<AppState>>,
+    context: RequestContext,
+    Query(query): Query<AdminUserSearchSchema>,
+  ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+    // Which filters were used, not their values, so searched emails stay out of the log
+    let filters = json!({
+      "email": query.email.as_deref().is_some_and(|email| !email.is_empty()),
+      "name": query.name.as_deref().is_some_and(|name| !name.is_empty()),
+      "verified": query.verified,
+      "createdAfter": query.created_after,
+      "createdBefore": query.created_before,
+    });
+    let page_size = query.page_size.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+    let mut users_query = user::table
+      .filter(user::deleted_at.is_null())
+      .into_boxed();
+
+    if let Some(email) = query.email.filter(|email| !email.is_empty()) {
+      users_query = users_query.filter(user::email.ilike(format!("%{}%", escape_like(&email))));
+    }
+    if let Some(name) = query.name.filter(|name| !name.is_empty()) {
+      users_query = users_query.filter(user::name.ilike(format!("%{}%", escape_like(&name))));
+    }
+    if let Some(verified) = query.verified {
+      users_query = users_query.filter(user::verified.eq(verified));
+    }
+    if let Some(created_after) = query.created_after {
+      users_query = users_query.filter(user::created_at.ge(created_after.naive_utc()));
+    }
+    if let Some(created_before) = query.created_before {
+      users_query = users_query.filter(user::created_at.lt(created_before.naive_utc()));
+    }
+    if let Some(continuous_token) = query.continuous_token {
+      users_query = users_query.filter(user::id.gt(continuous_token));
+    }
+
+    let result = users_query
+      .order(user::id.asc())
+      .limit(page_size + 1)
+      .load::<User>(&mut conn);
+
+    let mut users = match result {
+      Ok(users) => users,
+      Err(e) => {
+        audit_admin_event(&mut conn, &context, "admin.user.search", jwtauth.actor_id(), None, OUTCOME_FAILURE, json!({ "filters": filters }));
+        return Err(admin_error(StatusCode::INTERNAL_SERVER_ERROR, &format!("Error fetching users: {}", e)));
+      }
+    };
+
+    // One extra row tells us whether there is another page
+    let continuous_token = if users.len() as i64 > page_size {
+      users.truncate(page_size as usize);
+      users.last().map(|user| user.id.clone())
+    } else {
+      None
+    };
+
+    let user_ids = users.iter().map(|user| user.id.as_str()).collect::<Vec<_>>();
+    audit_admin_event(&mut conn, &context, "admin.user.search", jwtauth.actor_id(), None, OUTCOME_SUCCESS, json!({ "filters": filters, "userIds": user_ids }));
+
+    Ok(Json(json!({
+      "status": "success",
+      "users": users.iter().map(filter_admin_user).collect::<Vec<_>>(),
+      "continuousToken": continuous_token,
+    })))
+  }
+
+// The user with their linked identities and unexpired session tokens
+pub async fn get_user_handler(
+    Extension(jwtauth): Extension<JWTAuthMiddleware>,
+    State(data): State<Arc<AppState>>,
+    context: RequestContext,
+    Path(user_id): Path<String>,
+  ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+    let user = find_admin_user(&mut conn, &user_id)
+      .inspect_err(|_| {
+        audit_admin_event(&mut conn, &context, "admin.user.read", jwtauth.actor_id(), Some(&user_id), OUTCOME_FAILURE, json!({ "reason": "not_found" }));
+      })?;
+
+    let providers = social_provider::table
+      .load::<SocialProvider>(&mut conn)
+      .unwrap_or_default()
+      .into_iter()
+      .map(|provider| (provider.id, provider.name))
+      .collect::<HashMap<_, _>>();
+
+    let identities = identities::table
+      .filter(identities::user_id.eq(user_id.clone()))
+      .filter(identities::deleted_at.is_null())
+      .load::<Identity>(&mut conn)
+      .unwrap_or_default()
+      .into_iter()
+      .map(|identity| FilteredIdentity {
+        provider: providers.get(&identity.provider_id).cloned().unwrap_or(identity.provider_id),
+        id: identity.id,
+        email: identity.email,
+        emailVerified: identity.email_verified,
+        lastSigninAt: identity.last_signin_at,
+        createdAt: identity.created_at,
+      })
+      .collect::<Vec<_>>();
+
+    let sessions = tokens::table
+      .filter(tokens::user_id.eq(user_id.clone()))
+      .filter(tokens::blacklisted.eq(false))
+      .filter(tokens::expires.gt(Utc::now().naive_utc()))
+      .order(tokens::created_at.desc())
+      .load::<Token>(&mut conn)
+      .unwrap_or_default()
+      .into_iter()
+      .map(|token| FilteredSession {
+        id: token.id,
+        createdAt: token.created_at,
+        expiresAt: token.expires,
+      })
+      .collect::<Vec<_>>();
+
+    audit_admin_event(&mut conn, &context, "admin.user.read", jwtauth.actor_id(), Some(&user_id), OUTCOME_SUCCESS, json!({}));
+
+    Ok(Json(json!({
+      "status": "success",
+      "user": filter_admin_user(&user),
+      "identities": identities,
+      "sessions": sessions,
+    })))
+  }
+
+pub async fn update_user_handler(
+    Extension(jwtauth): Extension<JWTAuthMiddleware>,
+    State(data): State<Arc<AppState>>,
+    context: RequestContext,
+    Path(user_id): Path<String>,
+    Json(body): Json<AdminUpdateUserSchema>,
+  ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let admin = jwtauth.session_user()?;
+    let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+    let previous = find_admin_user(&mut conn, &user_id)?;
+
+    let name = body.name.map(|name| name.trim().to_string());
+    if name.as_deref() == Some("") {
+      return Err(admin_error(StatusCode::BAD_REQUEST, "Name cannot be empty"));
+    }
+
+    let email = body.email.map(|email| email.trim().to_lowercase());
+    if let Some(email) = email.as_deref() {
+      if !email.contains('@') {
+        return Err(admin_error(StatusCode::BAD_REQUEST, "Email is invalid"));
+      }
+
+      let taken = user::table
+        .filter(user::email.eq(email))
+        .filter(user::id.ne(user_id.clone()))
+        .first::<User>(&mut conn)
+        .optional();
+
+      if let Ok(Some(_)) = taken {
+        return Err(admin_error(StatusCode::CONFLICT, "Email is already in use"));
+      }
+    }
+
+    let locale = match body.locale.as_deref() {
+      Some(locale) => Some(normalize_locale(locale).ok_or_else(|| admin_error(StatusCode::BAD_REQUEST, "Locale is invalid"))?),
+      None => None,
+    };
+
+    let changed = json!({
+      "name": name.is_some(),
+      "email": email.is_some(),
+      "verified": body.verified,
+      "locale": locale,
+    });
+
+    let result = diesel::update(user::table)
+      .filter(user::id.eq(user_id.clone()))
+      .set(&UserAdminUpdate {
+        name,
+        email,
+        verified: body.verified,
+        locale,
+        updated_at: Some(Utc::now().naive_utc()),
+      })
+      .get_result::<User>(&mut conn);
+
+    let user = match result {
+      Ok(user) => user,
+      Err(e) => return Err(admin_error(StatusCode::BAD_REQUEST, &format!("User not updated: {}", e))),
+    };
+
+    audit_admin_action(&mut conn, &context, "admin.user.update", admin, &user_id, changed);
+
+    if let (Some(previous_email), Some(new_email)) = (previous.email, user.email.clone()) {
+      if previous_email != new_email {
+        security_notice::notify(&data, &mut conn, &user_id, None, &context, SecurityNotice::EmailChanged { previous_email, new_email }).await;
+      }
+    }
+
+    Ok(Json(json!({ "status": "success", "user": filter_admin_user(&user) })))
+  }
+
+// Disabling also ends every session, enabling leaves the user to sign in again
+pub async fn disable_user_handler(
+    Extension(jwtauth): Extension<JWTAuthMiddleware>,
+    State(data): State<Arc<AppState>>,
+    context: RequestContext,
+    Path(user_id): Path<String>,
+  ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let admin = jwtauth.session_user()?;
+    not_self(admin, &user_id)?;
+    let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+    find_admin_user(&mut conn, &user_id)?;
+
+    let timestamp = Utc::now().naive_utc();
+    let result = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+      let user = diesel::update(user::table)
+        .filter(user::id.eq(user_id.clone()))
+        .set(&UserDisable {
+          disabled_at: Some(timestamp),
+          updated_at: Some(timestamp),
+        })
+        .get_result::<User>(conn)?;
+
+      revoke_user_tokens(conn, &user_id)?;
+      Ok(user)
+    });
+
+    let user = match result {
+      Ok(user) => user,
+      Err(e) => return Err(admin_error(StatusCode::BAD_REQUEST, &format!("User could not be disabled: {}", e))),
+    };
+
+    audit_admin_action(&mut conn, &context, "admin.user.disable", admin, &user_id, json!({}));
+
+    Ok(Json(json!({ "status": "success", "user": filter_admin_user(&user) })))
+  }
+
+pub async fn enable_user_handler(
+    Extension(jwtauth): Extension<JWTAuthMiddleware>,
+    State(data): State<Arc<AppState>>,
+    context: RequestContext,
+    Path(user_id): Path<String>,
+  ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let admin = jwtauth.session_user()?;
+    let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+    find_admin_user(&mut conn, &user_id)?;
+
+    let result = diesel::update(user::table)
+      .filter(user::id.eq(user_id.clone()))
+      .set(&UserDisable {
+        disabled_at: None,
+        updated_at: Some(Utc::now().naive_utc()),
+      })
+      .get_result::<User>(&mut conn);
+
+    let user = match result {
+      Ok(user) => user,
+      Err(e) => return Err(admin_error(StatusCode::BAD_REQUEST, &format!("User could not be enabled: {}", e))),
+    };
+
+    audit_admin_action(&mut conn, &context, "admin.user.enable", admin, &user_id, json!({}));
+
+    Ok(Json(json!({ "status": "success", "user": filter_admin_user(&user) })))
+  }
+
+// Soft delete, the row stays so audit events and tuples still point somewhere
+pub async fn delete_user_handler(
+    Extension(jwtauth): Extension<JWTAuthMiddleware>,
+    State(data): State<Arc<AppState>>,
+    context: RequestContext,
+    Path(user_id): Path<String>,
+  ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let admin = jwtauth.session_user()?;
+    not_self(admin, &user_id)?;
+    let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+    find_admin_user(&mut conn, &user_id)?;
+
+    let result = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+      diesel::update(user::table)
+        .filter(user::id.eq(user_id.clone()))
+        .set(&UserDelete {
+          deleted_at: Some(Utc::now().naive_utc()),
+        })
+        .execute(conn)?;
+
+      webhook::enqueue(conn, webhook::USER_DELETED, json!({ "userId": user_id }));
+      revoke_user_tokens(conn, &user_id)
+    });
+
+    if let Err(e) = result {
+      return Err(admin_error(StatusCode::BAD_REQUEST, &format!("User could not be deleted: {}", e)));
+    }
+
+    audit_admin_action(&mut conn, &context, "admin.user.delete", admin, &user_id, json!({}));
+
+    Ok(Json(json!({ "status": "success" })))
+  }
+
+pub async fn logout_user_handler(
+    Extension(jwtauth): Extension<JWTAuthMiddleware>,
+    State(data): State<Arc<AppState>>,
+    context: RequestContext,
+    Path(user_id): Path<String>,
+  ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let admin = jwtauth.session_user()?;
+    let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+    find_admin_user(&mut conn, &user_id)?;
+
+    let revoked = match revoke_user_tokens(&mut conn, &user_id) {
+      Ok(revoked) => revoked,
+      Err(e) => return Err(admin_error(StatusCode::BAD_REQUEST, &format!("Sessions could not be revoked: {}", e))),
+    };
+
+    audit_admin_action(&mut conn, &context, "admin.user.logout", admin, &user_id, json!({ "revoked": revoked }));
+
+    Ok(Json(json!({ "status": "success", "revoked": revoked })))
+  }
+
+pub async fn reset_user_password_handler(
+    Extension(jwtauth): Extension<JWTAuthMiddleware>,
+    State(data): State<Arc<AppState>>,
+    context: RequestContext,
+    Path(user_id): Path<String>,
+    Json(body): Json<AdminPasswordResetSchema>,
+  ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let admin = jwtauth.session_user()?;
+    let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+    let user = find_admin_user(&mut conn, &user_id)?;
+
+    validate_redirect(&data.env, &body.redirect_to)?;
+
+    let email = user.email.ok_or_else(|| admin_error(StatusCode::BAD_REQUEST, "User has no email"))?;
+
+    let sent = send_password_reset(data.clone(), &mut conn, user_id.clone(), email, body.redirect_to, None, preferred_locales(user.locale.as_deref(), None)).await?;
+
+    audit_admin_action(&mut conn, &context, "admin.user.password_reset", admin, &user_id, json!({ "sent": sent }));
+
+    Ok(Json(json!({ "status": if sent { "success" } else { "fail" } })))
+  }
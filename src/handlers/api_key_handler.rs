@@ -0,0 +1,163 @@
+use std::sync::Arc;
+use axum::{
+  extract::{Path, State}, http::StatusCode, response::IntoResponse, Extension, Json
+};
+use anyhow::Result;
+use chrono::Utc;
+use diesel::{query_dsl::methods::{FilterDsl, OrderDsl}, ExpressionMethods, RunQueryDsl};
+use serde_json::json;
+use ulid::Ulid;
+use crate::{
+  api_key::generate_api_key, audit::{record_user_event, RequestContext, OUTCOME_SUCCESS}, jwt_auth::JWTAuthMiddleware, model::CreateApiKeySchema, response::FilteredApiKey, security_notice::{self, SecurityNotice},
+  schema::{api_keys, ApiKey, ApiKeyRevoke}, utils::hash_token, AppState
+};
+
+fn filter_api_key(api_key: &ApiKey) -> FilteredApiKey {
+  FilteredApiKey {
+    id: api_key.id.to_owned(),
+    name: api_key.name.to_owned(),
+    prefix: api_key.prefix.to_owned(),
+    scopes: api_key.scopes.to_owned(),
+    expiresAt: api_key.expires,
+    lastUsedAt: api_key.last_used_at,
+    lastUsedIp: api_key.last_used_ip.to_owned(),
+    createdAt: api_key.created_at,
+  }
+}
+
+pub async fn create_api_key_handler(
+    Extension(jwtauth): Extension<JWTAuthMiddleware>,
+    State(data): State<Arc<AppState>>,
+    context: RequestContext,
+    Json(body): Json<CreateApiKeySchema>,
+  ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    // Keys are managed from a signed in session, so a leaked key can't mint more keys
+    let user = jwtauth.session_user()?;
+    let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
+    let name = body.name.trim().to_string();
+    if name.is_empty() {
+      let error_response = serde_json::json!({
+          "status": "fail",
+          "message": "Name is required"
+      });
+      return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+    }
+
+    if matches!(body.expires_at, Some(expires_at) if expires_at <= Utc::now()) {
+      let error_response = serde_json::json!({
+          "status": "fail",
+          "message": "Expiry must be in the future"
+      });
+      return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+    }
+
+    let (key, prefix) = generate_api_key();
+    let api_key = ApiKey {
+      id: Ulid::new().to_string(),
+      user_id: user.id.clone(),
+      name,
+      prefix,
+      key_hash: hash_token(&key),
+      scopes: body.scopes.unwrap_or_default().trim().to_string(),
+      expires: body.expires_at.map(|expires_at| expires_at.naive_utc()),
+      last_used_at: None,
+      last_used_ip: None,
+      revoked_at: None,
+      created_at: Utc::now().naive_utc(),
+      updated_at: None,
+      deleted_at: None
+    };
+
+    let statement = diesel::insert_into(api_keys::table)
+      .values(&api_key)
+      .execute(&mut conn);
+
+    if let Err(e) = statement {
+      let error_response = serde_json::json!({
+          "status": "fail",
+          "message": format!("API key not saved to database: validation error\nDetails: {:?}", e)
+      });
+      return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+    }
+
+    record_user_event(&mut conn, &context, "api_key.created", Some(&user.id), OUTCOME_SUCCESS, json!({ "apiKeyId": api_key.id }));
+    security_notice::notify(&data, &mut conn, &user.id, jwtauth.tenant_id.clone(), &context, SecurityNotice::ApiKeyCreated { name: api_key.name.clone() }).await;
+
+    // The only time the full key is ever returned
+    Ok((StatusCode::CREATED, Json(serde_json::json!({
+      "status": "success",
+      "key": key,
+      "apiKey": filter_api_key(&api_key),
+    }))))
+  }
+
+pub async fn list_api_keys_handler(
+    Extension(jwtauth): Extension<JWTAuthMiddleware>,
+    State(data): State<Arc<AppState>>,
+  ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let user = jwtauth.session_user()?;
+    let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
+    let result = api_keys::table
+      .filter(api_keys::user_id.eq(user.id.clone()))
+      .filter(api_keys::revoked_at.is_null())
+      .order(api_keys::created_at.desc())
+      .load::<ApiKey>(&mut conn);
+
+    match result {
+      Ok(keys) => Ok(Json(serde_json::json!({
+        "status": "success",
+        "apiKeys": keys.iter().map(filter_api_key).collect::<Vec<_>>(),
+      }))),
+      Err(e) => {
+        let error_response = serde_json::json!({
+            "status": "fail",
+            "message": format!("Error fetching API keys: {}", e)
+        });
+        Err((StatusCode::INTERNAL_SERVER_ERROR, Json(error_response)))
+      }
+    }
+  }
+
+pub async fn revoke_api_key_handler(
+    Extension(jwtauth): Extension<JWTAuthMiddleware>,
+    State(data): State<Arc<AppState>>,
+    context: RequestContext,
+    Path(id): Path<String>,
+  ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let user = jwtauth.session_user()?;
+    let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
+    let timestamp = Utc::now().naive_utc();
+    let statement = diesel::update(api_keys::table)
+      .filter(api_keys::id.eq(id.clone()))
+      .filter(api_keys::user_id.eq(user.id.clone()))
+      .filter(api_keys::revoked_at.is_null())
+      .set(&ApiKeyRevoke {
+        revoked_at: Some(timestamp),
+        updated_at: Some(timestamp),
+      })
+      .execute(&mut conn);
+
+    match statement {
+      Ok(1) => {
+        record_user_event(&mut conn, &context, "api_key.revoked", Some(&user.id), OUTCOME_SUCCESS, json!({ "apiKeyId": id }));
+        Ok(Json(serde_json::json!({ "status": "success" })))
+      }
+      Ok(_) => {
+        let error_response = serde_json::json!({
+            "status": "fail",
+            "message": "API key not found"
+        });
+        Err((StatusCode::NOT_FOUND, Json(error_response)))
+      }
+      Err(e) => {
+        let error_response = serde_json::json!({
+            "status": "fail",
+            "message": format!("API key could not be revoked: {}", e)
+        });
+        Err((StatusCode::BAD_REQUEST, Json(error_response)))
+      }
+    }
+  }
@@ -0,0 +1,129 @@
+use std::sync::Arc;
+use axum::{
+  extract::{Query, State}, http::StatusCode, response::IntoResponse, Extension, Json
+};
+use anyhow::Result;
+use diesel::prelude::*;
+use serde_json::json;
+use crate::{
+  jwt_auth::JWTAuthMiddleware, model::{ActivitySchema, AuditEventSearchSchema}, response::FilteredAuditEvent,
+  schema::{audit_events, AuditEvent}, utils::escape_like, AppState
+};
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 200;
+
+fn filter_audit_event(event: &AuditEvent) -> FilteredAuditEvent {
+  FilteredAuditEvent {
+    id: event.id.to_owned(),
+    eventType: event.event_type.to_owned(),
+    actorId: event.actor_id.to_owned(),
+    subjectId: event.subject_id.to_owned(),
+    outcome: event.outcome.to_owned(),
+    ip: event.ip.to_owned(),
+    userAgent: event.user_agent.to_owned(),
+    metadata: event.metadata.to_owned(),
+    createdAt: event.created_at,
+  }
+}
+
+// Ids are ulids, so newest first is id order and the last id seen is the cursor
+fn load_page(
+  conn: &mut PgConnection,
+  events_query: audit_events::BoxedQuery<'static, diesel::pg::Pg>,
+  page_size: Option<i64>,
+  continuous_token: Option<String>,
+) -> Result<serde_json::Value, (StatusCode, Json<serde_json::Value>)> {
+  let page_size = page_size.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+  let mut events_query = events_query;
+  if let Some(continuous_token) = continuous_token {
+    events_query = events_query.filter(audit_events::id.lt(continuous_token));
+  }
+
+  let result = events_query
+    .order(audit_events::id.desc())
+    .limit(page_size + 1)
+    .load::<AuditEvent>(conn);
+
+  let mut events = match result {
+    Ok(events) => events,
+    Err(e) => {
+      let error_response = serde_json::json!({
+          "status": "fail",
+          "message": format!("Error fetching audit events: {}", e)
+      });
+      return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(error_response)));
+    }
+  };
+
+  // One extra row tells us whether there is another page
+  let continuous_token = if events.len() as i64 > page_size {
+    events.truncate(page_size as usize);
+    events.last().map(|event| event.id.clone())
+  } else {
+    None
+  };
+
+  Ok(json!({
+    "status": "success",
+    "events": events.iter().map(filter_audit_event).collect::<Vec<_>>(),
+    "continuousToken": continuous_token,
+  }))
+}
+
+// Events matching every given filter, newest first
+pub async fn search_audit_events_handler(
+    State(data): State<Arc<AppState>>,
+    Query(query): Query<AuditEventSearchSchema>,
+  ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
+    let mut events_query = audit_events::table.into_boxed();
+
+    if let Some(actor_id) = query.actor_id {
+      events_query = events_query.filter(audit_events::actor_id.eq(actor_id));
+    }
+    if let Some(subject_id) = query.subject_id {
+      events_query = events_query.filter(audit_events::subject_id.eq(subject_id));
+    }
+    // A trailing '.' matches a whole family of events, e.g. "admin."
+    if let Some(event_type) = query.event_type.filter(|event_type| !event_type.is_empty()) {
+      events_query = if event_type.ends_with('.') {
+        events_query.filter(audit_events::event_type.like(format!("{}%", escape_like(&event_type))))
+      } else {
+        events_query.filter(audit_events::event_type.eq(event_type))
+      };
+    }
+    if let Some(outcome) = query.outcome {
+      events_query = events_query.filter(audit_events::outcome.eq(outcome));
+    }
+    if let Some(since) = query.since {
+      events_query = events_query.filter(audit_events::created_at.ge(since.naive_utc()));
+    }
+    if let Some(until) = query.until {
+      events_query = events_query.filter(audit_events::created_at.lt(until.naive_utc()));
+    }
+
+    let page = load_page(&mut conn, events_query, query.page_size, query.continuous_token)?;
+
+    Ok(Json(page))
+  }
+
+// What happened to the signed in user's own account
+pub async fn my_activity_handler(
+    Extension(jwtauth): Extension<JWTAuthMiddleware>,
+    State(data): State<Arc<AppState>>,
+    Query(query): Query<ActivitySchema>,
+  ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let user = jwtauth.user()?;
+    let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
+    let events_query = audit_events::table
+      .filter(audit_events::subject_id.eq(user.id.clone()))
+      .into_boxed();
+
+    let page = load_page(&mut conn, events_query, query.page_size, query.continuous_token)?;
+
+    Ok(Json(page))
+  }
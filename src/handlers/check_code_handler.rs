@@ -6,7 +6,7 @@ use anyhow::Result;
 use diesel::{query_dsl::methods::FilterDsl, ExpressionMethods, OptionalExtension, RunQueryDsl};
 use serde_json::json;
 use crate::{
-  model::CheckCodeSchema, schema::{email_confirmation, EmailConfirmation}, AppState
+  model::CheckCodeSchema, schema::{email_confirmation, EmailConfirmation, PURPOSE_PASSWORD_RESET}, AppState
 };
 
 pub async fn check_code_handler(
@@ -18,6 +18,7 @@ pub async fn check_code_handler(
   let confirmation_exists = email_confirmation::table
     .filter(email_confirmation::code.eq(body.code.to_owned()))
     .filter(email_confirmation::flow.eq("created"))
+    .filter(email_confirmation::purpose.eq(PURPOSE_PASSWORD_RESET))
     .first::<EmailConfirmation>(&mut conn)
     .optional();
 
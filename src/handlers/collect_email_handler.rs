@@ -0,0 +1,124 @@
+use std::sync::Arc;
+use axum::{
+  extract::State, http::{header, HeaderMap, Response, StatusCode}, response::IntoResponse, Extension, Json
+};
+use anyhow::Result;
+use diesel::{query_dsl::methods::FilterDsl, ExpressionMethods, OptionalExtension, RunQueryDsl};
+use serde_json::json;
+use ulid::Ulid;
+use chrono::{Duration, Utc};
+use crate::{
+  audit::{record_user_event, RequestContext, OUTCOME_FAILURE, OUTCOME_SUCCESS}, jwt_auth::JWTAuthMiddleware, model::CollectEmailSchema, redirect::validate_redirect, schema::{email_confirmation, user, EmailConfirmation, PURPOSE_EMAIL_VERIFICATION, User}, smtp::{self, generate_random_string, EmailBaseParams, EmailParams}, template::preferred_locales, AppState
+};
+
+// Social sign-ins that came without a verified email land here to add one
+pub async fn collect_email_handler(
+    Extension(jwtauth): Extension<JWTAuthMiddleware>,
+    State(data): State<Arc<AppState>>,
+    context: RequestContext,
+    Json(body): Json<CollectEmailSchema>,
+  ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let user = jwtauth.session_user()?;
+    let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+    let email = body.email.trim().to_lowercase();
+
+    validate_redirect(&data.env, &body.redirect_to)
+      .inspect_err(|_| {
+        record_user_event(&mut conn, &context, "email.verification_requested", Some(&user.id), OUTCOME_FAILURE, json!({ "reason": "invalid_redirect" }));
+      })?;
+
+    if user.email.is_some() {
+      record_user_event(&mut conn, &context, "email.verification_requested", Some(&user.id), OUTCOME_FAILURE, json!({ "reason": "email_present" }));
+      let error_response = serde_json::json!({
+          "status": "fail",
+          "message": "User already has an email"
+      });
+      return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+    }
+
+    // check whether the email belongs to another user
+    let user_exists = user::table
+      .filter(user::email.eq(email.clone()))
+      .first::<User>(&mut conn)
+      .optional();
+
+    if let Ok(Some(_)) = user_exists {
+      record_user_event(&mut conn, &context, "email.verification_requested", Some(&user.id), OUTCOME_FAILURE, json!({ "reason": "email_in_use" }));
+      let error_response = serde_json::json!({
+          "status": "fail",
+          "message": "Email is already in use"
+      });
+      return Err((StatusCode::CONFLICT, Json(error_response)));
+    }
+
+    // insert into database, the email is only stored on the user once verified
+    let code = generate_random_string();
+    let expires = (Utc::now() + Duration::days(1)).naive_utc();
+    let timestamp = Utc::now().naive_utc();
+    let statement = diesel::insert_into(email_confirmation::table)
+      .values(&EmailConfirmation {
+        id: Ulid::new().to_string(),
+        user_id: user.id.clone(),
+        code: code.clone(),
+        redirect_to: Some(body.redirect_to.clone()),
+        email: Some(email.clone()),
+        device_nonce: None,
+        tenant_id: None,
+        requester_ip: None,
+        requester_user_agent: None,
+        expires,
+        flow: "created".into(),
+        purpose: PURPOSE_EMAIL_VERIFICATION.into(),
+        created_at: timestamp,
+        updated_at: None,
+        deleted_at: None
+      })
+      .execute(&mut conn);
+
+    if let Err(e) = statement {
+      record_user_event(&mut conn, &context, "email.verification_requested", Some(&user.id), OUTCOME_FAILURE, json!({ "reason": "code_not_saved" }));
+      let error_response = serde_json::json!({
+          "status": "fail",
+          "message": format!("verification code not saved to database: validation error\nDetails: {:?}", e)
+      });
+      return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+    }
+
+    let base_params = EmailBaseParams {
+      from: data.env.mailer_from.clone(),
+      from_name: data.env.mailer_from_name.clone(),
+      to: email,
+      subject: "Verify your email".to_string(),
+      tenant_id: jwtauth.tenant_id.clone(),
+      locales: preferred_locales(user.locale.as_deref(), context.accept_language.as_deref()),
+    };
+
+    let params = EmailParams::EmailVerification {
+      base: base_params,
+      code,
+    };
+
+    let result = smtp::send_email(params, axum::extract::State(data)).await;
+    let outcome = if result.is_ok() { OUTCOME_SUCCESS } else { OUTCOME_FAILURE };
+    record_user_event(&mut conn, &context, "email.verification_requested", Some(&user.id), outcome, json!({}));
+
+    let mut headers = HeaderMap::new();
+    headers.append(
+      header::CONTENT_TYPE,
+      "application/json".parse().unwrap(),
+    );
+
+    let mut response = Response::new(
+      json!({
+        "status": match result {
+            Ok(_) => "success",
+            Err(_) => "fail"
+        }
+      })
+      .to_string(),
+    );
+
+    response.headers_mut().extend(headers);
+
+    Ok(response)
+  }
@@ -0,0 +1,132 @@
+use std::sync::Arc;
+use axum::{
+  extract::{Path, Query, State}, http::StatusCode, response::IntoResponse, Extension, Json
+};
+use anyhow::Result;
+use chrono::Utc;
+use diesel::prelude::*;
+use serde_json::json;
+use crate::{
+  audit::{self, AuditRecord, RequestContext, OUTCOME_SUCCESS},
+  email_outbox::{STATUS_FAILED, STATUS_PENDING},
+  handlers::admin_handler::admin_error,
+  jwt_auth::JWTAuthMiddleware,
+  model::AdminEmailSearchSchema,
+  response::FilteredOutboxEmail,
+  schema::{email_outbox, OutboxEmail, OutboxEmailResult}, AppState
+};
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 200;
+
+fn filter_email(email: &OutboxEmail) -> FilteredOutboxEmail {
+  FilteredOutboxEmail {
+    id: email.id.to_owned(),
+    template: email.template.to_owned(),
+    to: email.to_address.to_owned(),
+    subject: email.subject.to_owned(),
+    tenantId: email.tenant_id.to_owned(),
+    status: email.status.to_owned(),
+    attempts: email.attempts,
+    nextAttemptAt: email.next_attempt_at,
+    lastError: email.last_error.to_owned(),
+    sentAt: email.sent_at,
+    createdAt: email.created_at,
+  }
+}
+
+// Queued emails newest first, usually filtered to status=failed
+pub async fn search_emails_handler(
+    State(data): State<Arc<AppState>>,
+    Query(query): Query<AdminEmailSearchSchema>,
+  ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+    let page_size = query.page_size.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+    let mut emails_query = email_outbox::table.into_boxed();
+
+    if let Some(status) = query.status {
+      emails_query = emails_query.filter(email_outbox::status.eq(status));
+    }
+    if let Some(to) = query.to.filter(|to| !to.is_empty()) {
+      emails_query = emails_query.filter(email_outbox::to_address.eq(to.to_lowercase()));
+    }
+    if let Some(continuous_token) = query.continuous_token {
+      emails_query = emails_query.filter(email_outbox::id.lt(continuous_token));
+    }
+
+    let result = emails_query
+      .order(email_outbox::id.desc())
+      .limit(page_size + 1)
+      .load::<OutboxEmail>(&mut conn);
+
+    let mut emails = match result {
+      Ok(emails) => emails,
+      Err(e) => {
+        return Err(admin_error(StatusCode::INTERNAL_SERVER_ERROR, &format!("Error fetching emails: {}", e)));
+      }
+    };
+
+    // One extra row tells us whether there is another page
+    let continuous_token = if emails.len() as i64 > page_size {
+      emails.truncate(page_size as usize);
+      emails.last().map(|email| email.id.clone())
+    } else {
+      None
+    };
+
+    Ok(Json(json!({
+      "status": "success",
+      "emails": emails.iter().map(filter_email).collect::<Vec<_>>(),
+      "continuousToken": continuous_token,
+    })))
+  }
+
+// Gives a failed email a fresh set of attempts, its content is sent exactly as first rendered
+pub async fn resend_email_handler(
+    Extension(jwtauth): Extension<JWTAuthMiddleware>,
+    State(data): State<Arc<AppState>>,
+    context: RequestContext,
+    Path(id): Path<String>,
+  ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
+    let email = email_outbox::table
+      .filter(email_outbox::id.eq(id.clone()))
+      .first::<OutboxEmail>(&mut conn)
+      .optional()
+      .unwrap_or(None)
+      .ok_or_else(|| admin_error(StatusCode::NOT_FOUND, "Email not found"))?;
+
+    if email.status != STATUS_FAILED {
+      return Err(admin_error(StatusCode::CONFLICT, "Only failed emails can be resent"));
+    }
+
+    let timestamp = Utc::now().naive_utc();
+    let statement = diesel::update(email_outbox::table)
+      .filter(email_outbox::id.eq(id.clone()))
+      .filter(email_outbox::status.eq(STATUS_FAILED))
+      .set(&OutboxEmailResult {
+        status: STATUS_PENDING.into(),
+        attempts: 0,
+        next_attempt_at: timestamp,
+        last_error: None,
+        sent_at: None,
+        updated_at: Some(timestamp),
+      })
+      .execute(&mut conn);
+
+    if let Err(e) = statement {
+      return Err(admin_error(StatusCode::BAD_REQUEST, &format!("Email could not be resent: {}", e)));
+    }
+
+    audit::record(&mut conn, &context, AuditRecord {
+      event_type: "admin.email.resend",
+      actor_id: Some(jwtauth.actor_id()),
+      subject_id: None,
+      outcome: OUTCOME_SUCCESS,
+      metadata: json!({ "emailId": id, "template": email.template }),
+    });
+
+    Ok(Json(json!({ "status": "success" })))
+  }
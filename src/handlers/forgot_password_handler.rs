@@ -3,21 +3,26 @@ use axum::{
   extract::State, http::{header, HeaderMap, Response, StatusCode}, response::IntoResponse, Json
 };
 use anyhow::Result;
-use diesel::{query_dsl::methods::FilterDsl, ExpressionMethods, OptionalExtension, RunQueryDsl};
+use diesel::{query_dsl::methods::FilterDsl, ExpressionMethods, OptionalExtension, PgConnection, RunQueryDsl};
 use serde_json::json;
 use ulid::Ulid;
 use chrono::{Duration, Utc};
 use crate::{
-  model::ForgotPasswordSchema, schema::{email_confirmation, user, EmailConfirmation, User}, smtp::{self, generate_random_string, EmailBaseParams, EmailParams}, AppState
+  audit::{record_user_event, RequestContext, OUTCOME_FAILURE, OUTCOME_SUCCESS}, model::ForgotPasswordSchema, redirect::validate_redirect, schema::{email_confirmation, user, EmailConfirmation, PURPOSE_PASSWORD_RESET, User}, smtp::{self, generate_random_string, EmailBaseParams, EmailParams}, template::preferred_locales,
+  tenant::resolve_tenant_id, AppState
 };
 
 pub async fn forgot_password_handler(
     State(data): State<Arc<AppState>>,
+    request_headers: HeaderMap,
+    context: RequestContext,
     Json(body): Json<ForgotPasswordSchema>,
   ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
     let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
     let email = body.email.to_owned();
     let redirect_to = body.redirect_to.to_owned();
+
+    validate_redirect(&data.env, &redirect_to)?;
   
     // check whether user email exists
     let user_exists = user::table
@@ -25,8 +30,8 @@ pub async fn forgot_password_handler(
     .first::<User>(&mut conn)
     .optional();
 
-    let user_id = if let Ok(Some(user)) = user_exists {
-      user.id
+    let user = if let Ok(Some(user)) = user_exists {
+      user
     } else {
       let error_response = serde_json::json!({
           "status": "fail",
@@ -35,23 +40,67 @@ pub async fn forgot_password_handler(
       return Err((StatusCode::BAD_REQUEST, Json(error_response)));
     };
   
-    // insert into database        
+    let tenant_id = resolve_tenant_id(&mut conn, &request_headers, body.tenant_id.as_deref());
+    let user_id = user.id;
+    let locales = preferred_locales(user.locale.as_deref(), context.accept_language.as_deref());
+    let result = send_password_reset(data, &mut conn, user_id.clone(), email, redirect_to, tenant_id, locales).await;
+    let outcome = if matches!(result, Ok(true)) { OUTCOME_SUCCESS } else { OUTCOME_FAILURE };
+    record_user_event(&mut conn, &context, "password.reset_requested", Some(&user_id), outcome, json!({}));
+  
+    let mut headers = HeaderMap::new();
+    headers.append(
+      header::CONTENT_TYPE,
+      "application/json".parse().unwrap(),
+    );
+  
+    let mut response = Response::new(
+      json!({
+        "status": match result? {
+            true => "success",
+            false => "fail"
+        }
+      })
+      .to_string(),
+    );
+  
+    response.headers_mut().extend(headers);
+  
+    Ok(response)
+  }
+
+// Stores a reset code and emails it, also used by admins to send a reset on a user's behalf.
+// Returns whether the email went out
+pub async fn send_password_reset(
+    data: Arc<AppState>,
+    conn: &mut PgConnection,
+    user_id: String,
+    email: String,
+    redirect_to: String,
+    tenant_id: Option<String>,
+    locales: Vec<String>,
+  ) -> Result<bool, (StatusCode, Json<serde_json::Value>)> {
     let code = generate_random_string();
     let expires = (Utc::now() + Duration::days(10)).naive_utc();
     let timestamp = Utc::now().naive_utc();
     let statement = diesel::insert_into(email_confirmation::table)
       .values(&EmailConfirmation {
         id: Ulid::new().to_string(),
-        user_id: user_id.into(),
-        code: code.clone().into(),
-        redirect_to: redirect_to.into(),
+        user_id,
+        code: code.clone(),
+        redirect_to: Some(redirect_to),
+        email: None,
+        device_nonce: None,
+        tenant_id: None,
+        requester_ip: None,
+        requester_user_agent: None,
         expires,
         flow: "created".into(),
+        purpose: PURPOSE_PASSWORD_RESET.into(),
         created_at: timestamp,
         updated_at: None,
         deleted_at: None
       })
-      .execute(&mut conn);
+      .execute(conn);
     
     if let Err(e) = statement {
       let error_response = serde_json::json!({
@@ -66,6 +115,8 @@ pub async fn forgot_password_handler(
       from_name: data.env.mailer_from_name.clone(),    
       to: email,
       subject: "Forgot Password".to_string(),
+      tenant_id,
+      locales,
     };
 
     let params = EmailParams::PasswordReset {
@@ -73,25 +124,5 @@ pub async fn forgot_password_handler(
       code: code.to_string(),
     };
   
-    let result = smtp::send_email(params, axum::extract::State(data)).await;
-  
-    let mut headers = HeaderMap::new();
-    headers.append(
-      header::CONTENT_TYPE,
-      "application/json".parse().unwrap(),
-    );
-  
-    let mut response = Response::new(
-      json!({
-        "status": match result {
-            Ok(_) => "success",
-            Err(_) => "fail"
-        }
-      })
-      .to_string(),
-    );
-  
-    response.headers_mut().extend(headers);
-  
-    Ok(response)
-  }
\ No newline at end of file
+    Ok(smtp::send_email(params, axum::extract::State(data)).await.is_ok())
+  }
@@ -3,20 +3,182 @@ use axum::{
   extract::State, http::{header, HeaderMap, Response, StatusCode}, response::IntoResponse, Json
 };
 use anyhow::Result;
-use diesel::{query_dsl::methods::FilterDsl, ExpressionMethods, OptionalExtension, RunQueryDsl};
+use axum_extra::extract::cookie::{Cookie, SameSite};
+use diesel::{query_dsl::methods::FilterDsl, ExpressionMethods, OptionalExtension, PgConnection, RunQueryDsl};
 use serde_json::json;
 use ulid::Ulid;
 use chrono::{Duration, Utc};
 use crate::{
-  model::MagicLinkSchema, schema::{email_confirmation, user, EmailConfirmation, User}, smtp::{self, generate_random_string, EmailBaseParams, EmailParams}, AppState
+  audit::{record_user_event, RequestContext, OUTCOME_FAILURE, OUTCOME_SUCCESS}, config::Config, email_otp, hooks::{self, Hook}, model::MagicLinkSchema, redirect::validate_redirect, schema::{email_confirmation, user, EmailConfirmation, PURPOSE_MAGIC_LINK, User}, security_notice::{self, SecurityNotice}, smtp::{self, generate_random_string, EmailBaseParams, EmailParams}, template::preferred_locales,
+  tenant::{check_sign_in_policy, find_membership, resolve_tenant_id, tenant_claims, tenant_config}, token::{issue_session_tokens, session_claims}, utils::{hash_token, parse_duration}, webhook, AppState
 };
 
+// Lax, as the link is opened by a top-level navigation from the mail client
+pub const MAGICLINK_NONCE_COOKIE: &str = "magiclink_nonce";
+const MAGICLINK_DAYS: i64 = 10;
+
+type TenantSignIn = (Config, Vec<(&'static str, String)>);
+
+// Sent once the requesting browser is signed in, so the nonce cannot match an older link later
+pub fn expired_nonce_cookie() -> Cookie<'static> {
+  Cookie::build((MAGICLINK_NONCE_COOKIE, ""))
+    .path("/")
+    .secure(true)
+    .max_age(time::Duration::ZERO)
+    .same_site(SameSite::Lax)
+    .http_only(true)
+    .build()
+}
+
+// The tenant a link was requested on applies again when it is redeemed, by link, code or approval.
+// Returns the config to sign in with and the claims placing the session in the tenant
+pub fn magiclink_tenant(conn: &mut PgConnection, env: &Config, tenant_id: Option<&str>, user_id: &str)
+  -> Result<TenantSignIn, (StatusCode, Json<serde_json::Value>)> {
+  let mut claims = vec![];
+  if let Some(tenant_id) = tenant_id {
+    if find_membership(conn, tenant_id, user_id).is_none() {
+      let error_response = serde_json::json!({
+        "status": "fail",
+        "message": "You are not a member of this tenant"
+      });
+      return Err((StatusCode::FORBIDDEN, Json(error_response)));
+    }
+    claims.extend(tenant_claims(tenant_id));
+  }
+
+  let config = tenant_config(conn, env, tenant_id);
+  if let Err(message) = check_sign_in_policy(&config, Some("otp"), None) {
+    let error_response = serde_json::json!({
+      "status": "fail",
+      "message": message
+    });
+    return Err((StatusCode::FORBIDDEN, Json(error_response)));
+  }
+
+  Ok((config, claims))
+}
+
+// How a magic link sign-in was finished: the link in the requesting browser, an approval from
+// another browser or the emailed code
+#[derive(Debug, Clone, Copy)]
+pub enum MagicLinkVia {
+  Link,
+  Approval,
+  Code,
+}
+
+impl MagicLinkVia {
+  fn name(&self) -> &'static str {
+    match self {
+      MagicLinkVia::Link => "link",
+      MagicLinkVia::Approval => "approval",
+      MagicLinkVia::Code => "code",
+    }
+  }
+
+  // As the new sign-in notice describes it
+  fn method(&self) -> &'static str {
+    match self {
+      MagicLinkVia::Link | MagicLinkVia::Approval => "magic link",
+      MagicLinkVia::Code => "email code",
+    }
+  }
+}
+
+// Signs the user in once a magic link was redeemed, whichever way. Returns the access token and the
+// session cookies, which also clear the link's nonce cookie
+pub async fn complete_magiclink_sign_in(
+  data: &Arc<AppState>,
+  conn: &mut PgConnection,
+  context: &RequestContext,
+  user_id: &str,
+  tenant_id: Option<&str>,
+  via: MagicLinkVia,
+) -> Result<(String, HeaderMap), (StatusCode, Json<serde_json::Value>)> {
+  // Recorded in the tokens for OIDC auth_time and amr
+  let mut auth_claims = session_claims(Utc::now().timestamp(), "otp");
+
+  // Requested on a tenant, the session acts in it while the tenant still allows magic links
+  let (env, tenant_auth_claims) = magiclink_tenant(conn, &data.env, tenant_id, user_id)
+    .inspect_err(|(_, Json(error))| {
+      record_user_event(conn, context, "magiclink.login", Some(user_id), OUTCOME_FAILURE, json!({ "via": via.name(), "reason": error["message"], "tenantId": tenant_id }));
+    })?;
+  auth_claims.extend(tenant_auth_claims);
+
+  hooks::run(&env, Hook::BeforeLogin, json!({ "userId": user_id, "method": "otp", "tenantId": tenant_id }))
+    .await
+    .inspect_err(|(_, Json(error))| {
+      record_user_event(conn, context, "magiclink.login", Some(user_id), OUTCOME_FAILURE, json!({ "via": via.name(), "reason": error["message"] }));
+    })?;
+
+  let (access_token_details, refresh_token_details) = issue_session_tokens(conn, &env, user_id, &auth_claims).await
+    .inspect_err(|(_, Json(error))| {
+      record_user_event(conn, context, "magiclink.login", Some(user_id), OUTCOME_FAILURE, json!({ "via": via.name(), "reason": error["message"] }));
+    })?;
+  let unrecognized = security_notice::is_unrecognized_sign_in(conn, user_id, context);
+  record_user_event(conn, context, "magiclink.login", Some(user_id), OUTCOME_SUCCESS, json!({ "method": "otp", "tenantId": tenant_id, "via": via.name() }));
+  webhook::enqueue(conn, webhook::USER_LOGIN, json!({ "userId": user_id, "method": "otp", "tenantId": tenant_id }));
+  if unrecognized {
+    let notice = SecurityNotice::NewSignIn { method: via.method().into() };
+    security_notice::notify(data, conn, user_id, tenant_id.map(String::from), context, notice).await;
+  }
+
+  let access_cookie = Cookie::build(
+    ("access_token",
+    access_token_details.token.clone().unwrap_or_default()),
+  )
+    .path("/")
+    .secure(true)
+    .max_age(time::Duration::seconds(parse_duration(&env.access_token_expires_in).unwrap_or(900))) // 15 minutes default
+    .same_site(SameSite::Strict)
+    .http_only(true);
+
+  let refresh_cookie = Cookie::build(
+    ("refresh_token",
+    refresh_token_details.token.clone().unwrap_or_default()),
+  )
+    .path("/")
+    .secure(true)
+    .max_age(time::Duration::seconds(parse_duration(&env.refresh_token_expires_in).unwrap_or(900))) // 15 minutes default
+    .same_site(SameSite::Strict)
+    .http_only(true);
+
+  let mut cookies = HeaderMap::new();
+  cookies.append(
+    header::SET_COOKIE,
+    access_cookie.to_string().parse().unwrap(),
+  );
+  cookies.append(
+    header::SET_COOKIE,
+    refresh_cookie.to_string().parse().unwrap(),
+  );
+  cookies.append(
+    header::SET_COOKIE,
+    expired_nonce_cookie().to_string().parse().unwrap(),
+  );
+
+  Ok((access_token_details.token.unwrap_or_default(), cookies))
+}
+
 pub async fn generate_magiclink_handler(
     State(data): State<Arc<AppState>>,
+    request_headers: HeaderMap,
+    context: RequestContext,
     Json(body): Json<MagicLinkSchema>,
   ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {    
     let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
-    let email = body.email;
+    let email = body.email.to_ascii_lowercase();
+
+    let tenant_id = resolve_tenant_id(&mut conn, &request_headers, body.tenant_id.as_deref());
+    if !tenant_config(&mut conn, &data.env, tenant_id.as_deref()).provider_enabled("magiclink") {
+      let error_response = serde_json::json!({
+          "status": "fail",
+          "message": "Magic link sign in is not enabled"
+      });
+      return Err((StatusCode::FORBIDDEN, Json(error_response)));
+    }
+
+    validate_redirect(&data.env, &body.redirect_to)?;
     
     // check whether user email exists
     let user_exists = user::table
@@ -24,8 +186,8 @@ pub async fn generate_magiclink_handler(
     .first::<User>(&mut conn)
     .optional();
 
-    let user_id = if let Ok(Some(user)) = user_exists {
-      user.id
+    let (user_id, user_locale) = if let Ok(Some(user)) = user_exists {
+      (user.id, user.locale)
     } else {
       let error_response = serde_json::json!({
           "status": "fail",
@@ -36,16 +198,25 @@ pub async fn generate_magiclink_handler(
   
     // insert into database
     let code = generate_random_string();
-    let expires = (Utc::now() + Duration::days(10)).naive_utc();
+    let expires = (Utc::now() + Duration::days(MAGICLINK_DAYS)).naive_utc();
     let timestamp = Utc::now().naive_utc();
+    // The requesting browser keeps the nonce, the row only its hash
+    let nonce = data.env.magiclink_device_binding.then(generate_random_string);
+    let confirmation_id = Ulid::new().to_string();
     let statement = diesel::insert_into(email_confirmation::table)
       .values(&EmailConfirmation {
-        id: Ulid::new().to_string(),
-        user_id: user_id.into(),
+        id: confirmation_id.clone(),
+        user_id: user_id.clone(),
         code: code.clone().into(),
         redirect_to: None,
+        email: None,
+        device_nonce: nonce.as_deref().map(hash_token),
+        tenant_id: tenant_id.clone(),
+        requester_ip: context.ip.clone(),
+        requester_user_agent: context.user_agent.clone(),
         expires,
         flow: "created".into(),
+        purpose: PURPOSE_MAGIC_LINK.into(),
         created_at: timestamp,
         updated_at: None,
         deleted_at: None
@@ -65,15 +236,35 @@ pub async fn generate_magiclink_handler(
       from_name: data.env.mailer_from_name.clone(),    
       to: email,
       subject: "One-time password-less authorization".to_string(),
+      tenant_id,
+      locales: preferred_locales(user_locale.as_deref(), context.accept_language.as_deref()),
+    };
+
+    let otp = if body.send_code {
+      match email_otp::create(&mut conn, &data.env, &confirmation_id, &user_id) {
+        Ok(otp) => Some(otp),
+        Err(e) => {
+          let error_response = serde_json::json!({
+              "status": "fail",
+              "message": format!("magic link code not saved to database: validation error\nDetails: {:?}", e)
+          });
+          return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+        }
+      }
+    } else {
+      None
     };
 
     let params = EmailParams::MagicLink {
       base: base_params,
       code: code.to_string(),
-      redirect_to: body.redirect_to.to_string()
+      redirect_to: body.redirect_to.to_string(),
+      otp,
     };
   
     let result = smtp::send_email(params, axum::extract::State(data)).await;
+    let outcome = if result.is_ok() { OUTCOME_SUCCESS } else { OUTCOME_FAILURE };
+    record_user_event(&mut conn, &context, "magiclink.requested", Some(&user_id), outcome, json!({}));
   
     let mut headers = HeaderMap::new();
     headers.append(
@@ -91,6 +282,19 @@ pub async fn generate_magiclink_handler(
       .to_string(),
     );
   
+    if let Some(nonce) = nonce {
+      let nonce_cookie = Cookie::build((MAGICLINK_NONCE_COOKIE, nonce))
+        .path("/")
+        .secure(true)
+        .max_age(time::Duration::days(MAGICLINK_DAYS))
+        .same_site(SameSite::Lax)
+        .http_only(true);
+      headers.append(
+        header::SET_COOKIE,
+        nonce_cookie.to_string().parse().unwrap(),
+      );
+    }
+
     response.headers_mut().extend(headers);
   
     Ok(response)
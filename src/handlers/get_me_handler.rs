@@ -11,14 +11,18 @@ use crate::{
 pub async fn get_me_handler(
     Extension(jwtauth): Extension<JWTAuthMiddleware>,
   ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
-    let user = &jwtauth.user;
+    let user = jwtauth.user()?;
     let json_response = serde_json::json!({
       "user": FilteredUser {
         id: user.id.to_string(),
         email: user.email.to_owned(),
         name: user.name.to_owned(),
         verified: user.verified,
-        role: "".into(),
+        role: jwtauth.roles.join(" "),
+        permissions: jwtauth.permissions.clone(),
+        tenantId: jwtauth.tenant_id.clone(),
+        tenantRole: jwtauth.tenant_role.clone(),
+        impersonatedBy: jwtauth.impersonator_id.clone(),
         photo: "".into(),
         createdAt: user.created_at,
         updatedAt: user.updated_at,
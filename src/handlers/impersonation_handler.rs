@@ -0,0 +1,226 @@
+use std::sync::Arc;
+use axum::{
+  extract::{Path, State}, http::{header, HeaderMap, Response, StatusCode}, response::IntoResponse, Extension, Json
+};
+use anyhow::Result;
+use axum_extra::extract::{
+  cookie::{Cookie, SameSite},
+  CookieJar,
+};
+use serde_json::json;
+use crate::{
+  audit::RequestContext,
+  handlers::admin_handler::{admin_error, audit_admin_action, find_admin_user, not_self},
+  jwt_auth::JWTAuthMiddleware,
+  tenant::{find_membership, tenant_claims, tenant_config},
+  token::{self, delete_token, is_token_active, is_user_active, idp_claims, issue_impersonation_token, issue_session_tokens, session_claims},
+  utils::parse_duration, AppState
+};
+
+// Holds the admin's own refresh token while they impersonate, so their session can be restored
+const IMPERSONATOR_COOKIE: &str = "impersonator_token";
+
+pub async fn impersonate_user_handler(
+    Extension(jwtauth): Extension<JWTAuthMiddleware>,
+    cookie_jar: CookieJar,
+    State(data): State<Arc<AppState>>,
+    context: RequestContext,
+    Path(user_id): Path<String>,
+  ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let admin = jwtauth.session_user()?;
+    not_self(admin, &user_id)?;
+    let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
+    let target = find_admin_user(&mut conn, &user_id)?;
+    if !is_user_active(&target) {
+      return Err(admin_error(StatusCode::BAD_REQUEST, "Disabled users cannot be impersonated"));
+    }
+
+    let token_details = issue_impersonation_token(&mut conn, &data.env, &user_id, &admin.id)?;
+
+    audit_admin_action(&mut conn, &context, "admin.impersonation.start", admin, &user_id, json!({
+      "tokenId": token_details.token_uuid.to_string(),
+      "expiresIn": data.env.impersonation_max_age * 60,
+    }));
+
+    let access_cookie = Cookie::build(
+      ("access_token",
+      token_details.token.clone().unwrap_or_default()),
+    )
+      .path("/")
+      .secure(true)
+      .max_age(time::Duration::minutes(data.env.impersonation_max_age))
+      .same_site(SameSite::Strict)
+      .http_only(true);
+
+    // No refresh while impersonating, the admin's refresh token waits in its own cookie
+    let refresh_cookie = Cookie::build(("refresh_token", ""))
+      .path("/")
+      .secure(true)
+      .max_age(time::Duration::minutes(-1))
+      .same_site(SameSite::Strict)
+      .http_only(true);
+
+    let mut headers = HeaderMap::new();
+    headers.append(
+      header::SET_COOKIE,
+      access_cookie.to_string().parse().unwrap(),
+    );
+
+    if let Some(admin_refresh_token) = cookie_jar.get("refresh_token") {
+      let impersonator_cookie = Cookie::build((IMPERSONATOR_COOKIE, admin_refresh_token.value().to_string()))
+        .path("/")
+        .secure(true)
+        .max_age(time::Duration::seconds(parse_duration(&data.env.refresh_token_expires_in).unwrap_or(900)))
+        .same_site(SameSite::Strict)
+        .http_only(true);
+
+      headers.append(
+        header::SET_COOKIE,
+        refresh_cookie.to_string().parse().unwrap(),
+      );
+      headers.append(
+        header::SET_COOKIE,
+        impersonator_cookie.to_string().parse().unwrap(),
+      );
+    }
+
+    headers.append(
+      header::CONTENT_TYPE,
+      "application/json".parse().unwrap(),
+    );
+
+    let mut response = Response::new(
+      json!({
+        "status": "success",
+        "access_token": token_details.token.unwrap_or_default(),
+        "expires_in": data.env.impersonation_max_age * 60,
+        "impersonating": user_id,
+      })
+        .to_string(),
+    );
+    response.headers_mut().extend(headers);
+
+    Ok(response)
+  }
+
+// Ends the impersonation and, when the admin's refresh token was kept, signs the admin back in
+pub async fn end_impersonation_handler(
+    Extension(jwtauth): Extension<JWTAuthMiddleware>,
+    cookie_jar: CookieJar,
+    State(data): State<Arc<AppState>>,
+    context: RequestContext,
+  ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let impersonator_id = jwtauth.impersonator_id.clone()
+      .ok_or_else(|| admin_error(StatusCode::BAD_REQUEST, "This session is not an impersonation"))?;
+    let user = jwtauth.user()?;
+    let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
+    let _ = delete_token(&mut conn, &jwtauth.access_token_uuid);
+
+    let admin = find_admin_user(&mut conn, &impersonator_id)?;
+    audit_admin_action(&mut conn, &context, "admin.impersonation.end", &admin, &user.id, json!({
+      "tokenId": jwtauth.access_token_uuid.to_string(),
+    }));
+
+    // The kept refresh token must still be live and belong to the admin who started this
+    let admin_session = cookie_jar
+      .get(IMPERSONATOR_COOKIE)
+      .and_then(|cookie| token::verify_paseto_token(&data.env.auth_key, cookie.value()).ok())
+      .filter(|details| details.user_id == impersonator_id)
+      .filter(|details| is_token_active(&mut conn, &details.token_uuid));
+
+    let clear_impersonator_cookie = Cookie::build((IMPERSONATOR_COOKIE, ""))
+      .path("/")
+      .secure(true)
+      .max_age(time::Duration::minutes(-1))
+      .same_site(SameSite::Strict)
+      .http_only(true);
+
+    let mut headers = HeaderMap::new();
+    headers.append(
+      header::SET_COOKIE,
+      clear_impersonator_cookie.to_string().parse().unwrap(),
+    );
+    headers.append(
+      header::CONTENT_TYPE,
+      "application/json".parse().unwrap(),
+    );
+
+    let Some(admin_session) = admin_session else {
+      let access_cookie = Cookie::build(("access_token", ""))
+        .path("/")
+        .secure(true)
+        .max_age(time::Duration::minutes(-1))
+        .same_site(SameSite::Strict)
+        .http_only(true);
+      headers.append(
+        header::SET_COOKIE,
+        access_cookie.to_string().parse().unwrap(),
+      );
+
+      let mut response = Response::new(json!({ "status": "success", "restored": false }).to_string());
+      response.headers_mut().extend(headers);
+      return Ok(response);
+    };
+
+    // The restored session keeps the admin's original sign-in time, method and tenant
+    let mut auth_claims = match (admin_session.auth_time, admin_session.amr.as_deref()) {
+      (Some(auth_time), Some(amr)) => session_claims(auth_time, amr),
+      _ => vec![],
+    };
+    if let Some(idp) = admin_session.idp.as_deref() {
+      auth_claims.extend(idp_claims(idp));
+    }
+    let tenant_id = admin_session.tenant_id
+      .as_deref()
+      .filter(|tenant_id| find_membership(&mut conn, tenant_id, &impersonator_id).is_some());
+    if let Some(tenant_id) = tenant_id {
+      auth_claims.extend(tenant_claims(tenant_id));
+    }
+    let env = tenant_config(&mut conn, &data.env, tenant_id);
+
+    let (access_token_details, refresh_token_details) = issue_session_tokens(&mut conn, &env, &impersonator_id, &auth_claims).await?;
+    let _ = delete_token(&mut conn, &admin_session.token_uuid);
+
+    let access_cookie = Cookie::build(
+      ("access_token",
+      access_token_details.token.clone().unwrap_or_default()),
+    )
+      .path("/")
+      .secure(true)
+      .max_age(time::Duration::seconds(parse_duration(&env.access_token_expires_in).unwrap_or(900))) // 15 minutes default
+      .same_site(SameSite::Strict)
+      .http_only(true);
+
+    let refresh_cookie = Cookie::build(
+      ("refresh_token",
+      refresh_token_details.token.clone().unwrap_or_default()),
+    )
+      .path("/")
+      .secure(true)
+      .max_age(time::Duration::seconds(parse_duration(&env.refresh_token_expires_in).unwrap_or(900))) // 15 minutes default
+      .same_site(SameSite::Strict)
+      .http_only(true);
+
+    headers.append(
+      header::SET_COOKIE,
+      access_cookie.to_string().parse().unwrap(),
+    );
+    headers.append(
+      header::SET_COOKIE,
+      refresh_cookie.to_string().parse().unwrap(),
+    );
+
+    let mut response = Response::new(
+      json!({
+        "status": "success",
+        "restored": true,
+        "access_token": access_token_details.token.unwrap_or_default(),
+      })
+        .to_string(),
+    );
+    response.headers_mut().extend(headers);
+
+    Ok(response)
+  }
@@ -10,18 +10,31 @@ use axum_extra::extract::cookie::{Cookie, SameSite};
 use anyhow::Result;
 use diesel::{query_dsl::methods::FilterDsl, ExpressionMethods, OptionalExtension, RunQueryDsl};
 use serde_json::json;
-use chrono::{DateTime, Utc};
-use ulid::Ulid;
+use chrono::Utc;
 use crate::{
-  model::LoginUserSchema, schema::{tokens, user, Token, User}, token::generate_paseto_token, utils::parse_duration, AppState
+  audit::{record_user_event, RequestContext, OUTCOME_FAILURE, OUTCOME_SUCCESS}, hooks::{self, Hook}, model::LoginUserSchema, schema::{user, User}, security_notice::{self, SecurityNotice}, tenant::{find_membership, resolve_tenant_id, tenant_claims, tenant_config},
+  token::{issue_session_tokens, session_claims}, utils::parse_duration, webhook, AppState
 };
 
 pub async fn login_user_handler(
   State(data): State<Arc<AppState>>,
+  request_headers: HeaderMap,
+  context: RequestContext,
   Json(body): Json<LoginUserSchema>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
   let email = body.email.to_owned().to_ascii_lowercase();
   let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
+  let tenant_id = resolve_tenant_id(&mut conn, &request_headers, body.tenant_id.as_deref());
+  let env = tenant_config(&mut conn, &data.env, tenant_id.as_deref());
+
+  if !env.provider_enabled("password") {
+    let error_response = serde_json::json!({
+        "status": "fail",
+        "message": "Password sign in is not enabled"
+    });
+    return Err((StatusCode::FORBIDDEN, Json(error_response)));
+  }
   
   let user_exists = user::table
     .filter(user::email.eq(email.clone()))
@@ -31,6 +44,7 @@ pub async fn login_user_handler(
   let user = if let Ok(Some(user)) = user_exists {
     user
   } else {
+    record_user_event(&mut conn, &context, "user.login", None, OUTCOME_FAILURE, json!({ "email": email, "reason": "unknown_email" }));
     let error_response = serde_json::json!({
         "status": "fail",
         "message": "Invalid email or password"
@@ -39,9 +53,10 @@ pub async fn login_user_handler(
   };
 
   let user_id = user.id.as_str();
-  let password_hash = user.password.unwrap();
+  // Accounts made by magic link or a social sign-in have no password to check
+  let password_hash = user.password.as_deref().unwrap_or_default();
   
-  let is_valid_password = match PasswordHash::new(&password_hash) {
+  let is_valid_password = match PasswordHash::new(password_hash) {
     Ok(parsed_hash) => Argon2::default()
       .verify_password(body.password.as_bytes(), &parsed_hash)
       .map_or(false, |_| true),
@@ -49,6 +64,7 @@ pub async fn login_user_handler(
   };    
 
   if !is_valid_password {
+    record_user_event(&mut conn, &context, "user.login", Some(user_id), OUTCOME_FAILURE, json!({ "reason": "invalid_password" }));
     let error_response = serde_json::json!({
       "status": "fail",
       "message": "Invalid email or password"
@@ -56,17 +72,52 @@ pub async fn login_user_handler(
     return Err((StatusCode::BAD_REQUEST, Json(error_response)));
   }    
 
-  let access_token_details = generate_paseto_token(
-    user_id.to_string(),
-    data.env.access_token_max_age,
-    &data.env.auth_key,
-  ).unwrap();
+  // There is no second factor to step up to yet, so a password alone cannot satisfy the requirement
+  if env.mfa_required {
+    record_user_event(&mut conn, &context, "user.login", Some(user_id), OUTCOME_FAILURE, json!({ "reason": "mfa_required" }));
+    let error_response = serde_json::json!({
+      "status": "fail",
+      "message": "Multi-factor authentication is required"
+    });
+    return Err((StatusCode::FORBIDDEN, Json(error_response)));
+  }
+
+  // Recorded in the tokens for OIDC auth_time and amr
+  let mut auth_claims = session_claims(Utc::now().timestamp(), "pwd");
+
+  // Signing in on a tenant needs a membership there
+  if let Some(tenant_id) = tenant_id.as_deref() {
+    if find_membership(&mut conn, tenant_id, user_id).is_none() {
+      record_user_event(&mut conn, &context, "user.login", Some(user_id), OUTCOME_FAILURE, json!({ "reason": "not_a_member", "tenantId": tenant_id }));
+      let error_response = serde_json::json!({
+        "status": "fail",
+        "message": "You are not a member of this tenant"
+      });
+      return Err((StatusCode::FORBIDDEN, Json(error_response)));
+    }
+    auth_claims.extend(tenant_claims(tenant_id));
+  }
 
-  let refresh_token_details = generate_paseto_token(
-    user_id.to_string(),
-    data.env.refresh_token_max_age,
-    &data.env.auth_key,
-  ).unwrap();
+  hooks::run(&env, Hook::BeforeLogin, json!({
+    "userId": user_id,
+    "email": user.email,
+    "method": "pwd",
+    "tenantId": tenant_id,
+  })).await.inspect_err(|(_, Json(error))| {
+    record_user_event(&mut conn, &context, "user.login", Some(user_id), OUTCOME_FAILURE, json!({ "reason": error["message"] }));
+  })?;
+
+  let (access_token_details, refresh_token_details) = issue_session_tokens(&mut conn, &env, user_id, &auth_claims).await
+    .inspect_err(|(_, Json(error))| {
+      record_user_event(&mut conn, &context, "user.login", Some(user_id), OUTCOME_FAILURE, json!({ "reason": error["message"] }));
+    })?;
+  // Checked before this sign-in is recorded, otherwise it would recognize itself
+  let unrecognized = security_notice::is_unrecognized_sign_in(&mut conn, user_id, &context);
+  record_user_event(&mut conn, &context, "user.login", Some(user_id), OUTCOME_SUCCESS, json!({ "method": "pwd", "tenantId": tenant_id }));
+  webhook::enqueue(&mut conn, webhook::USER_LOGIN, json!({ "userId": user_id, "method": "pwd", "tenantId": tenant_id }));
+  if unrecognized {
+    security_notice::notify(&data, &mut conn, user_id, tenant_id.clone(), &context, SecurityNotice::NewSignIn { method: "password".into() }).await;
+  }
 
   let access_cookie = Cookie::build(
     ("access_token",
@@ -74,7 +125,7 @@ pub async fn login_user_handler(
   )
     .path("/")
     .secure(true)
-    .max_age(time::Duration::seconds(parse_duration(&data.env.access_token_expires_in).unwrap_or(900))) // 15 minutes default
+    .max_age(time::Duration::seconds(parse_duration(&env.access_token_expires_in).unwrap_or(900))) // 15 minutes default
     .same_site(SameSite::Strict)
     .http_only(true);
 
@@ -84,74 +135,10 @@ pub async fn login_user_handler(
   )
     .path("/")
     .secure(true)
-    .max_age(time::Duration::seconds(parse_duration(&data.env.refresh_token_expires_in).unwrap_or(900))) // 15 minutes default
+    .max_age(time::Duration::seconds(parse_duration(&env.refresh_token_expires_in).unwrap_or(900))) // 15 minutes default
     .same_site(SameSite::Strict)
     .http_only(true);
 
-  let expires = DateTime::<Utc>::from_timestamp(access_token_details.expires_in.unwrap(), 0)
-    .map(|dt| dt.naive_utc())
-    .unwrap_or_else(|| {
-      // Handle invalid timestamps
-      DateTime::<Utc>::from_timestamp(0, 0)
-        .unwrap()
-        .naive_utc()
-    });
-  let timestamp = Utc::now().naive_utc();
-  let statement = diesel::insert_into(tokens::table)
-    .values(&Token {
-      id: Ulid::new().to_string(),
-      user_id: user_id.into(),
-      expires,
-      blacklisted: false,
-      token: access_token_details.token.clone().unwrap(),
-      token_uuid: access_token_details.token_uuid.to_string(),
-      created_at: timestamp,
-      updated_at: None,
-      deleted_at: None
-    })
-    .execute(&mut conn);
-  
-  if let Err(e) = statement {
-    let error_message = format!("Failed to save access token: validation error\nDetails: {:?}", e);
-    let error_response = serde_json::json!({
-        "status": "fail",
-        "message": error_message
-    });
-    return Err((StatusCode::BAD_REQUEST, Json(error_response)));
-  }
-
-  let expires = DateTime::<Utc>::from_timestamp(refresh_token_details.expires_in.unwrap(), 0)
-    .map(|dt| dt.naive_utc())
-    .unwrap_or_else(|| {
-      // Handle invalid timestamps
-      DateTime::<Utc>::from_timestamp(0, 0)
-        .unwrap()
-        .naive_utc()
-    });
-  let timestamp = Utc::now().naive_utc();
-  let statement = diesel::insert_into(tokens::table)
-    .values(&Token {
-      id: Ulid::new().to_string(),
-      user_id: user_id.into(),
-      expires,
-      blacklisted: false,
-      token: refresh_token_details.token.clone().unwrap(),
-      token_uuid: refresh_token_details.token_uuid.to_string(),
-      created_at: timestamp,
-      updated_at: None,
-      deleted_at: None
-    })
-    .execute(&mut conn);
-
-  if let Err(e) = statement {
-    let error_message = format!("Failed to save refresh token: validation error\nDetails: {:?}", e);
-    let error_response = serde_json::json!({
-      "status": "fail",
-      "message": error_message
-    });
-    return Err((StatusCode::BAD_REQUEST, Json(error_response)));
-  }
-
   let mut response = Response::new(
     json!({"status": "success", "access_token": access_token_details.token.unwrap()})
       .to_string(),
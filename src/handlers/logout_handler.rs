@@ -9,11 +9,12 @@ use axum_extra::extract::{
 };
 use anyhow::Result;
 use serde_json::json;
-use crate::{token::blacklist_token, AppState};
+use crate::{audit::{record_user_event, RequestContext, OUTCOME_SUCCESS}, token::{blacklist_token, verify_paseto_token}, AppState};
 
 pub async fn logout_handler(
   cookie_jar: CookieJar,
   State(data): State<Arc<AppState>>,
+  context: RequestContext,
 ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
   let access_cookie = Cookie::build(("access_token", ""))
     .path("/")
@@ -66,6 +67,11 @@ pub async fn logout_handler(
     return Err((StatusCode::BAD_REQUEST, Json(error_response)));
   }
 
+  if let Ok(token_details) = verify_paseto_token(&data.env.auth_key, &refresh_token) {
+    let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+    record_user_event(&mut conn, &context, "user.logout", Some(&token_details.user_id), OUTCOME_SUCCESS, json!({}));
+  }
+
   let mut headers = HeaderMap::new();
   headers.append(
     header::SET_COOKIE,
@@ -0,0 +1,150 @@
+use std::sync::Arc;
+use axum::{
+  extract::{Query, State}, http::StatusCode, response::{IntoResponse, Response}, Json
+};
+use axum_extra::extract::cookie::CookieJar;
+use anyhow::Result;
+use chrono::{DateTime, TimeZone, Utc};
+use diesel::{query_dsl::methods::{FilterDsl, OrderDsl}, ExpressionMethods, OptionalExtension, RunQueryDsl};
+use serde_json::json;
+use crate::{
+  audit::{record_user_event, RequestContext, OUTCOME_SUCCESS}, email_otp, handlers::generate_magiclink_handler::{complete_magiclink_sign_in, MagicLinkVia, MAGICLINK_NONCE_COOKIE},
+  model::{MagicLinkApprovalSchema, MagicLinkLookupSchema}, schema::{email_confirmation, EmailConfirmation, EmailConfirmationFlowUpdate, PURPOSE_MAGIC_LINK}, utils::hash_token, AppState
+};
+
+fn magiclink_error(status: StatusCode, message: &str) -> (StatusCode, Json<serde_json::Value>) {
+  let error_response = serde_json::json!({
+    "status": "fail",
+    "message": message
+  });
+  (status, Json(error_response))
+}
+
+fn is_expired(confirmation: &EmailConfirmation) -> bool {
+  let expiry_time: DateTime<Utc> = Utc.from_utc_datetime(&confirmation.expires);
+  Utc::now() > expiry_time
+}
+
+// Moves the link on from `from` only if nobody else did first
+fn set_flow(conn: &mut diesel::PgConnection, id: &str, from: &str, to: &str) -> bool {
+  diesel::update(email_confirmation::table)
+    .filter(email_confirmation::id.eq(id))
+    .filter(email_confirmation::flow.eq(from))
+    .set(&EmailConfirmationFlowUpdate {
+      flow: to.into(),
+      updated_at: Some(Utc::now().naive_utc()),
+    })
+    .execute(conn)
+    .map(|updated| updated == 1)
+    .unwrap_or(false)
+}
+
+// A bound link still waiting for a decision, "viewed" once the approval page has shown it
+fn find_pending(conn: &mut diesel::PgConnection, code: &str, flows: &[&str]) -> Option<EmailConfirmation> {
+  email_confirmation::table
+    .filter(email_confirmation::code.eq(code))
+    .filter(email_confirmation::flow.eq_any(flows))
+    .filter(email_confirmation::purpose.eq(PURPOSE_MAGIC_LINK))
+    .filter(email_confirmation::device_nonce.is_not_null())
+    .first::<EmailConfirmation>(conn)
+    .optional()
+    .unwrap_or(None)
+    .filter(|confirmation| !is_expired(confirmation))
+}
+
+// Lets the approval page show where the sign-in was requested from before the user decides
+pub async fn lookup_magiclink_handler(
+  State(data): State<Arc<AppState>>,
+  Query(body): Query<MagicLinkLookupSchema>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+  let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
+  let confirmation = find_pending(&mut conn, &body.code, &["created", "viewed"])
+    .ok_or_else(|| magiclink_error(StatusCode::BAD_REQUEST, "Code is invalid or has expired"))?;
+
+  // Approving is only possible after this, so nobody approves a request they were not shown
+  if confirmation.flow == "created" && !set_flow(&mut conn, &confirmation.id, "created", "viewed") {
+    return Err(magiclink_error(StatusCode::BAD_REQUEST, "Code is invalid or has expired"));
+  }
+
+  Ok(Json(json!({
+    "status": "success",
+    "ip": confirmation.requester_ip,
+    "userAgent": confirmation.requester_user_agent,
+    "requestedAt": confirmation.created_at,
+  })))
+}
+
+// The approval page, reached by opening a bound magic link in another browser, approves or denies
+// the sign-in of the browser that asked for it
+pub async fn approve_magiclink_handler(
+  State(data): State<Arc<AppState>>,
+  context: RequestContext,
+  Json(body): Json<MagicLinkApprovalSchema>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+  let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
+  let confirmation = find_pending(&mut conn, &body.code, &["viewed"])
+    .ok_or_else(|| magiclink_error(StatusCode::BAD_REQUEST, "Code is invalid or has expired"))?;
+
+  let flow = if body.approved { "approved" } else { "denied" };
+  if !set_flow(&mut conn, &confirmation.id, "viewed", flow) {
+    return Err(magiclink_error(StatusCode::BAD_REQUEST, "Code is invalid or has expired"));
+  }
+
+  // A denied request is over, including the code sent with the link
+  if !body.approved {
+    email_otp::invalidate(&mut conn, &confirmation.id);
+  }
+
+  record_user_event(&mut conn, &context, &format!("magiclink.{}", flow), Some(&confirmation.user_id), OUTCOME_SUCCESS, json!({}));
+
+  Ok(Json(json!({ "status": "success" })))
+}
+
+// Polled by the browser that asked for the magic link, signs it in once the link was approved elsewhere
+pub async fn poll_magiclink_handler(
+  cookie_jar: CookieJar,
+  State(data): State<Arc<AppState>>,
+  context: RequestContext,
+) -> Result<Response, (StatusCode, Json<serde_json::Value>)> {
+  let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
+  let nonce = cookie_jar
+    .get(MAGICLINK_NONCE_COOKIE)
+    .map(|cookie| cookie.value().to_string())
+    .ok_or_else(|| magiclink_error(StatusCode::BAD_REQUEST, "No magic link was requested from this browser"))?;
+
+  let confirmation = email_confirmation::table
+    .filter(email_confirmation::device_nonce.eq(hash_token(&nonce)))
+    .order(email_confirmation::created_at.desc())
+    .first::<EmailConfirmation>(&mut conn)
+    .optional()
+    .unwrap_or(None)
+    .ok_or_else(|| magiclink_error(StatusCode::BAD_REQUEST, "No magic link was requested from this browser"))?;
+
+  if is_expired(&confirmation) {
+    return Err(magiclink_error(StatusCode::BAD_REQUEST, "Code is invalid or has expired"));
+  }
+
+  match confirmation.flow.as_str() {
+    "created" | "viewed" => return Ok((StatusCode::ACCEPTED, Json(json!({ "status": "pending" }))).into_response()),
+    "denied" => return Err(magiclink_error(StatusCode::FORBIDDEN, "The sign-in was denied")),
+    "approved" => {},
+    _ => return Err(magiclink_error(StatusCode::BAD_REQUEST, "Magic link has already been used")),
+  }
+
+  if !set_flow(&mut conn, &confirmation.id, "approved", "completed") {
+    return Err(magiclink_error(StatusCode::BAD_REQUEST, "Magic link has already been used"));
+  }
+  email_otp::invalidate(&mut conn, &confirmation.id);
+
+  let (access_token, cookies) = complete_magiclink_sign_in(
+    &data, &mut conn, &context, &confirmation.user_id, confirmation.tenant_id.as_deref(), MagicLinkVia::Approval,
+  ).await?;
+
+  let mut response = Json(json!({"status": "success", "access_token": access_token})).into_response();
+  response.headers_mut().extend(cookies);
+
+  Ok(response)
+}
@@ -1,11 +1,24 @@
+pub mod admin_handler;
+pub mod api_key_handler;
+pub mod audit_handler;
 pub mod check_code_handler;
+pub mod collect_email_handler;
+pub mod email_outbox_handler;
 pub mod forgot_password_handler;
 pub mod generate_magiclink_handler;
 pub mod get_me_handler;
+pub mod impersonation_handler;
 pub mod login_user_handler;
 pub mod logout_handler;
+pub mod magiclink_approval_handler;
+pub mod not_me_handler;
 pub mod refresh_access_token_handler;
 pub mod register_user_handler;
 pub mod reset_password_handler;
+pub mod role_handler;
+pub mod tenant_handler;
 pub mod verify_code_handler;
+pub mod verify_email_handler;
 pub mod verify_magiclink_code_handler;
+pub mod verify_magiclink_otp_handler;
+pub mod webhook_handler;
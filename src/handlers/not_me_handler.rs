@@ -0,0 +1,113 @@
+use std::sync::Arc;
+use axum::{
+  extract::{Query, State}, http::StatusCode, response::{IntoResponse, Redirect}, Json
+};
+use anyhow::Result;
+use chrono::{DateTime, TimeZone, Utc};
+use diesel::{query_dsl::methods::FilterDsl, BoolExpressionMethods, ExpressionMethods, OptionalExtension, RunQueryDsl};
+use serde_json::json;
+use crate::{
+  audit::{record_user_event, RequestContext, OUTCOME_SUCCESS}, handlers::forgot_password_handler::send_password_reset, model::NotMeSchema,
+  redirect::append_query_param, schema::{email_confirmation, user, EmailConfirmation, PURPOSE_NOT_ME, User}, security_notice::NOT_ME_FLOW,
+  template::preferred_locales, token::revoke_user_tokens, utils::update_confirm_code, AppState
+};
+
+// The "this wasn't me" link from security notices. Mail scanners open every link, so this only leads
+// on to the client's confirmation page
+pub async fn not_me_page_handler(
+  State(data): State<Arc<AppState>>,
+  Query(body): Query<NotMeSchema>,
+) -> impl IntoResponse {
+  Redirect::temporary(&append_query_param(&data.env.not_me_url, "code", &body.code))
+}
+
+// Confirmed from that page. Ends every session and emails a password reset
+pub async fn not_me_handler(
+  State(data): State<Arc<AppState>>,
+  context: RequestContext,
+  Json(body): Json<NotMeSchema>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+  let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
+  let confirmation_exists = email_confirmation::table
+    .filter(email_confirmation::code.eq(body.code.to_owned()))
+    .filter(email_confirmation::flow.eq(NOT_ME_FLOW))
+    .filter(email_confirmation::purpose.eq(PURPOSE_NOT_ME))
+    .first::<EmailConfirmation>(&mut conn)
+    .optional();
+
+  let confirmation = if let Ok(Some(confirmation)) = confirmation_exists {
+    confirmation
+  } else {
+    let error_response = serde_json::json!({
+      "status": "fail",
+      "message": "Code is invalid or has expired"
+    });
+    return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+  };
+
+  let current_time = Utc::now();
+  let expiry_time: DateTime<Utc> = Utc.from_utc_datetime(&confirmation.expires);
+
+  if current_time > expiry_time {
+    let error_response = serde_json::json!({
+      "status": "fail",
+      "message": "Code is invalid or has expired"
+    });
+    return Err((StatusCode::UNAUTHORIZED, Json(error_response)));
+  }
+
+  let _ = update_confirm_code(axum::extract::State(data.clone()), confirmation.id.to_string(), "completed".to_string()).await;
+
+  let user_id = confirmation.user_id;
+  let revoked = revoke_user_tokens(&mut conn, &user_id).map_err(|e| {
+    let error_response = serde_json::json!({
+      "status": "fail",
+      "message": format!("Sessions could not be revoked: {}", e)
+    });
+    (StatusCode::INTERNAL_SERVER_ERROR, Json(error_response))
+  })?;
+
+  // A notice about an email change went to the previous address, which is put back unless another
+  // user has taken it since
+  let email_restored = match confirmation.email.as_deref() {
+    Some(previous_email) => {
+      let taken = user::table
+        .filter(user::email.eq(previous_email).and(user::id.ne(user_id.clone())))
+        .first::<User>(&mut conn)
+        .optional();
+      match taken {
+        Ok(None) => diesel::update(user::table)
+          .filter(user::id.eq(user_id.clone()))
+          .set((user::email.eq(previous_email), user::updated_at.eq(Some(Utc::now().naive_utc()))))
+          .execute(&mut conn)
+          .map(|updated| updated == 1)
+          .unwrap_or(false),
+        _ => false,
+      }
+    }
+    None => false,
+  };
+
+  // The reset goes to the address the notice was sent to, otherwise to the one on file
+  let user = user::table
+    .filter(user::id.eq(user_id.clone()))
+    .first::<User>(&mut conn)
+    .optional()
+    .unwrap_or(None);
+  let reset_email = match &user {
+    Some(user) => confirmation.email.clone().or(user.email.clone()),
+    None => None,
+  };
+  let reset_sent = match (reset_email, user) {
+    (Some(email), Some(User { locale, .. })) => {
+      let locales = preferred_locales(locale.as_deref(), context.accept_language.as_deref());
+      send_password_reset(data.clone(), &mut conn, user_id.clone(), email, data.env.default_redirect_url.clone(), None, locales).await?
+    }
+    _ => false,
+  };
+
+  record_user_event(&mut conn, &context, "user.not_me", Some(&user_id), OUTCOME_SUCCESS, json!({ "revoked": revoked, "resetSent": reset_sent, "emailRestored": email_restored }));
+
+  Ok(Json(json!({ "status": "success", "resetSent": reset_sent, "emailRestored": email_restored })))
+}
@@ -12,17 +12,21 @@ use serde_json::json;
 use chrono::{DateTime, Duration, Utc, TimeZone};
 use ulid::Ulid;
 use crate::{
-  schema::{tokens, Token}, token::{self, blacklist_token, generate_paseto_token}, utils::parse_duration, AppState
+  audit::{record_user_event, RequestContext, OUTCOME_FAILURE, OUTCOME_SUCCESS}, hooks, rbac, schema::{tokens, Token}, tenant, token::{self, blacklist_token, generate_paseto_token, idp_claims, session_claims}, utils::parse_duration, AppState
 };
 
 pub async fn refresh_access_token_handler(
   cookie_jar: CookieJar,
   State(data): State<Arc<AppState>>,
+  context: RequestContext,
 ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+  let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
   let refresh_token = cookie_jar
     .get("refresh_token")
     .map(|cookie| cookie.value().to_string())
     .ok_or_else(|| {
+      record_user_event(&mut conn, &context, "token.refreshed", None, OUTCOME_FAILURE, json!({ "reason": "missing_token" }));
       let error_response = serde_json::json!({
         "status": "fail",
         "message": "could not delete refresh token"
@@ -35,6 +39,7 @@ pub async fn refresh_access_token_handler(
     {
       Ok(token_details) => token_details,
       Err(e) => {
+        record_user_event(&mut conn, &context, "token.refreshed", None, OUTCOME_FAILURE, json!({ "reason": "invalid_token" }));
         let error_response = serde_json::json!({
           "status": "fail",
           "message": format_args!("{:?}", e)
@@ -45,14 +50,55 @@ pub async fn refresh_access_token_handler(
 
   let user_id = refresh_token_details.user_id;
 
+  // A logged out or revoked session cannot be refreshed back to life, and impersonation never can be
+  if !token::is_token_active(&mut conn, &refresh_token_details.token_uuid) || refresh_token_details.act.is_some() {
+    record_user_event(&mut conn, &context, "token.refreshed", Some(&user_id), OUTCOME_FAILURE, json!({ "reason": "revoked" }));
+    let error_response = serde_json::json!({
+      "status": "fail",
+      "message": "Token has been revoked"
+    });
+    return Err((StatusCode::UNAUTHORIZED, Json(error_response)));
+  }
+
+  // Refreshing keeps the original sign-in time and method
+  let mut auth_claims = match (refresh_token_details.auth_time, refresh_token_details.amr.as_deref()) {
+    (Some(auth_time), Some(amr)) => session_claims(auth_time, amr),
+    _ => vec![],
+  };
+  if let Some(idp) = refresh_token_details.idp.as_deref() {
+    auth_claims.extend(idp_claims(idp));
+  }
+
+  // The active tenant is kept only while the user is still a member and the tenant still allows the
+  // way the session signed in, otherwise the session carries on outside it
+  let mut tenant_id = refresh_token_details.tenant_id
+    .as_deref()
+    .filter(|tenant_id| tenant::find_membership(&mut conn, tenant_id, &user_id).is_some());
+  let mut env = tenant::tenant_config(&mut conn, &data.env, tenant_id);
+  if tenant_id.is_some() && tenant::check_sign_in_policy(&env, refresh_token_details.amr.as_deref(), refresh_token_details.idp.as_deref()).is_err() {
+    tenant_id = None;
+    env = data.env.clone();
+  }
+  if let Some(tenant_id) = tenant_id {
+    auth_claims.extend(tenant::tenant_claims(tenant_id));
+  }
+
+  // Roles are looked up again so changes apply from the next refresh
+  let mut access_claims = auth_claims.clone();
+  access_claims.extend(rbac::access_claims(&mut conn, &user_id));
+  let hook_claims = hooks::access_token_claims(&env, &user_id, &access_claims).await
+    .inspect_err(|(_, Json(error))| {
+      record_user_event(&mut conn, &context, "token.refreshed", Some(&user_id), OUTCOME_FAILURE, json!({ "reason": error["message"] }));
+    })?;
+  access_claims.extend(hook_claims.iter().map(|(name, value)| (name.as_str(), value.clone())));
+
   let access_token_details = generate_paseto_token(
     user_id.clone().into(),
-    data.env.access_token_max_age,
+    env.access_token_max_age,
     &data.env.auth_key,
+    &access_claims,
   ).unwrap();
 
-  let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
-
   let expires = DateTime::<Utc>::from_timestamp(access_token_details.expires_in.unwrap(), 0)
     .map(|dt| dt.naive_utc())
     .unwrap_or_else(|| {
@@ -77,6 +123,7 @@ pub async fn refresh_access_token_handler(
     .get_result::<Token>(&mut conn);
 
   if let Err(e) = statement {
+    record_user_event(&mut conn, &context, "token.refreshed", Some(&user_id), OUTCOME_FAILURE, json!({ "reason": "token_not_saved" }));
     let error_message = format!("Failed to save access token: validation error\nDetails: {:?}", e);
     let error_response = serde_json::json!({
       "status": "fail",
@@ -91,7 +138,7 @@ pub async fn refresh_access_token_handler(
   )
     .path("/")
     .secure(true)
-    .max_age(time::Duration::seconds(parse_duration(&data.env.access_token_expires_in).unwrap_or(900))) // 15 minutes default
+    .max_age(time::Duration::seconds(parse_duration(&env.access_token_expires_in).unwrap_or(900))) // 15 minutes default
     .same_site(SameSite::Strict)
     .http_only(true);
 
@@ -114,9 +161,11 @@ pub async fn refresh_access_token_handler(
   let current_time = Utc::now();
   let time_until_expiry = expiry_time.signed_duration_since(current_time);
 
-  if time_until_expiry <= Duration::hours(1) {
+  let rotated = time_until_expiry <= Duration::hours(1);
+  if rotated {
     // blacklist old refresh token
     if let Ok(false) = blacklist_token(axum::extract::State(data.clone()), &data.env.auth_key, &refresh_token).await {
+      record_user_event(&mut conn, &context, "token.refreshed", Some(&user_id), OUTCOME_FAILURE, json!({ "reason": "blacklist_failed" }));
       let error_response = serde_json::json!({
           "status": "fail",
           "message": "Failed to blacklist refresh token"
@@ -127,8 +176,9 @@ pub async fn refresh_access_token_handler(
     // recreate refresh_token
     let refresh_token_details = generate_paseto_token(
       user_id.clone().into(),
-      data.env.refresh_token_max_age,
+      env.refresh_token_max_age,
       &data.env.auth_key,
+      &auth_claims,
     ).unwrap();
 
     let expires = DateTime::<Utc>::from_timestamp(access_token_details.expires_in.unwrap(), 0)
@@ -143,7 +193,7 @@ pub async fn refresh_access_token_handler(
     let statement = diesel::insert_into(tokens::table)
       .values(&Token {
         id: Ulid::new().to_string(),
-        user_id: user_id.into(),
+        user_id: user_id.clone(),
         token: refresh_token_details.token.clone().unwrap().into(),
         token_uuid: refresh_token_details.token_uuid.to_string(),
         expires,
@@ -155,6 +205,7 @@ pub async fn refresh_access_token_handler(
       .get_result::<Token>(&mut conn);
 
     if let Err(e) = statement {
+      record_user_event(&mut conn, &context, "token.refreshed", Some(&user_id), OUTCOME_FAILURE, json!({ "reason": "token_not_saved" }));
       let error_message = format!("Failed to save access token: validation error\nDetails: {:?}", e);
       let error_response = serde_json::json!({
           "status": "fail",
@@ -169,7 +220,7 @@ pub async fn refresh_access_token_handler(
     )
     .path("/")
     .secure(true)
-    .max_age(time::Duration::seconds(parse_duration(&data.env.refresh_token_expires_in).unwrap_or(900))) // 15 minutes default
+    .max_age(time::Duration::seconds(parse_duration(&env.refresh_token_expires_in).unwrap_or(900))) // 15 minutes default
     .same_site(SameSite::Strict)
     .http_only(true);
 
@@ -179,6 +230,8 @@ pub async fn refresh_access_token_handler(
     );
   }
 
+  record_user_event(&mut conn, &context, "token.refreshed", Some(&user_id), OUTCOME_SUCCESS, json!({ "tenantId": tenant_id, "rotated": rotated }));
+
   response.headers_mut().extend(headers);
   Ok(response)
 }
\ No newline at end of file
@@ -4,23 +4,44 @@ use argon2::{
     Argon2,
   };
 use axum::{
-  extract::State, http::StatusCode, response::IntoResponse, Json
+  extract::State, http::{HeaderMap, StatusCode}, response::IntoResponse, Json
 };
 use anyhow::Result;
 use diesel::{query_dsl::methods::FilterDsl, ExpressionMethods, OptionalExtension, RunQueryDsl};
 use ulid::Ulid;
 use chrono::Utc;
 use crate::{
-  model::RegisterUserSchema, schema::{user, User}, AppState
+  audit::{record_user_event, RequestContext, OUTCOME_FAILURE, OUTCOME_SUCCESS}, hooks::{self, Hook}, model::RegisterUserSchema, schema::{user, User}, template::preferred_locales, tenant::{resolve_tenant_id, tenant_config}, webhook, AppState
 };
 
 pub async fn register_user_handler(
   State(data): State<Arc<AppState>>,
+  request_headers: HeaderMap,
+  context: RequestContext,
   Json(body): Json<RegisterUserSchema>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
   let email = body.email.to_owned().to_ascii_lowercase();
   let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
 
+  let tenant_id = resolve_tenant_id(&mut conn, &request_headers, body.tenant_id.as_deref());
+  let env = tenant_config(&mut conn, &data.env, tenant_id.as_deref());
+
+  if !env.provider_enabled("password") {
+    let error_response = serde_json::json!({
+      "status": "fail",
+      "message": "Password sign up is not enabled",
+    });
+    return Err((StatusCode::FORBIDDEN, Json(error_response)));
+  }
+
+  if let Err(message) = env.check_password(&body.password) {
+    let error_response = serde_json::json!({
+      "status": "fail",
+      "message": message,
+    });
+    return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+  }
+
   let user_exists = user::table
     .filter(user::email.eq(email.clone()))
     .first::<User>(&mut conn)
@@ -45,6 +66,15 @@ pub async fn register_user_handler(
     },
   };
 
+  hooks::run(&env, Hook::BeforeRegister, serde_json::json!({
+    "email": email,
+    "name": body.name,
+    "method": "password",
+    "tenantId": tenant_id,
+  })).await.inspect_err(|(_, Json(error))| {
+    record_user_event(&mut conn, &context, "user.register", None, OUTCOME_FAILURE, serde_json::json!({ "email": email, "reason": error["message"] }));
+  })?;
+
   let salt = SaltString::generate(&mut OsRng);
   let hashed_password = Argon2::default()
     .hash_password(body.password.as_bytes(), &salt)
@@ -57,14 +87,17 @@ pub async fn register_user_handler(
     })
     .map(|hash| hash.to_string())?;
 
+  let locale = preferred_locales(body.locale.as_deref(), context.accept_language.as_deref()).into_iter().next();
   let timestamp = Utc::now().naive_utc();
   let result = diesel::insert_into(user::table)
     .values(&User {
         id: Ulid::new().to_string(),
         name: body.name,
-        email: body.email,
+        email: Some(body.email),
         password: Some(hashed_password),
         verified: false,
+        disabled_at: None,
+        locale,
         created_at: timestamp,
         updated_at: None,
         deleted_at: None
@@ -73,6 +106,14 @@ pub async fn register_user_handler(
 
   match result {
     Ok(inserted_user) => {
+      record_user_event(&mut conn, &context, "user.register", Some(&inserted_user.id), OUTCOME_SUCCESS, serde_json::json!({ "tenantId": tenant_id }));
+      webhook::enqueue(&mut conn, webhook::USER_REGISTERED, serde_json::json!({
+        "userId": inserted_user.id,
+        "email": inserted_user.email,
+        "name": inserted_user.name,
+        "method": "password",
+        "tenantId": tenant_id,
+      }));
       Ok(Json(serde_json::json!({
         "status": "success",
         "message": "User registered successfully",
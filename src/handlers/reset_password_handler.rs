@@ -11,25 +11,39 @@ use chrono::Utc;
 use diesel::{query_dsl::methods::FilterDsl, ExpressionMethods, OptionalExtension, RunQueryDsl};
 use serde_json::json;
 use crate::{
-  model::ResetPasswordSchema, schema::{email_confirmation, user, EmailConfirmation, UserPasswordUpdate}, utils::update_confirm_code, AppState
+  audit::{record_user_event, RequestContext, OUTCOME_FAILURE, OUTCOME_SUCCESS}, model::ResetPasswordSchema, schema::{email_confirmation, user, EmailConfirmation, PURPOSE_PASSWORD_RESET, UserPasswordUpdate}, security_notice::{self, SecurityNotice},
+  tenant::{resolve_tenant_id, tenant_config}, utils::update_confirm_code, webhook, AppState
 };
 
 pub async fn reset_password_handler(
   State(data): State<Arc<AppState>>,
+  request_headers: HeaderMap,
+  context: RequestContext,
   Json(body): Json<ResetPasswordSchema>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
   let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
   let code = body.code.to_owned();
 
+  let tenant_id = resolve_tenant_id(&mut conn, &request_headers, None);
+  if let Err(message) = tenant_config(&mut conn, &data.env, tenant_id.as_deref()).check_password(&body.password) {
+    let error_response = serde_json::json!({
+      "status": "fail",
+      "message": message
+    });
+    return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+  }
+
   let confirmation_exists = email_confirmation::table
     .filter(email_confirmation::code.eq(code.clone().to_owned()))
     .filter(email_confirmation::flow.eq("created"))
+    .filter(email_confirmation::purpose.eq(PURPOSE_PASSWORD_RESET))
     .first::<EmailConfirmation>(&mut conn)
     .optional();
 
   let confirmation = if let Ok(Some(confirmation)) = confirmation_exists {
     confirmation
   } else {
+    record_user_event(&mut conn, &context, "password.reset", None, OUTCOME_FAILURE, json!({ "reason": "invalid_code" }));
     let error_response = serde_json::json!({
       "status": "fail",
       "message": "verification code does not exist"
@@ -51,7 +65,7 @@ pub async fn reset_password_handler(
 
     let timestamp = Utc::now().naive_utc();
     let statement = diesel::update(user::table)
-    .filter(user::id.eq(confirmation.user_id))
+    .filter(user::id.eq(confirmation.user_id.clone()))
     .set(&UserPasswordUpdate {
         password: hashed_password.into(),
         updated_at: timestamp.into(),
@@ -66,6 +80,10 @@ pub async fn reset_password_handler(
     return Err((StatusCode::BAD_REQUEST, Json(error_response)));
   }
 
+  record_user_event(&mut conn, &context, "password.reset", Some(&confirmation.user_id), OUTCOME_SUCCESS, json!({}));
+  webhook::enqueue(&mut conn, webhook::USER_PASSWORD_CHANGED, json!({ "userId": confirmation.user_id }));
+  security_notice::notify(&data, &mut conn, &confirmation.user_id, tenant_id, &context, SecurityNotice::PasswordChanged).await;
+
   let _ = update_confirm_code(axum::extract::State(data), confirmation.id.to_string(), "completed".to_string()).await;
 
   let mut response = Response::new(
@@ -0,0 +1,177 @@
+use std::sync::Arc;
+use axum::{
+  extract::{Path, State}, http::StatusCode, response::IntoResponse, Extension, Json
+};
+use anyhow::Result;
+use chrono::Utc;
+use diesel::prelude::*;
+use serde_json::json;
+use ulid::Ulid;
+use crate::{
+  audit::{self, AuditRecord, RequestContext, OUTCOME_SUCCESS}, jwt_auth::JWTAuthMiddleware, model::AssignRoleSchema, rbac::role_permission_names, response::FilteredRole,
+  schema::{roles, user, user_roles, Role, User, UserRole, UserRoleDelete}, AppState
+};
+
+// Roles and permissions themselves are seeded in the database, these routes manage assignments
+pub async fn list_roles_handler(
+    State(data): State<Arc<AppState>>,
+  ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
+    let result = roles::table
+      .filter(roles::deleted_at.is_null())
+      .order(roles::name.asc())
+      .load::<Role>(&mut conn);
+
+    let roles = match result {
+      Ok(roles) => roles,
+      Err(e) => {
+        let error_response = serde_json::json!({
+            "status": "fail",
+            "message": format!("Error fetching roles: {}", e)
+        });
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(error_response)));
+      }
+    };
+
+    let roles = roles.into_iter().map(|role| {
+      let permissions = role_permission_names(&mut conn, std::slice::from_ref(&role.id));
+
+      FilteredRole {
+        id: role.id,
+        name: role.name,
+        description: role.description,
+        permissions,
+      }
+    }).collect::<Vec<_>>();
+
+    Ok(Json(serde_json::json!({
+      "status": "success",
+      "roles": roles,
+    })))
+  }
+
+pub async fn assign_role_handler(
+    Extension(jwtauth): Extension<JWTAuthMiddleware>,
+    State(data): State<Arc<AppState>>,
+    context: RequestContext,
+    Path(user_id): Path<String>,
+    Json(body): Json<AssignRoleSchema>,
+  ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
+    let user_exists = user::table
+      .filter(user::id.eq(user_id.clone()))
+      .first::<User>(&mut conn)
+      .optional();
+
+    if !matches!(user_exists, Ok(Some(_))) {
+      let error_response = serde_json::json!({
+          "status": "fail",
+          "message": "User not found"
+      });
+      return Err((StatusCode::NOT_FOUND, Json(error_response)));
+    }
+
+    let role_exists = roles::table
+      .filter(roles::id.eq(body.role_id.clone()))
+      .filter(roles::deleted_at.is_null())
+      .first::<Role>(&mut conn)
+      .optional();
+
+    if !matches!(role_exists, Ok(Some(_))) {
+      let error_response = serde_json::json!({
+          "status": "fail",
+          "message": "Role not found"
+      });
+      return Err((StatusCode::NOT_FOUND, Json(error_response)));
+    }
+
+    let assigned = user_roles::table
+      .filter(user_roles::user_id.eq(user_id.clone()))
+      .filter(user_roles::role_id.eq(body.role_id.clone()))
+      .filter(user_roles::deleted_at.is_null())
+      .first::<UserRole>(&mut conn)
+      .optional();
+
+    if let Ok(Some(_)) = assigned {
+      return Ok(Json(serde_json::json!({ "status": "success" })));
+    }
+
+    let statement = diesel::insert_into(user_roles::table)
+      .values(&UserRole {
+        id: Ulid::new().to_string(),
+        user_id: user_id.clone(),
+        role_id: body.role_id.clone(),
+        created_at: Utc::now().naive_utc(),
+        updated_at: None,
+        deleted_at: None
+      })
+      .execute(&mut conn);
+
+    if let Err(e) = statement {
+      let error_response = serde_json::json!({
+          "status": "fail",
+          "message": format!("Role not assigned: validation error\nDetails: {:?}", e)
+      });
+      return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+    }
+
+    audit::record(&mut conn, &context, AuditRecord {
+      event_type: "role.assigned",
+      actor_id: Some(jwtauth.actor_id()),
+      subject_id: Some(&user_id),
+      outcome: OUTCOME_SUCCESS,
+      metadata: json!({ "roleId": body.role_id }),
+    });
+
+    Ok(Json(serde_json::json!({ "status": "success" })))
+  }
+
+pub async fn remove_role_handler(
+    Extension(jwtauth): Extension<JWTAuthMiddleware>,
+    State(data): State<Arc<AppState>>,
+    context: RequestContext,
+    Path((user_id, role_id)): Path<(String, String)>,
+  ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
+    // Kept as a deleted row so past assignments stay on record
+    let timestamp = Utc::now().naive_utc();
+    let statement = diesel::update(user_roles::table)
+      .filter(user_roles::user_id.eq(user_id.clone()))
+      .filter(user_roles::role_id.eq(role_id.clone()))
+      .filter(user_roles::deleted_at.is_null())
+      .set(&UserRoleDelete {
+        updated_at: Some(timestamp),
+        deleted_at: Some(timestamp),
+      })
+      .execute(&mut conn);
+
+    match statement {
+      Ok(0) => {
+        let error_response = serde_json::json!({
+            "status": "fail",
+            "message": "Role is not assigned to this user"
+        });
+        Err((StatusCode::NOT_FOUND, Json(error_response)))
+      }
+      Ok(_) => {
+        audit::record(&mut conn, &context, AuditRecord {
+          event_type: "role.removed",
+          actor_id: Some(jwtauth.actor_id()),
+          subject_id: Some(&user_id),
+          outcome: OUTCOME_SUCCESS,
+          metadata: json!({ "roleId": role_id }),
+        });
+        Ok(Json(serde_json::json!({ "status": "success" })))
+      }
+      Err(e) => {
+        let error_response = serde_json::json!({
+            "status": "fail",
+            "message": format!("Role could not be removed: {}", e)
+        });
+        Err((StatusCode::BAD_REQUEST, Json(error_response)))
+      }
+    }
+  }
@@ -0,0 +1,662 @@
+use std::sync::Arc;
+use axum::{
+  extract::{Path, Query, State}, http::{header, HeaderMap, Response, StatusCode}, response::IntoResponse, Extension, Json
+};
+use anyhow::Result;
+use axum_extra::extract::cookie::{Cookie, SameSite};
+use chrono::Utc;
+use diesel::prelude::*;
+use serde_json::json;
+use ulid::Ulid;
+use crate::{
+  audit::{self, AuditRecord, RequestContext, OUTCOME_FAILURE, OUTCOME_SUCCESS}, jwt_auth::JWTAuthMiddleware, model::{CreateTenantSchema, ListTenantsSchema, SwitchTenantSchema, TenantMemberSchema, TenantSettingsSchema},
+  response::{FilteredTenant, FilteredTenantMember, FilteredTenantSettings},
+  schema::{
+    tenant_members, tenant_settings, tenants, user, Tenant, TenantDelete, TenantMember, TenantMemberRoleUpdate, TenantSettings,
+    TenantSettingsUpdate, User,
+  },
+  social_handlers::model::OAuthProvider,
+  tenant::{self, can_manage, find_membership, find_settings, find_tenant, is_tenant_role, TENANT_OWNER},
+  token::{idp_claims, issue_session_tokens, session_claims}, utils::parse_duration, AppState
+};
+
+const DEFAULT_PAGE_SIZE: i64 = 20;
+const MAX_PAGE_SIZE: i64 = 100;
+
+// Tenant changes are made by a member, about the tenant or another member of it
+fn audit_tenant_action(
+  conn: &mut PgConnection,
+  context: &RequestContext,
+  event_type: &str,
+  actor: &User,
+  subject_id: Option<&str>,
+  tenant_id: &str,
+) {
+  audit::record(conn, context, AuditRecord {
+    event_type,
+    actor_id: Some(&actor.id),
+    subject_id,
+    outcome: OUTCOME_SUCCESS,
+    metadata: json!({ "tenantId": tenant_id }),
+  });
+}
+
+fn filter_tenant(tenant: &Tenant) -> FilteredTenant {
+  FilteredTenant {
+    id: tenant.id.to_owned(),
+    name: tenant.name.to_owned(),
+    createdAt: tenant.created_at,
+  }
+}
+
+fn tenant_not_found() -> (StatusCode, Json<serde_json::Value>) {
+  let error_response = serde_json::json!({
+      "status": "fail",
+      "message": "Tenant not found"
+  });
+  (StatusCode::NOT_FOUND, Json(error_response))
+}
+
+fn not_allowed(message: &str) -> (StatusCode, Json<serde_json::Value>) {
+  let error_response = serde_json::json!({
+      "status": "fail",
+      "message": message
+  });
+  (StatusCode::FORBIDDEN, Json(error_response))
+}
+
+// Ids show up in URLs and relation tuples, so only simple slugs are accepted
+fn is_valid_tenant_id(id: &str) -> bool {
+  !id.is_empty()
+    && id.len() <= 64
+    && id.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+fn count_owners(conn: &mut PgConnection, tenant_id: &str) -> i64 {
+  tenant_members::table
+    .filter(tenant_members::tenant_id.eq(tenant_id))
+    .filter(tenant_members::role.eq(TENANT_OWNER))
+    .filter(tenant_members::deleted_at.is_null())
+    .count()
+    .get_result::<i64>(conn)
+    .unwrap_or(0)
+}
+
+pub async fn create_tenant_handler(
+    Extension(jwtauth): Extension<JWTAuthMiddleware>,
+    State(data): State<Arc<AppState>>,
+    context: RequestContext,
+    Json(body): Json<CreateTenantSchema>,
+  ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let user = jwtauth.session_user()?;
+    let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
+    let name = body.name.trim().to_string();
+    if name.is_empty() {
+      let error_response = serde_json::json!({
+          "status": "fail",
+          "message": "Name is required"
+      });
+      return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+    }
+
+    let id = body.id.unwrap_or_else(|| Ulid::new().to_string().to_lowercase());
+    if !is_valid_tenant_id(&id) {
+      let error_response = serde_json::json!({
+          "status": "fail",
+          "message": "Tenant id may only contain lowercase letters, digits, '-' and '_'"
+      });
+      return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+    }
+
+    // Deleted tenants keep their id so old tokens and tuples can't be picked up by a new owner
+    let tenant_exists = tenants::table
+      .filter(tenants::id.eq(id.clone()))
+      .first::<Tenant>(&mut conn)
+      .optional();
+
+    if let Ok(Some(_)) = tenant_exists {
+      let error_response = serde_json::json!({
+          "status": "fail",
+          "message": "Tenant id is already taken"
+      });
+      return Err((StatusCode::CONFLICT, Json(error_response)));
+    }
+
+    let timestamp = Utc::now().naive_utc();
+    let tenant = Tenant {
+      id: id.clone(),
+      name,
+      created_at: timestamp,
+      updated_at: None,
+      deleted_at: None
+    };
+
+    // The creator becomes the first owner
+    let statement = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+      diesel::insert_into(tenants::table)
+        .values(&tenant)
+        .execute(conn)?;
+
+      diesel::insert_into(tenant_members::table)
+        .values(&TenantMember {
+          id: Ulid::new().to_string(),
+          tenant_id: id.clone(),
+          user_id: user.id.clone(),
+          role: TENANT_OWNER.into(),
+          created_at: timestamp,
+          updated_at: None,
+          deleted_at: None
+        })
+        .execute(conn)
+    });
+
+    if let Err(e) = statement {
+      let error_response = serde_json::json!({
+          "status": "fail",
+          "message": format!("Tenant not saved to database: validation error\nDetails: {:?}", e)
+      });
+      return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+    }
+
+    audit_tenant_action(&mut conn, &context, "tenant.created", user, None, &tenant.id);
+
+    Ok((StatusCode::CREATED, Json(json!({ "tenant": filter_tenant(&tenant) }))))
+  }
+
+// The tenants the user belongs to, paged by id
+pub async fn list_tenants_handler(
+    Extension(jwtauth): Extension<JWTAuthMiddleware>,
+    State(data): State<Arc<AppState>>,
+    Query(query): Query<ListTenantsSchema>,
+  ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let user = jwtauth.user()?;
+    let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+    let page_size = query.page_size.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+    let tenant_ids = tenant_members::table
+      .filter(tenant_members::user_id.eq(user.id.clone()))
+      .filter(tenant_members::deleted_at.is_null())
+      .select(tenant_members::tenant_id)
+      .load::<String>(&mut conn)
+      .unwrap_or_default();
+
+    let mut tenants_query = tenants::table
+      .filter(tenants::id.eq_any(tenant_ids))
+      .filter(tenants::deleted_at.is_null())
+      .into_boxed();
+
+    if let Some(continuous_token) = query.continuous_token {
+      tenants_query = tenants_query.filter(tenants::id.gt(continuous_token));
+    }
+
+    let result = tenants_query
+      .order(tenants::id.asc())
+      .limit(page_size + 1)
+      .load::<Tenant>(&mut conn);
+
+    let mut tenants = match result {
+      Ok(tenants) => tenants,
+      Err(e) => {
+        let error_response = serde_json::json!({
+            "status": "fail",
+            "message": format!("Error fetching tenants: {}", e)
+        });
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(error_response)));
+      }
+    };
+
+    // One extra row tells us whether there is another page
+    let continuous_token = if tenants.len() as i64 > page_size {
+      tenants.truncate(page_size as usize);
+      tenants.last().map(|tenant| tenant.id.clone())
+    } else {
+      None
+    };
+
+    Ok(Json(json!({
+      "tenants": tenants.iter().map(filter_tenant).collect::<Vec<_>>(),
+      "continuousToken": continuous_token,
+    })))
+  }
+
+pub async fn delete_tenant_handler(
+    Extension(jwtauth): Extension<JWTAuthMiddleware>,
+    State(data): State<Arc<AppState>>,
+    context: RequestContext,
+    Path(tenant_id): Path<String>,
+  ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let user = jwtauth.session_user()?;
+    let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
+    let membership = find_membership(&mut conn, &tenant_id, &user.id).ok_or_else(tenant_not_found)?;
+    if membership.role != TENANT_OWNER {
+      return Err(not_allowed("Only an owner can delete the tenant"));
+    }
+
+    let tenant = find_tenant(&mut conn, &tenant_id).ok_or_else(tenant_not_found)?;
+
+    let statement = diesel::update(tenants::table)
+      .filter(tenants::id.eq(tenant_id.clone()))
+      .set(&TenantDelete {
+        deleted_at: Some(Utc::now().naive_utc()),
+      })
+      .execute(&mut conn);
+
+    if let Err(e) = statement {
+      let error_response = serde_json::json!({
+          "status": "fail",
+          "message": format!("Tenant could not be deleted: {}", e)
+      });
+      return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+    }
+
+    audit_tenant_action(&mut conn, &context, "tenant.deleted", user, None, &tenant_id);
+
+    Ok(Json(json!({ "tenant": filter_tenant(&tenant) })))
+  }
+
+pub async fn list_tenant_members_handler(
+    Extension(jwtauth): Extension<JWTAuthMiddleware>,
+    State(data): State<Arc<AppState>>,
+    Path(tenant_id): Path<String>,
+  ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let user = jwtauth.user()?;
+    let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
+    find_membership(&mut conn, &tenant_id, &user.id).ok_or_else(tenant_not_found)?;
+
+    let members = tenant_members::table
+      .filter(tenant_members::tenant_id.eq(tenant_id))
+      .filter(tenant_members::deleted_at.is_null())
+      .order(tenant_members::created_at.asc())
+      .load::<TenantMember>(&mut conn)
+      .unwrap_or_default();
+
+    let members = members
+      .into_iter()
+      .map(|member| FilteredTenantMember {
+        userId: member.user_id,
+        role: member.role,
+        createdAt: member.created_at,
+      })
+      .collect::<Vec<_>>();
+
+    Ok(Json(json!({ "status": "success", "members": members })))
+  }
+
+// Adds a member, or changes the role of an existing one
+pub async fn put_tenant_member_handler(
+    Extension(jwtauth): Extension<JWTAuthMiddleware>,
+    State(data): State<Arc<AppState>>,
+    context: RequestContext,
+    Path(tenant_id): Path<String>,
+    Json(body): Json<TenantMemberSchema>,
+  ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let user = jwtauth.session_user()?;
+    let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
+    let membership = find_membership(&mut conn, &tenant_id, &user.id).ok_or_else(tenant_not_found)?;
+    if !can_manage(&membership.role) {
+      return Err(not_allowed("Only owners and admins can manage members"));
+    }
+
+    if !is_tenant_role(&body.role) {
+      let error_response = serde_json::json!({
+          "status": "fail",
+          "message": "Role must be owner, admin or member"
+      });
+      return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+    }
+
+    let existing = find_membership(&mut conn, &tenant_id, &body.user_id);
+
+    // Only owners may hand out or take away ownership
+    let touches_owner = body.role == TENANT_OWNER
+      || existing.as_ref().map(|member| member.role == TENANT_OWNER).unwrap_or(false);
+    if touches_owner && membership.role != TENANT_OWNER {
+      return Err(not_allowed("Only an owner can change ownership"));
+    }
+
+    let timestamp = Utc::now().naive_utc();
+    let statement = match existing {
+      Some(member) => {
+        if member.role == TENANT_OWNER && body.role != TENANT_OWNER && count_owners(&mut conn, &tenant_id) <= 1 {
+          return Err(not_allowed("A tenant needs at least one owner"));
+        }
+
+        diesel::update(tenant_members::table)
+          .filter(tenant_members::id.eq(member.id))
+          .set(&TenantMemberRoleUpdate {
+            role: body.role.clone(),
+            updated_at: Some(timestamp),
+          })
+          .execute(&mut conn)
+      }
+      None => {
+        let user_exists = user::table
+          .filter(user::id.eq(body.user_id.clone()))
+          .first::<User>(&mut conn)
+          .optional();
+
+        if !matches!(user_exists, Ok(Some(_))) {
+          let error_response = serde_json::json!({
+              "status": "fail",
+              "message": "User not found"
+          });
+          return Err((StatusCode::NOT_FOUND, Json(error_response)));
+        }
+
+        diesel::insert_into(tenant_members::table)
+          .values(&TenantMember {
+            id: Ulid::new().to_string(),
+            tenant_id: tenant_id.clone(),
+            user_id: body.user_id.clone(),
+            role: body.role.clone(),
+            created_at: timestamp,
+            updated_at: None,
+            deleted_at: None
+          })
+          .execute(&mut conn)
+      }
+    };
+
+    if let Err(e) = statement {
+      let error_response = serde_json::json!({
+          "status": "fail",
+          "message": format!("Member not saved to database: validation error\nDetails: {:?}", e)
+      });
+      return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+    }
+
+    audit::record(&mut conn, &context, AuditRecord {
+      event_type: "tenant.member.updated",
+      actor_id: Some(&user.id),
+      subject_id: Some(&body.user_id),
+      outcome: OUTCOME_SUCCESS,
+      metadata: json!({ "tenantId": tenant_id, "role": body.role }),
+    });
+
+    Ok(Json(json!({ "status": "success" })))
+  }
+
+// Owners and admins remove others, anyone may leave
+pub async fn remove_tenant_member_handler(
+    Extension(jwtauth): Extension<JWTAuthMiddleware>,
+    State(data): State<Arc<AppState>>,
+    context: RequestContext,
+    Path((tenant_id, user_id)): Path<(String, String)>,
+  ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let user = jwtauth.session_user()?;
+    let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
+    let membership = find_membership(&mut conn, &tenant_id, &user.id).ok_or_else(tenant_not_found)?;
+    let member = find_membership(&mut conn, &tenant_id, &user_id).ok_or_else(|| {
+      let error_response = serde_json::json!({
+          "status": "fail",
+          "message": "Member not found"
+      });
+      (StatusCode::NOT_FOUND, Json(error_response))
+    })?;
+
+    let is_self = user_id == user.id;
+    if !is_self && !can_manage(&membership.role) {
+      return Err(not_allowed("Only owners and admins can remove members"));
+    }
+    if member.role == TENANT_OWNER && !is_self && membership.role != TENANT_OWNER {
+      return Err(not_allowed("Only an owner can remove an owner"));
+    }
+    if member.role == TENANT_OWNER && count_owners(&mut conn, &tenant_id) <= 1 {
+      return Err(not_allowed("A tenant needs at least one owner"));
+    }
+
+    let statement = diesel::delete(tenant_members::table)
+      .filter(tenant_members::id.eq(member.id))
+      .execute(&mut conn);
+
+    if let Err(e) = statement {
+      let error_response = serde_json::json!({
+          "status": "fail",
+          "message": format!("Member could not be removed: {}", e)
+      });
+      return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+    }
+
+    audit_tenant_action(&mut conn, &context, "tenant.member.removed", user, Some(&user_id), &tenant_id);
+
+    Ok(Json(json!({ "status": "success" })))
+  }
+
+// Issues a new session acting in the tenant, or in no tenant when tenantId is left out
+pub async fn switch_tenant_handler(
+    Extension(jwtauth): Extension<JWTAuthMiddleware>,
+    State(data): State<Arc<AppState>>,
+    context: RequestContext,
+    Json(body): Json<SwitchTenantSchema>,
+  ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let user = jwtauth.session_user()?;
+    let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
+    // The new session keeps the original sign-in time and method
+    let mut auth_claims = match (jwtauth.auth_time, jwtauth.amr.as_deref()) {
+      (Some(auth_time), Some(amr)) => session_claims(auth_time, amr),
+      _ => vec![],
+    };
+    if let Some(idp) = jwtauth.idp.as_deref() {
+      auth_claims.extend(idp_claims(idp));
+    }
+
+    if let Some(tenant_id) = body.tenant_id.as_deref() {
+      find_membership(&mut conn, tenant_id, &user.id).ok_or_else(tenant_not_found)?;
+      auth_claims.extend(tenant::tenant_claims(tenant_id));
+    }
+    let env = tenant::tenant_config(&mut conn, &data.env, body.tenant_id.as_deref());
+
+    // The tenant's providers and MFA requirement apply to the session moving in, as they would at sign-in
+    if let Err(message) = tenant::check_sign_in_policy(&env, jwtauth.amr.as_deref(), jwtauth.idp.as_deref()) {
+      audit::record_user_event(&mut conn, &context, "tenant.switched", Some(&user.id), OUTCOME_FAILURE, json!({ "tenantId": body.tenant_id, "reason": message }));
+      return Err(not_allowed(message));
+    }
+
+    let (access_token_details, refresh_token_details) = issue_session_tokens(&mut conn, &env, &user.id, &auth_claims).await?;
+    audit::record_user_event(&mut conn, &context, "tenant.switched", Some(&user.id), OUTCOME_SUCCESS, json!({ "tenantId": body.tenant_id }));
+
+    let access_cookie = Cookie::build(
+      ("access_token",
+      access_token_details.token.clone().unwrap_or_default()),
+    )
+      .path("/")
+      .secure(true)
+      .max_age(time::Duration::seconds(parse_duration(&env.access_token_expires_in).unwrap_or(900))) // 15 minutes default
+      .same_site(SameSite::Strict)
+      .http_only(true);
+
+    let refresh_cookie = Cookie::build(
+      ("refresh_token",
+      refresh_token_details.token.clone().unwrap_or_default()),
+    )
+      .path("/")
+      .secure(true)
+      .max_age(time::Duration::seconds(parse_duration(&env.refresh_token_expires_in).unwrap_or(900))) // 15 minutes default
+      .same_site(SameSite::Strict)
+      .http_only(true);
+
+    let mut response = Response::new(
+      json!({
+        "status": "success",
+        "access_token": access_token_details.token.unwrap(),
+        "tenantId": body.tenant_id,
+      })
+        .to_string(),
+    );
+    let mut headers = HeaderMap::new();
+    headers.append(
+      header::SET_COOKIE,
+      access_cookie.to_string().parse().unwrap(),
+    );
+    headers.append(
+      header::SET_COOKIE,
+      refresh_cookie.to_string().parse().unwrap(),
+    );
+    headers.append(
+      header::CONTENT_TYPE,
+      "application/json".parse().unwrap(),
+    );
+
+    response.headers_mut().extend(headers);
+
+    Ok(response)
+  }
+
+fn filter_tenant_settings(tenant_id: &str, settings: Option<TenantSettings>) -> FilteredTenantSettings {
+  let settings = settings.as_ref();
+  FilteredTenantSettings {
+    tenantId: tenant_id.to_owned(),
+    host: settings.and_then(|settings| settings.host.clone()),
+    enabledProviders: settings.and_then(|settings| settings.enabled_providers.clone()),
+    passwordMinLength: settings.and_then(|settings| settings.password_min_length),
+    passwordRequireDigit: settings.and_then(|settings| settings.password_require_digit),
+    passwordRequireSymbol: settings.and_then(|settings| settings.password_require_symbol),
+    mfaRequired: settings.and_then(|settings| settings.mfa_required),
+    accessTokenMaxAge: settings.and_then(|settings| settings.access_token_max_age),
+    refreshTokenMaxAge: settings.and_then(|settings| settings.refresh_token_max_age),
+    mailerFrom: settings.and_then(|settings| settings.mailer_from.clone()),
+    mailerFromName: settings.and_then(|settings| settings.mailer_from_name.clone()),
+  }
+}
+
+fn validate_tenant_settings(conn: &mut PgConnection, tenant_id: &str, body: &TenantSettingsSchema) -> Result<(), String> {
+  if let Some(providers) = &body.enabled_providers {
+    let unknown = providers.iter().find(|provider| {
+      !["password", "magiclink"].contains(&provider.to_lowercase().as_str()) && OAuthProvider::from_str(provider).is_none()
+    });
+    if let Some(provider) = unknown {
+      return Err(format!("Unknown provider: {}", provider));
+    }
+  }
+
+  if body.password_min_length.is_some_and(|length| !(1..=128).contains(&length)) {
+    return Err("Password minimum length must be between 1 and 128".into());
+  }
+
+  if body.access_token_max_age.is_some_and(|minutes| minutes <= 0)
+    || body.refresh_token_max_age.is_some_and(|minutes| minutes <= 0) {
+    return Err("Token lifetimes must be a positive number of minutes".into());
+  }
+
+  // A host picks the tenant for unauthenticated requests, so it can only belong to one
+  if let Some(host) = &body.host {
+    let taken = tenant_settings::table
+      .filter(tenant_settings::host.eq(host.to_lowercase()))
+      .filter(tenant_settings::tenant_id.ne(tenant_id))
+      .filter(tenant_settings::deleted_at.is_null())
+      .count()
+      .get_result::<i64>(conn)
+      .unwrap_or(0);
+    if taken > 0 {
+      return Err("Host is already used by another tenant".into());
+    }
+  }
+
+  Ok(())
+}
+
+pub async fn get_tenant_settings_handler(
+    Extension(jwtauth): Extension<JWTAuthMiddleware>,
+    State(data): State<Arc<AppState>>,
+    Path(tenant_id): Path<String>,
+  ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let user = jwtauth.user()?;
+    let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
+    let membership = find_membership(&mut conn, &tenant_id, &user.id).ok_or_else(tenant_not_found)?;
+    if !can_manage(&membership.role) {
+      return Err(not_allowed("Only owners and admins can view settings"));
+    }
+
+    let settings = find_settings(&mut conn, &tenant_id);
+
+    Ok(Json(json!({ "status": "success", "settings": filter_tenant_settings(&tenant_id, settings) })))
+  }
+
+// Replaces the tenant's overrides, fields left out go back to the global config
+pub async fn put_tenant_settings_handler(
+    Extension(jwtauth): Extension<JWTAuthMiddleware>,
+    State(data): State<Arc<AppState>>,
+    context: RequestContext,
+    Path(tenant_id): Path<String>,
+    Json(body): Json<TenantSettingsSchema>,
+  ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let user = jwtauth.session_user()?;
+    let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
+    let membership = find_membership(&mut conn, &tenant_id, &user.id).ok_or_else(tenant_not_found)?;
+    if !can_manage(&membership.role) {
+      return Err(not_allowed("Only owners and admins can change settings"));
+    }
+
+    if let Err(message) = validate_tenant_settings(&mut conn, &tenant_id, &body) {
+      let error_response = serde_json::json!({
+          "status": "fail",
+          "message": message
+      });
+      return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+    }
+
+    let host = body.host.map(|host| host.trim().to_lowercase()).filter(|host| !host.is_empty());
+    let enabled_providers = body.enabled_providers
+      .map(|providers| providers.iter().map(|provider| provider.to_lowercase()).collect::<Vec<_>>());
+
+    let timestamp = Utc::now().naive_utc();
+    let statement = match find_settings(&mut conn, &tenant_id) {
+      Some(settings) => diesel::update(tenant_settings::table)
+        .filter(tenant_settings::id.eq(settings.id))
+        .set(&TenantSettingsUpdate {
+          host,
+          enabled_providers,
+          password_min_length: body.password_min_length,
+          password_require_digit: body.password_require_digit,
+          password_require_symbol: body.password_require_symbol,
+          mfa_required: body.mfa_required,
+          access_token_max_age: body.access_token_max_age,
+          refresh_token_max_age: body.refresh_token_max_age,
+          mailer_from: body.mailer_from,
+          mailer_from_name: body.mailer_from_name,
+          updated_at: Some(timestamp),
+        })
+        .execute(&mut conn),
+      None => diesel::insert_into(tenant_settings::table)
+        .values(&TenantSettings {
+          id: Ulid::new().to_string(),
+          tenant_id: tenant_id.clone(),
+          host,
+          enabled_providers,
+          password_min_length: body.password_min_length,
+          password_require_digit: body.password_require_digit,
+          password_require_symbol: body.password_require_symbol,
+          mfa_required: body.mfa_required,
+          access_token_max_age: body.access_token_max_age,
+          refresh_token_max_age: body.refresh_token_max_age,
+          mailer_from: body.mailer_from,
+          mailer_from_name: body.mailer_from_name,
+          created_at: timestamp,
+          updated_at: None,
+          deleted_at: None
+        })
+        .execute(&mut conn),
+    };
+
+    if let Err(e) = statement {
+      let error_response = serde_json::json!({
+          "status": "fail",
+          "message": format!("Settings not saved to database: {}", e)
+      });
+      return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+    }
+
+    audit_tenant_action(&mut conn, &context, "tenant.settings.updated", user, None, &tenant_id);
+
+    let settings = find_settings(&mut conn, &tenant_id);
+
+    Ok(Json(json!({ "status": "success", "settings": filter_tenant_settings(&tenant_id, settings) })))
+  }
@@ -5,12 +5,14 @@ use axum::{
 use anyhow::Result;
 use chrono::{DateTime, TimeZone, Utc};
 use diesel::{query_dsl::methods::FilterDsl, ExpressionMethods, OptionalExtension, RunQueryDsl};
+use serde_json::json;
 use crate::{
-  model::VerifyCodeSchema, schema::{email_confirmation, EmailConfirmation}, utils::update_confirm_code, AppState
+  audit::{record_user_event, RequestContext, OUTCOME_FAILURE, OUTCOME_SUCCESS}, model::VerifyCodeSchema, redirect::safe_redirect_url, schema::{email_confirmation, EmailConfirmation, PURPOSE_PASSWORD_RESET}, utils::update_confirm_code, AppState
 };
 
 pub async fn verify_code_handler(
   State(data): State<Arc<AppState>>,
+  context: RequestContext,
   Query(body): Query<VerifyCodeSchema>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
   let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
@@ -20,12 +22,14 @@ pub async fn verify_code_handler(
   let confirmation_exists = email_confirmation::table
     .filter(email_confirmation::code.eq(code.clone().to_owned()))
     .filter(email_confirmation::flow.eq("created"))
+    .filter(email_confirmation::purpose.eq(PURPOSE_PASSWORD_RESET))
     .first::<EmailConfirmation>(&mut conn)
     .optional();
 
   let confirmation = if let Ok(Some(confirmation)) = confirmation_exists {
     confirmation
   } else {
+    record_user_event(&mut conn, &context, "password.reset_code_verified", None, OUTCOME_FAILURE, json!({ "reason": "invalid_code" }));
     let error_response = serde_json::json!({
       "status": "fail",
       "message": "verification code does not exist"
@@ -37,6 +41,7 @@ pub async fn verify_code_handler(
   let expiry_time: DateTime<Utc> = Utc.from_utc_datetime(&confirmation.expires);
 
   if current_time > expiry_time {
+    record_user_event(&mut conn, &context, "password.reset_code_verified", Some(&confirmation.user_id), OUTCOME_FAILURE, json!({ "reason": "expired_code" }));
     let error_response = serde_json::json!({
       "status": "fail",
       "message": "Code is invalid or has expired"
@@ -44,7 +49,11 @@ pub async fn verify_code_handler(
     return Err((StatusCode::UNAUTHORIZED, Json(error_response)));
   }
 
+  record_user_event(&mut conn, &context, "password.reset_code_verified", Some(&confirmation.user_id), OUTCOME_SUCCESS, json!({}));
+
+  let redirect_to = safe_redirect_url(&data.env, &confirmation.redirect_to.unwrap_or_default());
+
   let _ = update_confirm_code(axum::extract::State(data), confirmation.id.to_string(), "seen".to_string()).await;
 
-  Ok(Redirect::temporary(&format!("{}?code={}", confirmation.redirect_to.unwrap(), confirmation.code)))
+  Ok(Redirect::temporary(&format!("{}?code={}", redirect_to, confirmation.code)))
 }
\ No newline at end of file
@@ -0,0 +1,92 @@
+use std::sync::Arc;
+use axum::{
+  extract::{Query, State}, http::StatusCode, response::{IntoResponse, Redirect}, Json
+};
+use anyhow::Result;
+use chrono::{DateTime, TimeZone, Utc};
+use diesel::{query_dsl::methods::FilterDsl, ExpressionMethods, OptionalExtension, RunQueryDsl};
+use serde_json::json;
+use crate::{
+  audit::{record_user_event, RequestContext, OUTCOME_SUCCESS}, model::VerifyEmailSchema, redirect::safe_redirect_url, schema::{email_confirmation, user, EmailConfirmation, PURPOSE_EMAIL_VERIFICATION, User, UserEmailUpdate}, utils::update_confirm_code, webhook, AppState
+};
+
+pub async fn verify_email_handler(
+  State(data): State<Arc<AppState>>,
+  context: RequestContext,
+  Query(body): Query<VerifyEmailSchema>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+  let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
+  // only confirmations carrying an email are email verifications
+  let confirmation_exists = email_confirmation::table
+    .filter(email_confirmation::code.eq(body.code.to_owned()))
+    .filter(email_confirmation::flow.eq("created"))
+    .filter(email_confirmation::purpose.eq(PURPOSE_EMAIL_VERIFICATION))
+    .filter(email_confirmation::email.is_not_null())
+    .first::<EmailConfirmation>(&mut conn)
+    .optional();
+
+  let confirmation = if let Ok(Some(confirmation)) = confirmation_exists {
+    confirmation
+  } else {
+    let error_response = serde_json::json!({
+      "status": "fail",
+      "message": "verification code does not exist"
+    });
+    return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+  };
+
+  let current_time = Utc::now();
+  let expiry_time: DateTime<Utc> = Utc.from_utc_datetime(&confirmation.expires);
+
+  if current_time > expiry_time {
+    let error_response = serde_json::json!({
+      "status": "fail",
+      "message": "Code is invalid or has expired"
+    });
+    return Err((StatusCode::UNAUTHORIZED, Json(error_response)));
+  }
+
+  let email = confirmation.email.unwrap_or_default();
+
+  // the address may have been claimed since the code was sent
+  let user_exists = user::table
+    .filter(user::email.eq(email.clone()))
+    .first::<User>(&mut conn)
+    .optional();
+
+  if let Ok(Some(_)) = user_exists {
+    let error_response = serde_json::json!({
+      "status": "fail",
+      "message": "Email is already in use"
+    });
+    return Err((StatusCode::CONFLICT, Json(error_response)));
+  }
+
+  let timestamp = Utc::now().naive_utc();
+  let statement = diesel::update(user::table)
+    .filter(user::id.eq(confirmation.user_id.clone()))
+    .set(&UserEmailUpdate {
+      email: Some(email.clone()),
+      verified: true,
+      updated_at: Some(timestamp),
+    })
+    .execute(&mut conn);
+
+  if let Err(e) = statement {
+    let error_response = serde_json::json!({
+      "status": "fail",
+      "message": format!("Failed to verify email: validation error\nDetails: {:?}", e)
+    });
+    return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+  }
+
+  record_user_event(&mut conn, &context, "email.verified", Some(&confirmation.user_id), OUTCOME_SUCCESS, json!({ "email": email }));
+  webhook::enqueue(&mut conn, webhook::USER_EMAIL_VERIFIED, json!({ "userId": confirmation.user_id, "email": email }));
+
+  let redirect_to = safe_redirect_url(&data.env, &confirmation.redirect_to.unwrap_or_default());
+
+  let _ = update_confirm_code(axum::extract::State(data), confirmation.id.to_string(), "completed".to_string()).await;
+
+  Ok(Redirect::temporary(&redirect_to))
+}
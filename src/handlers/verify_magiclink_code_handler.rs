@@ -1,32 +1,36 @@
 use std::sync::Arc;
 use axum::{
-  extract::{Query, State}, http::{header, StatusCode}, response::{IntoResponse, Redirect}, Json
+  extract::{Query, State}, http::StatusCode, response::{IntoResponse, Redirect}, Json
 };
 use anyhow::Result;
-use axum_extra::extract::cookie::{Cookie, SameSite};
-use chrono::{DateTime, Utc};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::{DateTime, TimeZone, Utc};
 use diesel::{query_dsl::methods::FilterDsl, ExpressionMethods, OptionalExtension, RunQueryDsl};
-use ulid::Ulid;
+use serde_json::json;
 use crate::{
-  model::VerifyMagicLinkSchema, schema::{email_confirmation, tokens, EmailConfirmation, Token}, token::generate_paseto_token, utils::{parse_duration, update_confirm_code}, AppState
+  audit::{record_user_event, RequestContext, OUTCOME_FAILURE}, email_otp, handlers::generate_magiclink_handler::{complete_magiclink_sign_in, MagicLinkVia, MAGICLINK_NONCE_COOKIE}, model::VerifyMagicLinkSchema, redirect::{append_query_param, safe_redirect_url}, schema::{email_confirmation, EmailConfirmation, PURPOSE_MAGIC_LINK}, utils::{hash_token, update_confirm_code}, AppState
 };
 
 pub async fn verify_magiclink_code_handler(
+  cookie_jar: CookieJar,
   State(data): State<Arc<AppState>>,
+  context: RequestContext,
   Query(body): Query<VerifyMagicLinkSchema>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
-  let redirect_to = body.redirect_to.to_owned();
+  let redirect_to = safe_redirect_url(&data.env, &body.redirect_to);
   let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
   
   let confirmation_exists = email_confirmation::table
     .filter(email_confirmation::code.eq(body.code.to_owned()))
-    .filter(email_confirmation::flow.eq("created"))
+    .filter(email_confirmation::flow.eq_any(["created", "viewed"]))
+    .filter(email_confirmation::purpose.eq(PURPOSE_MAGIC_LINK))
     .first::<EmailConfirmation>(&mut conn)
     .optional();
 
   let confirmation = if let Ok(Some(confirmation)) = confirmation_exists {
     confirmation
   } else {
+    record_user_event(&mut conn, &context, "magiclink.login", None, OUTCOME_FAILURE, json!({ "reason": "invalid_code" }));
     let error_response = serde_json::json!({
       "status": "fail",
       "message": "Code is invalid or has expired"
@@ -34,122 +38,39 @@ pub async fn verify_magiclink_code_handler(
     return Err((StatusCode::BAD_REQUEST, Json(error_response)));
   };
 
-  let user_id = confirmation.user_id;
-  let code_id = confirmation.code;
+  let current_time = Utc::now();
+  let expiry_time: DateTime<Utc> = Utc.from_utc_datetime(&confirmation.expires);
 
-  let access_token_details = generate_paseto_token(
-    user_id.to_string(),
-    data.env.access_token_max_age,
-    &data.env.auth_key,
-  ).unwrap();
-
-  let refresh_token_details = generate_paseto_token(
-    user_id.to_string(),
-    data.env.refresh_token_max_age,
-    &data.env.auth_key,
-  ).unwrap();
-
-  // Save tokens
-  // Access token
-  let expires = DateTime::<Utc>::from_timestamp(access_token_details.expires_in.unwrap(), 0)
-    .map(|dt| dt.naive_utc())
-    .unwrap_or_else(|| {
-      // Handle invalid timestamps
-      DateTime::<Utc>::from_timestamp(0, 0)
-        .unwrap()
-        .naive_utc()
-    });
-  let timestamp = Utc::now().naive_utc();
-  let statement = diesel::insert_into(tokens::table)
-    .values(&Token {
-      id: Ulid::new().to_string(),
-      user_id: user_id.clone().into(),
-      expires,
-      blacklisted: false,
-      token: access_token_details.token.clone().unwrap(),
-      token_uuid: access_token_details.token_uuid.to_string(),
-      created_at: timestamp,
-      updated_at: None,
-      deleted_at: None
-    })
-    .execute(&mut conn);
-  
-  if let Err(e) = statement {
-    let error_message = format!("Failed to save access token: validation error\nDetails: {:?}", e);
+  if current_time > expiry_time {
+    record_user_event(&mut conn, &context, "magiclink.login", Some(&confirmation.user_id), OUTCOME_FAILURE, json!({ "reason": "expired_code" }));
     let error_response = serde_json::json!({
-        "status": "fail",
-        "message": error_message
+      "status": "fail",
+      "message": "Code is invalid or has expired"
     });
-    return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+    return Err((StatusCode::UNAUTHORIZED, Json(error_response)));
   }
 
-  // Refresh Token
-  let expires = DateTime::<Utc>::from_timestamp(access_token_details.expires_in.unwrap(), 0)
-    .map(|dt| dt.naive_utc())
-    .unwrap_or_else(|| {
-      // Handle invalid timestamps
-      DateTime::<Utc>::from_timestamp(0, 0)
-        .unwrap()
-        .naive_utc()
-    });
-  let timestamp = Utc::now().naive_utc();
-  let statement = diesel::insert_into(tokens::table)
-    .values(&Token {
-      id: Ulid::new().to_string(),
-      user_id: user_id.into(),
-      expires,
-      blacklisted: false,
-      token: refresh_token_details.token.clone().unwrap(),
-      token_uuid: refresh_token_details.token_uuid.to_string(),
-      created_at: timestamp,
-      updated_at: None,
-      deleted_at: None
-    })
-    .execute(&mut conn);
-  
-  if let Err(e) = statement {
-    let error_message = format!("Failed to save refresh token: validation error\nDetails: {:?}", e);
-    let error_response = serde_json::json!({
-        "status": "fail",
-        "message": error_message
-    });
-    return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+  // Opened in a browser other than the one that asked, the link only approves the sign-in there
+  if let Some(device_nonce) = confirmation.device_nonce.as_deref() {
+    let same_device = cookie_jar
+      .get(MAGICLINK_NONCE_COOKIE)
+      .is_some_and(|cookie| hash_token(cookie.value()) == device_nonce);
+    if !same_device {
+      let approval_url = append_query_param(&data.env.magiclink_approval_url, "code", &confirmation.code);
+      return Ok(Redirect::temporary(&approval_url).into_response());
+    }
   }
 
-  let _ = update_confirm_code(axum::extract::State(data.clone()), code_id.to_string(), "completed".to_string()).await;
-
-  // set cookies
-  let access_cookie = Cookie::build(
-    ("access_token",
-    access_token_details.token.clone().unwrap_or_default()),
-  )
-    .path("/")
-    .secure(true)
-    .max_age(time::Duration::seconds(parse_duration(&data.env.access_token_expires_in).unwrap_or(900))) // 15 minutes default
-    .same_site(SameSite::Strict)
-    .http_only(true);
-
-  let refresh_cookie = Cookie::build(
-    ("refresh_token",
-    refresh_token_details.token.clone().unwrap_or_default()),
-  )
-    .path("/")
-    .secure(true)
-    .max_age(time::Duration::seconds(parse_duration(&data.env.refresh_token_expires_in).unwrap_or(900))) // 15 minutes default
-    .same_site(SameSite::Strict)
-    .http_only(true);
+  let (_, cookies) = complete_magiclink_sign_in(
+    &data, &mut conn, &context, &confirmation.user_id, confirmation.tenant_id.as_deref(), MagicLinkVia::Link,
+  ).await?;
 
-  let redirect = Redirect::temporary(&redirect_to);
-  let mut response = redirect.into_response();
+  let _ = update_confirm_code(axum::extract::State(data.clone()), confirmation.id.clone(), "completed".to_string()).await;
+  // The code emailed with this link can no longer be used either
+  email_otp::invalidate(&mut conn, &confirmation.id);
 
-  response.headers_mut().append(
-      header::SET_COOKIE,
-      access_cookie.to_string().parse().unwrap(),
-  );
-  response.headers_mut().append(
-      header::SET_COOKIE,
-      refresh_cookie.to_string().parse().unwrap(),
-  );
+  let mut response = Redirect::temporary(&redirect_to).into_response();
+  response.headers_mut().extend(cookies);
 
   Ok(response)
-}
\ No newline at end of file
+}
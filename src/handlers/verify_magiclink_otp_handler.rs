@@ -0,0 +1,85 @@
+use std::sync::Arc;
+use axum::{
+  extract::State, http::{header, HeaderMap, Response, StatusCode}, response::IntoResponse, Json
+};
+use anyhow::Result;
+use diesel::{query_dsl::methods::{FilterDsl, SelectDsl}, ExpressionMethods, OptionalExtension, RunQueryDsl};
+use serde_json::json;
+use crate::{
+  audit::{record_user_event, RequestContext, OUTCOME_FAILURE}, email_otp::{self, OtpError}, handlers::generate_magiclink_handler::{complete_magiclink_sign_in, MagicLinkVia},
+  model::VerifyMagicLinkOtpSchema, schema::{email_confirmation, user, User}, utils::update_confirm_code, AppState
+};
+
+// Signs in with the code from a magic link email, in the client that asked for it
+pub async fn verify_magiclink_otp_handler(
+  State(data): State<Arc<AppState>>,
+  context: RequestContext,
+  Json(body): Json<VerifyMagicLinkOtpSchema>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+  let email = body.email.to_owned().to_ascii_lowercase();
+  let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
+  let user_exists = user::table
+    .filter(user::email.eq(email.clone()))
+    .first::<User>(&mut conn)
+    .optional();
+
+  let user_id = if let Ok(Some(user)) = user_exists {
+    user.id
+  } else {
+    record_user_event(&mut conn, &context, "magiclink.login", None, OUTCOME_FAILURE, json!({ "email": email, "reason": "unknown_email" }));
+    let error_response = serde_json::json!({
+      "status": "fail",
+      "message": "Code is invalid or has expired"
+    });
+    return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+  };
+
+  let otp = match email_otp::redeem(&mut conn, &data.env, &user_id, &body.code) {
+    Ok(otp) => otp,
+    Err(OtpError::Invalid) => {
+      record_user_event(&mut conn, &context, "magiclink.login", Some(&user_id), OUTCOME_FAILURE, json!({ "method": "otp", "reason": "invalid_code" }));
+      let error_response = serde_json::json!({
+        "status": "fail",
+        "message": "Code is invalid or has expired"
+      });
+      return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+    }
+    Err(OtpError::TooManyAttempts) => {
+      record_user_event(&mut conn, &context, "magiclink.login", Some(&user_id), OUTCOME_FAILURE, json!({ "method": "otp", "reason": "too_many_attempts" }));
+      let error_response = serde_json::json!({
+        "status": "fail",
+        "message": "Too many attempts, request a new code"
+      });
+      return Err((StatusCode::TOO_MANY_REQUESTS, Json(error_response)));
+    }
+  };
+
+  // The link in the same email stops working once its code is used
+  let _ = update_confirm_code(axum::extract::State(data.clone()), otp.confirmation_id.clone(), "completed".to_string()).await;
+
+  let tenant_id = email_confirmation::table
+    .filter(email_confirmation::id.eq(otp.confirmation_id.clone()))
+    .select(email_confirmation::tenant_id)
+    .first::<Option<String>>(&mut conn)
+    .optional()
+    .unwrap_or(None)
+    .flatten();
+
+  let (access_token, cookies) = complete_magiclink_sign_in(&data, &mut conn, &context, &user_id, tenant_id.as_deref(), MagicLinkVia::Code).await?;
+
+  let mut response = Response::new(
+    json!({"status": "success", "access_token": access_token})
+      .to_string(),
+  );
+  let mut headers = HeaderMap::new();
+  headers.append(
+    header::CONTENT_TYPE,
+    "application/json".parse().unwrap(),
+  );
+  headers.extend(cookies);
+
+  response.headers_mut().extend(headers);
+
+  Ok(response)
+}
@@ -0,0 +1,351 @@
+use std::sync::Arc;
+use axum::{
+  extract::{Path, Query, State}, http::StatusCode, response::IntoResponse, Extension, Json
+};
+use anyhow::Result;
+use chrono::Utc;
+use diesel::prelude::*;
+use serde_json::json;
+use ulid::Ulid;
+use crate::{
+  audit::{self, AuditRecord, RequestContext, OUTCOME_SUCCESS},
+  handlers::admin_handler::admin_error,
+  jwt_auth::JWTAuthMiddleware,
+  model::{CreateWebhookSchema, UpdateWebhookSchema, WebhookDeliveriesSchema, WebhookMessagesSchema},
+  response::{FilteredWebhook, FilteredWebhookDelivery, FilteredWebhookMessage},
+  schema::{
+    webhook_deliveries, webhook_outbox, webhooks, Webhook, WebhookDelete, WebhookDelivery, WebhookMessage, WebhookMessageResult,
+    WebhookUpdate,
+  },
+  webhook::{self, STATUS_PENDING}, AppState
+};
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 200;
+
+fn filter_webhook(webhook: &Webhook) -> FilteredWebhook {
+  FilteredWebhook {
+    id: webhook.id.to_owned(),
+    url: webhook.url.to_owned(),
+    eventTypes: webhook.event_types.to_owned(),
+    description: webhook.description.to_owned(),
+    active: webhook.active,
+    createdAt: webhook.created_at,
+    updatedAt: webhook.updated_at,
+  }
+}
+
+fn filter_message(message: &WebhookMessage) -> FilteredWebhookMessage {
+  FilteredWebhookMessage {
+    id: message.id.to_owned(),
+    eventType: message.event_type.to_owned(),
+    payload: message.payload.to_owned(),
+    status: message.status.to_owned(),
+    attempts: message.attempts,
+    nextAttemptAt: message.next_attempt_at,
+    lastError: message.last_error.to_owned(),
+    createdAt: message.created_at,
+  }
+}
+
+fn filter_delivery(delivery: &WebhookDelivery) -> FilteredWebhookDelivery {
+  FilteredWebhookDelivery {
+    id: delivery.id.to_owned(),
+    messageId: delivery.message_id.to_owned(),
+    eventType: delivery.event_type.to_owned(),
+    attempt: delivery.attempt,
+    statusCode: delivery.status_code,
+    error: delivery.error.to_owned(),
+    durationMs: delivery.duration_ms,
+    createdAt: delivery.created_at,
+  }
+}
+
+fn validate_url(url: &str) -> Result<(), (StatusCode, Json<serde_json::Value>)> {
+  match reqwest::Url::parse(url) {
+    Ok(parsed) if ["http", "https"].contains(&parsed.scheme()) && parsed.host().is_some() => Ok(()),
+    _ => Err(admin_error(StatusCode::BAD_REQUEST, "Webhook url must be an absolute http(s) url")),
+  }
+}
+
+fn validate_event_types(event_types: &[String]) -> Result<(), (StatusCode, Json<serde_json::Value>)> {
+  if event_types.is_empty() {
+    return Err(admin_error(StatusCode::BAD_REQUEST, "At least one event type is required"));
+  }
+  if let Some(unknown) = event_types.iter().find(|event_type| !webhook::is_event_type(event_type)) {
+    return Err(admin_error(StatusCode::BAD_REQUEST, &format!("Unknown event type: {}", unknown)));
+  }
+  Ok(())
+}
+
+fn find_webhook(conn: &mut PgConnection, id: &str) -> Result<Webhook, (StatusCode, Json<serde_json::Value>)> {
+  webhooks::table
+    .filter(webhooks::id.eq(id))
+    .filter(webhooks::deleted_at.is_null())
+    .first::<Webhook>(conn)
+    .optional()
+    .unwrap_or(None)
+    .ok_or_else(|| admin_error(StatusCode::NOT_FOUND, "Webhook not found"))
+}
+
+fn audit_webhook_action(conn: &mut PgConnection, context: &RequestContext, event_type: &str, jwtauth: &JWTAuthMiddleware, metadata: serde_json::Value) {
+  audit::record(conn, context, AuditRecord {
+    event_type,
+    actor_id: Some(jwtauth.actor_id()),
+    subject_id: None,
+    outcome: OUTCOME_SUCCESS,
+    metadata,
+  });
+}
+
+pub async fn create_webhook_handler(
+    Extension(jwtauth): Extension<JWTAuthMiddleware>,
+    State(data): State<Arc<AppState>>,
+    context: RequestContext,
+    Json(body): Json<CreateWebhookSchema>,
+  ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
+    validate_url(&body.url)?;
+    validate_event_types(&body.event_types)?;
+
+    let secret = webhook::generate_secret();
+    let webhook = Webhook {
+      id: Ulid::new().to_string(),
+      url: body.url,
+      secret: secret.clone(),
+      event_types: body.event_types,
+      description: body.description,
+      active: true,
+      created_at: Utc::now().naive_utc(),
+      updated_at: None,
+      deleted_at: None
+    };
+
+    let statement = diesel::insert_into(webhooks::table)
+      .values(&webhook)
+      .execute(&mut conn);
+
+    if let Err(e) = statement {
+      return Err(admin_error(StatusCode::BAD_REQUEST, &format!("Webhook not saved to database: validation error\nDetails: {:?}", e)));
+    }
+
+    audit_webhook_action(&mut conn, &context, "admin.webhook.create", &jwtauth, json!({ "webhookId": webhook.id, "url": webhook.url }));
+
+    // The only time the signing secret is ever returned
+    Ok((StatusCode::CREATED, Json(json!({
+      "status": "success",
+      "secret": secret,
+      "webhook": filter_webhook(&webhook),
+    }))))
+  }
+
+pub async fn list_webhooks_handler(
+    State(data): State<Arc<AppState>>,
+  ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
+    let result = webhooks::table
+      .filter(webhooks::deleted_at.is_null())
+      .order(webhooks::created_at.desc())
+      .load::<Webhook>(&mut conn);
+
+    match result {
+      Ok(webhooks) => Ok(Json(json!({
+        "status": "success",
+        "webhooks": webhooks.iter().map(filter_webhook).collect::<Vec<_>>(),
+      }))),
+      Err(e) => Err(admin_error(StatusCode::INTERNAL_SERVER_ERROR, &format!("Error fetching webhooks: {}", e))),
+    }
+  }
+
+pub async fn update_webhook_handler(
+    Extension(jwtauth): Extension<JWTAuthMiddleware>,
+    State(data): State<Arc<AppState>>,
+    context: RequestContext,
+    Path(id): Path<String>,
+    Json(body): Json<UpdateWebhookSchema>,
+  ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+    find_webhook(&mut conn, &id)?;
+
+    if let Some(url) = &body.url {
+      validate_url(url)?;
+    }
+    if let Some(event_types) = &body.event_types {
+      validate_event_types(event_types)?;
+    }
+
+    let statement = diesel::update(webhooks::table)
+      .filter(webhooks::id.eq(id.clone()))
+      .set(&WebhookUpdate {
+        url: body.url,
+        event_types: body.event_types,
+        description: body.description,
+        active: body.active,
+        updated_at: Some(Utc::now().naive_utc()),
+      })
+      .execute(&mut conn);
+
+    if let Err(e) = statement {
+      return Err(admin_error(StatusCode::BAD_REQUEST, &format!("Webhook could not be updated: {}", e)));
+    }
+
+    let webhook = find_webhook(&mut conn, &id)?;
+    audit_webhook_action(&mut conn, &context, "admin.webhook.update", &jwtauth, json!({ "webhookId": webhook.id }));
+
+    Ok(Json(json!({ "status": "success", "webhook": filter_webhook(&webhook) })))
+  }
+
+// Soft deleted, the delivery log stays readable
+pub async fn delete_webhook_handler(
+    Extension(jwtauth): Extension<JWTAuthMiddleware>,
+    State(data): State<Arc<AppState>>,
+    context: RequestContext,
+    Path(id): Path<String>,
+  ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+    find_webhook(&mut conn, &id)?;
+
+    let statement = diesel::update(webhooks::table)
+      .filter(webhooks::id.eq(id.clone()))
+      .set(&WebhookDelete {
+        active: false,
+        deleted_at: Some(Utc::now().naive_utc()),
+      })
+      .execute(&mut conn);
+
+    if let Err(e) = statement {
+      return Err(admin_error(StatusCode::BAD_REQUEST, &format!("Webhook could not be deleted: {}", e)));
+    }
+
+    audit_webhook_action(&mut conn, &context, "admin.webhook.delete", &jwtauth, json!({ "webhookId": id }));
+
+    Ok(Json(json!({ "status": "success" })))
+  }
+
+// The outbox for one webhook, newest first
+pub async fn list_webhook_messages_handler(
+    State(data): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Query(query): Query<WebhookMessagesSchema>,
+  ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+    let page_size = query.page_size.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+    let mut messages_query = webhook_outbox::table
+      .filter(webhook_outbox::webhook_id.eq(id))
+      .into_boxed();
+
+    if let Some(status) = query.status {
+      messages_query = messages_query.filter(webhook_outbox::status.eq(status));
+    }
+    if let Some(continuous_token) = query.continuous_token {
+      messages_query = messages_query.filter(webhook_outbox::id.lt(continuous_token));
+    }
+
+    let result = messages_query
+      .order(webhook_outbox::id.desc())
+      .limit(page_size + 1)
+      .load::<WebhookMessage>(&mut conn);
+
+    let mut messages = match result {
+      Ok(messages) => messages,
+      Err(e) => {
+        return Err(admin_error(StatusCode::INTERNAL_SERVER_ERROR, &format!("Error fetching webhook messages: {}", e)));
+      }
+    };
+
+    // One extra row tells us whether there is another page
+    let continuous_token = if messages.len() as i64 > page_size {
+      messages.truncate(page_size as usize);
+      messages.last().map(|message| message.id.clone())
+    } else {
+      None
+    };
+
+    Ok(Json(json!({
+      "status": "success",
+      "messages": messages.iter().map(filter_message).collect::<Vec<_>>(),
+      "continuousToken": continuous_token,
+    })))
+  }
+
+// Every attempt made for one webhook, optionally for a single message
+pub async fn list_webhook_deliveries_handler(
+    State(data): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Query(query): Query<WebhookDeliveriesSchema>,
+  ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+    let page_size = query.page_size.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+    let mut deliveries_query = webhook_deliveries::table
+      .filter(webhook_deliveries::webhook_id.eq(id))
+      .into_boxed();
+
+    if let Some(message_id) = query.message_id {
+      deliveries_query = deliveries_query.filter(webhook_deliveries::message_id.eq(message_id));
+    }
+    if let Some(continuous_token) = query.continuous_token {
+      deliveries_query = deliveries_query.filter(webhook_deliveries::id.lt(continuous_token));
+    }
+
+    let result = deliveries_query
+      .order(webhook_deliveries::id.desc())
+      .limit(page_size + 1)
+      .load::<WebhookDelivery>(&mut conn);
+
+    let mut deliveries = match result {
+      Ok(deliveries) => deliveries,
+      Err(e) => {
+        return Err(admin_error(StatusCode::INTERNAL_SERVER_ERROR, &format!("Error fetching webhook deliveries: {}", e)));
+      }
+    };
+
+    let continuous_token = if deliveries.len() as i64 > page_size {
+      deliveries.truncate(page_size as usize);
+      deliveries.last().map(|delivery| delivery.id.clone())
+    } else {
+      None
+    };
+
+    Ok(Json(json!({
+      "status": "success",
+      "deliveries": deliveries.iter().map(filter_delivery).collect::<Vec<_>>(),
+      "continuousToken": continuous_token,
+    })))
+  }
+
+// Puts a message back in the outbox with a fresh set of attempts, whatever happened to it before
+pub async fn redeliver_webhook_message_handler(
+    Extension(jwtauth): Extension<JWTAuthMiddleware>,
+    State(data): State<Arc<AppState>>,
+    context: RequestContext,
+    Path((id, message_id)): Path<(String, String)>,
+  ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+    find_webhook(&mut conn, &id)?;
+
+    let timestamp = Utc::now().naive_utc();
+    let statement = diesel::update(webhook_outbox::table)
+      .filter(webhook_outbox::id.eq(message_id.clone()))
+      .filter(webhook_outbox::webhook_id.eq(id.clone()))
+      .set(&WebhookMessageResult {
+        status: STATUS_PENDING.into(),
+        attempts: 0,
+        next_attempt_at: timestamp,
+        last_error: None,
+        updated_at: Some(timestamp),
+      })
+      .execute(&mut conn);
+
+    match statement {
+      Ok(0) => Err(admin_error(StatusCode::NOT_FOUND, "Webhook message not found")),
+      Ok(_) => {
+        audit_webhook_action(&mut conn, &context, "admin.webhook.redeliver", &jwtauth, json!({ "webhookId": id, "messageId": message_id }));
+        Ok(Json(json!({ "status": "success" })))
+      }
+      Err(e) => Err(admin_error(StatusCode::BAD_REQUEST, &format!("Webhook message could not be redelivered: {}", e))),
+    }
+  }
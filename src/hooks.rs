@@ -0,0 +1,339 @@
+use std::time::Duration;
+
+use axum::{http::StatusCode, Json};
+use chrono::Utc;
+use serde::Deserialize;
+
+use crate::{config::Config, webhook::{sign, SIGNATURE_HEADER}};
+
+pub const HOOK_HEADER: &str = "X-Heimdall-Hook";
+
+// Claims the server sets itself, a hook cannot override them
+const RESERVED_CLAIMS: [&str; 18] = [
+  "sub", "iss", "aud", "exp", "nbf", "iat", "jti", "token_uuid", "auth_time", "amr", "idp",
+  "client_id", "scope", "principal", "roles", "permissions", "tenant_id", "act",
+];
+
+// The subject and the verified email decide which account an OAuth sign-in lands on, so the
+// after_oauth_profile hook cannot change them
+const PROTECTED_PROFILE_FIELDS: [&str; 5] = ["provider", "subject", "email", "emailVerified", "tenantId"];
+
+#[derive(Debug, Clone, Copy)]
+pub enum Hook {
+  BeforeRegister,
+  BeforeLogin,
+  AccessTokenClaims,
+  OAuthProfile,
+}
+
+impl Hook {
+  pub fn name(&self) -> &'static str {
+    match self {
+      Hook::BeforeRegister => "before_register",
+      Hook::BeforeLogin => "before_login",
+      Hook::AccessTokenClaims => "custom_access_token_claims",
+      Hook::OAuthProfile => "after_oauth_profile",
+    }
+  }
+
+  fn url<'a>(&self, env: &'a Config) -> Option<&'a str> {
+    match self {
+      Hook::BeforeRegister => env.hook_before_register_url.as_deref(),
+      Hook::BeforeLogin => env.hook_before_login_url.as_deref(),
+      Hook::AccessTokenClaims => env.hook_access_token_claims_url.as_deref(),
+      Hook::OAuthProfile => env.hook_oauth_profile_url.as_deref(),
+    }
+  }
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Decision {
+  Allow,
+  Deny,
+}
+
+// What a hook answers with. A decision other than "allow" or "deny" counts as a failed hook
+#[derive(Debug, Deserialize)]
+struct HookResponse {
+  decision: Decision,
+  message: Option<String>,
+  claims: Option<serde_json::Map<String, serde_json::Value>>,
+}
+
+fn hook_error(status: StatusCode, message: &str) -> (StatusCode, Json<serde_json::Value>) {
+  let error_response = serde_json::json!({
+    "status": "fail",
+    "message": message
+  });
+  (status, Json(error_response))
+}
+
+// Where and how a configured hook is called
+struct HookTarget<'a> {
+  url: &'a str,
+  secret: &'a str,
+  timeout_ms: u64,
+  fail_open: bool,
+}
+
+async fn call(target: &HookTarget<'_>, hook: Hook, data: serde_json::Value) -> Result<HookResponse, String> {
+  let body = serde_json::json!({
+    "hook": hook.name(),
+    "createdAt": Utc::now().naive_utc(),
+    "data": data,
+  }).to_string();
+  let signature = sign(target.secret, Utc::now().timestamp(), &body);
+
+  let client = reqwest::Client::builder()
+    .user_agent("Heimdall-Hooks/1.0")
+    .timeout(Duration::from_millis(target.timeout_ms))
+    .build()
+    .map_err(|e| e.to_string())?;
+
+  let response = client
+    .post(target.url)
+    .header(reqwest::header::CONTENT_TYPE, "application/json")
+    .header(SIGNATURE_HEADER, signature)
+    .header(HOOK_HEADER, hook.name())
+    .body(body)
+    .send()
+    .await
+    .map_err(|e| e.to_string())?;
+
+  if !response.status().is_success() {
+    return Err(format!("Hook responded with {}", response.status()));
+  }
+
+  response.json::<HookResponse>().await.map_err(|e| e.to_string())
+}
+
+// Calls the hook when it is configured. Ok carries any claims it returned, a denial becomes a 403
+// with the hook's message. An unreachable hook fails closed unless AUTH_HOOK_FAIL_OPEN is set
+pub async fn run(
+  env: &Config,
+  hook: Hook,
+  data: serde_json::Value,
+) -> Result<serde_json::Map<String, serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
+  let Some(url) = hook.url(env) else {
+    return Ok(serde_json::Map::new());
+  };
+
+  let target = HookTarget {
+    url,
+    secret: &env.hook_secret,
+    timeout_ms: env.hook_timeout_ms,
+    fail_open: env.hook_fail_open,
+  };
+  run_at(&target, hook, data).await
+}
+
+async fn run_at(
+  target: &HookTarget<'_>,
+  hook: Hook,
+  data: serde_json::Value,
+) -> Result<serde_json::Map<String, serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
+  match call(target, hook, data).await {
+    Ok(response) if response.decision == Decision::Deny => {
+      let message = response.message.unwrap_or_else(|| "Request was denied".into());
+      Err(hook_error(StatusCode::FORBIDDEN, &message))
+    }
+    Ok(response) => Ok(response.claims.unwrap_or_default()),
+    Err(e) => {
+      tracing::error!("Auth hook {} failed: {}", hook.name(), e);
+      if target.fail_open {
+        Ok(serde_json::Map::new())
+      } else {
+        Err(hook_error(StatusCode::SERVICE_UNAVAILABLE, "Authentication hook is unavailable"))
+      }
+    }
+  }
+}
+
+// Extra access token claims from the custom-access-token-claims hook. Token claims are strings, so
+// other JSON values are kept as their JSON text
+pub async fn access_token_claims(
+  env: &Config,
+  user_id: &str,
+  claims: &[(&str, String)],
+) -> Result<Vec<(String, String)>, (StatusCode, Json<serde_json::Value>)> {
+  if env.hook_access_token_claims_url.is_none() {
+    return Ok(vec![]);
+  }
+
+  let current = claims
+    .iter()
+    .map(|(name, value)| (name.to_string(), serde_json::Value::String(value.clone())))
+    .collect::<serde_json::Map<_, _>>();
+
+  let extra = run(env, Hook::AccessTokenClaims, serde_json::json!({
+    "userId": user_id,
+    "claims": current,
+  })).await?;
+
+  Ok(token_claims(extra))
+}
+
+fn without_reserved(values: serde_json::Map<String, serde_json::Value>) -> serde_json::Map<String, serde_json::Value> {
+  values
+    .into_iter()
+    .filter(|(name, _)| !RESERVED_CLAIMS.contains(&name.as_str()))
+    .collect()
+}
+
+fn token_claims(extra: serde_json::Map<String, serde_json::Value>) -> Vec<(String, String)> {
+  without_reserved(extra)
+    .into_iter()
+    .map(|(name, value)| match value {
+      serde_json::Value::String(value) => (name, value),
+      value => (name, value.to_string()),
+    })
+    .collect()
+}
+
+// Changes the after_oauth_profile hook answers with, as `claims` like the token claims hook. A
+// `name` replaces the provider's, anything else is added to the stored profile
+pub async fn oauth_profile(
+  env: &Config,
+  profile: serde_json::Value,
+) -> Result<serde_json::Map<String, serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
+  let changes = run(env, Hook::OAuthProfile, profile).await?;
+  Ok(profile_changes(changes))
+}
+
+fn profile_changes(changes: serde_json::Map<String, serde_json::Value>) -> serde_json::Map<String, serde_json::Value> {
+  without_reserved(changes)
+    .into_iter()
+    .filter(|(name, _)| !PROTECTED_PROFILE_FIELDS.contains(&name.as_str()))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::{Arc, Mutex};
+
+  use axum::{http::{HeaderMap, StatusCode}, routing::post, Json, Router};
+  use serde_json::json;
+
+  use super::*;
+  use crate::test_server::serve;
+
+  const SECRET: &str = "hook-secret";
+
+  fn target(url: &str, fail_open: bool) -> HookTarget<'_> {
+    HookTarget { url, secret: SECRET, timeout_ms: 500, fail_open }
+  }
+
+  // A stand-in hook that always gives the same answer
+  async fn hook_answering(status: StatusCode, answer: serde_json::Value) -> String {
+    let router = Router::new().route("/hook", post(move || async move { (status, Json(answer)) }));
+    format!("{}/hook", serve(router).await)
+  }
+
+  #[tokio::test]
+  async fn allow_returns_claims() {
+    let url = hook_answering(StatusCode::OK, json!({ "decision": "allow", "claims": { "plan": "pro" } })).await;
+    let claims = run_at(&target(&url, false), Hook::AccessTokenClaims, json!({})).await.unwrap();
+
+    assert_eq!(claims.get("plan"), Some(&json!("pro")));
+  }
+
+  #[tokio::test]
+  async fn deny_is_forbidden_with_the_hook_message() {
+    let url = hook_answering(StatusCode::OK, json!({ "decision": "deny", "message": "Domain is not allowed" })).await;
+    let (status, Json(error)) = run_at(&target(&url, false), Hook::BeforeRegister, json!({})).await.unwrap_err();
+
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(error["message"], "Domain is not allowed");
+  }
+
+  #[tokio::test]
+  async fn failing_hook_fails_closed() {
+    let url = hook_answering(StatusCode::INTERNAL_SERVER_ERROR, json!({})).await;
+    let (status, _) = run_at(&target(&url, false), Hook::BeforeLogin, json!({})).await.unwrap_err();
+
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+  }
+
+  #[tokio::test]
+  async fn slow_hook_times_out_and_fails_closed() {
+    let router = Router::new().route("/hook", post(|| async {
+      tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+      Json(json!({ "decision": "allow" }))
+    }));
+    let url = format!("{}/hook", serve(router).await);
+    let (status, _) = run_at(&target(&url, false), Hook::BeforeLogin, json!({})).await.unwrap_err();
+
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+  }
+
+  #[tokio::test]
+  async fn unknown_decision_fails_closed() {
+    let url = hook_answering(StatusCode::OK, json!({ "decision": "Deny", "claims": { "plan": "pro" } })).await;
+    let (status, _) = run_at(&target(&url, false), Hook::BeforeLogin, json!({})).await.unwrap_err();
+
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+  }
+
+  #[tokio::test]
+  async fn failing_hook_lets_requests_through_when_fail_open() {
+    let url = hook_answering(StatusCode::INTERNAL_SERVER_ERROR, json!({})).await;
+    let claims = run_at(&target(&url, true), Hook::BeforeLogin, json!({})).await.unwrap();
+
+    assert!(claims.is_empty());
+  }
+
+  #[tokio::test]
+  async fn reserved_claims_are_stripped_from_token_claims() {
+    let url = hook_answering(StatusCode::OK, json!({
+      "decision": "allow",
+      "claims": { "sub": "someone-else", "roles": "admin", "tenant_id": "other", "idp": "google", "plan": "pro", "seats": 3 }
+    })).await;
+    let extra = run_at(&target(&url, false), Hook::AccessTokenClaims, json!({})).await.unwrap();
+
+    let mut claims = token_claims(extra);
+    claims.sort();
+    assert_eq!(claims, vec![("plan".to_string(), "pro".to_string()), ("seats".to_string(), "3".to_string())]);
+  }
+
+  #[tokio::test]
+  async fn profile_changes_keep_identity_fields() {
+    let url = hook_answering(StatusCode::OK, json!({
+      "decision": "allow",
+      "claims": { "name": "Ada Lovelace", "email": "someone@else.com", "emailVerified": true, "subject": "1", "sub": "1", "company": "Acme" }
+    })).await;
+    let changes = profile_changes(run_at(&target(&url, false), Hook::OAuthProfile, json!({})).await.unwrap());
+
+    assert_eq!(changes.len(), 2);
+    assert_eq!(changes.get("name"), Some(&json!("Ada Lovelace")));
+    assert_eq!(changes.get("company"), Some(&json!("Acme")));
+  }
+
+  #[tokio::test]
+  async fn payload_is_signed() {
+    let received = Arc::new(Mutex::new(None));
+    let router = Router::new().route("/hook", post({
+      let received = received.clone();
+      move |headers: HeaderMap, body: String| async move {
+        *received.lock().unwrap() = Some((headers, body));
+        Json(json!({ "decision": "allow" }))
+      }
+    }));
+    let url = format!("{}/hook", serve(router).await);
+    run_at(&target(&url, false), Hook::BeforeLogin, json!({ "userId": "user-1" })).await.unwrap();
+
+    let (headers, body) = received.lock().unwrap().take().unwrap();
+    let signature = headers.get(SIGNATURE_HEADER).unwrap().to_str().unwrap();
+    let timestamp = signature
+      .strip_prefix("t=")
+      .and_then(|rest| rest.split(',').next())
+      .and_then(|timestamp| timestamp.parse::<i64>().ok())
+      .unwrap();
+    assert_eq!(signature, sign(SECRET, timestamp, &body));
+    assert_eq!(headers.get(HOOK_HEADER).unwrap(), "before_login");
+
+    let payload: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(payload["hook"], "before_login");
+    assert_eq!(payload["data"]["userId"], "user-1");
+  }
+}
@@ -9,10 +9,14 @@ use axum::{
 };
 
 use axum_extra::extract::cookie::CookieJar;
-use diesel::{query_dsl::methods::FilterDsl, ExpressionMethods, OptionalExtension, RunQueryDsl};
+use diesel::{query_dsl::methods::FilterDsl, ExpressionMethods, OptionalExtension, PgConnection, RunQueryDsl};
 use serde::Serialize;
 
-use crate::{schema::{user, User}, token, AppState};
+use crate::{
+  api_key::{find_active_key, is_api_key, record_usage}, audit::{self, AuditRecord, RequestContext, OUTCOME_SUCCESS},
+  oauth_server::client::find_client, rbac,
+  schema::{user, RegisteredClient, User}, tenant, token, utils::client_ip, AppState
+};
 
 #[derive(Debug, Serialize)]
 pub struct ErrorResponse {
@@ -20,19 +24,155 @@ pub struct ErrorResponse {
   pub message: String,
 }
 
+// Who a token speaks for: a person, or a service client using the client_credentials grant
+#[derive(Clone)]
+pub enum Principal {
+  User(User),
+  Client(RegisteredClient),
+}
+
 #[derive(Clone)]
 pub struct JWTAuthMiddleware {
-  pub user: User,
-  #[allow(dead_code)]
+  pub principal: Principal,
   pub access_token_uuid: uuid::Uuid,
+  pub auth_time: Option<i64>,
+  pub amr: Option<String>,
+  pub idp: Option<String>,
+  // Set when the token was issued to an OAuth client rather than to the user's own session
+  pub client_id: Option<String>,
+  pub scope: Option<String>,
+  // Set when the request was made with a personal API key instead of a session
+  pub api_key_id: Option<String>,
+  pub roles: Vec<String>,
+  pub permissions: Vec<String>,
+  // The active tenant and the user's role in it, membership is checked on every request
+  pub tenant_id: Option<String>,
+  pub tenant_role: Option<String>,
+  // The admin acting as this user, impersonation sessions cannot mint credentials
+  pub impersonator_id: Option<String>,
 }
 
+impl JWTAuthMiddleware {
+  // For routes that only make sense for a person
+  pub fn user(&self) -> Result<&User, (StatusCode, Json<serde_json::Value>)> {
+    match &self.principal {
+      Principal::User(user) => Ok(user),
+      Principal::Client(_) => {
+        let error_response = serde_json::json!({
+          "status": "fail",
+          "message": "This endpoint requires a user",
+        });
+        Err((StatusCode::FORBIDDEN, Json(error_response)))
+      }
+    }
+  }
+
+  // For routes that grant new credentials, where a leaked API key must not be enough
+  pub fn session_user(&self) -> Result<&User, (StatusCode, Json<serde_json::Value>)> {
+    if self.api_key_id.is_some() {
+      let error_response = serde_json::json!({
+        "status": "fail",
+        "message": "This endpoint requires a signed in session",
+      });
+      return Err((StatusCode::FORBIDDEN, Json(error_response)));
+    }
+
+    if self.impersonator_id.is_some() {
+      let error_response = serde_json::json!({
+        "status": "fail",
+        "message": "This endpoint is not available while impersonating",
+      });
+      return Err((StatusCode::FORBIDDEN, Json(error_response)));
+    }
+
+    self.user()
+  }
+
+  // Who to record in the audit log for this request
+  pub fn actor_id(&self) -> &str {
+    match &self.principal {
+      Principal::User(user) => &user.id,
+      Principal::Client(client) => &client.id,
+    }
+  }
+
+  pub fn has_permission(&self, permission: &str) -> bool {
+    self.permissions.iter().any(|granted| granted == permission)
+  }
+}
+
+// First-party routes, tokens a user delegated to an OAuth client are refused
 pub async fn auth(
   cookie_jar: CookieJar,
   State(data): State<Arc<AppState>>,
   mut req: Request<Body>,
   next: Next,
 ) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+  let auth = authenticate(&cookie_jar, &data, &req)?;
+
+  if matches!(auth.principal, Principal::User(_)) && auth.client_id.is_some() {
+    let error_response = ErrorResponse {
+      status: "fail",
+      message: "Token was issued to an OAuth client".to_string(),
+    };
+    return Err((StatusCode::FORBIDDEN, Json(error_response)));
+  }
+
+  // Everything done while impersonating is attributed to the admin behind it
+  if let (Some(impersonator_id), Principal::User(user)) = (&auth.impersonator_id, &auth.principal) {
+    let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+    audit::record(&mut conn, &RequestContext::new(req.headers(), req.extensions()), AuditRecord {
+      event_type: "impersonation.request",
+      actor_id: Some(impersonator_id),
+      subject_id: Some(&user.id),
+      outcome: OUTCOME_SUCCESS,
+      metadata: serde_json::json!({ "method": req.method().as_str(), "path": req.uri().path() }),
+    });
+  }
+
+  req.extensions_mut().insert(auth);
+  Ok(next.run(req).await)
+}
+
+// Routes meant for OAuth clients, such as /userinfo
+pub async fn oauth_auth(
+  cookie_jar: CookieJar,
+  State(data): State<Arc<AppState>>,
+  mut req: Request<Body>,
+  next: Next,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+  let auth = authenticate(&cookie_jar, &data, &req)?;
+  req.extensions_mut().insert(auth);
+  Ok(next.run(req).await)
+}
+
+// Route layer placed inside `auth`, e.g. from_fn_with_state("roles:read", require_permission)
+pub async fn require_permission(
+  State(permission): State<&'static str>,
+  req: Request<Body>,
+  next: Next,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+  let allowed = req.extensions()
+    .get::<JWTAuthMiddleware>()
+    .map(|auth| auth.has_permission(permission))
+    .unwrap_or(false);
+
+  if !allowed {
+    let error_response = ErrorResponse {
+      status: "fail",
+      message: format!("Missing permission: {}", permission),
+    };
+    return Err((StatusCode::FORBIDDEN, Json(error_response)));
+  }
+
+  Ok(next.run(req).await)
+}
+
+fn authenticate(
+  cookie_jar: &CookieJar,
+  data: &AppState,
+  req: &Request<Body>,
+) -> Result<JWTAuthMiddleware, (StatusCode, Json<ErrorResponse>)> {
   let access_token = cookie_jar
     .get("access_token")
     .map(|cookie| cookie.value().to_string())
@@ -57,6 +197,12 @@ pub async fn auth(
     (StatusCode::UNAUTHORIZED, Json(error_response))
   })?;  
 
+  let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
+  if is_api_key(&access_token) {
+    return authenticate_api_key(&mut conn, &access_token, req);
+  }
+
   let access_token_details =
     match token::verify_paseto_token(&data.env.auth_key, &access_token) {
       Ok(token_details) => token_details,
@@ -78,37 +224,134 @@ pub async fn auth(
       (StatusCode::UNAUTHORIZED, Json(error_response))
     })?;
 
-  let user_id = access_token_details.user_id.to_string();
+  // Service tokens carry the client as their subject
+  let principal = if access_token_details.principal.as_deref() == Some("client") {
+    match find_client(&mut conn, &access_token_details.user_id) {
+      Some(client) => Principal::Client(client),
+      None => {
+        let error_response = ErrorResponse {
+          status: "fail",
+          message: "Client not found".to_string(),
+        };
+        return Err((StatusCode::UNAUTHORIZED, Json(error_response)));
+      }
+    }
+  } else {
+    let user_id = access_token_details.user_id.to_string();
 
-  let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
-  
+    // Session tokens stop working as soon as they are logged out or revoked
+    if !token::is_token_active(&mut conn, &access_token_uuid) {
+      let error_response = ErrorResponse {
+        status: "fail",
+        message: "Token has been revoked".to_string(),
+      };
+      return Err((StatusCode::UNAUTHORIZED, Json(error_response)));
+    }
+
+    Principal::User(find_user(&mut conn, &user_id)?)
+  };
+
+  let tenant_role = match (&principal, access_token_details.tenant_id.as_deref()) {
+    (Principal::User(user), Some(tenant_id)) => match tenant::find_membership(&mut conn, tenant_id, &user.id) {
+      Some(membership) => Some(membership.role),
+      None => {
+        let error_response = ErrorResponse {
+          status: "fail",
+          message: "You are not a member of this tenant".to_string(),
+        };
+        return Err((StatusCode::FORBIDDEN, Json(error_response)));
+      }
+    },
+    _ => None,
+  };
+
+  Ok(JWTAuthMiddleware {
+    principal,
+    access_token_uuid,
+    auth_time: access_token_details.auth_time,
+    amr: access_token_details.amr,
+    idp: access_token_details.idp,
+    client_id: access_token_details.client_id,
+    scope: access_token_details.scope,
+    api_key_id: None,
+    roles: rbac::split_claim(access_token_details.roles.as_deref()),
+    permissions: rbac::split_claim(access_token_details.permissions.as_deref()),
+    tenant_id: access_token_details.tenant_id,
+    tenant_role,
+    impersonator_id: access_token_details.act,
+  })
+}
+
+fn find_user(conn: &mut PgConnection, user_id: &str) -> Result<User, (StatusCode, Json<ErrorResponse>)> {
   let user_result = user::table
     .filter(user::id.eq(user_id.to_string()))
-    .first::<User>(&mut conn)
+    .first::<User>(conn)
     .optional();
 
-  let user = match user_result {
-    Ok(Some(user)) => user,
+  match user_result {
+    Ok(Some(user)) if token::is_user_active(&user) => Ok(user),
+    Ok(Some(_)) => {
+      let error_response = ErrorResponse {
+        status: "fail",
+        message: "This account is disabled".to_string(),
+      };
+      Err((StatusCode::FORBIDDEN, Json(error_response)))
+    }
     Ok(None) => {
       let error_response = ErrorResponse {
         status: "fail",
         message: "User not found".to_string(),
       };
-      return Err((StatusCode::NOT_FOUND, Json(error_response)));
+      Err((StatusCode::NOT_FOUND, Json(error_response)))
     }
     Err(_) => {
       let error_response = ErrorResponse {
         status: "fail",
         message: "Error fetching user from database".to_string(),
       };
-      return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(error_response)));
+      Err((StatusCode::INTERNAL_SERVER_ERROR, Json(error_response)))
     }
-  };
+  }
+}
 
-  req.extensions_mut().insert(JWTAuthMiddleware {
-    user,
-    access_token_uuid,
-  });
+// Personal API keys act as their owner, limited to the key's scopes
+fn authenticate_api_key(
+  conn: &mut PgConnection,
+  key: &str,
+  req: &Request<Body>,
+) -> Result<JWTAuthMiddleware, (StatusCode, Json<ErrorResponse>)> {
+  let api_key = find_active_key(conn, key).ok_or_else(|| {
+    let error_response = ErrorResponse {
+      status: "fail",
+      message: "API key is invalid, expired or revoked".to_string(),
+    };
+    (StatusCode::UNAUTHORIZED, Json(error_response))
+  })?;
 
-  Ok(next.run(req).await)
+  let user = find_user(conn, &api_key.user_id)?;
+  record_usage(conn, &api_key.id, client_ip(req));
+
+  // A key only keeps the permissions it was scoped to, a key without scopes has none
+  let access = rbac::user_access(conn, &user.id);
+  let key_scopes = rbac::split_claim(Some(&api_key.scopes));
+  let permissions = access.permissions
+    .into_iter()
+    .filter(|permission| key_scopes.contains(permission))
+    .collect();
+
+  Ok(JWTAuthMiddleware {
+    principal: Principal::User(user),
+    access_token_uuid: uuid::Uuid::nil(),
+    auth_time: None,
+    amr: None,
+    idp: None,
+    client_id: None,
+    scope: Some(api_key.scopes),
+    api_key_id: Some(api_key.id),
+    roles: access.roles,
+    permissions,
+    tenant_id: None,
+    tenant_role: None,
+    impersonator_id: None,
+  })
 }
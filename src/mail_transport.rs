@@ -0,0 +1,233 @@
+use std::{path::PathBuf, sync::{Arc, Mutex}};
+
+use async_trait::async_trait;
+use mail_send::{mail_builder::MessageBuilder, Credentials, SmtpClient, SmtpClientBuilder};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::{config::Config, schema::OutboxEmail};
+
+// Sends one rendered email from the outbox, the error is kept as the email's last_error
+#[async_trait]
+pub trait MailTransport: Send + Sync {
+  async fn send(&self, email: &OutboxEmail) -> Result<(), String>;
+}
+
+fn build_message(email: &OutboxEmail) -> MessageBuilder<'_> {
+  MessageBuilder::new()
+    .from((email.from_name.as_str(), email.from_address.as_str()))
+    .to(vec![email.to_address.as_str()])
+    .subject(email.subject.as_str())
+    .html_body(email.html_body.as_str())
+    .text_body(email.text_body.as_str())
+}
+
+// Plain and TLS connections share the pool behind one stream type
+trait SmtpStream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> SmtpStream for T {}
+
+type PooledClient = SmtpClient<Box<dyn SmtpStream>>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Security {
+  None,
+  StartTls,
+  Tls,
+}
+
+pub struct SmtpTransport {
+  builder: SmtpClientBuilder<String>,
+  security: Security,
+  pool_size: usize,
+  idle: tokio::sync::Mutex<Vec<PooledClient>>,
+}
+
+impl SmtpTransport {
+  pub fn new(env: &Config) -> Self {
+    let security = match env.mailer_security.as_str() {
+      "tls" => Security::Tls,
+      "starttls" => Security::StartTls,
+      _ => Security::None,
+    };
+
+    let mut builder = SmtpClientBuilder::new(env.mailer_server.clone(), env.mailer_port)
+      .implicit_tls(security == Security::Tls);
+    // mail-send picks the strongest mechanism both sides support, LOGIN or PLAIN on most relays
+    if let (Some(username), Some(password)) = (&env.mailer_username, &env.mailer_password) {
+      builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+    }
+
+    SmtpTransport {
+      builder,
+      security,
+      pool_size: env.mailer_pool_size,
+      idle: tokio::sync::Mutex::new(vec![]),
+    }
+  }
+
+  async fn connect(&self) -> Result<PooledClient, String> {
+    let client = match self.security {
+      Security::None => {
+        let client = self.builder.connect_plain().await.map_err(|e| e.to_string())?;
+        SmtpClient { stream: Box::new(client.stream) as Box<dyn SmtpStream>, timeout: client.timeout }
+      }
+      Security::StartTls | Security::Tls => {
+        let client = self.builder.connect().await.map_err(|e| e.to_string())?;
+        SmtpClient { stream: Box::new(client.stream) as Box<dyn SmtpStream>, timeout: client.timeout }
+      }
+    };
+    Ok(client)
+  }
+
+  // Reuses an idle connection when the server still answers, otherwise opens a new one
+  async fn checkout(&self) -> Result<PooledClient, String> {
+    loop {
+      let client = self.idle.lock().await.pop();
+      match client {
+        Some(mut client) => {
+          if client.noop().await.is_ok() {
+            return Ok(client);
+          }
+        }
+        None => return self.connect().await,
+      }
+    }
+  }
+}
+
+#[async_trait]
+impl MailTransport for SmtpTransport {
+  async fn send(&self, email: &OutboxEmail) -> Result<(), String> {
+    let mut client = self.checkout().await?;
+    client.send(build_message(email)).await.map_err(|e| e.to_string())?;
+
+    // A failed connection is dropped above, only healthy ones go back to the pool
+    let mut idle = self.idle.lock().await;
+    if idle.len() < self.pool_size {
+      idle.push(client);
+    }
+    Ok(())
+  }
+}
+
+// Writes each email into a maildir, any mail client can open it during development
+pub struct FileTransport {
+  dir: PathBuf,
+}
+
+impl FileTransport {
+  pub fn new(dir: &str) -> Self {
+    FileTransport { dir: PathBuf::from(dir) }
+  }
+}
+
+#[async_trait]
+impl MailTransport for FileTransport {
+  async fn send(&self, email: &OutboxEmail) -> Result<(), String> {
+    let message = build_message(email).write_to_vec().map_err(|e| e.to_string())?;
+
+    for folder in ["tmp", "new", "cur"] {
+      tokio::fs::create_dir_all(self.dir.join(folder)).await.map_err(|e| e.to_string())?;
+    }
+
+    // Maildir readers only look in new/, so the file appears there complete
+    let name = format!("{}.eml", email.id);
+    let tmp = self.dir.join("tmp").join(&name);
+    tokio::fs::write(&tmp, message).await.map_err(|e| e.to_string())?;
+    tokio::fs::rename(&tmp, self.dir.join("new").join(&name)).await.map_err(|e| e.to_string())
+  }
+}
+
+// Prints emails to the server log instead of sending them
+pub struct LogTransport;
+
+#[async_trait]
+impl MailTransport for LogTransport {
+  async fn send(&self, email: &OutboxEmail) -> Result<(), String> {
+    tracing::info!(
+      "Email {} ({}) to {}: {}\n{}",
+      email.id, email.template, email.to_address, email.subject, email.text_body
+    );
+    Ok(())
+  }
+}
+
+// Keeps sent emails in memory so integration tests can assert on them
+#[derive(Clone, Default)]
+pub struct MemoryTransport {
+  sent: Arc<Mutex<Vec<OutboxEmail>>>,
+}
+
+impl MemoryTransport {
+  #[cfg(test)]
+  pub fn sent(&self) -> Vec<OutboxEmail> {
+    self.sent.lock().unwrap().clone()
+  }
+}
+
+#[async_trait]
+impl MailTransport for MemoryTransport {
+  async fn send(&self, email: &OutboxEmail) -> Result<(), String> {
+    self.sent.lock().unwrap().push(email.clone());
+    Ok(())
+  }
+}
+
+// MAIL_TRANSPORT and the SMTP credentials are validated when the config loads
+pub fn from_config(env: &Config) -> Arc<dyn MailTransport> {
+  match env.mailer_transport.as_str() {
+    "file" => Arc::new(FileTransport::new(&env.mailer_file_dir)),
+    "log" => Arc::new(LogTransport),
+    "memory" => Arc::new(MemoryTransport::default()),
+    _ => Arc::new(SmtpTransport::new(env)),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn email(id: &str) -> OutboxEmail {
+    let now = chrono::Utc::now().naive_utc();
+    OutboxEmail {
+      id: id.to_string(),
+      template: "magic_link".to_string(),
+      from_address: "noreply@example.com".to_string(),
+      from_name: "Example".to_string(),
+      to_address: "person@example.com".to_string(),
+      subject: "Sign in".to_string(),
+      html_body: "<p>Sign in</p>".to_string(),
+      text_body: "Sign in".to_string(),
+      tenant_id: None,
+      status: "pending".to_string(),
+      attempts: 0,
+      next_attempt_at: now,
+      last_error: None,
+      sent_at: None,
+      created_at: now,
+      updated_at: None,
+    }
+  }
+
+  #[tokio::test]
+  async fn memory_transport_keeps_sent_emails() {
+    let transport = MemoryTransport::default();
+    let shared = transport.clone();
+    transport.send(&email("first")).await.unwrap();
+    transport.send(&email("second")).await.unwrap();
+
+    let ids: Vec<String> = shared.sent().into_iter().map(|email| email.id).collect();
+    assert_eq!(ids, vec!["first", "second"]);
+  }
+
+  #[tokio::test]
+  async fn file_transport_writes_into_new() {
+    let dir = std::env::temp_dir().join(format!("mail-{}", ulid::Ulid::new()));
+    let transport = FileTransport::new(dir.to_str().unwrap());
+    transport.send(&email("message")).await.unwrap();
+
+    let written = std::fs::read_to_string(dir.join("new").join("message.eml")).unwrap();
+    assert!(written.contains("Subject: Sign in"));
+    assert!(std::fs::read_dir(dir.join("tmp")).unwrap().next().is_none());
+    std::fs::remove_dir_all(dir).unwrap();
+  }
+}
@@ -1,27 +1,45 @@
+mod api_key;
+mod audit;
 mod config;
+mod email_otp;
+mod email_outbox;
 mod social_handlers;
 mod handlers;
+mod hooks;
 mod jwt_auth;
+mod mail_transport;
 mod model;
+mod oauth_server;
+mod permissions;
+mod rbac;
+mod redirect;
 mod response;
 mod route;
 mod token;
 mod schema;
+mod security_notice;
 mod smtp;
 mod template;
+mod tenant;
+#[cfg(test)]
+mod test_server;
 mod utils;
+mod webhook;
 
 use config::Config;
 use diesel::r2d2::{self, ConnectionManager};
 use diesel::PgConnection;
-use std::sync::Arc;
+use std::{net::SocketAddr, path::PathBuf, sync::Arc};
+use axum::Extension;
 use axum::http::{
     header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE, 
       ORIGIN, USER_AGENT, ACCESS_CONTROL_REQUEST_HEADERS,
-      ACCESS_CONTROL_REQUEST_METHOD}, HeaderValue, Method
+      ACCESS_CONTROL_REQUEST_METHOD}, HeaderName, HeaderValue, Method
 };
 use dotenv::dotenv;
 use route::create_router;
+use utils::TrustedProxies;
+use template::TemplateEngine;
 use tower_http::cors::CorsLayer;
 use rcgen::{generate_simple_self_signed, CertifiedKey};
 use axum_server::tls_rustls::RustlsConfig;
@@ -33,6 +51,7 @@ type DbPool = r2d2::Pool<ConnectionManager<PgConnection>>;
 pub struct AppState {
   db_pool: DbPool,
   env: Config,
+  templates: Arc<TemplateEngine>,
 }
 
 #[tokio::main]
@@ -60,7 +79,7 @@ async fn main() {
 
   let cors = CorsLayer::new()
     .allow_origin(config.clone().client_origin.parse::<HeaderValue>().unwrap()) // Explicit frontend origin
-    .allow_methods([Method::GET, Method::POST, Method::PATCH, Method::DELETE, Method::OPTIONS]) // Add OPTIONS
+    .allow_methods([Method::GET, Method::POST, Method::PUT, Method::PATCH, Method::DELETE, Method::OPTIONS]) // Add OPTIONS
     .allow_credentials(true)
     .allow_headers([
       AUTHORIZATION, 
@@ -70,6 +89,7 @@ async fn main() {
       USER_AGENT,
       ACCESS_CONTROL_REQUEST_HEADERS,
       ACCESS_CONTROL_REQUEST_METHOD,
+      HeaderName::from_static("x-tenant-id"),
     ]);
 
   let manager = ConnectionManager::<PgConnection>::new(&config.database_url);
@@ -77,10 +97,23 @@ async fn main() {
     .build(manager)
     .expect("Failed to create pool.");
 
+  audit::spawn_retention_task(pool.clone(), config.audit_retention_days);
+  webhook::spawn_dispatcher(pool.clone(), config.webhook_max_attempts);
+  email_outbox::spawn_worker(pool.clone(), &config, mail_transport::from_config(&config));
+
+  let templates = Arc::new(
+    TemplateEngine::new(config.email_template_dir.clone().map(PathBuf::from)).expect("Failed to load email templates")
+  );
+  if config.email_template_watch {
+    templates.clone().spawn_watcher();
+  }
+
   let app = create_router(Arc::new(AppState {
     db_pool: pool,
     env: config.clone(),
+    templates,
   }))
+  .layer(Extension(TrustedProxies(config.trusted_proxies.clone())))
   .layer(cors);
 
   // Create the app router for HTTPS
@@ -103,14 +136,14 @@ async fn main() {
         .unwrap();
 
       axum_server::bind_rustls("0.0.0.0:9179".parse().unwrap(), config)
-        .serve(https_app.into_make_service())
+        .serve(https_app.into_make_service_with_connect_info::<SocketAddr>())
         .await
         .unwrap();
     },
     // HTTP Server
     async {
       let listener = tokio::net::TcpListener::bind("0.0.0.0:9178").await.unwrap();
-      axum::serve(listener, app).await.unwrap();
+      axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await.unwrap();
     }
   );
 }
\ No newline at end of file
@@ -21,12 +21,19 @@ pub struct RegisterUserSchema {
   pub name: String,
   pub email: String,
   pub password: String,
+  // Otherwise resolved from the X-Tenant-Id header or the request host
+  #[serde(rename = "tenantId")]
+  pub tenant_id: Option<String>,
+  // Defaults to the first Accept-Language entry
+  pub locale: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct LoginUserSchema {
   pub email: String,
   pub password: String,
+  #[serde(rename = "tenantId")]
+  pub tenant_id: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -34,6 +41,8 @@ pub struct ForgotPasswordSchema {
   pub email: String,
   #[serde(rename = "redirectTo")]
   pub redirect_to: String,
+  #[serde(rename = "tenantId")]
+  pub tenant_id: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -57,6 +66,11 @@ pub struct MagicLinkSchema {
   pub email: String,  
   #[serde(rename = "redirectTo")]
   pub redirect_to: String,
+  #[serde(rename = "tenantId")]
+  pub tenant_id: Option<String>,
+  // Also email a numeric code, for clients where the link opens in another browser
+  #[serde(rename = "sendCode", default)]
+  pub send_code: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -65,11 +79,29 @@ pub struct VerifyMagicLinkSchema {
   pub redirect_to: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct MagicLinkLookupSchema {
+  pub code: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MagicLinkApprovalSchema {
+  pub code: String,
+  pub approved: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyMagicLinkOtpSchema {
+  pub email: String,
+  pub code: String,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct OAuthSchema {
   pub provider: String,
   pub scopes: String,
   pub callback_url: String,
+  pub tenant_id: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -78,3 +110,168 @@ pub struct PkceChallenge {
   pub method: String,
 }
 
+
+#[derive(Debug, Deserialize)]
+pub struct CollectEmailSchema {
+  pub email: String,
+  #[serde(rename = "redirectTo")]
+  pub redirect_to: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyEmailSchema {
+  pub code: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NotMeSchema {
+  pub code: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateApiKeySchema {
+  pub name: String,
+  pub scopes: Option<String>,
+  #[serde(rename = "expiresAt")]
+  pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AssignRoleSchema {
+  #[serde(rename = "roleId")]
+  pub role_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateTenantSchema {
+  pub id: Option<String>,
+  pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListTenantsSchema {
+  pub page_size: Option<i64>,
+  pub continuous_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SwitchTenantSchema {
+  #[serde(rename = "tenantId")]
+  pub tenant_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TenantMemberSchema {
+  #[serde(rename = "userId")]
+  pub user_id: String,
+  pub role: String,
+}
+
+// Every field left out falls back to the global config
+#[derive(Debug, Deserialize)]
+pub struct TenantSettingsSchema {
+  pub host: Option<String>,
+  #[serde(rename = "enabledProviders")]
+  pub enabled_providers: Option<Vec<String>>,
+  #[serde(rename = "passwordMinLength")]
+  pub password_min_length: Option<i32>,
+  #[serde(rename = "passwordRequireDigit")]
+  pub password_require_digit: Option<bool>,
+  #[serde(rename = "passwordRequireSymbol")]
+  pub password_require_symbol: Option<bool>,
+  #[serde(rename = "mfaRequired")]
+  pub mfa_required: Option<bool>,
+  #[serde(rename = "accessTokenMaxAge")]
+  pub access_token_max_age: Option<i64>,
+  #[serde(rename = "refreshTokenMaxAge")]
+  pub refresh_token_max_age: Option<i64>,
+  #[serde(rename = "mailerFrom")]
+  pub mailer_from: Option<String>,
+  #[serde(rename = "mailerFromName")]
+  pub mailer_from_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AdminUserSearchSchema {
+  // Partial, case-insensitive matches
+  pub email: Option<String>,
+  pub name: Option<String>,
+  pub verified: Option<bool>,
+  pub created_after: Option<DateTime<Utc>>,
+  pub created_before: Option<DateTime<Utc>>,
+  pub page_size: Option<i64>,
+  pub continuous_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AdminUpdateUserSchema {
+  pub name: Option<String>,
+  pub email: Option<String>,
+  pub verified: Option<bool>,
+  pub locale: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AdminPasswordResetSchema {
+  #[serde(rename = "redirectTo")]
+  pub redirect_to: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditEventSearchSchema {
+  pub actor_id: Option<String>,
+  pub subject_id: Option<String>,
+  pub event_type: Option<String>,
+  pub outcome: Option<String>,
+  pub since: Option<DateTime<Utc>>,
+  pub until: Option<DateTime<Utc>>,
+  pub page_size: Option<i64>,
+  pub continuous_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ActivitySchema {
+  pub page_size: Option<i64>,
+  pub continuous_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateWebhookSchema {
+  pub url: String,
+  #[serde(rename = "eventTypes")]
+  pub event_types: Vec<String>,
+  pub description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateWebhookSchema {
+  pub url: Option<String>,
+  #[serde(rename = "eventTypes")]
+  pub event_types: Option<Vec<String>>,
+  pub description: Option<String>,
+  pub active: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WebhookMessagesSchema {
+  // pending, delivered or failed
+  pub status: Option<String>,
+  pub page_size: Option<i64>,
+  pub continuous_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WebhookDeliveriesSchema {
+  pub message_id: Option<String>,
+  pub page_size: Option<i64>,
+  pub continuous_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AdminEmailSearchSchema {
+  // pending, sent or failed
+  pub status: Option<String>,
+  pub to: Option<String>,
+  pub page_size: Option<i64>,
+  pub continuous_token: Option<String>,
+}
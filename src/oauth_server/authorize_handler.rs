@@ -0,0 +1,218 @@
+use std::sync::Arc;
+use axum::{
+  extract::{Query, State},
+  http::{header, HeaderMap, StatusCode},
+  response::{IntoResponse, Redirect, Response},
+  Json,
+};
+use axum_extra::extract::CookieJar;
+use chrono::{DateTime, Duration, Utc};
+use diesel::{query_dsl::methods::FilterDsl, ExpressionMethods, OptionalExtension, PgConnection, RunQueryDsl};
+use ulid::Ulid;
+
+use crate::{
+  redirect::append_query_param, schema::{oauth_codes, oauth_consents, user, OAuthCode, OAuthConsent, RegisteredClient, User}, smtp::generate_random_string, token::{is_token_active, is_user_active, verify_paseto_token, TokenDetails}, utils::hash_token, AppState
+};
+
+use super::{client::find_client, model::{scopes_allowed, AuthorizeSchema, OAUTH_CODE_TTL_SECONDS}};
+
+pub enum AuthorizeError {
+  // The client or redirect_uri can't be trusted, so the error is shown here instead of redirecting
+  Invalid(String),
+  // Any other error goes back to the client's redirect_uri
+  Redirect(String),
+}
+
+pub fn error_redirect_url(request: &AuthorizeSchema, error: &str, description: &str) -> String {
+  let mut url = append_query_param(&request.redirect_uri, "error", error);
+  url = append_query_param(&url, "error_description", description);
+  if let Some(state) = request.state.as_deref() {
+    url = append_query_param(&url, "state", state);
+  }
+  url
+}
+
+// Checks the request and returns the client with the scopes being granted
+pub fn validate_authorize_request(conn: &mut PgConnection, request: &AuthorizeSchema)
+  -> Result<(RegisteredClient, String), AuthorizeError> {
+  let client = find_client(conn, &request.client_id)
+    .ok_or_else(|| AuthorizeError::Invalid("Unknown client".into()))?;
+
+  // Exact match only, no wildcards or prefix matching
+  if !client.redirect_uris.iter().any(|uri| uri == &request.redirect_uri) {
+    return Err(AuthorizeError::Invalid("Redirect URI is not registered for this client".into()));
+  }
+
+  if !client.grant_types.iter().any(|grant_type| grant_type == "authorization_code") {
+    return Err(AuthorizeError::Redirect(error_redirect_url(
+      request, "unauthorized_client", "Client is not allowed to use the authorization code grant",
+    )));
+  }
+
+  if request.response_type != "code" {
+    return Err(AuthorizeError::Redirect(error_redirect_url(
+      request, "unsupported_response_type", "Only the code response type is supported",
+    )));
+  }
+
+  // OAuth 2.1 requires PKCE for every client, and the plain method is not accepted
+  if request.code_challenge.as_deref().unwrap_or_default().is_empty()
+    || request.code_challenge_method.as_deref() != Some("S256") {
+    return Err(AuthorizeError::Redirect(error_redirect_url(
+      request, "invalid_request", "PKCE with the S256 method is required",
+    )));
+  }
+
+  let scopes = request.scope.clone().unwrap_or_else(|| client.scopes.clone());
+  if !scopes_allowed(&scopes, &client.scopes) {
+    return Err(AuthorizeError::Redirect(error_redirect_url(
+      request, "invalid_scope", "Requested scope is not allowed for this client",
+    )));
+  }
+
+  Ok((client, scopes))
+}
+
+pub fn has_consent(conn: &mut PgConnection, user_id: &str, client_id: &str, scopes: &str) -> bool {
+  let consent = oauth_consents::table
+    .filter(oauth_consents::user_id.eq(user_id))
+    .filter(oauth_consents::client_id.eq(client_id))
+    .first::<OAuthConsent>(conn)
+    .optional();
+
+  match consent {
+    Ok(Some(consent)) => scopes_allowed(scopes, &consent.scopes),
+    _ => false,
+  }
+}
+
+// Stores a single-use code and returns the client redirect carrying it
+pub fn issue_authorization_code(
+  conn: &mut PgConnection,
+  request: &AuthorizeSchema,
+  user_id: &str,
+  scopes: &str,
+  auth_time: Option<i64>,
+  amr: Option<String>,
+) -> Result<String, (StatusCode, Json<serde_json::Value>)> {
+  let code = generate_random_string();
+  let expires = (Utc::now() + Duration::seconds(OAUTH_CODE_TTL_SECONDS)).naive_utc();
+  let timestamp = Utc::now().naive_utc();
+  let statement = diesel::insert_into(oauth_codes::table)
+    .values(&OAuthCode {
+      id: Ulid::new().to_string(),
+      code: hash_token(&code),
+      client_id: request.client_id.clone(),
+      user_id: user_id.to_string(),
+      redirect_uri: request.redirect_uri.clone(),
+      scopes: scopes.to_string(),
+      code_challenge: request.code_challenge.clone().unwrap_or_default(),
+      nonce: request.nonce.clone(),
+      auth_time: auth_time
+        .and_then(|auth_time| DateTime::<Utc>::from_timestamp(auth_time, 0))
+        .map(|auth_time| auth_time.naive_utc()),
+      amr,
+      expires,
+      created_at: timestamp,
+      updated_at: None,
+      deleted_at: None
+    })
+    .execute(conn);
+
+  if let Err(e) = statement {
+    let error_response = serde_json::json!({
+      "status": "fail",
+      "message": format!("Authorization code not saved to database: {}", e),
+    });
+    return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+  }
+
+  let mut url = append_query_param(&request.redirect_uri, "code", &code);
+  if let Some(state) = request.state.as_deref() {
+    url = append_query_param(&url, "state", state);
+  }
+  Ok(url)
+}
+
+// The user's own session, read the same way as the auth middleware
+fn session_user(cookie_jar: &CookieJar, headers: &HeaderMap, conn: &mut PgConnection, data: &AppState)
+  -> Option<(User, TokenDetails)> {
+  let access_token = cookie_jar
+    .get("access_token")
+    .map(|cookie| cookie.value().to_string())
+    .or_else(|| {
+      headers
+        .get(header::AUTHORIZATION)
+        .and_then(|auth_header| auth_header.to_str().ok())
+        .and_then(|auth_value| auth_value.strip_prefix("Bearer "))
+        .map(|token| token.to_string())
+    })?;
+
+  let token_details = verify_paseto_token(&data.env.auth_key, &access_token).ok()?;
+
+  // A token held by an OAuth client must never stand in for the user's session, and neither may an
+  // impersonation session, which cannot mint credentials
+  if token_details.client_id.is_some() || token_details.principal.is_some() || token_details.act.is_some() {
+    return None;
+  }
+
+  // Logged out or revoked sessions stop here, like in the auth middleware
+  if !is_token_active(conn, &token_details.token_uuid) {
+    return None;
+  }
+
+  let user = user::table
+    .filter(user::id.eq(token_details.user_id.clone()))
+    .first::<User>(conn)
+    .optional()
+    .unwrap_or(None)
+    .filter(is_user_active)?;
+
+  Some((user, token_details))
+}
+
+pub async fn authorize_handler(
+  cookie_jar: CookieJar,
+  headers: HeaderMap,
+  State(data): State<Arc<AppState>>,
+  Query(params): Query<AuthorizeSchema>,
+) -> Result<Response, (StatusCode, Json<serde_json::Value>)> {
+  let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
+  let (client, scopes) = match validate_authorize_request(&mut conn, &params) {
+    Ok(validated) => validated,
+    Err(AuthorizeError::Invalid(message)) => {
+      let error_response = serde_json::json!({
+        "status": "fail",
+        "message": message,
+      });
+      return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+    },
+    Err(AuthorizeError::Redirect(url)) => return Ok(Redirect::temporary(&url).into_response()),
+  };
+
+  // Spell out the granted scopes so the consent screen shows what is being asked for
+  let mut params = params;
+  params.scope = Some(scopes.clone());
+  let query = serde_qs::to_string(&params).unwrap_or_default();
+
+  // Not signed in yet, the login page sends the user back here afterwards
+  let (user, session) = match session_user(&cookie_jar, &headers, &mut conn, &data) {
+    Some(session_user) => session_user,
+    None => {
+      let authorize_url = format!("{}/authorize?{}", data.env.server_url, query);
+      let login_url = append_query_param(&data.env.oauth_login_url, "redirect_to", &authorize_url);
+      return Ok(Redirect::temporary(&login_url).into_response());
+    }
+  };
+
+  // First-party apps and previously approved scopes skip the consent screen
+  if client.first_party || has_consent(&mut conn, &user.id, &client.id, &scopes) {
+    let url = issue_authorization_code(&mut conn, &params, &user.id, &scopes, session.auth_time, session.amr)?;
+    return Ok(Redirect::temporary(&url).into_response());
+  }
+
+  let consent_url = format!("{}?{}", data.env.oauth_consent_url, query);
+  let consent_url = append_query_param(&consent_url, "client_name", &client.name);
+  Ok(Redirect::temporary(&consent_url).into_response())
+}
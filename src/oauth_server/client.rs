@@ -0,0 +1,69 @@
+use argon2::{
+  password_hash::{PasswordHash, PasswordVerifier},
+  Argon2,
+};
+use axum::{
+  http::{header, HeaderMap, StatusCode},
+  Json,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use diesel::{query_dsl::methods::FilterDsl, ExpressionMethods, OptionalExtension, PgConnection, RunQueryDsl};
+
+use crate::schema::{oauth_clients, RegisteredClient};
+
+use super::model::oauth_error;
+
+pub fn find_client(conn: &mut PgConnection, client_id: &str) -> Option<RegisteredClient> {
+  oauth_clients::table
+    .filter(oauth_clients::id.eq(client_id))
+    .filter(oauth_clients::deleted_at.is_null())
+    .first::<RegisteredClient>(conn)
+    .optional()
+    .unwrap_or(None)
+}
+
+// Credentials come from client_secret_basic, falling back to client_secret_post
+fn client_credentials(headers: &HeaderMap, client_id: Option<String>, client_secret: Option<String>)
+  -> Option<(String, Option<String>)> {
+  let basic = headers
+    .get(header::AUTHORIZATION)
+    .and_then(|value| value.to_str().ok())
+    .and_then(|value| value.strip_prefix("Basic "))
+    .and_then(|value| STANDARD.decode(value).ok())
+    .and_then(|value| String::from_utf8(value).ok());
+
+  if let Some(basic) = basic {
+    let (client_id, client_secret) = basic.split_once(':')?;
+    return Some((client_id.to_string(), Some(client_secret.to_string())));
+  }
+
+  client_id.map(|client_id| (client_id, client_secret))
+}
+
+// Public clients (SPAs, mobile apps) have no secret and rely on PKCE instead
+pub fn authenticate_client(
+  conn: &mut PgConnection,
+  headers: &HeaderMap,
+  client_id: Option<String>,
+  client_secret: Option<String>,
+) -> Result<RegisteredClient, (StatusCode, Json<serde_json::Value>)> {
+  let invalid_client = || oauth_error(StatusCode::UNAUTHORIZED, "invalid_client", "Client authentication failed");
+
+  let (client_id, client_secret) = client_credentials(headers, client_id, client_secret).ok_or_else(invalid_client)?;
+  let client = find_client(conn, &client_id).ok_or_else(invalid_client)?;
+
+  if let Some(secret_hash) = client.client_secret.as_deref() {
+    let is_valid_secret = match (PasswordHash::new(secret_hash), client_secret) {
+      (Ok(parsed_hash), Some(secret)) => Argon2::default()
+        .verify_password(secret.as_bytes(), &parsed_hash)
+        .is_ok(),
+      _ => false,
+    };
+
+    if !is_valid_secret {
+      return Err(invalid_client());
+    }
+  }
+
+  Ok(client)
+}
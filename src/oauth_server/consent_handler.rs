@@ -0,0 +1,101 @@
+use std::sync::Arc;
+use axum::{
+  extract::State,
+  http::StatusCode,
+  response::IntoResponse,
+  Extension, Json,
+};
+use chrono::Utc;
+use diesel::{query_dsl::methods::FilterDsl, ExpressionMethods, OptionalExtension, RunQueryDsl};
+use ulid::Ulid;
+
+use crate::{
+  audit::{record_user_event, RequestContext, OUTCOME_SUCCESS}, jwt_auth::JWTAuthMiddleware, schema::{oauth_consents, OAuthConsent, OAuthConsentUpdate}, AppState
+};
+
+use super::{
+  authorize_handler::{error_redirect_url, issue_authorization_code, validate_authorize_request, AuthorizeError},
+  model::{split_scopes, ConsentSchema},
+};
+
+// Called by the consent screen with the original authorize parameters and the user's answer
+pub async fn consent_handler(
+  Extension(jwtauth): Extension<JWTAuthMiddleware>,
+  State(data): State<Arc<AppState>>,
+  context: RequestContext,
+  Json(body): Json<ConsentSchema>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+  let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+  let request = body.request;
+  let user_id = jwtauth.session_user()?.id.clone();
+
+  let (client, scopes) = match validate_authorize_request(&mut conn, &request) {
+    Ok(validated) => validated,
+    Err(AuthorizeError::Invalid(message)) => {
+      let error_response = serde_json::json!({
+        "status": "fail",
+        "message": message,
+      });
+      return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+    },
+    Err(AuthorizeError::Redirect(url)) => return Ok(Json(serde_json::json!({ "redirect_to": url }))),
+  };
+
+  if !body.approved {
+    record_user_event(&mut conn, &context, "oauth.consent.denied", Some(&user_id), OUTCOME_SUCCESS, serde_json::json!({ "clientId": client.id }));
+    let url = error_redirect_url(&request, "access_denied", "The user denied the request");
+    return Ok(Json(serde_json::json!({ "redirect_to": url })));
+  }
+
+  let consent_exists = oauth_consents::table
+    .filter(oauth_consents::user_id.eq(user_id.clone()))
+    .filter(oauth_consents::client_id.eq(client.id.clone()))
+    .first::<OAuthConsent>(&mut conn)
+    .optional();
+
+  let timestamp = Utc::now().naive_utc();
+  let statement = match consent_exists {
+    // Earlier grants are kept, so approving new scopes only ever widens the consent
+    Ok(Some(consent)) => {
+      let mut granted = split_scopes(&consent.scopes);
+      for scope in split_scopes(&scopes) {
+        if !granted.contains(&scope) {
+          granted.push(scope);
+        }
+      }
+
+      diesel::update(oauth_consents::table)
+        .filter(oauth_consents::id.eq(consent.id))
+        .set(&OAuthConsentUpdate {
+          scopes: granted.join(" "),
+          updated_at: Some(timestamp),
+        })
+        .execute(&mut conn)
+    },
+    _ => diesel::insert_into(oauth_consents::table)
+      .values(&OAuthConsent {
+        id: Ulid::new().to_string(),
+        user_id: user_id.clone(),
+        client_id: client.id.clone(),
+        scopes: scopes.clone(),
+        created_at: timestamp,
+        updated_at: None,
+        deleted_at: None
+      })
+      .execute(&mut conn),
+  };
+
+  if let Err(e) = statement {
+    let error_response = serde_json::json!({
+      "status": "fail",
+      "message": format!("Consent could not be saved to database: {}", e),
+    });
+    return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+  }
+
+  record_user_event(&mut conn, &context, "oauth.consent.granted", Some(&user_id), OUTCOME_SUCCESS, serde_json::json!({ "clientId": client.id, "scope": scopes }));
+
+  let url = issue_authorization_code(&mut conn, &request, &user_id, &scopes, jwtauth.auth_time, jwtauth.amr)?;
+
+  Ok(Json(serde_json::json!({ "redirect_to": url })))
+}
@@ -0,0 +1,249 @@
+use std::sync::Arc;
+use axum::{
+  extract::{Form, Query, State},
+  http::{header, HeaderMap, StatusCode},
+  response::IntoResponse,
+  Extension, Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use diesel::{query_dsl::methods::FilterDsl, ExpressionMethods, OptionalExtension, PgConnection, RunQueryDsl};
+use rand::Rng;
+use ulid::Ulid;
+
+use crate::{
+  audit::{record_user_event, RequestContext, OUTCOME_SUCCESS}, jwt_auth::JWTAuthMiddleware, redirect::append_query_param,
+  schema::{oauth_device_codes, OAuthDeviceCode, OAuthDeviceCodeDecision, OAuthDeviceCodePoll, RegisteredClient},
+  smtp::generate_random_string, utils::hash_token, AppState
+};
+
+use super::{
+  client::{authenticate_client, find_client},
+  model::{
+    oauth_error, scopes_allowed, DeviceCodeSchema, DeviceLookupSchema, DeviceVerifySchema, Grant, TokenSchema,
+    DEVICE_CODE_GRANT, DEVICE_CODE_INTERVAL_SECONDS, DEVICE_CODE_SLOW_DOWN_SECONDS, DEVICE_CODE_TTL_SECONDS,
+  },
+};
+
+// No vowels so codes never spell words, and no digits to avoid 0/O and 1/I mix-ups
+const USER_CODE_CHARSET: &[u8] = b"BCDFGHJKLMNPQRSTVWXZ";
+
+fn generate_user_code() -> String {
+  let mut rng = rand::thread_rng();
+  (0..8)
+    .map(|_| USER_CODE_CHARSET[rng.gen_range(0..USER_CODE_CHARSET.len())] as char)
+    .collect()
+}
+
+// Users may type the code in lower case or without the dash
+fn normalize_user_code(user_code: &str) -> String {
+  user_code
+    .chars()
+    .filter(|c| c.is_ascii_alphabetic())
+    .map(|c| c.to_ascii_uppercase())
+    .collect()
+}
+
+fn find_pending_code(conn: &mut PgConnection, user_code: &str) -> Option<OAuthDeviceCode> {
+  oauth_device_codes::table
+    .filter(oauth_device_codes::user_code.eq(normalize_user_code(user_code)))
+    .filter(oauth_device_codes::status.eq("pending"))
+    .filter(oauth_device_codes::expires.gt(Utc::now().naive_utc()))
+    .first::<OAuthDeviceCode>(conn)
+    .optional()
+    .unwrap_or(None)
+}
+
+fn invalid_user_code() -> (StatusCode, Json<serde_json::Value>) {
+  let error_response = serde_json::json!({
+    "status": "fail",
+    "message": "Code is invalid or has expired",
+  });
+  (StatusCode::BAD_REQUEST, Json(error_response))
+}
+
+// Step one, the device asks for a code pair to show the user
+pub async fn device_code_handler(
+  headers: HeaderMap,
+  State(data): State<Arc<AppState>>,
+  Form(body): Form<DeviceCodeSchema>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+  let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
+  let client = authenticate_client(&mut conn, &headers, body.client_id, body.client_secret)?;
+
+  if !client.grant_types.iter().any(|grant_type| grant_type == DEVICE_CODE_GRANT) {
+    return Err(oauth_error(StatusCode::BAD_REQUEST, "unauthorized_client", "Grant type is not allowed for this client"));
+  }
+
+  let scopes = body.scope.unwrap_or_else(|| client.scopes.clone());
+  if !scopes_allowed(&scopes, &client.scopes) {
+    return Err(oauth_error(StatusCode::BAD_REQUEST, "invalid_scope", "Requested scope is not allowed for this client"));
+  }
+
+  let device_code = generate_random_string();
+  let user_code = generate_user_code();
+  let timestamp = Utc::now().naive_utc();
+  let statement = diesel::insert_into(oauth_device_codes::table)
+    .values(&OAuthDeviceCode {
+      id: Ulid::new().to_string(),
+      device_code: hash_token(&device_code),
+      user_code: user_code.clone(),
+      client_id: client.id.clone(),
+      scopes,
+      status: "pending".into(),
+      user_id: None,
+      auth_time: None,
+      amr: None,
+      interval: DEVICE_CODE_INTERVAL_SECONDS,
+      last_polled_at: None,
+      expires: timestamp + Duration::seconds(DEVICE_CODE_TTL_SECONDS),
+      created_at: timestamp,
+      updated_at: None,
+      deleted_at: None
+    })
+    .execute(&mut conn);
+
+  if let Err(e) = statement {
+    return Err(oauth_error(StatusCode::INTERNAL_SERVER_ERROR, "server_error", &format!("Device code not saved to database: {}", e)));
+  }
+
+  let display_code = format!("{}-{}", &user_code[..4], &user_code[4..]);
+
+  Ok((
+    [(header::CACHE_CONTROL, "no-store")],
+    Json(serde_json::json!({
+      "device_code": device_code,
+      "user_code": display_code,
+      "verification_uri": data.env.oauth_device_url,
+      "verification_uri_complete": append_query_param(&data.env.oauth_device_url, "user_code", &display_code),
+      "expires_in": DEVICE_CODE_TTL_SECONDS,
+      "interval": DEVICE_CODE_INTERVAL_SECONDS,
+    })),
+  ))
+}
+
+// Lets the verification page show which app is asking before the user approves
+pub async fn device_lookup_handler(
+  Extension(jwtauth): Extension<JWTAuthMiddleware>,
+  State(data): State<Arc<AppState>>,
+  Query(body): Query<DeviceLookupSchema>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+  jwtauth.user()?;
+  let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
+  let device_code = find_pending_code(&mut conn, &body.user_code).ok_or_else(invalid_user_code)?;
+  let client = find_client(&mut conn, &device_code.client_id).ok_or_else(invalid_user_code)?;
+
+  Ok(Json(serde_json::json!({
+    "client_name": client.name,
+    "scope": device_code.scopes,
+  })))
+}
+
+// The verification page approves or denies with the user's existing session
+pub async fn device_verify_handler(
+  Extension(jwtauth): Extension<JWTAuthMiddleware>,
+  State(data): State<Arc<AppState>>,
+  context: RequestContext,
+  Json(body): Json<DeviceVerifySchema>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+  let user = jwtauth.session_user()?;
+  let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
+  let device_code = find_pending_code(&mut conn, &body.user_code).ok_or_else(invalid_user_code)?;
+
+  let timestamp = Utc::now().naive_utc();
+  let statement = diesel::update(oauth_device_codes::table)
+    .filter(oauth_device_codes::id.eq(device_code.id.clone()))
+    .filter(oauth_device_codes::status.eq("pending"))
+    .set(&OAuthDeviceCodeDecision {
+      status: if body.approved { "approved".into() } else { "denied".into() },
+      user_id: Some(user.id.clone()),
+      auth_time: jwtauth.auth_time
+        .and_then(|auth_time| DateTime::<Utc>::from_timestamp(auth_time, 0))
+        .map(|auth_time| auth_time.naive_utc()),
+      amr: jwtauth.amr.clone(),
+      updated_at: Some(timestamp),
+    })
+    .execute(&mut conn);
+
+  match statement {
+    Ok(1) => {
+      let event_type = if body.approved { "oauth.device.approved" } else { "oauth.device.denied" };
+      record_user_event(&mut conn, &context, event_type, Some(&user.id), OUTCOME_SUCCESS, serde_json::json!({ "clientId": device_code.client_id }));
+      Ok(Json(serde_json::json!({ "status": "success" })))
+    }
+    Ok(_) => Err(invalid_user_code()),
+    Err(e) => {
+      let error_response = serde_json::json!({
+        "status": "fail",
+        "message": format!("Device code could not be updated: {}", e),
+      });
+      Err((StatusCode::BAD_REQUEST, Json(error_response)))
+    }
+  }
+}
+
+// Step two, the device polls the token endpoint until the user has decided
+pub fn poll_device_code(conn: &mut PgConnection, client: &RegisteredClient, body: &TokenSchema)
+  -> Result<Grant, (StatusCode, Json<serde_json::Value>)> {
+  let device_code = body.device_code.as_deref().unwrap_or_default();
+
+  let code_exists = oauth_device_codes::table
+    .filter(oauth_device_codes::device_code.eq(hash_token(device_code)))
+    .filter(oauth_device_codes::client_id.eq(client.id.clone()))
+    .first::<OAuthDeviceCode>(conn)
+    .optional();
+
+  let code = match code_exists {
+    Ok(Some(code)) => code,
+    _ => return Err(oauth_error(StatusCode::BAD_REQUEST, "invalid_grant", "Device code is invalid")),
+  };
+
+  let now = Utc::now().naive_utc();
+
+  if code.expires < now {
+    return Err(oauth_error(StatusCode::BAD_REQUEST, "expired_token", "Device code has expired"));
+  }
+
+  // Polling faster than the interval pushes the interval back further
+  let too_fast = code.last_polled_at
+    .map(|last_polled_at| now < last_polled_at + Duration::seconds(code.interval as i64))
+    .unwrap_or(false);
+  let interval = if too_fast { code.interval + DEVICE_CODE_SLOW_DOWN_SECONDS } else { code.interval };
+
+  let _ = diesel::update(oauth_device_codes::table)
+    .filter(oauth_device_codes::id.eq(code.id.clone()))
+    .set(&OAuthDeviceCodePoll {
+      interval,
+      last_polled_at: Some(now),
+    })
+    .execute(conn);
+
+  if too_fast {
+    return Err(oauth_error(StatusCode::BAD_REQUEST, "slow_down", "Polling too frequently"));
+  }
+
+  match code.status.as_str() {
+    "approved" => {},
+    "denied" => return Err(oauth_error(StatusCode::BAD_REQUEST, "access_denied", "The user denied the request")),
+    _ => return Err(oauth_error(StatusCode::BAD_REQUEST, "authorization_pending", "The user has not approved the request yet")),
+  }
+
+  // Deleting the row is what makes the approval single use
+  let deleted = diesel::delete(oauth_device_codes::table)
+    .filter(oauth_device_codes::id.eq(code.id))
+    .execute(conn);
+
+  if !matches!(deleted, Ok(1)) {
+    return Err(oauth_error(StatusCode::BAD_REQUEST, "invalid_grant", "Device code is invalid"));
+  }
+
+  Ok(Grant {
+    user_id: code.user_id.unwrap_or_default(),
+    scopes: code.scopes,
+    nonce: None,
+    auth_time: code.auth_time,
+    amr: code.amr,
+  })
+}
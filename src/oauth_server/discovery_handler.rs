@@ -0,0 +1,54 @@
+use std::sync::Arc;
+use axum::{
+  extract::State,
+  http::StatusCode,
+  response::IntoResponse,
+  Json,
+};
+use serde_json::json;
+
+use crate::AppState;
+
+use super::id_token::jwks;
+
+pub async fn openid_configuration_handler(
+  State(data): State<Arc<AppState>>,
+) -> impl IntoResponse {
+  let issuer = data.env.server_url.clone();
+
+  Json(json!({
+    "issuer": issuer,
+    "authorization_endpoint": format!("{}/authorize", issuer),
+    "token_endpoint": format!("{}/token", issuer),
+    "userinfo_endpoint": format!("{}/userinfo", issuer),
+    "jwks_uri": format!("{}/.well-known/jwks.json", issuer),
+    "response_types_supported": ["code"],
+    "response_modes_supported": ["query"],
+    "grant_types_supported": ["authorization_code", "refresh_token", "client_credentials", "urn:ietf:params:oauth:grant-type:device_code"],
+    "device_authorization_endpoint": format!("{}/device/code", issuer),
+    "subject_types_supported": ["public"],
+    "id_token_signing_alg_values_supported": ["RS256"],
+    "scopes_supported": ["openid", "profile", "email"],
+    "token_endpoint_auth_methods_supported": ["client_secret_basic", "client_secret_post", "none"],
+    "code_challenge_methods_supported": ["S256"],
+    "claims_supported": [
+      "sub", "iss", "aud", "exp", "iat", "auth_time", "nonce", "acr", "amr",
+      "name", "updated_at", "email", "email_verified"
+    ],
+  }))
+}
+
+pub async fn jwks_handler(
+  State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+  match jwks(&data.env) {
+    Ok(keys) => Ok(Json(keys)),
+    Err(e) => {
+      let error_response = serde_json::json!({
+        "status": "error",
+        "message": format!("Signing key is not configured: {}", e),
+      });
+      Err((StatusCode::INTERNAL_SERVER_ERROR, Json(error_response)))
+    }
+  }
+}
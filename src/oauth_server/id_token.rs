@@ -0,0 +1,109 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{Duration, Utc};
+use rsa::{
+  pkcs1v15::SigningKey,
+  pkcs8::DecodePrivateKey,
+  signature::{SignatureEncoding, Signer},
+  traits::PublicKeyParts,
+  RsaPrivateKey,
+};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::{config::Config, schema::User};
+
+use super::model::{has_scope, Grant};
+
+// Single factor sign-ins only, so every session sits at the lowest assurance level
+const ACR_SINGLE_FACTOR: &str = "1";
+
+fn signing_key(config: &Config) -> Result<RsaPrivateKey, Box<dyn std::error::Error>> {
+  let private_key = config.oidc_private_key.replace("\\n", "\n");
+  Ok(RsaPrivateKey::from_pkcs8_pem(&private_key)?)
+}
+
+// RFC 7638 thumbprint, stable for as long as the key is
+fn key_id(n: &str, e: &str) -> String {
+  let canonical = format!(r#"{{"e":"{}","kty":"RSA","n":"{}"}}"#, e, n);
+  URL_SAFE_NO_PAD.encode(Sha256::digest(canonical.as_bytes()))
+}
+
+pub fn jwks(config: &Config) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+  let public_key = signing_key(config)?.to_public_key();
+  let n = URL_SAFE_NO_PAD.encode(public_key.n().to_bytes_be());
+  let e = URL_SAFE_NO_PAD.encode(public_key.e().to_bytes_be());
+
+  Ok(json!({
+    "keys": [{
+      "kty": "RSA",
+      "use": "sig",
+      "alg": "RS256",
+      "kid": key_id(&n, &e),
+      "n": n,
+      "e": e,
+    }]
+  }))
+}
+
+// Claims shared by the ID token and /userinfo, released per granted scope
+pub fn user_claims(user: &User, scopes: &str) -> serde_json::Map<String, serde_json::Value> {
+  let mut claims = serde_json::Map::new();
+  claims.insert("sub".into(), json!(user.id));
+
+  if has_scope(scopes, "profile") {
+    claims.insert("name".into(), json!(user.name));
+    claims.insert("updated_at".into(), json!(user.updated_at.unwrap_or(user.created_at).and_utc().timestamp()));
+  }
+
+  if has_scope(scopes, "email") {
+    if let Some(email) = user.email.as_deref() {
+      claims.insert("email".into(), json!(email));
+      claims.insert("email_verified".into(), json!(user.verified));
+    }
+  }
+
+  claims
+}
+
+pub fn generate_id_token(config: &Config, client_id: &str, user: &User, grant: &Grant)
+  -> Result<String, Box<dyn std::error::Error>> {
+  let private_key = signing_key(config)?;
+  let public_key = private_key.to_public_key();
+  let kid = key_id(
+    &URL_SAFE_NO_PAD.encode(public_key.n().to_bytes_be()),
+    &URL_SAFE_NO_PAD.encode(public_key.e().to_bytes_be()),
+  );
+
+  let now = Utc::now();
+  let mut claims = user_claims(user, &grant.scopes);
+  claims.insert("iss".into(), json!(config.server_url));
+  claims.insert("aud".into(), json!(client_id));
+  claims.insert("iat".into(), json!(now.timestamp()));
+  claims.insert("exp".into(), json!((now + Duration::minutes(config.access_token_max_age)).timestamp()));
+  claims.insert("acr".into(), json!(ACR_SINGLE_FACTOR));
+
+  if let Some(auth_time) = grant.auth_time {
+    claims.insert("auth_time".into(), json!(auth_time.and_utc().timestamp()));
+  }
+  if let Some(amr) = grant.amr.as_deref() {
+    claims.insert("amr".into(), json!([amr]));
+  }
+  if let Some(nonce) = grant.nonce.as_deref() {
+    claims.insert("nonce".into(), json!(nonce));
+  }
+
+  let header = json!({
+    "alg": "RS256",
+    "typ": "JWT",
+    "kid": kid,
+  });
+
+  let signing_input = format!(
+    "{}.{}",
+    URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header)?),
+    URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims)?),
+  );
+  let signature = SigningKey::<Sha256>::new(private_key).sign(signing_input.as_bytes());
+
+  Ok(format!("{}.{}", signing_input, URL_SAFE_NO_PAD.encode(signature.to_bytes())))
+}
@@ -0,0 +1,9 @@
+pub mod authorize_handler;
+pub mod client;
+pub mod consent_handler;
+pub mod device_handler;
+pub mod discovery_handler;
+pub mod id_token;
+pub mod model;
+pub mod token_handler;
+pub mod userinfo_handler;
@@ -0,0 +1,100 @@
+use axum::{http::StatusCode, Json};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+// Authorization codes are exchanged right away, so they only live briefly
+pub const OAUTH_CODE_TTL_SECONDS: i64 = 60;
+
+pub const DEVICE_CODE_GRANT: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+// Long enough for the user to find another device and sign in
+pub const DEVICE_CODE_TTL_SECONDS: i64 = 600;
+
+// Minimum seconds between polls, raised by DEVICE_CODE_SLOW_DOWN_SECONDS each time a device polls too fast
+pub const DEVICE_CODE_INTERVAL_SECONDS: i32 = 5;
+pub const DEVICE_CODE_SLOW_DOWN_SECONDS: i32 = 5;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AuthorizeSchema {
+  pub response_type: String,
+  pub client_id: String,
+  pub redirect_uri: String,
+  pub scope: Option<String>,
+  pub state: Option<String>,
+  pub code_challenge: Option<String>,
+  pub code_challenge_method: Option<String>,
+  pub nonce: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConsentSchema {
+  #[serde(flatten)]
+  pub request: AuthorizeSchema,
+  pub approved: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TokenSchema {
+  pub grant_type: String,
+  pub code: Option<String>,
+  pub redirect_uri: Option<String>,
+  pub code_verifier: Option<String>,
+  pub refresh_token: Option<String>,
+  pub device_code: Option<String>,
+  pub scope: Option<String>,
+  pub client_id: Option<String>,
+  pub client_secret: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeviceCodeSchema {
+  pub client_id: Option<String>,
+  pub client_secret: Option<String>,
+  pub scope: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeviceLookupSchema {
+  pub user_code: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeviceVerifySchema {
+  pub user_code: String,
+  pub approved: bool,
+}
+
+// What a grant resolved to, everything needed to mint its tokens
+#[derive(Debug)]
+pub struct Grant {
+  pub user_id: String,
+  pub scopes: String,
+  pub nonce: Option<String>,
+  pub auth_time: Option<NaiveDateTime>,
+  pub amr: Option<String>,
+}
+
+// Token endpoint errors use the RFC 6749 shape so standard clients can read them
+pub fn oauth_error(status: StatusCode, error: &str, description: &str)
+  -> (StatusCode, Json<serde_json::Value>) {
+  let error_response = serde_json::json!({
+    "error": error,
+    "error_description": description,
+  });
+  (status, Json(error_response))
+}
+
+pub fn split_scopes(scopes: &str) -> Vec<&str> {
+  scopes.split_whitespace().collect()
+}
+
+pub fn scopes_allowed(requested: &str, allowed: &str) -> bool {
+  let allowed = split_scopes(allowed);
+  split_scopes(requested)
+    .iter()
+    .all(|scope| allowed.contains(scope))
+}
+
+pub fn has_scope(scopes: &str, scope: &str) -> bool {
+  split_scopes(scopes).contains(&scope)
+}
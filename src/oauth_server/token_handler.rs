@@ -0,0 +1,299 @@
+use std::sync::Arc;
+use axum::{
+  extract::{Form, State},
+  http::{header, HeaderMap, StatusCode},
+  response::IntoResponse,
+  Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use diesel::{query_dsl::methods::FilterDsl, ExpressionMethods, OptionalExtension, PgConnection, RunQueryDsl};
+use ulid::Ulid;
+
+use crate::{
+  schema::{oauth_codes, oauth_refresh_tokens, tokens, user, OAuthCode, OAuthRefreshToken, OAuthRefreshTokenRevoke, RegisteredClient, Token, User},
+  smtp::generate_random_string, token::{generate_paseto_token, is_user_active, issue_session_tokens, session_claims}, utils::hash_token, AppState
+};
+
+use super::{client::authenticate_client, device_handler::poll_device_code, id_token::generate_id_token, model::{has_scope, oauth_error, scopes_allowed, Grant, TokenSchema, DEVICE_CODE_GRANT}};
+
+fn invalid_grant(description: &str) -> (StatusCode, Json<serde_json::Value>) {
+  oauth_error(StatusCode::BAD_REQUEST, "invalid_grant", description)
+}
+
+// Resolves a valid authorization code to the grant it was issued for
+fn exchange_code(conn: &mut PgConnection, client: &RegisteredClient, body: &TokenSchema)
+  -> Result<Grant, (StatusCode, Json<serde_json::Value>)> {
+  let code = body.code.as_deref().unwrap_or_default();
+
+  // Deleting the row is what makes the code single use
+  let code_exists = diesel::delete(oauth_codes::table)
+    .filter(oauth_codes::code.eq(hash_token(code)))
+    .filter(oauth_codes::expires.gt(Utc::now().naive_utc()))
+    .get_result::<OAuthCode>(conn)
+    .optional();
+
+  let code = match code_exists {
+    Ok(Some(code)) => code,
+    _ => return Err(invalid_grant("Authorization code is invalid or has expired")),
+  };
+
+  if code.client_id != client.id {
+    return Err(invalid_grant("Authorization code was issued to another client"));
+  }
+
+  if body.redirect_uri.as_deref() != Some(code.redirect_uri.as_str()) {
+    return Err(invalid_grant("Redirect URI does not match the authorization request"));
+  }
+
+  // S256: the challenge is the base64url encoded SHA-256 of the verifier
+  let code_verifier = body.code_verifier.as_deref().unwrap_or_default();
+  if code_verifier.is_empty() || hash_token(code_verifier) != code.code_challenge {
+    return Err(invalid_grant("PKCE verification failed"));
+  }
+
+  Ok(Grant {
+    user_id: code.user_id,
+    scopes: code.scopes,
+    nonce: code.nonce,
+    auth_time: code.auth_time,
+    amr: code.amr,
+  })
+}
+
+// Refresh tokens rotate on every use, presenting a rotated one again revokes the whole family
+fn exchange_refresh_token(conn: &mut PgConnection, client: &RegisteredClient, body: &TokenSchema)
+  -> Result<Grant, (StatusCode, Json<serde_json::Value>)> {
+  let refresh_token = body.refresh_token.as_deref().unwrap_or_default();
+
+  let token_exists = oauth_refresh_tokens::table
+    .filter(oauth_refresh_tokens::token.eq(hash_token(refresh_token)))
+    .filter(oauth_refresh_tokens::client_id.eq(client.id.clone()))
+    .first::<OAuthRefreshToken>(conn)
+    .optional();
+
+  let token = match token_exists {
+    Ok(Some(token)) => token,
+    _ => return Err(invalid_grant("Refresh token is invalid")),
+  };
+
+  let timestamp = Utc::now().naive_utc();
+
+  if token.revoked {
+    let _ = diesel::update(oauth_refresh_tokens::table)
+      .filter(oauth_refresh_tokens::user_id.eq(token.user_id.clone()))
+      .filter(oauth_refresh_tokens::client_id.eq(client.id.clone()))
+      .set(&OAuthRefreshTokenRevoke {
+        revoked: true,
+        updated_at: Some(timestamp),
+      })
+      .execute(conn);
+
+    tracing::warn!("Refresh token reuse detected for client {}", client.id);
+    return Err(invalid_grant("Refresh token is invalid"));
+  }
+
+  if token.expires < timestamp {
+    return Err(invalid_grant("Refresh token has expired"));
+  }
+
+  // A narrower scope may be requested, never a wider one
+  let scopes = body.scope.clone().unwrap_or_else(|| token.scopes.clone());
+  if !scopes_allowed(&scopes, &token.scopes) {
+    return Err(oauth_error(StatusCode::BAD_REQUEST, "invalid_scope", "Requested scope exceeds the original grant"));
+  }
+
+  // Only one of two concurrent refreshes may win
+  let revoked = diesel::update(oauth_refresh_tokens::table)
+    .filter(oauth_refresh_tokens::id.eq(token.id))
+    .filter(oauth_refresh_tokens::revoked.eq(false))
+    .set(&OAuthRefreshTokenRevoke {
+      revoked: true,
+      updated_at: Some(timestamp),
+    })
+    .execute(conn);
+
+  if !matches!(revoked, Ok(1)) {
+    return Err(invalid_grant("Refresh token is invalid"));
+  }
+
+  // The nonce belonged to the original authentication request and is not repeated
+  Ok(Grant {
+    user_id: token.user_id,
+    scopes,
+    nonce: None,
+    auth_time: token.auth_time,
+    amr: token.amr,
+  })
+}
+
+// Issues an access token for the grant, along with a fresh refresh token and,
+// for the openid scope, an ID token
+pub fn issue_tokens(
+  conn: &mut PgConnection,
+  data: &AppState,
+  client_id: &str,
+  grant: &Grant,
+) -> Result<serde_json::Value, (StatusCode, Json<serde_json::Value>)> {
+  let server_error = |message: String| oauth_error(StatusCode::INTERNAL_SERVER_ERROR, "server_error", &message);
+  let user_id = grant.user_id.as_str();
+  let scopes = grant.scopes.as_str();
+
+  // A code or refresh token outlives neither a disabled nor a deleted account
+  let user = user::table
+    .filter(user::id.eq(user_id))
+    .first::<User>(conn)
+    .optional()
+    .map_err(|e| server_error(format!("Failed to load user: {}", e)))?
+    .filter(is_user_active)
+    .ok_or_else(|| invalid_grant("User is disabled or no longer exists"))?;
+
+  let access_token_details = generate_paseto_token(
+    user_id.to_string(),
+    data.env.access_token_max_age,
+    &data.env.auth_key,
+    &[("client_id", client_id.to_string()), ("scope", scopes.to_string())],
+  ).map_err(|e| server_error(format!("Failed to generate access token: {}", e)))?;
+
+  // Access token
+  let expires = DateTime::<Utc>::from_timestamp(access_token_details.expires_in.unwrap_or_default(), 0)
+    .map(|dt| dt.naive_utc())
+    .unwrap_or_else(|| {
+      // Handle invalid timestamps
+      DateTime::<Utc>::from_timestamp(0, 0)
+        .unwrap()
+        .naive_utc()
+    });
+  let timestamp = Utc::now().naive_utc();
+  let statement = diesel::insert_into(tokens::table)
+    .values(&Token {
+      id: Ulid::new().to_string(),
+      user_id: user_id.to_string(),
+      expires,
+      blacklisted: false,
+      token: access_token_details.token.clone().unwrap_or_default(),
+      token_uuid: access_token_details.token_uuid.to_string(),
+      created_at: timestamp,
+      updated_at: None,
+      deleted_at: None
+    })
+    .execute(conn);
+
+  if let Err(e) = statement {
+    return Err(server_error(format!("Failed to save access token: {}", e)));
+  }
+
+  // Refresh token, opaque and only stored hashed
+  let refresh_token = generate_random_string();
+  let statement = diesel::insert_into(oauth_refresh_tokens::table)
+    .values(&OAuthRefreshToken {
+      id: Ulid::new().to_string(),
+      token: hash_token(&refresh_token),
+      client_id: client_id.to_string(),
+      user_id: user_id.to_string(),
+      scopes: scopes.to_string(),
+      expires: timestamp + Duration::minutes(data.env.refresh_token_max_age),
+      revoked: false,
+      auth_time: grant.auth_time,
+      amr: grant.amr.clone(),
+      created_at: timestamp,
+      updated_at: None,
+      deleted_at: None
+    })
+    .execute(conn);
+
+  if let Err(e) = statement {
+    return Err(server_error(format!("Failed to save refresh token: {}", e)));
+  }
+
+  let mut response = serde_json::json!({
+    "access_token": access_token_details.token.unwrap_or_default(),
+    "token_type": "Bearer",
+    "expires_in": data.env.access_token_max_age * 60,
+    "refresh_token": refresh_token,
+    "scope": scopes,
+  });
+
+  if has_scope(scopes, "openid") {
+    let id_token = generate_id_token(&data.env, client_id, &user, grant)
+      .map_err(|e| server_error(format!("Failed to sign ID token: {}", e)))?;
+    response["id_token"] = serde_json::json!(id_token);
+  }
+
+  Ok(response)
+}
+
+// Machine-to-machine access, the client is both the caller and the subject
+fn issue_client_token(data: &AppState, client: &RegisteredClient, body: &TokenSchema)
+  -> Result<serde_json::Value, (StatusCode, Json<serde_json::Value>)> {
+  // Only clients able to keep a secret may act on their own behalf
+  if client.client_secret.is_none() {
+    return Err(oauth_error(StatusCode::BAD_REQUEST, "unauthorized_client", "Public clients cannot use this grant"));
+  }
+
+  let scopes = body.scope.clone().unwrap_or_else(|| client.scopes.clone());
+  if !scopes_allowed(&scopes, &client.scopes) {
+    return Err(oauth_error(StatusCode::BAD_REQUEST, "invalid_scope", "Requested scope is not allowed for this client"));
+  }
+
+  // Service tokens have no session behind them, so they are not stored and come without a refresh token
+  let access_token_details = generate_paseto_token(
+    client.id.clone(),
+    data.env.access_token_max_age,
+    &data.env.auth_key,
+    &[("client_id", client.id.clone()), ("scope", scopes.clone()), ("principal", "client".to_string())],
+  ).map_err(|e| oauth_error(StatusCode::INTERNAL_SERVER_ERROR, "server_error", &format!("Failed to generate access token: {}", e)))?;
+
+  Ok(serde_json::json!({
+    "access_token": access_token_details.token.unwrap_or_default(),
+    "token_type": "Bearer",
+    "expires_in": data.env.access_token_max_age * 60,
+    "scope": scopes,
+  }))
+}
+
+pub async fn token_handler(
+  headers: HeaderMap,
+  State(data): State<Arc<AppState>>,
+  Form(body): Form<TokenSchema>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+  let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
+  let client = authenticate_client(&mut conn, &headers, body.client_id.clone(), body.client_secret.clone())?;
+
+  if !client.grant_types.contains(&body.grant_type) {
+    return Err(oauth_error(StatusCode::BAD_REQUEST, "unauthorized_client", "Grant type is not allowed for this client"));
+  }
+
+  if body.grant_type == "client_credentials" {
+    let response = issue_client_token(&data, &client, &body)?;
+    return Ok(([(header::CACHE_CONTROL, "no-store")], Json(response)));
+  }
+
+  // Devices get the same session pair as a password login, not client-scoped tokens
+  if body.grant_type == DEVICE_CODE_GRANT {
+    let grant = poll_device_code(&mut conn, &client, &body)?;
+    let auth_claims = match (grant.auth_time, grant.amr.as_deref()) {
+      (Some(auth_time), Some(amr)) => session_claims(auth_time.and_utc().timestamp(), amr),
+      _ => vec![],
+    };
+    let (access_token_details, refresh_token_details) = issue_session_tokens(&mut conn, &data.env, &grant.user_id, &auth_claims).await?;
+
+    let response = serde_json::json!({
+      "access_token": access_token_details.token.unwrap_or_default(),
+      "token_type": "Bearer",
+      "expires_in": data.env.access_token_max_age * 60,
+      "refresh_token": refresh_token_details.token.unwrap_or_default(),
+    });
+    return Ok(([(header::CACHE_CONTROL, "no-store")], Json(response)));
+  }
+
+  let grant = match body.grant_type.as_str() {
+    "authorization_code" => exchange_code(&mut conn, &client, &body)?,
+    "refresh_token" => exchange_refresh_token(&mut conn, &client, &body)?,
+    _ => return Err(oauth_error(StatusCode::BAD_REQUEST, "unsupported_grant_type", "Grant type is not supported")),
+  };
+
+  let response = issue_tokens(&mut conn, &data, &client.id, &grant)?;
+
+  Ok(([(header::CACHE_CONTROL, "no-store")], Json(response)))
+}
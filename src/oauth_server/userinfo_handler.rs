@@ -0,0 +1,27 @@
+use axum::{
+  response::IntoResponse, Extension, Json
+};
+use anyhow::Result;
+use reqwest::StatusCode;
+use crate::jwt_auth::JWTAuthMiddleware;
+
+use super::{id_token::user_claims, model::{has_scope, oauth_error}};
+
+// The OIDC counterpart of /users/me, claims are limited to what the token's scopes allow
+pub async fn userinfo_handler(
+    Extension(jwtauth): Extension<JWTAuthMiddleware>,
+  ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let user = jwtauth.user()?;
+
+    // The user's own session may see everything
+    let scopes = match jwtauth.client_id {
+      Some(_) => jwtauth.scope.clone().unwrap_or_default(),
+      None => "openid profile email".to_string(),
+    };
+
+    if !has_scope(&scopes, "openid") {
+      return Err(oauth_error(StatusCode::FORBIDDEN, "insufficient_scope", "The openid scope is required"));
+    }
+
+    Ok(Json(user_claims(user, &scopes)))
+  }
@@ -0,0 +1,104 @@
+use std::sync::Arc;
+use axum::{
+  extract::State,
+  http::StatusCode,
+  response::IntoResponse,
+  Extension, Json,
+};
+
+use crate::{jwt_auth::JWTAuthMiddleware, AppState};
+
+use super::{
+  engine::{load_schema, Engine},
+  model::{
+    authorize_caller, permission_error, CheckSchema, ExpandSchema, LookupSubjectsSchema, PermissionMetadata,
+    CHECK_PERMISSION, DEFAULT_CHECK_DEPTH,
+  },
+};
+
+fn check_depth(metadata: &PermissionMetadata) -> i32 {
+  metadata.depth.unwrap_or(DEFAULT_CHECK_DEPTH).clamp(1, DEFAULT_CHECK_DEPTH)
+}
+
+// Can this subject do this to this entity, e.g. can user:1 edit document:7
+pub async fn check_handler(
+  Extension(jwtauth): Extension<JWTAuthMiddleware>,
+  State(data): State<Arc<AppState>>,
+  Json(body): Json<CheckSchema>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+  authorize_caller(&jwtauth, &body.tenant_id, CHECK_PERMISSION)?;
+  let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
+  let (schema_version, schema) = load_schema(&mut conn, &body.tenant_id, body.metadata.schema_version.as_deref())?;
+
+  let mut engine = Engine {
+    store: &mut *conn,
+    tenant_id: &body.tenant_id,
+    schema: &schema,
+    context: &body.context.tuples,
+  };
+
+  let allowed = engine
+    .check(&body.entity, &body.permission, &body.subject, check_depth(&body.metadata))
+    .map_err(|e| permission_error(StatusCode::BAD_REQUEST, &e))?;
+
+  Ok(Json(serde_json::json!({
+    "allowed": allowed,
+    "metadata": { "schemaVersion": schema_version },
+  })))
+}
+
+pub async fn expand_handler(
+  Extension(jwtauth): Extension<JWTAuthMiddleware>,
+  State(data): State<Arc<AppState>>,
+  Json(body): Json<ExpandSchema>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+  authorize_caller(&jwtauth, &body.tenant_id, CHECK_PERMISSION)?;
+  let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
+  let (schema_version, schema) = load_schema(&mut conn, &body.tenant_id, body.metadata.schema_version.as_deref())?;
+
+  let mut engine = Engine {
+    store: &mut *conn,
+    tenant_id: &body.tenant_id,
+    schema: &schema,
+    context: &body.context.tuples,
+  };
+
+  let tree = engine
+    .expand(&body.entity, &body.permission, check_depth(&body.metadata))
+    .map_err(|e| permission_error(StatusCode::BAD_REQUEST, &e))?;
+
+  Ok(Json(serde_json::json!({
+    "tree": tree,
+    "metadata": { "schemaVersion": schema_version },
+  })))
+}
+
+// Who can do this to this entity, e.g. every user that can view document:7
+pub async fn lookup_subjects_handler(
+  Extension(jwtauth): Extension<JWTAuthMiddleware>,
+  State(data): State<Arc<AppState>>,
+  Json(body): Json<LookupSubjectsSchema>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+  authorize_caller(&jwtauth, &body.tenant_id, CHECK_PERMISSION)?;
+  let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
+  let (schema_version, schema) = load_schema(&mut conn, &body.tenant_id, body.metadata.schema_version.as_deref())?;
+
+  let mut engine = Engine {
+    store: &mut *conn,
+    tenant_id: &body.tenant_id,
+    schema: &schema,
+    context: &body.context.tuples,
+  };
+
+  let subject_ids = engine
+    .lookup_subjects(&body.entity, &body.permission, &body.subject_reference, check_depth(&body.metadata))
+    .map_err(|e| permission_error(StatusCode::BAD_REQUEST, &e))?;
+
+  Ok(Json(serde_json::json!({
+    "subjectIds": subject_ids,
+    "metadata": { "schemaVersion": schema_version },
+  })))
+}
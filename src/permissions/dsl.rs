@@ -0,0 +1,422 @@
+// The schema language, for example:
+//
+//   entity user {}
+//
+//   entity organization {
+//     relation admin @user
+//     relation member @user
+//     relation banned @user
+//   }
+//
+//   entity document {
+//     relation owner @user
+//     relation parent @organization
+//     relation viewer @user @organization#member
+//
+//     permission edit = owner or parent.admin
+//     permission view = (viewer or edit) not parent.banned
+//   }
+//
+// `a.b` follows relation a to the related entity and evaluates b there, `not` excludes.
+use std::collections::HashMap;
+
+#[derive(Debug, Clone)]
+pub enum Expr {
+  Ref(String),
+  Arrow(String, String),
+  Or(Box<Expr>, Box<Expr>),
+  And(Box<Expr>, Box<Expr>),
+  Not(Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone)]
+pub struct SubjectType {
+  pub entity_type: String,
+  pub relation: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Member {
+  Relation(Vec<SubjectType>),
+  Permission(Expr),
+}
+
+type ParsedEntity = (String, Vec<(String, Member)>);
+
+#[derive(Debug, Clone, Default)]
+pub struct EntityDefinition {
+  pub members: HashMap<String, Member>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Schema {
+  pub entities: HashMap<String, EntityDefinition>,
+}
+
+impl Schema {
+  pub fn member(&self, entity_type: &str, name: &str) -> Option<&Member> {
+    self.entities.get(entity_type)?.members.get(name)
+  }
+
+  pub fn relation(&self, entity_type: &str, name: &str) -> Option<&Vec<SubjectType>> {
+    match self.member(entity_type, name)? {
+      Member::Relation(types) => Some(types),
+      Member::Permission(_) => None,
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+  Ident(String),
+  Symbol(char),
+}
+
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, String> {
+  let mut tokens = vec![];
+
+  for (index, line) in source.lines().enumerate() {
+    let line_number = index + 1;
+    let line = line.split("//").next().unwrap_or_default();
+    let mut chars = line.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+      if c.is_whitespace() {
+        chars.next();
+      } else if c.is_ascii_alphabetic() || c == '_' {
+        let mut ident = String::new();
+        while let Some(&c) = chars.peek() {
+          if !(c.is_ascii_alphanumeric() || c == '_') {
+            break;
+          }
+          ident.push(c);
+          chars.next();
+        }
+        tokens.push((Token::Ident(ident), line_number));
+      } else if "{}()=.@#".contains(c) {
+        tokens.push((Token::Symbol(c), line_number));
+        chars.next();
+      } else {
+        return Err(format!("Unexpected character '{}' on line {}", c, line_number));
+      }
+    }
+  }
+
+  Ok(tokens)
+}
+
+const KEYWORDS: [&str; 6] = ["entity", "relation", "permission", "or", "and", "not"];
+
+struct Parser {
+  tokens: Vec<(Token, usize)>,
+  position: usize,
+}
+
+impl Parser {
+  fn peek(&self) -> Option<&Token> {
+    self.tokens.get(self.position).map(|(token, _)| token)
+  }
+
+  fn line(&self) -> usize {
+    self.tokens
+      .get(self.position)
+      .or(self.tokens.last())
+      .map(|(_, line)| *line)
+      .unwrap_or(1)
+  }
+
+  fn next(&mut self) -> Option<Token> {
+    let token = self.tokens.get(self.position).map(|(token, _)| token.clone());
+    self.position += 1;
+    token
+  }
+
+  fn is_keyword(&self, keyword: &str) -> bool {
+    matches!(self.peek(), Some(Token::Ident(ident)) if ident == keyword)
+  }
+
+  fn is_symbol(&self, symbol: char) -> bool {
+    self.peek() == Some(&Token::Symbol(symbol))
+  }
+
+  fn expect_keyword(&mut self, keyword: &str) -> Result<(), String> {
+    if self.is_keyword(keyword) {
+      self.next();
+      Ok(())
+    } else {
+      Err(format!("Expected '{}' on line {}", keyword, self.line()))
+    }
+  }
+
+  fn expect_symbol(&mut self, symbol: char) -> Result<(), String> {
+    if self.is_symbol(symbol) {
+      self.next();
+      Ok(())
+    } else {
+      Err(format!("Expected '{}' on line {}", symbol, self.line()))
+    }
+  }
+
+  fn name(&mut self) -> Result<String, String> {
+    let line = self.line();
+    match self.next() {
+      Some(Token::Ident(ident)) if !KEYWORDS.contains(&ident.as_str()) => Ok(ident),
+      _ => Err(format!("Expected a name on line {}", line)),
+    }
+  }
+
+  fn schema(&mut self) -> Result<Vec<ParsedEntity>, String> {
+    let mut entities = vec![];
+    while self.peek().is_some() {
+      entities.push(self.entity()?);
+    }
+    Ok(entities)
+  }
+
+  fn entity(&mut self) -> Result<ParsedEntity, String> {
+    self.expect_keyword("entity")?;
+    let name = self.name()?;
+    self.expect_symbol('{')?;
+
+    let mut members = vec![];
+    while !self.is_symbol('}') {
+      if self.is_keyword("relation") {
+        self.next();
+        members.push(self.relation()?);
+      } else if self.is_keyword("permission") {
+        self.next();
+        let name = self.name()?;
+        self.expect_symbol('=')?;
+        members.push((name, Member::Permission(self.or_expr()?)));
+      } else {
+        return Err(format!("Expected 'relation', 'permission' or '}}' on line {}", self.line()));
+      }
+    }
+    self.expect_symbol('}')?;
+
+    Ok((name, members))
+  }
+
+  fn relation(&mut self) -> Result<(String, Member), String> {
+    let name = self.name()?;
+    let mut types = vec![];
+
+    while self.is_symbol('@') {
+      self.next();
+      let entity_type = self.name()?;
+      let relation = if self.is_symbol('#') {
+        self.next();
+        Some(self.name()?)
+      } else {
+        None
+      };
+      types.push(SubjectType { entity_type, relation });
+    }
+
+    if types.is_empty() {
+      return Err(format!("Relation '{}' needs at least one @type on line {}", name, self.line()));
+    }
+
+    Ok((name, Member::Relation(types)))
+  }
+
+  fn or_expr(&mut self) -> Result<Expr, String> {
+    let mut expr = self.and_expr()?;
+    while self.is_keyword("or") {
+      self.next();
+      expr = Expr::Or(Box::new(expr), Box::new(self.and_expr()?));
+    }
+    Ok(expr)
+  }
+
+  fn and_expr(&mut self) -> Result<Expr, String> {
+    let mut expr = self.term()?;
+    loop {
+      if self.is_keyword("and") {
+        self.next();
+        expr = Expr::And(Box::new(expr), Box::new(self.term()?));
+      } else if self.is_keyword("not") {
+        self.next();
+        expr = Expr::Not(Box::new(expr), Box::new(self.term()?));
+      } else {
+        return Ok(expr);
+      }
+    }
+  }
+
+  fn term(&mut self) -> Result<Expr, String> {
+    if self.is_symbol('(') {
+      self.next();
+      let expr = self.or_expr()?;
+      self.expect_symbol(')')?;
+      return Ok(expr);
+    }
+
+    let name = self.name()?;
+    if self.is_symbol('.') {
+      self.next();
+      return Ok(Expr::Arrow(name, self.name()?));
+    }
+    Ok(Expr::Ref(name))
+  }
+}
+
+pub fn parse_schema(source: &str) -> Result<Schema, String> {
+  let mut parser = Parser {
+    tokens: tokenize(source)?,
+    position: 0,
+  };
+
+  let mut schema = Schema::default();
+  for (name, members) in parser.schema()? {
+    let mut definition = EntityDefinition::default();
+    for (member_name, member) in members {
+      if definition.members.insert(member_name.clone(), member).is_some() {
+        return Err(format!("'{}' is defined twice on entity '{}'", member_name, name));
+      }
+    }
+    if schema.entities.insert(name.clone(), definition).is_some() {
+      return Err(format!("Entity '{}' is defined twice", name));
+    }
+  }
+
+  validate(&schema)?;
+  Ok(schema)
+}
+
+// Every name used must resolve, so checks never meet an undefined relation at runtime
+fn validate(schema: &Schema) -> Result<(), String> {
+  for (entity_name, definition) in &schema.entities {
+    for (member_name, member) in &definition.members {
+      match member {
+        Member::Relation(types) => {
+          for subject_type in types {
+            if !schema.entities.contains_key(&subject_type.entity_type) {
+              return Err(format!("{}.{} refers to unknown entity '{}'", entity_name, member_name, subject_type.entity_type));
+            }
+            if let Some(relation) = &subject_type.relation {
+              if schema.member(&subject_type.entity_type, relation).is_none() {
+                return Err(format!("{}.{} refers to unknown '{}#{}'", entity_name, member_name, subject_type.entity_type, relation));
+              }
+            }
+          }
+        }
+        Member::Permission(expr) => validate_expr(schema, entity_name, member_name, expr)?,
+      }
+    }
+  }
+  Ok(())
+}
+
+fn validate_expr(schema: &Schema, entity_name: &str, member_name: &str, expr: &Expr) -> Result<(), String> {
+  match expr {
+    Expr::Ref(name) => {
+      if schema.member(entity_name, name).is_none() {
+        return Err(format!("{}.{} refers to unknown '{}'", entity_name, member_name, name));
+      }
+    }
+    Expr::Arrow(relation, permission) => {
+      let types = schema.relation(entity_name, relation).ok_or_else(|| {
+        format!("{}.{} follows '{}', which is not a relation", entity_name, member_name, relation)
+      })?;
+      for subject_type in types {
+        if schema.member(&subject_type.entity_type, permission).is_none() {
+          return Err(format!("{}.{} refers to '{}', which '{}' does not define", entity_name, member_name, permission, subject_type.entity_type));
+        }
+      }
+    }
+    Expr::Or(left, right) | Expr::And(left, right) | Expr::Not(left, right) => {
+      validate_expr(schema, entity_name, member_name, left)?;
+      validate_expr(schema, entity_name, member_name, right)?;
+    }
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // The example at the top of this file
+  const EXAMPLE: &str = "
+    entity user {}
+
+    entity organization {
+      relation admin @user
+      relation member @user
+      relation banned @user
+    }
+
+    entity document {
+      relation owner @user
+      relation parent @organization
+      relation viewer @user @organization#member
+
+      permission edit = owner or parent.admin
+      permission view = (viewer or edit) not parent.banned
+    }
+  ";
+
+  fn permission(source: &str, entity: &str, name: &str) -> String {
+    let schema = parse_schema(source).unwrap();
+    match schema.member(entity, name) {
+      Some(Member::Permission(expr)) => format!("{:?}", expr),
+      other => panic!("{} is not a permission: {:?}", name, other),
+    }
+  }
+
+  fn doc(permission: &str) -> String {
+    format!("entity user {{}} entity doc {{ relation a @user relation b @user relation c @user permission p = {} }}", permission)
+  }
+
+  #[test]
+  fn parses_the_example() {
+    let schema = parse_schema(EXAMPLE).unwrap();
+    assert_eq!(schema.entities.len(), 3);
+    assert_eq!(
+      permission(EXAMPLE, "document", "view"),
+      r#"Not(Or(Ref("viewer"), Ref("edit")), Arrow("parent", "banned"))"#,
+    );
+  }
+
+  #[test]
+  fn and_binds_tighter_than_or() {
+    assert_eq!(permission(&doc("a or b and c"), "doc", "p"), r#"Or(Ref("a"), And(Ref("b"), Ref("c")))"#);
+    assert_eq!(permission(&doc("(a or b) and c"), "doc", "p"), r#"And(Or(Ref("a"), Ref("b")), Ref("c"))"#);
+  }
+
+  #[test]
+  fn not_binds_like_and_from_the_left() {
+    assert_eq!(permission(&doc("a not b and c"), "doc", "p"), r#"And(Not(Ref("a"), Ref("b")), Ref("c"))"#);
+    assert_eq!(permission(&doc("a or b not c"), "doc", "p"), r#"Or(Ref("a"), Not(Ref("b"), Ref("c")))"#);
+  }
+
+  #[test]
+  fn rejects_unknown_names() {
+    assert!(parse_schema(&doc("a or missing")).unwrap_err().contains("unknown 'missing'"));
+    assert!(parse_schema("entity doc { relation owner @person }").unwrap_err().contains("unknown entity 'person'"));
+    assert!(parse_schema("entity user {} entity doc { relation viewer @user#member }").unwrap_err().contains("'user#member'"));
+  }
+
+  #[test]
+  fn rejects_arrows_that_do_not_resolve() {
+    let missing_permission = "entity user {} entity org {} entity doc { relation parent @org permission p = parent.admin }";
+    assert!(parse_schema(missing_permission).unwrap_err().contains("'org' does not define"));
+
+    let not_a_relation = "entity user {} entity doc { relation a @user permission q = a permission p = q.a }";
+    assert!(parse_schema(not_a_relation).unwrap_err().contains("not a relation"));
+  }
+
+  #[test]
+  fn rejects_bad_syntax_with_the_line() {
+    assert_eq!(parse_schema("entity user {}\nentity doc {\n  relation owner user\n}").unwrap_err(), "Relation 'owner' needs at least one @type on line 3");
+    assert_eq!(parse_schema("entity doc { permission p = owner or }").unwrap_err(), "Expected a name on line 1");
+    assert_eq!(parse_schema("entity doc { relation a @user; }").unwrap_err(), "Unexpected character ';' on line 1");
+  }
+
+  #[test]
+  fn rejects_duplicates() {
+    assert!(parse_schema("entity user {} entity user {}").unwrap_err().contains("defined twice"));
+    assert!(parse_schema("entity user {} entity doc { relation a @user relation a @user }").unwrap_err().contains("defined twice"));
+  }
+}
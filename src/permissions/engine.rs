@@ -0,0 +1,389 @@
+use std::collections::BTreeSet;
+use axum::{http::StatusCode, Json};
+use diesel::prelude::*;
+use serde_json::json;
+
+use crate::schema::{permission_schemas, relation_tuples, PermissionSchema, RelationTuple};
+
+use super::{
+  dsl::{parse_schema, Expr, Member, Schema},
+  model::{permission_error, Entity, Subject, SubjectReference, Tuple},
+};
+
+// The latest schema for the tenant, or the version the caller pinned
+pub fn load_schema(conn: &mut PgConnection, tenant_id: &str, version: Option<&str>)
+  -> Result<(String, Schema), (StatusCode, Json<serde_json::Value>)> {
+  let mut query = permission_schemas::table
+    .filter(permission_schemas::tenant_id.eq(tenant_id.to_string()))
+    .filter(permission_schemas::deleted_at.is_null())
+    .into_boxed();
+
+  if let Some(version) = version {
+    query = query.filter(permission_schemas::id.eq(version.to_string()));
+  }
+
+  let stored = query
+    .order(permission_schemas::created_at.desc())
+    .first::<PermissionSchema>(conn)
+    .optional()
+    .unwrap_or(None)
+    .ok_or_else(|| permission_error(StatusCode::NOT_FOUND, "No schema has been written for this tenant"))?;
+
+  let schema = parse_schema(&stored.schema)
+    .map_err(|e| permission_error(StatusCode::INTERNAL_SERVER_ERROR, &format!("Stored schema is invalid: {}", e)))?;
+
+  Ok((stored.id, schema))
+}
+
+fn related_entity(subject: &Subject) -> Entity {
+  Entity {
+    entity_type: subject.subject_type.clone(),
+    id: subject.id.clone(),
+  }
+}
+
+// Where stored tuples are read from, the database outside of tests
+pub trait TupleStore {
+  fn subjects(&mut self, tenant_id: &str, entity: &Entity, relation: &str) -> Vec<Subject>;
+}
+
+impl TupleStore for PgConnection {
+  fn subjects(&mut self, tenant_id: &str, entity: &Entity, relation: &str) -> Vec<Subject> {
+    relation_tuples::table
+      .filter(relation_tuples::tenant_id.eq(tenant_id.to_string()))
+      .filter(relation_tuples::entity_type.eq(entity.entity_type.clone()))
+      .filter(relation_tuples::entity_id.eq(entity.id.clone()))
+      .filter(relation_tuples::relation.eq(relation.to_string()))
+      .filter(relation_tuples::deleted_at.is_null())
+      .load::<RelationTuple>(self)
+      .unwrap_or_default()
+      .into_iter()
+      .map(|tuple| Subject {
+        subject_type: tuple.subject_type,
+        id: tuple.subject_id,
+        relation: tuple.subject_relation,
+      })
+      .collect()
+  }
+}
+
+pub struct Engine<'a> {
+  pub store: &'a mut dyn TupleStore,
+  pub tenant_id: &'a str,
+  pub schema: &'a Schema,
+  // Contextual tuples only exist for the one request
+  pub context: &'a [Tuple],
+}
+
+impl<'a> Engine<'a> {
+  fn subjects(&mut self, entity: &Entity, relation: &str) -> Vec<Subject> {
+    self.store
+      .subjects(self.tenant_id, entity, relation)
+      .into_iter()
+      .chain(
+        self.context
+          .iter()
+          .filter(|tuple| tuple.entity == *entity && tuple.relation == relation)
+          .map(|tuple| tuple.subject.clone()),
+      )
+      .collect()
+  }
+
+  fn member(&self, entity: &Entity, name: &str, depth: i32) -> Result<&'a Member, String> {
+    if depth <= 0 {
+      return Err("Depth limit reached".to_string());
+    }
+
+    self.schema
+      .member(&entity.entity_type, name)
+      .ok_or_else(|| format!("'{}' is not defined on '{}'", name, entity.entity_type))
+  }
+
+  pub fn check(&mut self, entity: &Entity, name: &str, subject: &Subject, depth: i32) -> Result<bool, String> {
+    match self.member(entity, name, depth)? {
+      Member::Permission(expr) => self.check_expr(entity, expr, subject, depth),
+      Member::Relation(_) => {
+        for related in self.subjects(entity, name) {
+          if related == *subject {
+            return Ok(true);
+          }
+          // Usersets such as organization:1#member are checked on the related entity
+          if let Some(relation) = &related.relation {
+            if self.check(&related_entity(&related), relation, subject, depth - 1)? {
+              return Ok(true);
+            }
+          }
+        }
+        Ok(false)
+      }
+    }
+  }
+
+  fn check_expr(&mut self, entity: &Entity, expr: &Expr, subject: &Subject, depth: i32) -> Result<bool, String> {
+    match expr {
+      Expr::Ref(name) => self.check(entity, name, subject, depth - 1),
+      Expr::Arrow(relation, permission) => {
+        for related in self.subjects(entity, relation) {
+          if self.check(&related_entity(&related), permission, subject, depth - 1)? {
+            return Ok(true);
+          }
+        }
+        Ok(false)
+      }
+      Expr::Or(left, right) => Ok(self.check_expr(entity, left, subject, depth)? || self.check_expr(entity, right, subject, depth)?),
+      Expr::And(left, right) => Ok(self.check_expr(entity, left, subject, depth)? && self.check_expr(entity, right, subject, depth)?),
+      Expr::Not(left, right) => Ok(self.check_expr(entity, left, subject, depth)? && !self.check_expr(entity, right, subject, depth)?),
+    }
+  }
+
+  // The tree of relations and operations that makes up a permission, with direct subjects as leaves
+  pub fn expand(&mut self, entity: &Entity, name: &str, depth: i32) -> Result<serde_json::Value, String> {
+    match self.member(entity, name, depth)? {
+      Member::Permission(expr) => {
+        let mut node = self.expand_expr(entity, expr, depth)?;
+        node["entity"] = json!(entity);
+        node["permission"] = json!(name);
+        Ok(node)
+      }
+      Member::Relation(_) => Ok(json!({
+        "entity": entity,
+        "relation": name,
+        "leaf": { "subjects": self.subjects(entity, name) },
+      })),
+    }
+  }
+
+  fn expand_expr(&mut self, entity: &Entity, expr: &Expr, depth: i32) -> Result<serde_json::Value, String> {
+    let (operation, children) = match expr {
+      Expr::Ref(name) => return self.expand(entity, name, depth - 1),
+      Expr::Arrow(relation, permission) => {
+        let mut children = vec![];
+        for related in self.subjects(entity, relation) {
+          children.push(self.expand(&related_entity(&related), permission, depth - 1)?);
+        }
+        return Ok(json!({
+          "entity": entity,
+          "relation": format!("{}.{}", relation, permission),
+          "operation": "union",
+          "children": children,
+        }));
+      }
+      Expr::Or(left, right) => ("union", (left, right)),
+      Expr::And(left, right) => ("intersection", (left, right)),
+      Expr::Not(left, right) => ("exclusion", (left, right)),
+    };
+
+    Ok(json!({
+      "operation": operation,
+      "children": [
+        self.expand_expr(entity, children.0, depth)?,
+        self.expand_expr(entity, children.1, depth)?,
+      ],
+    }))
+  }
+
+  // Every subject of the referenced type that holds the permission
+  pub fn lookup_subjects(&mut self, entity: &Entity, name: &str, reference: &SubjectReference, depth: i32)
+    -> Result<BTreeSet<String>, String> {
+    match self.member(entity, name, depth)? {
+      Member::Permission(expr) => self.lookup_expr(entity, expr, reference, depth),
+      Member::Relation(_) => {
+        let mut ids = BTreeSet::new();
+        for related in self.subjects(entity, name) {
+          if related.subject_type == reference.subject_type && related.relation == reference.relation {
+            ids.insert(related.id.clone());
+          }
+          if let Some(relation) = &related.relation {
+            ids.extend(self.lookup_subjects(&related_entity(&related), relation, reference, depth - 1)?);
+          }
+        }
+        Ok(ids)
+      }
+    }
+  }
+
+  fn lookup_expr(&mut self, entity: &Entity, expr: &Expr, reference: &SubjectReference, depth: i32)
+    -> Result<BTreeSet<String>, String> {
+    match expr {
+      Expr::Ref(name) => self.lookup_subjects(entity, name, reference, depth - 1),
+      Expr::Arrow(relation, permission) => {
+        let mut ids = BTreeSet::new();
+        for related in self.subjects(entity, relation) {
+          ids.extend(self.lookup_subjects(&related_entity(&related), permission, reference, depth - 1)?);
+        }
+        Ok(ids)
+      }
+      Expr::Or(left, right) => {
+        let left = self.lookup_expr(entity, left, reference, depth)?;
+        let right = self.lookup_expr(entity, right, reference, depth)?;
+        Ok(left.union(&right).cloned().collect())
+      }
+      Expr::And(left, right) => {
+        let left = self.lookup_expr(entity, left, reference, depth)?;
+        let right = self.lookup_expr(entity, right, reference, depth)?;
+        Ok(left.intersection(&right).cloned().collect())
+      }
+      Expr::Not(left, right) => {
+        let left = self.lookup_expr(entity, left, reference, depth)?;
+        let right = self.lookup_expr(entity, right, reference, depth)?;
+        Ok(left.difference(&right).cloned().collect())
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::permissions::model::DEFAULT_CHECK_DEPTH;
+
+  const SCHEMA: &str = "
+    entity user {}
+
+    entity group {
+      relation member @user @group#member
+    }
+
+    entity organization {
+      relation admin @user
+      relation member @user @group#member
+      relation banned @user
+    }
+
+    entity document {
+      relation owner @user
+      relation parent @organization
+      relation viewer @user @organization#member
+      relation approved @user
+
+      permission edit = owner or parent.admin
+      permission view = (viewer or edit) not parent.banned
+      permission publish = edit and approved
+    }
+  ";
+
+  // Stored tuples for a single tenant, in place of the database
+  struct MemoryTuples(Vec<Tuple>);
+
+  impl TupleStore for MemoryTuples {
+    fn subjects(&mut self, _tenant_id: &str, entity: &Entity, relation: &str) -> Vec<Subject> {
+      self.0
+        .iter()
+        .filter(|tuple| tuple.entity == *entity && tuple.relation == relation)
+        .map(|tuple| tuple.subject.clone())
+        .collect()
+    }
+  }
+
+  fn entity(reference: &str) -> Entity {
+    let (entity_type, id) = reference.split_once(':').unwrap();
+    Entity { entity_type: entity_type.into(), id: id.into() }
+  }
+
+  fn subject(reference: &str) -> Subject {
+    let (reference, relation) = match reference.split_once('#') {
+      Some((reference, relation)) => (reference, Some(relation.to_string())),
+      None => (reference, None),
+    };
+    let (subject_type, id) = reference.split_once(':').unwrap();
+    Subject { subject_type: subject_type.into(), id: id.into(), relation }
+  }
+
+  // "document:1#owner@user:ann"
+  fn tuple(text: &str) -> Tuple {
+    let (object, subject_reference) = text.split_once('@').unwrap();
+    let (object, relation) = object.split_once('#').unwrap();
+    Tuple { entity: entity(object), relation: relation.into(), subject: subject(subject_reference) }
+  }
+
+  fn check(tuples: &[&str], object: &str, permission: &str, who: &str) -> Result<bool, String> {
+    let schema = parse_schema(SCHEMA).unwrap();
+    let mut store = MemoryTuples(tuples.iter().map(|text| tuple(text)).collect());
+    let mut engine = Engine { store: &mut store, tenant_id: "tenant", schema: &schema, context: &[] };
+    engine.check(&entity(object), permission, &subject(who), DEFAULT_CHECK_DEPTH)
+  }
+
+  #[test]
+  fn direct_relations() {
+    let tuples = ["document:1#owner@user:ann"];
+    assert_eq!(check(&tuples, "document:1", "owner", "user:ann"), Ok(true));
+    assert_eq!(check(&tuples, "document:1", "owner", "user:bob"), Ok(false));
+    assert_eq!(check(&tuples, "document:2", "owner", "user:ann"), Ok(false));
+  }
+
+  #[test]
+  fn union_and_intersection() {
+    let tuples = ["document:1#owner@user:ann", "document:1#approved@user:ann", "document:1#owner@user:bob"];
+    assert_eq!(check(&tuples, "document:1", "edit", "user:bob"), Ok(true));
+    assert_eq!(check(&tuples, "document:1", "publish", "user:ann"), Ok(true));
+    assert_eq!(check(&tuples, "document:1", "publish", "user:bob"), Ok(false));
+  }
+
+  #[test]
+  fn arrows_follow_the_relation() {
+    let tuples = ["document:1#parent@organization:acme", "organization:acme#admin@user:ann"];
+    assert_eq!(check(&tuples, "document:1", "edit", "user:ann"), Ok(true));
+    assert_eq!(check(&tuples, "document:2", "edit", "user:ann"), Ok(false));
+  }
+
+  #[test]
+  fn usersets_are_checked_on_the_related_entity() {
+    let tuples = [
+      "document:1#viewer@organization:acme#member",
+      "organization:acme#member@group:eng#member",
+      "group:eng#member@user:ann",
+    ];
+    assert_eq!(check(&tuples, "document:1", "view", "user:ann"), Ok(true));
+    assert_eq!(check(&tuples, "document:1", "view", "user:bob"), Ok(false));
+  }
+
+  #[test]
+  fn not_excludes() {
+    let tuples = [
+      "document:1#viewer@user:ann",
+      "document:1#owner@user:bob",
+      "document:1#parent@organization:acme",
+      "organization:acme#banned@user:bob",
+    ];
+    assert_eq!(check(&tuples, "document:1", "view", "user:ann"), Ok(true));
+    assert_eq!(check(&tuples, "document:1", "edit", "user:bob"), Ok(true));
+    // Editing does not outweigh the ban, the exclusion applies to the whole parenthesis
+    assert_eq!(check(&tuples, "document:1", "view", "user:bob"), Ok(false));
+  }
+
+  #[test]
+  fn unknown_names_are_errors() {
+    assert!(check(&[], "document:1", "delete", "user:ann").unwrap_err().contains("'delete' is not defined"));
+    assert!(check(&[], "folder:1", "view", "user:ann").unwrap_err().contains("not defined on 'folder'"));
+  }
+
+  #[test]
+  fn cycles_stop_at_the_depth_limit() {
+    let tuples = ["group:a#member@group:b#member", "group:b#member@group:a#member"];
+    assert_eq!(check(&tuples, "group:a", "member", "user:ann"), Err("Depth limit reached".to_string()));
+  }
+
+  #[test]
+  fn context_tuples_count_for_one_check() {
+    let schema = parse_schema(SCHEMA).unwrap();
+    let mut store = MemoryTuples(vec![]);
+    let context = [tuple("document:1#owner@user:ann")];
+    let mut engine = Engine { store: &mut store, tenant_id: "tenant", schema: &schema, context: &context };
+    assert_eq!(engine.check(&entity("document:1"), "edit", &subject("user:ann"), DEFAULT_CHECK_DEPTH), Ok(true));
+  }
+
+  #[test]
+  fn lookup_subjects_applies_the_operations() {
+    let schema = parse_schema(SCHEMA).unwrap();
+    let mut store = MemoryTuples(
+      ["document:1#viewer@user:ann", "document:1#viewer@user:bob", "document:1#parent@organization:acme", "organization:acme#banned@user:bob"]
+        .iter()
+        .map(|text| tuple(text))
+        .collect(),
+    );
+    let mut engine = Engine { store: &mut store, tenant_id: "tenant", schema: &schema, context: &[] };
+    let reference = SubjectReference { subject_type: "user".into(), relation: None };
+    let ids = engine.lookup_subjects(&entity("document:1"), "view", &reference, DEFAULT_CHECK_DEPTH).unwrap();
+    assert_eq!(ids.into_iter().collect::<Vec<_>>(), vec!["ann".to_string()]);
+  }
+}
@@ -0,0 +1,6 @@
+pub mod check_handler;
+pub mod dsl;
+pub mod engine;
+pub mod model;
+pub mod relationship_handler;
+pub mod schema_handler;
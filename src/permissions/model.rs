@@ -0,0 +1,144 @@
+use axum::{http::StatusCode, Json};
+use serde::{Deserialize, Serialize};
+
+use crate::{jwt_auth::{JWTAuthMiddleware, Principal}, oauth_server::model::has_scope};
+
+pub const CHECK_PERMISSION: &str = "permissions:check";
+pub const WRITE_PERMISSION: &str = "permissions:write";
+
+// How many relations a check may follow before giving up, guards against cycles in the data
+pub const DEFAULT_CHECK_DEPTH: i32 = 20;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize)]
+pub struct Entity {
+  #[serde(rename = "type")]
+  pub entity_type: String,
+  pub id: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize)]
+pub struct Subject {
+  #[serde(rename = "type")]
+  pub subject_type: String,
+  pub id: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub relation: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Tuple {
+  pub entity: Entity,
+  pub relation: String,
+  pub subject: Subject,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct PermissionMetadata {
+  #[serde(rename = "schemaVersion")]
+  pub schema_version: Option<String>,
+  // Accepted for compatibility, reads always see the latest tuples
+  #[serde(rename = "snapToken")]
+  #[allow(dead_code)]
+  pub snap_token: Option<String>,
+  pub depth: Option<i32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct PermissionContext {
+  #[serde(default)]
+  pub tuples: Vec<Tuple>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SchemaWriteSchema {
+  pub schema: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CheckSchema {
+  #[serde(rename = "tenantId")]
+  pub tenant_id: String,
+  #[serde(default)]
+  pub metadata: PermissionMetadata,
+  pub entity: Entity,
+  pub permission: String,
+  pub subject: Subject,
+  #[serde(default)]
+  pub context: PermissionContext,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExpandSchema {
+  #[serde(rename = "tenantId")]
+  pub tenant_id: String,
+  #[serde(default)]
+  pub metadata: PermissionMetadata,
+  pub entity: Entity,
+  pub permission: String,
+  #[serde(default)]
+  pub context: PermissionContext,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SubjectReference {
+  #[serde(rename = "type")]
+  pub subject_type: String,
+  pub relation: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LookupSubjectsSchema {
+  #[serde(rename = "tenantId")]
+  pub tenant_id: String,
+  #[serde(default)]
+  pub metadata: PermissionMetadata,
+  pub entity: Entity,
+  pub permission: String,
+  #[serde(rename = "subjectReference")]
+  pub subject_reference: SubjectReference,
+  #[serde(default)]
+  pub context: PermissionContext,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RelationshipWriteSchema {
+  #[serde(rename = "tenantId")]
+  pub tenant_id: String,
+  #[serde(rename = "schemaVersion")]
+  pub schema_version: Option<String>,
+  pub tuples: Vec<Tuple>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RelationshipDeleteSchema {
+  #[serde(rename = "tenantId")]
+  pub tenant_id: String,
+  pub tuples: Vec<Tuple>,
+}
+
+pub fn permission_error(status: StatusCode, message: &str) -> (StatusCode, Json<serde_json::Value>) {
+  let error_response = serde_json::json!({
+    "status": "fail",
+    "message": message,
+  });
+  (status, Json(error_response))
+}
+
+// Services call these with a client_credentials token scoped to the permission, people need the
+// RBAC permission and must be acting in the tenant they ask about
+pub fn authorize_caller(jwtauth: &JWTAuthMiddleware, tenant_id: &str, permission: &str)
+  -> Result<(), (StatusCode, Json<serde_json::Value>)> {
+  let allowed = match jwtauth.principal {
+    Principal::Client(_) => has_scope(jwtauth.scope.as_deref().unwrap_or_default(), permission),
+    Principal::User(_) => jwtauth.has_permission(permission),
+  };
+
+  if !allowed {
+    return Err(permission_error(StatusCode::FORBIDDEN, &format!("Missing permission: {}", permission)));
+  }
+
+  if matches!(jwtauth.principal, Principal::User(_)) && jwtauth.tenant_id.as_deref() != Some(tenant_id) {
+    return Err(permission_error(StatusCode::FORBIDDEN, "Switch to this tenant first"));
+  }
+  Ok(())
+}
@@ -0,0 +1,140 @@
+use std::sync::Arc;
+use axum::{
+  extract::State,
+  http::StatusCode,
+  response::IntoResponse,
+  Extension, Json,
+};
+use chrono::Utc;
+use diesel::prelude::*;
+use ulid::Ulid;
+
+use crate::{jwt_auth::JWTAuthMiddleware, schema::{relation_tuples, RelationTuple}, AppState};
+
+use super::{
+  dsl::Schema,
+  engine::load_schema,
+  model::{authorize_caller, permission_error, RelationshipDeleteSchema, RelationshipWriteSchema, Tuple, WRITE_PERMISSION},
+};
+
+// A tuple may only use a relation the schema defines, with a subject type that relation allows
+fn validate_tuple(schema: &Schema, tuple: &Tuple) -> Result<(), String> {
+  let allowed = schema.relation(&tuple.entity.entity_type, &tuple.relation).ok_or_else(|| {
+    format!("'{}' is not a relation on '{}'", tuple.relation, tuple.entity.entity_type)
+  })?;
+
+  let subject_allowed = allowed.iter().any(|subject_type| {
+    subject_type.entity_type == tuple.subject.subject_type && subject_type.relation == tuple.subject.relation
+  });
+
+  if !subject_allowed {
+    let subject = match &tuple.subject.relation {
+      Some(relation) => format!("{}#{}", tuple.subject.subject_type, relation),
+      None => tuple.subject.subject_type.clone(),
+    };
+    return Err(format!("{}.{} does not allow '{}'", tuple.entity.entity_type, tuple.relation, subject));
+  }
+
+  Ok(())
+}
+
+fn matching_tuple(tenant_id: &str, tuple: &Tuple) -> relation_tuples::BoxedQuery<'static, diesel::pg::Pg> {
+  let query = relation_tuples::table
+    .filter(relation_tuples::tenant_id.eq(tenant_id.to_string()))
+    .filter(relation_tuples::entity_type.eq(tuple.entity.entity_type.clone()))
+    .filter(relation_tuples::entity_id.eq(tuple.entity.id.clone()))
+    .filter(relation_tuples::relation.eq(tuple.relation.clone()))
+    .filter(relation_tuples::subject_type.eq(tuple.subject.subject_type.clone()))
+    .filter(relation_tuples::subject_id.eq(tuple.subject.id.clone()))
+    .into_boxed();
+
+  match &tuple.subject.relation {
+    Some(relation) => query.filter(relation_tuples::subject_relation.eq(relation.clone())),
+    None => query.filter(relation_tuples::subject_relation.is_null()),
+  }
+}
+
+pub async fn write_relationships_handler(
+  Extension(jwtauth): Extension<JWTAuthMiddleware>,
+  State(data): State<Arc<AppState>>,
+  Json(body): Json<RelationshipWriteSchema>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+  authorize_caller(&jwtauth, &body.tenant_id, WRITE_PERMISSION)?;
+  let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
+  let (_, schema) = load_schema(&mut conn, &body.tenant_id, body.schema_version.as_deref())?;
+  for tuple in &body.tuples {
+    validate_tuple(&schema, tuple).map_err(|e| permission_error(StatusCode::BAD_REQUEST, &e))?;
+  }
+
+  // All or nothing, and writing a tuple that already exists is a no-op
+  let result = conn.transaction::<usize, diesel::result::Error, _>(|conn| {
+    let mut written = 0;
+    for tuple in &body.tuples {
+      let exists = matching_tuple(&body.tenant_id, tuple)
+        .filter(relation_tuples::deleted_at.is_null())
+        .first::<RelationTuple>(conn)
+        .optional()?;
+
+      if exists.is_some() {
+        continue;
+      }
+
+      written += diesel::insert_into(relation_tuples::table)
+        .values(&RelationTuple {
+          id: Ulid::new().to_string(),
+          tenant_id: body.tenant_id.clone(),
+          entity_type: tuple.entity.entity_type.clone(),
+          entity_id: tuple.entity.id.clone(),
+          relation: tuple.relation.clone(),
+          subject_type: tuple.subject.subject_type.clone(),
+          subject_id: tuple.subject.id.clone(),
+          subject_relation: tuple.subject.relation.clone(),
+          created_at: Utc::now().naive_utc(),
+          updated_at: None,
+          deleted_at: None
+        })
+        .execute(conn)?;
+    }
+    Ok(written)
+  });
+
+  match result {
+    Ok(written) => Ok(Json(serde_json::json!({
+      "status": "success",
+      "data": { "written": written },
+    }))),
+    Err(e) => Err(permission_error(StatusCode::BAD_REQUEST, &format!("Relationships not saved to database: {}", e))),
+  }
+}
+
+pub async fn delete_relationships_handler(
+  Extension(jwtauth): Extension<JWTAuthMiddleware>,
+  State(data): State<Arc<AppState>>,
+  Json(body): Json<RelationshipDeleteSchema>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+  authorize_caller(&jwtauth, &body.tenant_id, WRITE_PERMISSION)?;
+  let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
+  let result = conn.transaction::<usize, diesel::result::Error, _>(|conn| {
+    let mut deleted = 0;
+    for tuple in &body.tuples {
+      let ids = matching_tuple(&body.tenant_id, tuple)
+        .select(relation_tuples::id)
+        .load::<String>(conn)?;
+
+      deleted += diesel::delete(relation_tuples::table)
+        .filter(relation_tuples::id.eq_any(ids))
+        .execute(conn)?;
+    }
+    Ok(deleted)
+  });
+
+  match result {
+    Ok(deleted) => Ok(Json(serde_json::json!({
+      "status": "success",
+      "data": { "deleted": deleted },
+    }))),
+    Err(e) => Err(permission_error(StatusCode::BAD_REQUEST, &format!("Relationships could not be deleted: {}", e))),
+  }
+}
@@ -0,0 +1,72 @@
+use std::sync::Arc;
+use axum::{
+  extract::{Path, State},
+  http::StatusCode,
+  response::IntoResponse,
+  Extension, Json,
+};
+use chrono::Utc;
+use diesel::RunQueryDsl;
+use ulid::Ulid;
+
+use crate::{jwt_auth::JWTAuthMiddleware, schema::{permission_schemas, PermissionSchema}, AppState};
+
+use super::{
+  dsl::parse_schema,
+  engine::load_schema,
+  model::{authorize_caller, permission_error, SchemaWriteSchema, CHECK_PERMISSION, WRITE_PERMISSION},
+};
+
+// Each write is a new version, older versions stay readable for checks that pin them
+pub async fn write_schema_handler(
+  Extension(jwtauth): Extension<JWTAuthMiddleware>,
+  State(data): State<Arc<AppState>>,
+  Path(tenant_id): Path<String>,
+  Json(body): Json<SchemaWriteSchema>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+  authorize_caller(&jwtauth, &tenant_id, WRITE_PERMISSION)?;
+  let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
+  parse_schema(&body.schema).map_err(|e| permission_error(StatusCode::BAD_REQUEST, &e))?;
+
+  let schema_version = Ulid::new().to_string();
+  let statement = diesel::insert_into(permission_schemas::table)
+    .values(&PermissionSchema {
+      id: schema_version.clone(),
+      tenant_id,
+      schema: body.schema,
+      created_at: Utc::now().naive_utc(),
+      updated_at: None,
+      deleted_at: None
+    })
+    .execute(&mut conn);
+
+  if let Err(e) = statement {
+    return Err(permission_error(StatusCode::BAD_REQUEST, &format!("Schema not saved to database: {}", e)));
+  }
+
+  Ok(Json(serde_json::json!({
+    "data": { "schemaVersion": schema_version },
+  })))
+}
+
+pub async fn read_schema_handler(
+  Extension(jwtauth): Extension<JWTAuthMiddleware>,
+  State(data): State<Arc<AppState>>,
+  Path(tenant_id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+  authorize_caller(&jwtauth, &tenant_id, CHECK_PERMISSION)?;
+  let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
+
+  let (schema_version, schema) = load_schema(&mut conn, &tenant_id, None)?;
+
+  let mut entities = schema.entities.keys().cloned().collect::<Vec<_>>();
+  entities.sort();
+
+  Ok(Json(serde_json::json!({
+    "data": {
+      "schemaVersion": schema_version,
+      "entities": entities,
+    },
+  })))
+}
@@ -0,0 +1,65 @@
+use diesel::prelude::*;
+
+use crate::schema::{permissions, role_permissions, roles, user_roles};
+
+pub struct UserAccess {
+  pub roles: Vec<String>,
+  pub permissions: Vec<String>,
+}
+
+// A user's role names and the permissions granted through those roles
+pub fn user_access(conn: &mut PgConnection, user_id: &str) -> UserAccess {
+  let role_ids = user_roles::table
+    .filter(user_roles::user_id.eq(user_id))
+    .filter(user_roles::deleted_at.is_null())
+    .select(user_roles::role_id)
+    .load::<String>(conn)
+    .unwrap_or_default();
+
+  let mut role_names = roles::table
+    .filter(roles::id.eq_any(&role_ids))
+    .filter(roles::deleted_at.is_null())
+    .select(roles::name)
+    .load::<String>(conn)
+    .unwrap_or_default();
+  role_names.sort();
+
+  UserAccess {
+    roles: role_names,
+    permissions: role_permission_names(conn, &role_ids),
+  }
+}
+
+pub fn role_permission_names(conn: &mut PgConnection, role_ids: &[String]) -> Vec<String> {
+  let permission_ids = role_permissions::table
+    .filter(role_permissions::role_id.eq_any(role_ids))
+    .filter(role_permissions::deleted_at.is_null())
+    .select(role_permissions::permission_id)
+    .load::<String>(conn)
+    .unwrap_or_default();
+
+  let mut permission_names = permissions::table
+    .filter(permissions::id.eq_any(&permission_ids))
+    .filter(permissions::deleted_at.is_null())
+    .select(permissions::name)
+    .load::<String>(conn)
+    .unwrap_or_default();
+  permission_names.sort();
+  permission_names.dedup();
+
+  permission_names
+}
+
+// Access tokens carry roles and permissions so routes can be checked without a query
+pub fn access_claims(conn: &mut PgConnection, user_id: &str) -> Vec<(&'static str, String)> {
+  let access = user_access(conn, user_id);
+  vec![("roles", access.roles.join(" ")), ("permissions", access.permissions.join(" "))]
+}
+
+pub fn split_claim(value: Option<&str>) -> Vec<String> {
+  value
+    .unwrap_or_default()
+    .split_whitespace()
+    .map(|value| value.to_string())
+    .collect()
+}
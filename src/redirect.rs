@@ -0,0 +1,81 @@
+use axum::{http::StatusCode, Json};
+use reqwest::Url;
+
+use crate::config::Config;
+
+// An allowlist entry is an origin, optionally followed by a path.
+// A bare origin allows any path, a trailing `*` allows any path under the prefix,
+// anything else must match exactly.
+fn matches_rule(rule: &str, target: &Url) -> bool {
+  let rule_url = match Url::parse(rule) {
+    Ok(url) => url,
+    Err(_) => return false,
+  };
+
+  if rule_url.origin() != target.origin() {
+    return false;
+  }
+
+  let pattern = rule_url.path();
+  if pattern == "/" {
+    return true;
+  }
+
+  match pattern.strip_suffix('*') {
+    Some(prefix) => target.path().starts_with(prefix),
+    None => target.path() == pattern,
+  }
+}
+
+pub fn is_allowed_redirect(config: &Config, target: &str) -> bool {
+  let target = match Url::parse(target) {
+    Ok(url) => url,
+    Err(_) => return false,
+  };
+
+  // Credentials in the URL are a common trick to disguise the real host
+  if !target.username().is_empty() || target.password().is_some() {
+    return false;
+  }
+
+  if target.scheme() != "https" && target.scheme() != "http" {
+    return false;
+  }
+
+  config.redirect_allowlist
+    .iter()
+    .any(|rule| matches_rule(rule, &target))
+}
+
+// Checked again right before redirecting, falling back to the default landing page
+pub fn safe_redirect_url(config: &Config, target: &str) -> String {
+  if is_allowed_redirect(config, target) {
+    target.to_string()
+  } else {
+    tracing::warn!("Rejected redirect to {}", target);
+    config.default_redirect_url.clone()
+  }
+}
+
+pub fn append_query_param(target: &str, key: &str, value: &str) -> String {
+  match Url::parse(target) {
+    Ok(mut url) => {
+      url.query_pairs_mut().append_pair(key, value);
+      url.to_string()
+    },
+    Err(_) => target.to_string(),
+  }
+}
+
+pub fn validate_redirect(config: &Config, target: &str)
+  -> Result<(), (StatusCode, Json<serde_json::Value>)> {
+  if is_allowed_redirect(config, target) {
+    return Ok(());
+  }
+
+  let error_response = serde_json::json!({
+    "status": "fail",
+    "message": "Redirect URL is not allowed"
+  });
+  Err((StatusCode::BAD_REQUEST, Json(error_response)))
+}
@@ -6,14 +6,173 @@ use serde::Serialize;
 pub struct FilteredUser {
     pub id: String,
     pub name: String,
-    pub email: String,
+    pub email: Option<String>,
     pub role: String,
+    pub permissions: Vec<String>,
+    pub tenantId: Option<String>,
+    pub tenantRole: Option<String>,
+    // Set while an admin is impersonating this user
+    pub impersonatedBy: Option<String>,
     pub photo: String,
     pub verified: bool,
     pub createdAt: NaiveDateTime,
     pub updatedAt: Option<chrono::NaiveDateTime>,
 }
 
+#[allow(non_snake_case)]
+#[derive(Debug, Serialize)]
+pub struct FilteredApiKey {
+    pub id: String,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: String,
+    pub expiresAt: Option<NaiveDateTime>,
+    pub lastUsedAt: Option<NaiveDateTime>,
+    pub lastUsedIp: Option<String>,
+    pub createdAt: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FilteredRole {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub permissions: Vec<String>,
+}
+
+#[allow(non_snake_case)]
+#[derive(Debug, Serialize)]
+pub struct FilteredTenant {
+    pub id: String,
+    pub name: String,
+    pub createdAt: NaiveDateTime,
+}
+
+#[allow(non_snake_case)]
+#[derive(Debug, Serialize)]
+pub struct FilteredTenantMember {
+    pub userId: String,
+    pub role: String,
+    pub createdAt: NaiveDateTime,
+}
+
+#[allow(non_snake_case)]
+#[derive(Debug, Serialize)]
+pub struct FilteredTenantSettings {
+    pub tenantId: String,
+    pub host: Option<String>,
+    pub enabledProviders: Option<Vec<String>>,
+    pub passwordMinLength: Option<i32>,
+    pub passwordRequireDigit: Option<bool>,
+    pub passwordRequireSymbol: Option<bool>,
+    pub mfaRequired: Option<bool>,
+    pub accessTokenMaxAge: Option<i64>,
+    pub refreshTokenMaxAge: Option<i64>,
+    pub mailerFrom: Option<String>,
+    pub mailerFromName: Option<String>,
+}
+
+#[allow(non_snake_case)]
+#[derive(Debug, Serialize)]
+pub struct FilteredAdminUser {
+    pub id: String,
+    pub name: String,
+    pub email: Option<String>,
+    pub verified: bool,
+    pub locale: Option<String>,
+    pub disabledAt: Option<NaiveDateTime>,
+    pub createdAt: NaiveDateTime,
+    pub updatedAt: Option<NaiveDateTime>,
+}
+
+#[allow(non_snake_case)]
+#[derive(Debug, Serialize)]
+pub struct FilteredIdentity {
+    pub id: String,
+    pub provider: String,
+    pub email: Option<String>,
+    pub emailVerified: bool,
+    pub lastSigninAt: NaiveDateTime,
+    pub createdAt: NaiveDateTime,
+}
+
+#[allow(non_snake_case)]
+#[derive(Debug, Serialize)]
+pub struct FilteredSession {
+    pub id: String,
+    pub createdAt: NaiveDateTime,
+    pub expiresAt: NaiveDateTime,
+}
+
+#[allow(non_snake_case)]
+#[derive(Debug, Serialize)]
+pub struct FilteredAuditEvent {
+    pub id: String,
+    pub eventType: String,
+    pub actorId: Option<String>,
+    pub subjectId: Option<String>,
+    pub outcome: String,
+    pub ip: Option<String>,
+    pub userAgent: Option<String>,
+    pub metadata: serde_json::Value,
+    pub createdAt: NaiveDateTime,
+}
+
+// Bodies are left out, they carry sign-in codes
+#[allow(non_snake_case)]
+#[derive(Debug, Serialize)]
+pub struct FilteredOutboxEmail {
+    pub id: String,
+    pub template: String,
+    pub to: String,
+    pub subject: String,
+    pub tenantId: Option<String>,
+    pub status: String,
+    pub attempts: i32,
+    pub nextAttemptAt: NaiveDateTime,
+    pub lastError: Option<String>,
+    pub sentAt: Option<NaiveDateTime>,
+    pub createdAt: NaiveDateTime,
+}
+
+#[allow(non_snake_case)]
+#[derive(Debug, Serialize)]
+pub struct FilteredWebhook {
+    pub id: String,
+    pub url: String,
+    pub eventTypes: Vec<String>,
+    pub description: Option<String>,
+    pub active: bool,
+    pub createdAt: NaiveDateTime,
+    pub updatedAt: Option<NaiveDateTime>,
+}
+
+#[allow(non_snake_case)]
+#[derive(Debug, Serialize)]
+pub struct FilteredWebhookMessage {
+    pub id: String,
+    pub eventType: String,
+    pub payload: serde_json::Value,
+    pub status: String,
+    pub attempts: i32,
+    pub nextAttemptAt: NaiveDateTime,
+    pub lastError: Option<String>,
+    pub createdAt: NaiveDateTime,
+}
+
+#[allow(non_snake_case)]
+#[derive(Debug, Serialize)]
+pub struct FilteredWebhookDelivery {
+    pub id: String,
+    pub messageId: String,
+    pub eventType: String,
+    pub attempt: i32,
+    pub statusCode: Option<i32>,
+    pub error: Option<String>,
+    pub durationMs: i64,
+    pub createdAt: NaiveDateTime,
+}
+
 #[derive(Serialize, Debug)]
 pub struct UserData {
     pub user: FilteredUser,
@@ -2,12 +2,12 @@ use std::sync::Arc;
 
 use axum::{
   middleware,
-  routing::{get, post},
+  routing::{delete, get, patch, post},
   Router,
 };
 
 use crate::{
-  handlers::{check_code_handler::check_code_handler, forgot_password_handler::forgot_password_handler, generate_magiclink_handler::generate_magiclink_handler, get_me_handler::get_me_handler, login_user_handler::login_user_handler, logout_handler::logout_handler, refresh_access_token_handler::refresh_access_token_handler, register_user_handler::register_user_handler, reset_password_handler::reset_password_handler, verify_code_handler::verify_code_handler, verify_magiclink_code_handler::verify_magiclink_code_handler}, jwt_auth::auth, social_handlers::{callback_handler::callback_handler, url_handler::url_handler}, AppState
+  handlers::{admin_handler::{delete_user_handler, disable_user_handler, enable_user_handler, get_user_handler, logout_user_handler, reset_user_password_handler, search_users_handler, update_user_handler}, api_key_handler::{create_api_key_handler, list_api_keys_handler, revoke_api_key_handler}, audit_handler::{my_activity_handler, search_audit_events_handler}, check_code_handler::check_code_handler, collect_email_handler::collect_email_handler, email_outbox_handler::{resend_email_handler, search_emails_handler}, forgot_password_handler::forgot_password_handler, generate_magiclink_handler::generate_magiclink_handler, get_me_handler::get_me_handler, impersonation_handler::{end_impersonation_handler, impersonate_user_handler}, login_user_handler::login_user_handler, logout_handler::logout_handler, magiclink_approval_handler::{approve_magiclink_handler, lookup_magiclink_handler, poll_magiclink_handler}, not_me_handler::{not_me_handler, not_me_page_handler}, refresh_access_token_handler::refresh_access_token_handler, register_user_handler::register_user_handler, reset_password_handler::reset_password_handler, role_handler::{assign_role_handler, list_roles_handler, remove_role_handler}, tenant_handler::{create_tenant_handler, delete_tenant_handler, list_tenant_members_handler, get_tenant_settings_handler, list_tenants_handler, put_tenant_member_handler, put_tenant_settings_handler, remove_tenant_member_handler, switch_tenant_handler}, verify_code_handler::verify_code_handler, verify_email_handler::verify_email_handler, verify_magiclink_code_handler::verify_magiclink_code_handler, verify_magiclink_otp_handler::verify_magiclink_otp_handler, webhook_handler::{create_webhook_handler, delete_webhook_handler, list_webhook_deliveries_handler, list_webhook_messages_handler, list_webhooks_handler, redeliver_webhook_message_handler, update_webhook_handler}}, jwt_auth::{auth, oauth_auth, require_permission}, oauth_server::{authorize_handler::authorize_handler, consent_handler::consent_handler, device_handler::{device_code_handler, device_lookup_handler, device_verify_handler}, discovery_handler::{jwks_handler, openid_configuration_handler}, token_handler::token_handler, userinfo_handler::userinfo_handler}, permissions::{check_handler::{check_handler, expand_handler, lookup_subjects_handler}, relationship_handler::{delete_relationships_handler, write_relationships_handler}, schema_handler::{read_schema_handler, write_schema_handler}}, social_handlers::{callback_handler::{callback_form_handler, callback_handler}, url_handler::url_handler}, AppState
 };
 
 pub fn create_router(app_state: Arc<AppState>) -> Router {
@@ -22,9 +22,20 @@ pub fn create_router(app_state: Arc<AppState>) -> Router {
     .route("/reset_password", post(reset_password_handler))        
     .route("/generate_magiclink", post(generate_magiclink_handler))        
     .route("/verify_magiclink_code", get(verify_magiclink_code_handler))        
+    .route("/verify_magiclink_otp", post(verify_magiclink_otp_handler))
+    .route("/magiclink/approve", get(lookup_magiclink_handler).post(approve_magiclink_handler))
+    .route("/magiclink/poll", post(poll_magiclink_handler))
+    .route("/verify_email", get(verify_email_handler))        
+    .route("/not_me", get(not_me_page_handler).post(not_me_handler))
     //oauth
     .route("/oauth/url", post(url_handler))
-    .route("/oauth/callback", get(callback_handler))
+    .route("/oauth/callback", get(callback_handler).post(callback_form_handler))
+    // oauth authorization server
+    .route("/authorize", get(authorize_handler))
+    .route("/token", post(token_handler))
+    .route("/device/code", post(device_code_handler))
+    .route("/.well-known/openid-configuration", get(openid_configuration_handler))
+    .route("/.well-known/jwks.json", get(jwks_handler))
     // needs middleware
     .route(
       "/logout",
@@ -36,5 +47,239 @@ pub fn create_router(app_state: Arc<AppState>) -> Router {
       get(get_me_handler)
       .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
     )
+    .route(
+      "/users/me/activity",
+      get(my_activity_handler)
+      .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+    )
+    .route(
+      "/collect_email",
+      post(collect_email_handler)
+      .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+    )
+    .route(
+      "/api_keys",
+      get(list_api_keys_handler)
+      .post(create_api_key_handler)
+      .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+    )
+    .route(
+      "/api_keys/{id}",
+      delete(revoke_api_key_handler)
+      .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+    )
+    // needs a permission, checked after auth has run
+    .route(
+      "/roles",
+      get(list_roles_handler)
+      .route_layer(middleware::from_fn_with_state("roles:read", require_permission))
+      .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+    )
+    .route(
+      "/users/{id}/roles",
+      post(assign_role_handler)
+      .route_layer(middleware::from_fn_with_state("roles:write", require_permission))
+      .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+    )
+    .route(
+      "/users/{id}/roles/{role_id}",
+      delete(remove_role_handler)
+      .route_layer(middleware::from_fn_with_state("roles:write", require_permission))
+      .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+    )
+    // admin user management, reads and writes need separate permissions so the same path is registered twice
+    .route(
+      "/admin/users",
+      get(search_users_handler)
+      .route_layer(middleware::from_fn_with_state("users:read", require_permission))
+      .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+    )
+    .route(
+      "/admin/users/{id}",
+      get(get_user_handler)
+      .route_layer(middleware::from_fn_with_state("users:read", require_permission))
+      .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+    )
+    .route(
+      "/admin/users/{id}",
+      patch(update_user_handler)
+      .delete(delete_user_handler)
+      .route_layer(middleware::from_fn_with_state("users:write", require_permission))
+      .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+    )
+    .route(
+      "/admin/users/{id}/disable",
+      post(disable_user_handler)
+      .route_layer(middleware::from_fn_with_state("users:write", require_permission))
+      .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+    )
+    .route(
+      "/admin/users/{id}/enable",
+      post(enable_user_handler)
+      .route_layer(middleware::from_fn_with_state("users:write", require_permission))
+      .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+    )
+    .route(
+      "/admin/users/{id}/logout",
+      post(logout_user_handler)
+      .route_layer(middleware::from_fn_with_state("users:write", require_permission))
+      .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+    )
+    .route(
+      "/admin/users/{id}/password_reset",
+      post(reset_user_password_handler)
+      .route_layer(middleware::from_fn_with_state("users:write", require_permission))
+      .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+    )
+    .route(
+      "/admin/users/{id}/impersonate",
+      post(impersonate_user_handler)
+      .route_layer(middleware::from_fn_with_state("users:impersonate", require_permission))
+      .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+    )
+    .route(
+      "/admin/audit_events",
+      get(search_audit_events_handler)
+      .route_layer(middleware::from_fn_with_state("audit:read", require_permission))
+      .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+    )
+    .route(
+      "/admin/webhooks",
+      get(list_webhooks_handler)
+      .route_layer(middleware::from_fn_with_state("webhooks:read", require_permission))
+      .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+    )
+    .route(
+      "/admin/webhooks",
+      post(create_webhook_handler)
+      .route_layer(middleware::from_fn_with_state("webhooks:write", require_permission))
+      .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+    )
+    .route(
+      "/admin/webhooks/{id}",
+      patch(update_webhook_handler)
+      .delete(delete_webhook_handler)
+      .route_layer(middleware::from_fn_with_state("webhooks:write", require_permission))
+      .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+    )
+    .route(
+      "/admin/webhooks/{id}/messages",
+      get(list_webhook_messages_handler)
+      .route_layer(middleware::from_fn_with_state("webhooks:read", require_permission))
+      .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+    )
+    .route(
+      "/admin/webhooks/{id}/messages/{message_id}/redeliver",
+      post(redeliver_webhook_message_handler)
+      .route_layer(middleware::from_fn_with_state("webhooks:write", require_permission))
+      .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+    )
+    .route(
+      "/admin/webhooks/{id}/deliveries",
+      get(list_webhook_deliveries_handler)
+      .route_layer(middleware::from_fn_with_state("webhooks:read", require_permission))
+      .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+    )
+    .route(
+      "/admin/emails",
+      get(search_emails_handler)
+      .route_layer(middleware::from_fn_with_state("emails:read", require_permission))
+      .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+    )
+    .route(
+      "/admin/emails/{id}/resend",
+      post(resend_email_handler)
+      .route_layer(middleware::from_fn_with_state("emails:write", require_permission))
+      .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+    )
+    // impersonation tokens carry no permissions, so ending one only needs auth
+    .route(
+      "/impersonation/end",
+      post(end_impersonation_handler)
+      .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+    )
+    .route(
+      "/authorize/consent",
+      post(consent_handler)
+      .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+    )
+    .route(
+      "/userinfo",
+      get(userinfo_handler)
+      .post(userinfo_handler)
+      .route_layer(middleware::from_fn_with_state(app_state.clone(), oauth_auth)),
+    )
+    .route(
+      "/device",
+      get(device_lookup_handler)
+      .post(device_verify_handler)
+      .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+    )
+    // tenants
+    .route(
+      "/tenants",
+      get(list_tenants_handler)
+      .post(create_tenant_handler)
+      .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+    )
+    .route(
+      "/tenants/switch",
+      post(switch_tenant_handler)
+      .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+    )
+    .route(
+      "/tenants/{tenant_id}",
+      delete(delete_tenant_handler)
+      .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+    )
+    .route(
+      "/tenants/{tenant_id}/members",
+      get(list_tenant_members_handler)
+      .post(put_tenant_member_handler)
+      .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+    )
+    .route(
+      "/tenants/{tenant_id}/members/{user_id}",
+      delete(remove_tenant_member_handler)
+      .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+    )
+    .route(
+      "/tenants/{tenant_id}/settings",
+      get(get_tenant_settings_handler)
+      .put(put_tenant_settings_handler)
+      .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+    )
+    // relationship-based permissions
+    .route(
+      "/schemas/{tenant_id}",
+      get(read_schema_handler)
+      .post(write_schema_handler)
+      .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+    )
+    .route(
+      "/relationships/write",
+      post(write_relationships_handler)
+      .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+    )
+    .route(
+      "/relationships/delete",
+      post(delete_relationships_handler)
+      .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+    )
+    .route(
+      "/permissions/check",
+      post(check_handler)
+      .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+    )
+    .route(
+      "/permissions/expand",
+      post(expand_handler)
+      .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+    )
+    .route(
+      "/permissions/lookup-subjects",
+      post(lookup_subjects_handler)
+      .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+    )
     .with_state(app_state)
 }
@@ -1,6 +1,115 @@
 use diesel::prelude::*;
 use chrono::NaiveDateTime;
 
+#[derive(Queryable, Insertable)]
+#[diesel(table_name = api_keys)]
+pub struct ApiKey {
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub id: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub user_id: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub name: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub prefix: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub key_hash: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub scopes: String,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub expires: Option<NaiveDateTime>,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub last_used_at: Option<NaiveDateTime>,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+  pub last_used_ip: Option<String>,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub revoked_at: Option<NaiveDateTime>,
+  #[diesel(column_name = "created_at")]
+  #[diesel(sql_type = diesel::sql_types::Timestamp)]
+  pub created_at: NaiveDateTime,
+  #[diesel(column_name = "updated_at")]
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub updated_at: Option<NaiveDateTime>,
+  #[diesel(column_name = "deleted_at")]
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub deleted_at: Option<NaiveDateTime>,
+}
+
+table! {
+  api_keys (id) {
+    id -> Text,
+    user_id -> Text,
+    name -> Text,
+    prefix -> Text,
+    key_hash -> Text,
+    scopes -> Text,
+    expires -> Nullable<Timestamp>,
+    last_used_at -> Nullable<Timestamp>,
+    last_used_ip -> Nullable<Text>,
+    revoked_at -> Nullable<Timestamp>,
+    #[sql_name = "created_at"]
+    created_at -> Timestamp,
+    #[sql_name = "updated_at"]
+    updated_at -> Nullable<Timestamp>,
+    #[sql_name = "deleted_at"]
+    deleted_at -> Nullable<Timestamp>,
+  }
+}
+
+#[derive(AsChangeset)]
+#[diesel(table_name = api_keys)]
+pub struct ApiKeyUsage {
+  pub last_used_at: Option<NaiveDateTime>,
+  pub last_used_ip: Option<String>,
+}
+
+#[derive(AsChangeset)]
+#[diesel(table_name = api_keys)]
+pub struct ApiKeyRevoke {
+  pub revoked_at: Option<NaiveDateTime>,
+  pub updated_at: Option<NaiveDateTime>,
+}
+
+// Append-only, rows are never updated and only removed by retention
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[diesel(table_name = audit_events)]
+pub struct AuditEvent {
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub id: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub event_type: String,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+  pub actor_id: Option<String>,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+  pub subject_id: Option<String>,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub outcome: String,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+  pub ip: Option<String>,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+  pub user_agent: Option<String>,
+  #[diesel(sql_type = diesel::sql_types::Jsonb)]
+  pub metadata: serde_json::Value,
+  #[diesel(column_name = "created_at")]
+  #[diesel(sql_type = diesel::sql_types::Timestamp)]
+  pub created_at: NaiveDateTime,
+}
+
+table! {
+  audit_events (id) {
+    id -> Text,
+    event_type -> Text,
+    actor_id -> Nullable<Text>,
+    subject_id -> Nullable<Text>,
+    outcome -> Text,
+    ip -> Nullable<Text>,
+    user_agent -> Nullable<Text>,
+    metadata -> Jsonb,
+    #[sql_name = "created_at"]
+    created_at -> Timestamp,
+  }
+}
+
 #[derive(Queryable, Insertable)]
 #[diesel(table_name = auth_tokens)]
 pub struct AuthToken {
@@ -32,6 +141,12 @@ table! {
   }
 }
 
+// What an email_confirmation code was sent for, a code is only ever accepted for its own purpose
+pub const PURPOSE_PASSWORD_RESET: &str = "password_reset";
+pub const PURPOSE_MAGIC_LINK: &str = "magic_link";
+pub const PURPOSE_EMAIL_VERIFICATION: &str = "email_verification";
+pub const PURPOSE_NOT_ME: &str = "not_me";
+
 #[derive(Queryable, Insertable, Selectable)]
 #[diesel(table_name = email_confirmation)]
 pub struct EmailConfirmation {
@@ -46,7 +161,22 @@ pub struct EmailConfirmation {
   #[diesel(sql_type = diesel::sql_types::Text)]
   pub flow: String,  
   #[diesel(sql_type = diesel::sql_types::Text)]
+  pub purpose: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
   pub redirect_to: Option<String>,  
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+  pub email: Option<String>,  
+  // Hash of the cookie given to the browser that asked for a magic link, only it may sign in with the link
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+  pub device_nonce: Option<String>,
+  // The tenant a magic link was requested on, applied again when it is redeemed
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+  pub tenant_id: Option<String>,
+  // Where a magic link was requested from, shown on the approval page
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+  pub requester_ip: Option<String>,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+  pub requester_user_agent: Option<String>,
   #[diesel(column_name = "created_at")]
   #[diesel(sql_type = diesel::sql_types::Timestamp)]
   pub created_at: NaiveDateTime,
@@ -65,7 +195,13 @@ table! {
     code -> Text,
     expires -> Timestamp,
     flow -> Text,
+    purpose -> Text,
     redirect_to -> Nullable<Text>,    
+    email -> Nullable<Text>,
+    device_nonce -> Nullable<Text>,
+    tenant_id -> Nullable<Text>,
+    requester_ip -> Nullable<Text>,
+    requester_user_agent -> Nullable<Text>,
     #[sql_name = "created_at"]
     created_at -> Timestamp,
     #[sql_name = "updated_at"]
@@ -82,6 +218,137 @@ pub struct EmailConfirmationFlowUpdate {
   pub updated_at: Option<NaiveDateTime>,
 }
 
+// Numeric codes sent alongside a magic link, used by whichever of the two is redeemed first
+#[derive(Queryable, Insertable)]
+#[diesel(table_name = email_otps)]
+pub struct EmailOtp {
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub id: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub confirmation_id: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub user_id: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub code_hash: String,
+  #[diesel(sql_type = diesel::sql_types::Int4)]
+  pub attempts: i32,
+  #[diesel(sql_type = diesel::sql_types::Timestamp)]
+  pub expires: NaiveDateTime,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub used_at: Option<NaiveDateTime>,
+  #[diesel(column_name = "created_at")]
+  #[diesel(sql_type = diesel::sql_types::Timestamp)]
+  pub created_at: NaiveDateTime,
+  #[diesel(column_name = "updated_at")]
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub updated_at: Option<NaiveDateTime>,
+}
+
+table! {
+  email_otps (id) {
+    id -> Text,
+    confirmation_id -> Text,
+    user_id -> Text,
+    code_hash -> Text,
+    attempts -> Int4,
+    expires -> Timestamp,
+    used_at -> Nullable<Timestamp>,
+    #[sql_name = "created_at"]
+    created_at -> Timestamp,
+    #[sql_name = "updated_at"]
+    updated_at -> Nullable<Timestamp>,
+  }
+}
+
+#[derive(AsChangeset)]
+#[diesel(table_name = email_otps)]
+pub struct EmailOtpUse {
+  pub used_at: Option<NaiveDateTime>,
+  pub updated_at: Option<NaiveDateTime>,
+}
+
+// Rendered emails waiting for the outbox worker, kept afterwards as their delivery status
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[diesel(table_name = email_outbox)]
+pub struct OutboxEmail {
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub id: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub template: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub from_address: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub from_name: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub to_address: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub subject: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub html_body: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub text_body: String,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+  pub tenant_id: Option<String>,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub status: String,
+  #[diesel(sql_type = diesel::sql_types::Int4)]
+  pub attempts: i32,
+  #[diesel(sql_type = diesel::sql_types::Timestamp)]
+  pub next_attempt_at: NaiveDateTime,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+  pub last_error: Option<String>,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub sent_at: Option<NaiveDateTime>,
+  #[diesel(column_name = "created_at")]
+  #[diesel(sql_type = diesel::sql_types::Timestamp)]
+  pub created_at: NaiveDateTime,
+  #[diesel(column_name = "updated_at")]
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub updated_at: Option<NaiveDateTime>,
+}
+
+table! {
+  email_outbox (id) {
+    id -> Text,
+    template -> Text,
+    from_address -> Text,
+    from_name -> Text,
+    to_address -> Text,
+    subject -> Text,
+    html_body -> Text,
+    text_body -> Text,
+    tenant_id -> Nullable<Text>,
+    status -> Text,
+    attempts -> Int4,
+    next_attempt_at -> Timestamp,
+    last_error -> Nullable<Text>,
+    sent_at -> Nullable<Timestamp>,
+    #[sql_name = "created_at"]
+    created_at -> Timestamp,
+    #[sql_name = "updated_at"]
+    updated_at -> Nullable<Timestamp>,
+  }
+}
+
+// Pushes a claimed email out of reach of other workers while it is being sent
+#[derive(AsChangeset)]
+#[diesel(table_name = email_outbox)]
+pub struct OutboxEmailClaim {
+  pub next_attempt_at: NaiveDateTime,
+}
+
+#[derive(AsChangeset)]
+#[diesel(table_name = email_outbox)]
+#[diesel(treat_none_as_null = true)]
+pub struct OutboxEmailResult {
+  pub status: String,
+  pub attempts: i32,
+  pub next_attempt_at: NaiveDateTime,
+  pub last_error: Option<String>,
+  pub sent_at: Option<NaiveDateTime>,
+  pub updated_at: Option<NaiveDateTime>,
+}
+
 #[derive(Queryable, Insertable)]
 #[diesel(table_name = identities)]
 pub struct Identity {
@@ -91,6 +358,12 @@ pub struct Identity {
   pub user_id: String,  
   #[diesel(sql_type = diesel::sql_types::Text)]
   pub provider_id: String,  
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub provider_user_id: String,  
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+  pub email: Option<String>,  
+  #[diesel(sql_type = diesel::sql_types::Bool)]
+  pub email_verified: bool,  
   #[diesel(sql_type = diesel::sql_types::Json)]
   pub identity_data: serde_json::Value, 
   #[diesel(sql_type = diesel::sql_types::Timestamp)]
@@ -111,6 +384,9 @@ table! {
     id -> Text,
     user_id -> Text,    
     provider_id -> Text,    
+    provider_user_id -> Text,
+    email -> Nullable<Text>,
+    email_verified -> Bool,
     identity_data -> Json,    
     last_signin_at -> Timestamp,    
     #[sql_name = "created_at"]
@@ -122,21 +398,87 @@ table! {
   }
 }
 
+#[derive(AsChangeset)]
+#[diesel(table_name = identities)]
+pub struct IdentitySignInUpdate {
+  pub email: Option<String>,
+  pub email_verified: bool,
+  pub identity_data: serde_json::Value,
+  pub last_signin_at: NaiveDateTime,
+  pub updated_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[diesel(table_name = oauth_clients)]
+pub struct RegisteredClient {
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub id: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub name: String,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+  pub client_secret: Option<String>,
+  #[diesel(sql_type = diesel::sql_types::Array<diesel::sql_types::Text>)]
+  pub redirect_uris: Vec<String>,
+  #[diesel(sql_type = diesel::sql_types::Array<diesel::sql_types::Text>)]
+  pub grant_types: Vec<String>,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub scopes: String,
+  #[diesel(sql_type = diesel::sql_types::Bool)]
+  pub first_party: bool,
+  #[diesel(column_name = "created_at")]
+  #[diesel(sql_type = diesel::sql_types::Timestamp)]
+  pub created_at: NaiveDateTime,
+  #[diesel(column_name = "updated_at")]
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub updated_at: Option<NaiveDateTime>,
+  #[diesel(column_name = "deleted_at")]
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub deleted_at: Option<NaiveDateTime>,
+}
+
+table! {
+  oauth_clients (id) {
+    id -> Text,
+    name -> Text,
+    client_secret -> Nullable<Text>,
+    redirect_uris -> Array<Text>,
+    grant_types -> Array<Text>,
+    scopes -> Text,
+    first_party -> Bool,
+    #[sql_name = "created_at"]
+    created_at -> Timestamp,
+    #[sql_name = "updated_at"]
+    updated_at -> Nullable<Timestamp>,
+    #[sql_name = "deleted_at"]
+    deleted_at -> Nullable<Timestamp>,
+  }
+}
+
 #[derive(Queryable, Insertable)]
-#[diesel(table_name = social_auth)]
-pub struct SocialAuth {
+#[diesel(table_name = oauth_codes)]
+pub struct OAuthCode {
   #[diesel(sql_type = diesel::sql_types::Text)]
   pub id: String,
   #[diesel(sql_type = diesel::sql_types::Text)]
-  pub provider_id: String,  
+  pub code: String,
   #[diesel(sql_type = diesel::sql_types::Text)]
-  pub csrf: String,  
+  pub client_id: String,
   #[diesel(sql_type = diesel::sql_types::Text)]
-  pub pkce_verifier: String,  
-  #[diesel(sql_type = diesel::sql_types::Timestamp)]
-  pub expires: NaiveDateTime,  
+  pub user_id: String,
   #[diesel(sql_type = diesel::sql_types::Text)]
-  pub redirect_to: String,  
+  pub redirect_uri: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub scopes: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub code_challenge: String,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+  pub nonce: Option<String>,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub auth_time: Option<NaiveDateTime>,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+  pub amr: Option<String>,
+  #[diesel(sql_type = diesel::sql_types::Timestamp)]
+  pub expires: NaiveDateTime,
   #[diesel(column_name = "created_at")]
   #[diesel(sql_type = diesel::sql_types::Timestamp)]
   pub created_at: NaiveDateTime,
@@ -149,13 +491,18 @@ pub struct SocialAuth {
 }
 
 table! {
-  social_auth (id) {
+  oauth_codes (id) {
     id -> Text,
-    provider_id -> Text,
-    csrf -> Text,
-    pkce_verifier -> Text,
+    code -> Text,
+    client_id -> Text,
+    user_id -> Text,
+    redirect_uri -> Text,
+    scopes -> Text,
+    code_challenge -> Text,
+    nonce -> Nullable<Text>,
+    auth_time -> Nullable<Timestamp>,
+    amr -> Nullable<Text>,
     expires -> Timestamp,
-    redirect_to -> Text,  
     #[sql_name = "created_at"]
     created_at -> Timestamp,
     #[sql_name = "updated_at"]
@@ -166,12 +513,16 @@ table! {
 }
 
 #[derive(Queryable, Insertable)]
-#[diesel(table_name = social_provider)]
-pub struct SocialProvider {
+#[diesel(table_name = oauth_consents)]
+pub struct OAuthConsent {
   #[diesel(sql_type = diesel::sql_types::Text)]
   pub id: String,
   #[diesel(sql_type = diesel::sql_types::Text)]
-  pub name: String,  
+  pub user_id: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub client_id: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub scopes: String,
   #[diesel(column_name = "created_at")]
   #[diesel(sql_type = diesel::sql_types::Timestamp)]
   pub created_at: NaiveDateTime,
@@ -184,9 +535,11 @@ pub struct SocialProvider {
 }
 
 table! {
-  social_provider (id) {
+  oauth_consents (id) {
     id -> Text,
-    name -> Text,    
+    user_id -> Text,
+    client_id -> Text,
+    scopes -> Text,
     #[sql_name = "created_at"]
     created_at -> Timestamp,
     #[sql_name = "updated_at"]
@@ -196,21 +549,40 @@ table! {
   }
 }
 
+#[derive(AsChangeset)]
+#[diesel(table_name = oauth_consents)]
+pub struct OAuthConsentUpdate {
+  pub scopes: String,
+  pub updated_at: Option<NaiveDateTime>,
+}
+
 #[derive(Queryable, Insertable)]
-#[diesel(table_name = tokens)]
-pub struct Token {
+#[diesel(table_name = oauth_device_codes)]
+pub struct OAuthDeviceCode {
   #[diesel(sql_type = diesel::sql_types::Text)]
   pub id: String,
   #[diesel(sql_type = diesel::sql_types::Text)]
-  pub user_id: String,
+  pub device_code: String,
   #[diesel(sql_type = diesel::sql_types::Text)]
-  pub token: String,
+  pub user_code: String,
   #[diesel(sql_type = diesel::sql_types::Text)]
-  pub token_uuid: String,
+  pub client_id: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub scopes: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub status: String,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+  pub user_id: Option<String>,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub auth_time: Option<NaiveDateTime>,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+  pub amr: Option<String>,
+  #[diesel(sql_type = diesel::sql_types::Integer)]
+  pub interval: i32,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub last_polled_at: Option<NaiveDateTime>,
   #[diesel(sql_type = diesel::sql_types::Timestamp)]
   pub expires: NaiveDateTime,
-  #[diesel(sql_type = diesel::sql_types::Bool)]
-  pub blacklisted: bool,
   #[diesel(column_name = "created_at")]
   #[diesel(sql_type = diesel::sql_types::Timestamp)]
   pub created_at: NaiveDateTime,
@@ -223,13 +595,19 @@ pub struct Token {
 }
 
 table! {
-  tokens (id) {
+  oauth_device_codes (id) {
     id -> Text,
-    user_id -> Text,
-    token -> Text,
-    token_uuid -> Text,
+    device_code -> Text,
+    user_code -> Text,
+    client_id -> Text,
+    scopes -> Text,
+    status -> Text,
+    user_id -> Nullable<Text>,
+    auth_time -> Nullable<Timestamp>,
+    amr -> Nullable<Text>,
+    interval -> Integer,
+    last_polled_at -> Nullable<Timestamp>,
     expires -> Timestamp,
-    blacklisted -> Bool,
     #[sql_name = "created_at"]
     created_at -> Timestamp,
     #[sql_name = "updated_at"]
@@ -239,19 +617,44 @@ table! {
   }
 }
 
-#[derive(Debug, Clone, Queryable, Selectable, Insertable)]
-#[diesel(table_name = user)]
-pub struct User {
+#[derive(AsChangeset)]
+#[diesel(table_name = oauth_device_codes)]
+pub struct OAuthDeviceCodePoll {
+  pub interval: i32,
+  pub last_polled_at: Option<NaiveDateTime>,
+}
+
+#[derive(AsChangeset)]
+#[diesel(table_name = oauth_device_codes)]
+pub struct OAuthDeviceCodeDecision {
+  pub status: String,
+  pub user_id: Option<String>,
+  pub auth_time: Option<NaiveDateTime>,
+  pub amr: Option<String>,
+  pub updated_at: Option<NaiveDateTime>,
+}
+
+#[derive(Queryable, Insertable)]
+#[diesel(table_name = oauth_refresh_tokens)]
+pub struct OAuthRefreshToken {
   #[diesel(sql_type = diesel::sql_types::Text)]
   pub id: String,
   #[diesel(sql_type = diesel::sql_types::Text)]
-  pub name: String,
+  pub token: String,
   #[diesel(sql_type = diesel::sql_types::Text)]
-  pub email: String,
+  pub client_id: String,
   #[diesel(sql_type = diesel::sql_types::Text)]
-  pub password: Option<String>,
+  pub user_id: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub scopes: String,
+  #[diesel(sql_type = diesel::sql_types::Timestamp)]
+  pub expires: NaiveDateTime,
   #[diesel(sql_type = diesel::sql_types::Bool)]
-  pub verified: bool,
+  pub revoked: bool,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub auth_time: Option<NaiveDateTime>,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+  pub amr: Option<String>,
   #[diesel(column_name = "created_at")]
   #[diesel(sql_type = diesel::sql_types::Timestamp)]
   pub created_at: NaiveDateTime,
@@ -264,12 +667,16 @@ pub struct User {
 }
 
 table! {
-  user (id) {
+  oauth_refresh_tokens (id) {
     id -> Text,
-    name -> Text,
-    email -> Text,
-    password -> Nullable<Text>,
-    verified -> Bool,
+    token -> Text,
+    client_id -> Text,
+    user_id -> Text,
+    scopes -> Text,
+    expires -> Timestamp,
+    revoked -> Bool,
+    auth_time -> Nullable<Timestamp>,
+    amr -> Nullable<Text>,
     #[sql_name = "created_at"]
     created_at -> Timestamp,
     #[sql_name = "updated_at"]
@@ -280,10 +687,776 @@ table! {
 }
 
 #[derive(AsChangeset)]
-#[diesel(table_name = user)]
-pub struct UserPasswordUpdate {
-  pub password: Option<String>,
+#[diesel(table_name = oauth_refresh_tokens)]
+pub struct OAuthRefreshTokenRevoke {
+  pub revoked: bool,
   pub updated_at: Option<NaiveDateTime>,
 }
 
+#[derive(Queryable, Insertable)]
+#[diesel(table_name = permission_schemas)]
+pub struct PermissionSchema {
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub id: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub tenant_id: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub schema: String,
+  #[diesel(column_name = "created_at")]
+  #[diesel(sql_type = diesel::sql_types::Timestamp)]
+  pub created_at: NaiveDateTime,
+  #[diesel(column_name = "updated_at")]
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub updated_at: Option<NaiveDateTime>,
+  #[diesel(column_name = "deleted_at")]
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub deleted_at: Option<NaiveDateTime>,
+}
+
+table! {
+  permission_schemas (id) {
+    id -> Text,
+    tenant_id -> Text,
+    schema -> Text,
+    #[sql_name = "created_at"]
+    created_at -> Timestamp,
+    #[sql_name = "updated_at"]
+    updated_at -> Nullable<Timestamp>,
+    #[sql_name = "deleted_at"]
+    deleted_at -> Nullable<Timestamp>,
+  }
+}
+
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[diesel(table_name = permissions)]
+pub struct Permission {
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub id: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub name: String,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+  pub description: Option<String>,
+  #[diesel(column_name = "created_at")]
+  #[diesel(sql_type = diesel::sql_types::Timestamp)]
+  pub created_at: NaiveDateTime,
+  #[diesel(column_name = "updated_at")]
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub updated_at: Option<NaiveDateTime>,
+  #[diesel(column_name = "deleted_at")]
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub deleted_at: Option<NaiveDateTime>,
+}
+
+table! {
+  permissions (id) {
+    id -> Text,
+    name -> Text,
+    description -> Nullable<Text>,
+    #[sql_name = "created_at"]
+    created_at -> Timestamp,
+    #[sql_name = "updated_at"]
+    updated_at -> Nullable<Timestamp>,
+    #[sql_name = "deleted_at"]
+    deleted_at -> Nullable<Timestamp>,
+  }
+}
+
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[diesel(table_name = relation_tuples)]
+pub struct RelationTuple {
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub id: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub tenant_id: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub entity_type: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub entity_id: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub relation: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub subject_type: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub subject_id: String,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+  pub subject_relation: Option<String>,
+  #[diesel(column_name = "created_at")]
+  #[diesel(sql_type = diesel::sql_types::Timestamp)]
+  pub created_at: NaiveDateTime,
+  #[diesel(column_name = "updated_at")]
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub updated_at: Option<NaiveDateTime>,
+  #[diesel(column_name = "deleted_at")]
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub deleted_at: Option<NaiveDateTime>,
+}
+
+table! {
+  relation_tuples (id) {
+    id -> Text,
+    tenant_id -> Text,
+    entity_type -> Text,
+    entity_id -> Text,
+    relation -> Text,
+    subject_type -> Text,
+    subject_id -> Text,
+    subject_relation -> Nullable<Text>,
+    #[sql_name = "created_at"]
+    created_at -> Timestamp,
+    #[sql_name = "updated_at"]
+    updated_at -> Nullable<Timestamp>,
+    #[sql_name = "deleted_at"]
+    deleted_at -> Nullable<Timestamp>,
+  }
+}
+
+#[derive(Queryable, Insertable)]
+#[diesel(table_name = role_permissions)]
+pub struct RolePermission {
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub id: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub role_id: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub permission_id: String,
+  #[diesel(column_name = "created_at")]
+  #[diesel(sql_type = diesel::sql_types::Timestamp)]
+  pub created_at: NaiveDateTime,
+  #[diesel(column_name = "updated_at")]
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub updated_at: Option<NaiveDateTime>,
+  #[diesel(column_name = "deleted_at")]
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub deleted_at: Option<NaiveDateTime>,
+}
+
+table! {
+  role_permissions (id) {
+    id -> Text,
+    role_id -> Text,
+    permission_id -> Text,
+    #[sql_name = "created_at"]
+    created_at -> Timestamp,
+    #[sql_name = "updated_at"]
+    updated_at -> Nullable<Timestamp>,
+    #[sql_name = "deleted_at"]
+    deleted_at -> Nullable<Timestamp>,
+  }
+}
+
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[diesel(table_name = roles)]
+pub struct Role {
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub id: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub name: String,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+  pub description: Option<String>,
+  #[diesel(column_name = "created_at")]
+  #[diesel(sql_type = diesel::sql_types::Timestamp)]
+  pub created_at: NaiveDateTime,
+  #[diesel(column_name = "updated_at")]
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub updated_at: Option<NaiveDateTime>,
+  #[diesel(column_name = "deleted_at")]
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub deleted_at: Option<NaiveDateTime>,
+}
+
+table! {
+  roles (id) {
+    id -> Text,
+    name -> Text,
+    description -> Nullable<Text>,
+    #[sql_name = "created_at"]
+    created_at -> Timestamp,
+    #[sql_name = "updated_at"]
+    updated_at -> Nullable<Timestamp>,
+    #[sql_name = "deleted_at"]
+    deleted_at -> Nullable<Timestamp>,
+  }
+}
+
+#[derive(Queryable, Insertable)]
+#[diesel(table_name = social_auth)]
+pub struct SocialAuth {
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub id: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub provider_id: String,  
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub csrf: String,  
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub pkce_verifier: String,  
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+  pub nonce: Option<String>,  
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub browser_binding: String,  
+  #[diesel(sql_type = diesel::sql_types::Timestamp)]
+  pub expires: NaiveDateTime,  
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub redirect_to: String,  
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+  pub tenant_id: Option<String>,
+  #[diesel(column_name = "created_at")]
+  #[diesel(sql_type = diesel::sql_types::Timestamp)]
+  pub created_at: NaiveDateTime,
+  #[diesel(column_name = "updated_at")]
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub updated_at: Option<NaiveDateTime>,
+  #[diesel(column_name = "deleted_at")]
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub deleted_at: Option<NaiveDateTime>,
+}
+
+table! {
+  social_auth (id) {
+    id -> Text,
+    provider_id -> Text,
+    csrf -> Text,
+    pkce_verifier -> Text,
+    nonce -> Nullable<Text>,
+    browser_binding -> Text,
+    expires -> Timestamp,
+    redirect_to -> Text,  
+    tenant_id -> Nullable<Text>,
+    #[sql_name = "created_at"]
+    created_at -> Timestamp,
+    #[sql_name = "updated_at"]
+    updated_at -> Nullable<Timestamp>,
+    #[sql_name = "deleted_at"]
+    deleted_at -> Nullable<Timestamp>,
+  }
+}
+
+#[derive(Queryable, Insertable)]
+#[diesel(table_name = social_provider)]
+pub struct SocialProvider {
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub id: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub name: String,  
+  #[diesel(column_name = "created_at")]
+  #[diesel(sql_type = diesel::sql_types::Timestamp)]
+  pub created_at: NaiveDateTime,
+  #[diesel(column_name = "updated_at")]
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub updated_at: Option<NaiveDateTime>,
+  #[diesel(column_name = "deleted_at")]
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub deleted_at: Option<NaiveDateTime>,
+}
+
+table! {
+  social_provider (id) {
+    id -> Text,
+    name -> Text,    
+    #[sql_name = "created_at"]
+    created_at -> Timestamp,
+    #[sql_name = "updated_at"]
+    updated_at -> Nullable<Timestamp>,
+    #[sql_name = "deleted_at"]
+    deleted_at -> Nullable<Timestamp>,
+  }
+}
+
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[diesel(table_name = tenant_members)]
+pub struct TenantMember {
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub id: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub tenant_id: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub user_id: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub role: String,
+  #[diesel(column_name = "created_at")]
+  #[diesel(sql_type = diesel::sql_types::Timestamp)]
+  pub created_at: NaiveDateTime,
+  #[diesel(column_name = "updated_at")]
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub updated_at: Option<NaiveDateTime>,
+  #[diesel(column_name = "deleted_at")]
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub deleted_at: Option<NaiveDateTime>,
+}
+
+table! {
+  tenant_members (id) {
+    id -> Text,
+    tenant_id -> Text,
+    user_id -> Text,
+    role -> Text,
+    #[sql_name = "created_at"]
+    created_at -> Timestamp,
+    #[sql_name = "updated_at"]
+    updated_at -> Nullable<Timestamp>,
+    #[sql_name = "deleted_at"]
+    deleted_at -> Nullable<Timestamp>,
+  }
+}
+
+#[derive(AsChangeset)]
+#[diesel(table_name = tenant_members)]
+pub struct TenantMemberRoleUpdate {
+  pub role: String,
+  pub updated_at: Option<NaiveDateTime>,
+}
+
+// Every column but the tenant is optional, None falls back to the global Config
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[diesel(table_name = tenant_settings)]
+pub struct TenantSettings {
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub id: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub tenant_id: String,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+  pub host: Option<String>,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Array<diesel::sql_types::Text>>)]
+  pub enabled_providers: Option<Vec<String>>,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Int4>)]
+  pub password_min_length: Option<i32>,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Bool>)]
+  pub password_require_digit: Option<bool>,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Bool>)]
+  pub password_require_symbol: Option<bool>,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Bool>)]
+  pub mfa_required: Option<bool>,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Int8>)]
+  pub access_token_max_age: Option<i64>,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Int8>)]
+  pub refresh_token_max_age: Option<i64>,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+  pub mailer_from: Option<String>,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+  pub mailer_from_name: Option<String>,
+  #[diesel(column_name = "created_at")]
+  #[diesel(sql_type = diesel::sql_types::Timestamp)]
+  pub created_at: NaiveDateTime,
+  #[diesel(column_name = "updated_at")]
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub updated_at: Option<NaiveDateTime>,
+  #[diesel(column_name = "deleted_at")]
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub deleted_at: Option<NaiveDateTime>,
+}
+
+table! {
+  tenant_settings (id) {
+    id -> Text,
+    tenant_id -> Text,
+    host -> Nullable<Text>,
+    enabled_providers -> Nullable<Array<Text>>,
+    password_min_length -> Nullable<Int4>,
+    password_require_digit -> Nullable<Bool>,
+    password_require_symbol -> Nullable<Bool>,
+    mfa_required -> Nullable<Bool>,
+    access_token_max_age -> Nullable<Int8>,
+    refresh_token_max_age -> Nullable<Int8>,
+    mailer_from -> Nullable<Text>,
+    mailer_from_name -> Nullable<Text>,
+    #[sql_name = "created_at"]
+    created_at -> Timestamp,
+    #[sql_name = "updated_at"]
+    updated_at -> Nullable<Timestamp>,
+    #[sql_name = "deleted_at"]
+    deleted_at -> Nullable<Timestamp>,
+  }
+}
+
+// Replaces every override at once, a PUT with a field left out clears it
+#[derive(AsChangeset)]
+#[diesel(table_name = tenant_settings)]
+#[diesel(treat_none_as_null = true)]
+pub struct TenantSettingsUpdate {
+  pub host: Option<String>,
+  pub enabled_providers: Option<Vec<String>>,
+  pub password_min_length: Option<i32>,
+  pub password_require_digit: Option<bool>,
+  pub password_require_symbol: Option<bool>,
+  pub mfa_required: Option<bool>,
+  pub access_token_max_age: Option<i64>,
+  pub refresh_token_max_age: Option<i64>,
+  pub mailer_from: Option<String>,
+  pub mailer_from_name: Option<String>,
+  pub updated_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[diesel(table_name = tenants)]
+pub struct Tenant {
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub id: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub name: String,
+  #[diesel(column_name = "created_at")]
+  #[diesel(sql_type = diesel::sql_types::Timestamp)]
+  pub created_at: NaiveDateTime,
+  #[diesel(column_name = "updated_at")]
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub updated_at: Option<NaiveDateTime>,
+  #[diesel(column_name = "deleted_at")]
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub deleted_at: Option<NaiveDateTime>,
+}
+
+table! {
+  tenants (id) {
+    id -> Text,
+    name -> Text,
+    #[sql_name = "created_at"]
+    created_at -> Timestamp,
+    #[sql_name = "updated_at"]
+    updated_at -> Nullable<Timestamp>,
+    #[sql_name = "deleted_at"]
+    deleted_at -> Nullable<Timestamp>,
+  }
+}
+
+#[derive(AsChangeset)]
+#[diesel(table_name = tenants)]
+pub struct TenantDelete {
+  pub deleted_at: Option<NaiveDateTime>,
+}
+
+#[derive(Queryable, Insertable)]
+#[diesel(table_name = tokens)]
+pub struct Token {
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub id: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub user_id: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub token: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub token_uuid: String,
+  #[diesel(sql_type = diesel::sql_types::Timestamp)]
+  pub expires: NaiveDateTime,
+  #[diesel(sql_type = diesel::sql_types::Bool)]
+  pub blacklisted: bool,
+  #[diesel(column_name = "created_at")]
+  #[diesel(sql_type = diesel::sql_types::Timestamp)]
+  pub created_at: NaiveDateTime,
+  #[diesel(column_name = "updated_at")]
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub updated_at: Option<NaiveDateTime>,
+  #[diesel(column_name = "deleted_at")]
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub deleted_at: Option<NaiveDateTime>,
+}
+
+table! {
+  tokens (id) {
+    id -> Text,
+    user_id -> Text,
+    token -> Text,
+    token_uuid -> Text,
+    expires -> Timestamp,
+    blacklisted -> Bool,
+    #[sql_name = "created_at"]
+    created_at -> Timestamp,
+    #[sql_name = "updated_at"]
+    updated_at -> Nullable<Timestamp>,
+    #[sql_name = "deleted_at"]
+    deleted_at -> Nullable<Timestamp>,
+  }
+}
+
+#[derive(Debug, Clone, Queryable, Selectable, Insertable)]
+#[diesel(table_name = user)]
+pub struct User {
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub id: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub name: String,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+  pub email: Option<String>,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub password: Option<String>,
+  #[diesel(sql_type = diesel::sql_types::Bool)]
+  pub verified: bool,
+  // Disabled accounts keep their data but cannot sign in or use existing sessions
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub disabled_at: Option<NaiveDateTime>,
+  // BCP 47 tag such as "de" or "pt-br", picks the language of emails
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+  pub locale: Option<String>,
+  #[diesel(column_name = "created_at")]
+  #[diesel(sql_type = diesel::sql_types::Timestamp)]
+  pub created_at: NaiveDateTime,
+  #[diesel(column_name = "updated_at")]
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub updated_at: Option<NaiveDateTime>,
+  #[diesel(column_name = "deleted_at")]
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub deleted_at: Option<NaiveDateTime>,
+}
+
+table! {
+  user (id) {
+    id -> Text,
+    name -> Text,
+    email -> Nullable<Text>,
+    password -> Nullable<Text>,
+    verified -> Bool,
+    disabled_at -> Nullable<Timestamp>,
+    locale -> Nullable<Text>,
+    #[sql_name = "created_at"]
+    created_at -> Timestamp,
+    #[sql_name = "updated_at"]
+    updated_at -> Nullable<Timestamp>,
+    #[sql_name = "deleted_at"]
+    deleted_at -> Nullable<Timestamp>,
+  }
+}
+
+#[derive(AsChangeset)]
+#[diesel(table_name = user)]
+pub struct UserPasswordUpdate {
+  pub password: Option<String>,
+  pub updated_at: Option<NaiveDateTime>,
+}
+
+#[derive(AsChangeset)]
+#[diesel(table_name = user)]
+pub struct UserEmailUpdate {
+  pub email: Option<String>,
+  pub verified: bool,
+  pub updated_at: Option<NaiveDateTime>,
+}
+
+// Fields left as None are not changed
+#[derive(AsChangeset)]
+#[diesel(table_name = user)]
+pub struct UserAdminUpdate {
+  pub name: Option<String>,
+  pub email: Option<String>,
+  pub verified: Option<bool>,
+  pub locale: Option<String>,
+  pub updated_at: Option<NaiveDateTime>,
+}
+
+#[derive(AsChangeset)]
+#[diesel(table_name = user)]
+#[diesel(treat_none_as_null = true)]
+pub struct UserDisable {
+  pub disabled_at: Option<NaiveDateTime>,
+  pub updated_at: Option<NaiveDateTime>,
+}
+
+#[derive(AsChangeset)]
+#[diesel(table_name = user)]
+pub struct UserDelete {
+  pub deleted_at: Option<NaiveDateTime>,
+}
+
+#[derive(Queryable, Insertable)]
+#[diesel(table_name = user_roles)]
+pub struct UserRole {
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub id: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub user_id: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub role_id: String,
+  #[diesel(column_name = "created_at")]
+  #[diesel(sql_type = diesel::sql_types::Timestamp)]
+  pub created_at: NaiveDateTime,
+  #[diesel(column_name = "updated_at")]
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub updated_at: Option<NaiveDateTime>,
+  #[diesel(column_name = "deleted_at")]
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub deleted_at: Option<NaiveDateTime>,
+}
+
+table! {
+  user_roles (id) {
+    id -> Text,
+    user_id -> Text,
+    role_id -> Text,
+    #[sql_name = "created_at"]
+    created_at -> Timestamp,
+    #[sql_name = "updated_at"]
+    updated_at -> Nullable<Timestamp>,
+    #[sql_name = "deleted_at"]
+    deleted_at -> Nullable<Timestamp>,
+  }
+}
+
+#[derive(AsChangeset)]
+#[diesel(table_name = user_roles)]
+pub struct UserRoleDelete {
+  pub updated_at: Option<NaiveDateTime>,
+  pub deleted_at: Option<NaiveDateTime>,
+}
+
+// One row per attempt, the delivery log for the outbox
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[diesel(table_name = webhook_deliveries)]
+pub struct WebhookDelivery {
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub id: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub webhook_id: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub message_id: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub event_type: String,
+  #[diesel(sql_type = diesel::sql_types::Int4)]
+  pub attempt: i32,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Int4>)]
+  pub status_code: Option<i32>,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+  pub error: Option<String>,
+  #[diesel(sql_type = diesel::sql_types::Int8)]
+  pub duration_ms: i64,
+  #[diesel(column_name = "created_at")]
+  #[diesel(sql_type = diesel::sql_types::Timestamp)]
+  pub created_at: NaiveDateTime,
+}
+
+table! {
+  webhook_deliveries (id) {
+    id -> Text,
+    webhook_id -> Text,
+    message_id -> Text,
+    event_type -> Text,
+    attempt -> Int4,
+    status_code -> Nullable<Int4>,
+    error -> Nullable<Text>,
+    duration_ms -> Int8,
+    #[sql_name = "created_at"]
+    created_at -> Timestamp,
+  }
+}
+
+// The outbox, written alongside the change it describes and drained by the dispatcher
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[diesel(table_name = webhook_outbox)]
+pub struct WebhookMessage {
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub id: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub webhook_id: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub event_type: String,
+  #[diesel(sql_type = diesel::sql_types::Jsonb)]
+  pub payload: serde_json::Value,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub status: String,
+  #[diesel(sql_type = diesel::sql_types::Int4)]
+  pub attempts: i32,
+  #[diesel(sql_type = diesel::sql_types::Timestamp)]
+  pub next_attempt_at: NaiveDateTime,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+  pub last_error: Option<String>,
+  #[diesel(column_name = "created_at")]
+  #[diesel(sql_type = diesel::sql_types::Timestamp)]
+  pub created_at: NaiveDateTime,
+  #[diesel(column_name = "updated_at")]
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub updated_at: Option<NaiveDateTime>,
+}
+
+table! {
+  webhook_outbox (id) {
+    id -> Text,
+    webhook_id -> Text,
+    event_type -> Text,
+    payload -> Jsonb,
+    status -> Text,
+    attempts -> Int4,
+    next_attempt_at -> Timestamp,
+    last_error -> Nullable<Text>,
+    #[sql_name = "created_at"]
+    created_at -> Timestamp,
+    #[sql_name = "updated_at"]
+    updated_at -> Nullable<Timestamp>,
+  }
+}
+
+// Pushes a claimed message out of reach of other dispatchers while it is being sent
+#[derive(AsChangeset)]
+#[diesel(table_name = webhook_outbox)]
+pub struct WebhookMessageClaim {
+  pub next_attempt_at: NaiveDateTime,
+}
+
+#[derive(AsChangeset)]
+#[diesel(table_name = webhook_outbox)]
+#[diesel(treat_none_as_null = true)]
+pub struct WebhookMessageResult {
+  pub status: String,
+  pub attempts: i32,
+  pub next_attempt_at: NaiveDateTime,
+  pub last_error: Option<String>,
+  pub updated_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[diesel(table_name = webhooks)]
+pub struct Webhook {
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub id: String,
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub url: String,
+  // Kept as is, the dispatcher needs it to sign every payload
+  #[diesel(sql_type = diesel::sql_types::Text)]
+  pub secret: String,
+  #[diesel(sql_type = diesel::sql_types::Array<diesel::sql_types::Text>)]
+  pub event_types: Vec<String>,
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+  pub description: Option<String>,
+  #[diesel(sql_type = diesel::sql_types::Bool)]
+  pub active: bool,
+  #[diesel(column_name = "created_at")]
+  #[diesel(sql_type = diesel::sql_types::Timestamp)]
+  pub created_at: NaiveDateTime,
+  #[diesel(column_name = "updated_at")]
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub updated_at: Option<NaiveDateTime>,
+  #[diesel(column_name = "deleted_at")]
+  #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Timestamp>)]
+  pub deleted_at: Option<NaiveDateTime>,
+}
+
+table! {
+  webhooks (id) {
+    id -> Text,
+    url -> Text,
+    secret -> Text,
+    event_types -> Array<Text>,
+    description -> Nullable<Text>,
+    active -> Bool,
+    #[sql_name = "created_at"]
+    created_at -> Timestamp,
+    #[sql_name = "updated_at"]
+    updated_at -> Nullable<Timestamp>,
+    #[sql_name = "deleted_at"]
+    deleted_at -> Nullable<Timestamp>,
+  }
+}
+
+// Fields left out of a PATCH are kept
+#[derive(AsChangeset)]
+#[diesel(table_name = webhooks)]
+pub struct WebhookUpdate {
+  pub url: Option<String>,
+  pub event_types: Option<Vec<String>>,
+  pub description: Option<String>,
+  pub active: Option<bool>,
+  pub updated_at: Option<NaiveDateTime>,
+}
+
+#[derive(AsChangeset)]
+#[diesel(table_name = webhooks)]
+pub struct WebhookDelete {
+  pub active: bool,
+  pub deleted_at: Option<NaiveDateTime>,
+}
+
 allow_tables_to_appear_in_same_query!(email_confirmation, user);
\ No newline at end of file
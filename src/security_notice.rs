@@ -0,0 +1,153 @@
+use std::sync::Arc;
+
+use axum::extract::State;
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use ulid::Ulid;
+
+use crate::{
+  audit::{RequestContext, OUTCOME_SUCCESS},
+  schema::{audit_events, email_confirmation, user, EmailConfirmation, PURPOSE_NOT_ME, User},
+  smtp::{self, generate_random_string, EmailBaseParams, EmailParams, SecurityParams},
+  template::preferred_locales, AppState
+};
+
+// Codes behind the "this wasn't me" link, they never work as reset or sign-in codes
+pub const NOT_ME_FLOW: &str = "not_me";
+const NOT_ME_DAYS: i64 = 7;
+
+const SIGN_IN_EVENTS: [&str; 3] = ["user.login", "magiclink.login", "oauth.login"];
+// How many past sign-ins are compared against, older devices count as new again
+const SIGN_IN_HISTORY: i64 = 200;
+
+pub enum SecurityNotice {
+  NewSignIn { method: String },
+  PasswordChanged,
+  // Sent to the previous address, the new one may not belong to the user
+  EmailChanged { previous_email: String, new_email: String },
+  IdentityLinked { provider: String },
+  ApiKeyCreated { name: String },
+}
+
+// A device or network that never signed in to this account before. The very first sign-in is not
+// unrecognized, there is nothing to compare it with. A client without a User-Agent is never a known
+// device
+pub fn is_unrecognized_sign_in(conn: &mut PgConnection, user_id: &str, context: &RequestContext) -> bool {
+  let history = audit_events::table
+    .filter(audit_events::subject_id.eq(user_id))
+    .filter(audit_events::event_type.eq_any(SIGN_IN_EVENTS))
+    .filter(audit_events::outcome.eq(OUTCOME_SUCCESS))
+    .order(audit_events::id.desc())
+    .limit(SIGN_IN_HISTORY)
+    .select((audit_events::ip, audit_events::user_agent))
+    .load::<(Option<String>, Option<String>)>(conn)
+    .unwrap_or_default();
+
+  if history.is_empty() {
+    return false;
+  }
+
+  let known_device = context.user_agent.is_some() && history.iter().any(|(_, user_agent)| *user_agent == context.user_agent);
+  let known_network = context.ip.is_none() || history.iter().any(|(ip, _)| *ip == context.ip);
+  !(known_device && known_network)
+}
+
+// previous_email is kept on the code when the notice is about an email change, so the not-me flow
+// can restore it
+fn create_not_me_code(conn: &mut PgConnection, user_id: &str, previous_email: Option<String>) -> QueryResult<String> {
+  let code = generate_random_string();
+  let timestamp = Utc::now().naive_utc();
+  diesel::insert_into(email_confirmation::table)
+    .values(&EmailConfirmation {
+      id: Ulid::new().to_string(),
+      user_id: user_id.to_string(),
+      code: code.clone(),
+      redirect_to: None,
+      email: previous_email,
+      device_nonce: None,
+      tenant_id: None,
+      requester_ip: None,
+      requester_user_agent: None,
+      expires: timestamp + Duration::days(NOT_ME_DAYS),
+      flow: NOT_ME_FLOW.into(),
+      purpose: PURPOSE_NOT_ME.into(),
+      created_at: timestamp,
+      updated_at: None,
+      deleted_at: None
+    })
+    .execute(conn)?;
+  Ok(code)
+}
+
+// Emails the user about a change to their account. Failures are only logged, the change itself
+// already happened
+pub async fn notify(
+  data: &Arc<AppState>,
+  conn: &mut PgConnection,
+  user_id: &str,
+  tenant_id: Option<String>,
+  context: &RequestContext,
+  notice: SecurityNotice,
+) {
+  if !data.env.security_notifications {
+    return;
+  }
+
+  let user = match user::table.filter(user::id.eq(user_id)).first::<User>(conn) {
+    Ok(user) => user,
+    Err(e) => {
+      tracing::error!("Failed to load user {} for a security notice: {}", user_id, e);
+      return;
+    }
+  };
+
+  let previous_email = match &notice {
+    SecurityNotice::EmailChanged { previous_email, .. } => Some(previous_email.clone()),
+    _ => None,
+  };
+  let Some(to) = previous_email.clone().or(user.email.clone()) else {
+    return;
+  };
+
+  let not_me_code = match create_not_me_code(conn, user_id, previous_email) {
+    Ok(code) => code,
+    Err(e) => {
+      tracing::error!("Failed to save a not-me code for {}: {}", user_id, e);
+      return;
+    }
+  };
+
+  let subject = match &notice {
+    SecurityNotice::NewSignIn { .. } => "New sign-in to your account",
+    SecurityNotice::PasswordChanged => "Your password was changed",
+    SecurityNotice::EmailChanged { .. } => "Your email address was changed",
+    SecurityNotice::IdentityLinked { .. } => "A sign-in method was added to your account",
+    SecurityNotice::ApiKeyCreated { .. } => "A new API key was created",
+  };
+
+  let base = EmailBaseParams {
+    from: data.env.mailer_from.clone(),
+    from_name: data.env.mailer_from_name.clone(),
+    to,
+    subject: subject.to_string(),
+    tenant_id,
+    locales: preferred_locales(user.locale.as_deref(), context.accept_language.as_deref()),
+  };
+  let security = SecurityParams {
+    not_me_code,
+    ip: context.ip.clone(),
+    user_agent: context.user_agent.clone(),
+  };
+
+  let params = match notice {
+    SecurityNotice::NewSignIn { method } => EmailParams::NewSignIn { base, security, method },
+    SecurityNotice::PasswordChanged => EmailParams::PasswordChanged { base, security },
+    SecurityNotice::EmailChanged { new_email, .. } => EmailParams::EmailChanged { base, security, new_email },
+    SecurityNotice::IdentityLinked { provider } => EmailParams::IdentityLinked { base, security, provider },
+    SecurityNotice::ApiKeyCreated { name } => EmailParams::ApiKeyCreated { base, security, name },
+  };
+
+  if let Err(e) = smtp::send_email(params, State(data.clone())).await {
+    tracing::error!("Failed to queue security notice for {}: {}", user_id, e);
+  }
+}
@@ -1,10 +1,9 @@
 use axum::extract::State;
-use mail_send::{mail_builder::MessageBuilder, SmtpClientBuilder};
-use std::{path::PathBuf, sync::Arc};
+use std::sync::Arc;
+use chrono::Utc;
 use rand::{distributions::Alphanumeric, Rng};
-use serde_json::Value;
 
-use crate::{template::TemplateEngine, AppState};
+use crate::{email_outbox, tenant::find_settings, AppState};
 
 #[derive(Debug)]
 pub struct EmailBaseParams {
@@ -12,6 +11,18 @@ pub struct EmailBaseParams {
   pub from_name: String,
   pub to: String,
   pub subject: String,
+  // Set when the email belongs to a tenant, whose branding then replaces from and from_name
+  pub tenant_id: Option<String>,
+  // Most preferred first, picks the template variant, see template::preferred_locales
+  pub locales: Vec<String>,
+}
+
+// Shared by the security notices, the code backs their "this wasn't me" link
+#[derive(Debug)]
+pub struct SecurityParams {
+  pub not_me_code: String,
+  pub ip: Option<String>,
+  pub user_agent: Option<String>,
 }
 
 #[derive(Debug)]
@@ -24,9 +35,54 @@ pub enum EmailParams {
     base: EmailBaseParams,
     code: String,
     redirect_to: String,
+    // The numeric code to type in instead of following the link
+    otp: Option<String>,
+  },
+  EmailVerification {
+    base: EmailBaseParams,
+    code: String,
+  },
+  NewSignIn {
+    base: EmailBaseParams,
+    security: SecurityParams,
+    method: String,
+  },
+  PasswordChanged {
+    base: EmailBaseParams,
+    security: SecurityParams,
+  },
+  EmailChanged {
+    base: EmailBaseParams,
+    security: SecurityParams,
+    new_email: String,
+  },
+  IdentityLinked {
+    base: EmailBaseParams,
+    security: SecurityParams,
+    provider: String,
+  },
+  ApiKeyCreated {
+    base: EmailBaseParams,
+    security: SecurityParams,
+    name: String,
   },
 }
 
+// Details every security notice shows, extra holds the ones specific to the event
+fn security_params(server_url: &str, security: SecurityParams, extra: serde_json::Value) -> serde_json::Value {
+  let mut params = serde_json::json!({
+    "NotMeURL": format!("{}/not_me?code={}", server_url, security.not_me_code),
+    "IP": security.ip.unwrap_or_else(|| "unknown".into()),
+    "UserAgent": security.user_agent.unwrap_or_else(|| "unknown".into()),
+    "Time": Utc::now().format("%Y-%m-%d %H:%M UTC").to_string(),
+  });
+  if let (Some(params), serde_json::Value::Object(extra)) = (params.as_object_mut(), extra) {
+    params.extend(extra);
+  }
+  params
+}
+
+// Renders the email and queues it in the outbox, Ok means it is saved and will be sent in the background
 pub async fn send_email(
   email_params: EmailParams,
   State(data): State<Arc<AppState>>,
@@ -42,67 +98,79 @@ pub async fn send_email(
         
         ("password_reset", params, base)
     },
-    EmailParams::MagicLink { base, code, redirect_to } => {
+    EmailParams::MagicLink { base, code, redirect_to, otp } => {
         let params = serde_json::json!({
           "ConfirmationURL": format!(
             "{}/verify_magiclink_code", data.env.server_url
           ),
           "Code": code,
-          "RedirectTo": redirect_to
+          "RedirectTo": redirect_to,
+          "OTP": otp,
         });
         
         ("magic_link", params, base)
     },
+    EmailParams::EmailVerification { base, code } => {
+        let params = serde_json::json!({
+          "ConfirmationURL": format!(
+            "{}/verify_email", data.env.server_url
+          ),
+          "Code": code,
+        });
+        
+        ("email_verification", params, base)
+    },
+    EmailParams::NewSignIn { base, security, method } => {
+        let params = security_params(&data.env.server_url, security, serde_json::json!({ "Method": method }));
+        ("new_sign_in", params, base)
+    },
+    EmailParams::PasswordChanged { base, security } => {
+        let params = security_params(&data.env.server_url, security, serde_json::json!({}));
+        ("password_changed", params, base)
+    },
+    EmailParams::EmailChanged { base, security, new_email } => {
+        let params = security_params(&data.env.server_url, security, serde_json::json!({ "NewEmail": new_email }));
+        ("email_changed", params, base)
+    },
+    EmailParams::IdentityLinked { base, security, provider } => {
+        let params = security_params(&data.env.server_url, security, serde_json::json!({ "Provider": provider }));
+        ("identity_linked", params, base)
+    },
+    EmailParams::ApiKeyCreated { base, security, name } => {
+        let params = security_params(&data.env.server_url, security, serde_json::json!({ "Name": name }));
+        ("api_key_created", params, base)
+    },
     // Future email types can be handled here
 };
 
-let (html, text) = generate_email(GenerateOptions {
-    template_dir: std::env::current_dir()?.join("src").join("templates"),
-    template_params,
-    template_name: template_name.to_string(),
-})?;
+  let rendered = data.templates.render(template_name, &base.locales, &template_params).map_err(|e| {
+    tracing::error!("Failed to render {} email: {}", template_name, e);
+    "failed to send email"
+  })?;
 
-  // Build email message
-  let message = MessageBuilder::new()
-    .from((base.from_name, base.from))
-    .to(vec![base.to])
-    .subject(base.subject)
-    .html_body(html)
-    .text_body(text);
+  let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
 
-  // Send email
-  let result = SmtpClientBuilder::new(data.env.mailer_server.clone(), data.env.mailer_port.clone())
-    .connect_plain()
-    .await?
-    .send(message)
-    .await;
-
-  match result {
-    Ok(_) => Ok(true),
-    Err(_e) => Err("failed to send email".into())
+  let mut base = base;
+  if let Some(subject) = rendered.subject {
+    base.subject = subject;
+  }
+  if let Some(tenant_id) = base.tenant_id.as_deref() {
+    if let Some(settings) = find_settings(&mut conn, tenant_id) {
+      if let Some(from) = settings.mailer_from {
+        base.from = from;
+      }
+      if let Some(from_name) = settings.mailer_from_name {
+        base.from_name = from_name;
+      }
+    }
   }
-}
-
-#[derive(Debug)]
-pub struct GenerateOptions {
-  pub template_dir: PathBuf,
-  pub template_params: Value,
-  pub template_name: String,
-}
-
-fn generate_email(options: GenerateOptions) 
-  -> Result<(String, String), Box<dyn std::error::Error>> {
-  let mut engine = TemplateEngine::new(options.template_dir)?;
-    
-  // Load the template and its partials
-  engine.load_template(&options.template_name)?;
-
-  // Render both HTML and text versions using the pre-generated parameters
-  let rendered = engine.render(&options.template_name, &options.template_params);
 
-  match rendered {
-    Ok(rendered) => Ok((rendered.html, rendered.text)),
-    Err(_) => Err("failed to send email".into())
+  match email_outbox::enqueue(&mut conn, template_name, base, rendered.html, rendered.text) {
+    Ok(_) => Ok(true),
+    Err(e) => {
+      tracing::error!("Failed to queue {} email: {}", template_name, e);
+      Err("failed to send email".into())
+    }
   }
 }
 
@@ -1,41 +1,100 @@
 use std::sync::Arc;
 use axum::{
-  extract::{Query, State},
+  extract::{Form, Query, State},
   http::{header, StatusCode},
   response::{IntoResponse, Redirect},
   Json,
 };
 use anyhow::Result;
-use axum_extra::extract::cookie::{Cookie, SameSite};
+use axum_extra::extract::{
+  cookie::{Cookie, SameSite},
+  CookieJar,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use diesel::{query_dsl::methods::FilterDsl, ExpressionMethods, OptionalExtension, RunQueryDsl};
 use serde::{Deserialize, Serialize};
 use ulid::Ulid;
-use chrono::{DateTime, Utc};
+use chrono::Utc;
 use oauth2::{
-  basic::BasicClient, AuthType, AuthUrl, AuthorizationCode, ClientId, ClientSecret, PkceCodeVerifier, RedirectUrl, TokenResponse, TokenUrl
+  AuthType, AuthUrl, AuthorizationCode, ClientId, ClientSecret, PkceCodeVerifier, RedirectUrl, TokenResponse, TokenUrl
 };
 use crate::{
-  schema::{identities, social_auth, social_provider, tokens, user, Identity, SocialAuth, SocialProvider, Token, User}, token::generate_paseto_token, utils::parse_duration, AppState
+  audit::{record_user_event, RequestContext, OUTCOME_FAILURE, OUTCOME_SUCCESS}, hooks::{self, Hook}, redirect::{append_query_param, safe_redirect_url}, schema::{identities, social_auth, social_provider, user, Identity, IdentitySignInUpdate, SocialAuth, SocialProvider, User}, security_notice::{self, SecurityNotice}, template::preferred_locales, tenant::{find_membership, tenant_claims, tenant_config}, token::{idp_claims, issue_session_tokens, session_claims}, utils::{hash_token, parse_duration}, webhook, AppState
 };
 
-use super::{fetcher::handle_oauth_provider, get_users::{amazon::AmazonProvider, facebook::FacebookProvider, github::GithubProvider, google::GoogleProvider, instagram::InstagramProvider, linkedin::LinkedInProvider, microsoft::MicrosoftProvider, reddit::RedditProvider, tiktok::TiktokProvider, twitch::TwitchProvider, twitter::TwitterProvider}, model::OAuthProvider};
+use super::{fetcher::handle_oauth_provider, get_users::{amazon::AmazonProvider, apple::AppleProvider, bitbucket::{self, BitbucketProvider}, discord::{self, DiscordProvider}, facebook::FacebookProvider, github::GithubProvider, gitlab::GitlabProvider, google::GoogleProvider, instagram::InstagramProvider, linkedin::LinkedInProvider, microsoft::MicrosoftProvider, reddit::RedditProvider, slack::{self, SlackProvider}, tiktok::TiktokProvider, twitch::TwitchProvider, twitter::TwitterProvider}, model::{oauth_state_cookie_name, OAuthClient, OAuthProvider, OAuthTokenResponse}};
 
 // Generic OAuth callback parameters
 #[derive(Debug, Deserialize, Serialize)]
 pub struct OAuthCallbackParams {
   code: String,
   state: String,
+  // Apple posts the user's name and email here on first authorization only
+  user: Option<String>,
+}
+
+// Checks the nonce inside the id_token returned by the token endpoint.
+// The token comes straight from the provider over TLS, so the signature is not re-verified here.
+fn verify_id_token_nonce(
+  token_result: &OAuthTokenResponse,
+  nonce: &str,
+) -> Result<(), (StatusCode, Json<serde_json::Value>)> {
+  let invalid = |message: &str| {
+    let error_response = serde_json::json!({
+      "status": "fail",
+      "message": message,
+    });
+    (StatusCode::BAD_REQUEST, Json(error_response))
+  };
+
+  let id_token = token_result.extra_fields().id_token.as_ref()
+    .ok_or_else(|| invalid("id_token not provided"))?;
+
+  let payload = id_token.split('.').nth(1)
+    .ok_or_else(|| invalid("Malformed id_token"))?;
+  let payload = URL_SAFE_NO_PAD.decode(payload.trim_end_matches('='))
+    .map_err(|_| invalid("Malformed id_token"))?;
+  let claims: serde_json::Value = serde_json::from_slice(&payload)
+    .map_err(|_| invalid("Malformed id_token"))?;
+
+  match claims.get("nonce").and_then(|v| v.as_str()) {
+    Some(claim) if claim == nonce => Ok(()),
+    _ => Err(invalid("Invalid nonce")),
+  }
 }
 
 pub async fn callback_handler(
+  cookie_jar: CookieJar,
   State(data): State<Arc<AppState>>,
+  context: RequestContext,
   Query(params): Query<OAuthCallbackParams>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+  handle_callback(cookie_jar, data, context, params).await
+}
+
+// Sign in with Apple uses response_mode=form_post, so its callback arrives as a POST
+pub async fn callback_form_handler(
+  cookie_jar: CookieJar,
+  State(data): State<Arc<AppState>>,
+  context: RequestContext,
+  Form(params): Form<OAuthCallbackParams>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+  handle_callback(cookie_jar, data, context, params).await
+}
+
+async fn handle_callback(
+  cookie_jar: CookieJar,
+  data: Arc<AppState>,
+  context: RequestContext,
+  params: OAuthCallbackParams,
+) -> Result<axum::response::Response, (StatusCode, Json<serde_json::Value>)> {
   let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
   
   // Verify CSRF token
+  let now = Utc::now().naive_utc();
   let social_oauth_exists = social_auth::table
     .filter(social_auth::csrf.eq(params.state.clone()))
+    .filter(social_auth::expires.gt(now))
     .first::<SocialAuth>(&mut conn)
     .optional();
 
@@ -44,11 +103,38 @@ pub async fn callback_handler(
   } else {
     let error_response = serde_json::json!({
       "status": "fail",
-      "message": "CSRF token not found"
+      "message": "State is invalid or has expired"
     });
     return Err((StatusCode::BAD_REQUEST, Json(error_response)));
   };
 
+  // The state must come back to the same browser that requested it, checked before the state is
+  // consumed so a callback from another browser cannot use up a pending sign-in
+  let browser_binding = cookie_jar
+    .get(&oauth_state_cookie_name(&params.state))
+    .map(|cookie| hash_token(cookie.value()));
+
+  if browser_binding.as_deref() != Some(social_oauth.browser_binding.as_str()) {
+    let error_response = serde_json::json!({
+      "status": "fail",
+      "message": "State does not belong to this browser"
+    });
+    return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+  }
+
+  // Deleting the row is what makes the state single use
+  let consumed = diesel::delete(social_auth::table)
+    .filter(social_auth::id.eq(social_oauth.id.clone()))
+    .execute(&mut conn);
+
+  if !matches!(consumed, Ok(1)) {
+    let error_response = serde_json::json!({
+      "status": "fail",
+      "message": "State is invalid or has expired"
+    });
+    return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+  }
+
   let provider_exists = social_provider::table
     .filter(social_provider::id.eq(social_oauth.provider_id.clone()))
     .first::<SocialProvider>(&mut conn)
@@ -81,26 +167,27 @@ pub async fn callback_handler(
     (StatusCode::BAD_REQUEST, Json(error_response))
   })?;
 
-  let auth_url = AuthUrl::new(provider.auth_url().to_string())
+  let auth_url = AuthUrl::new(provider.auth_url(&data))
     .expect("Invalid authorization endpoint URL");
-  let token_url = TokenUrl::new(provider.token_url().to_string())
+  let token_url = TokenUrl::new(provider.token_url(&data))
     .expect("Invalid token endpoint URL");
   let redirect_url = RedirectUrl::new(provider.redirect_url(&data))
     .expect("Invalid redirect URL");
 
-  let client = BasicClient::new(ClientId::new(provider.client_id(&data)))
+  let client = OAuthClient::new(ClientId::new(provider.client_id(&data)))
     .set_client_secret(ClientSecret::new(provider.client_secret(&data)))
     .set_auth_uri(auth_url)
     .set_token_uri(token_url)
     .set_redirect_uri(redirect_url);
 
   let client = match provider {
+    OAuthProvider::Apple => client.set_auth_type(AuthType::RequestBody),
     OAuthProvider::LinkedIn => client.set_auth_type(AuthType::RequestBody),
     OAuthProvider::Twitch => client.set_auth_type(AuthType::RequestBody),
     _ => client,
   };
 
-  let token_response = if provider == OAuthProvider::Twitter {    
+  let token_response = if provider.supports_pkce() {    
     client
       .exchange_code(AuthorizationCode::new(params.code))
       .set_pkce_verifier(PkceCodeVerifier::new(social_oauth.pkce_verifier.to_string()))
@@ -122,6 +209,10 @@ pub async fn callback_handler(
     (StatusCode::INTERNAL_SERVER_ERROR, Json(error_response))
   })?;
 
+  if let Some(nonce) = social_oauth.nonce.as_deref() {
+    verify_id_token_nonce(&token_result, nonce)?;
+  }
+
   // println!("{:?}", token_result);
 
   // let token_result = token_response.request_async(&http_client)
@@ -144,164 +235,224 @@ pub async fn callback_handler(
   let access_token = token_result.access_token().secret();
 
   // Fetch user info based on provider
-  let (email, name, obj) = match provider {
+  let mut user_info = match provider {
     OAuthProvider::Amazon => handle_oauth_provider(&AmazonProvider, access_token).await?,
+    OAuthProvider::Apple => {
+      let id_token = token_result.extra_fields().id_token.as_deref().unwrap_or_default();
+      AppleProvider.extract_user_info(id_token, params.user.as_deref())?
+    },
+    OAuthProvider::Bitbucket => handle_oauth_provider(&BitbucketProvider::new(bitbucket::API_URL), access_token).await?,
+    OAuthProvider::Discord => handle_oauth_provider(&DiscordProvider::new(discord::API_URL), access_token).await?,
     OAuthProvider::Facebook => handle_oauth_provider(&FacebookProvider, access_token).await?,
     OAuthProvider::Github => handle_oauth_provider(&GithubProvider, access_token).await?,
+    OAuthProvider::Gitlab => handle_oauth_provider(&GitlabProvider::new(&data.env.gitlab_base_url), access_token).await?,
     OAuthProvider::Google => handle_oauth_provider(&GoogleProvider, access_token).await?,
     OAuthProvider::Instagram => handle_oauth_provider(&InstagramProvider, access_token).await?,
     OAuthProvider::LinkedIn => handle_oauth_provider(&LinkedInProvider, access_token).await?,
     OAuthProvider::Microsoft => handle_oauth_provider(&MicrosoftProvider, access_token).await?,
     OAuthProvider::Reddit => handle_oauth_provider(&RedditProvider, access_token).await?,
+    OAuthProvider::Slack => handle_oauth_provider(&SlackProvider::new(slack::API_URL), access_token).await?,
     OAuthProvider::Tiktok => handle_oauth_provider(&TiktokProvider, access_token).await?,
     OAuthProvider::Twitch => handle_oauth_provider(&TwitchProvider, access_token).await?,
     OAuthProvider::Twitter => handle_oauth_provider(&TwitterProvider, access_token).await?,
   };
 
-  let user_exists = user::table
-    .filter(user::email.eq(email.clone()))
-    .first::<User>(&mut conn)
+  // The profile is checked before anything is written, a denial leaves no user or identity behind
+  let mut profile_changes = hooks::oauth_profile(&data.env, serde_json::json!({
+    "provider": provider_name,
+    "subject": user_info.subject,
+    "email": user_info.email,
+    "emailVerified": user_info.email_verified,
+    "name": user_info.name,
+    "profile": user_info.data,
+    "tenantId": social_oauth.tenant_id,
+  })).await?;
+  if let Some(serde_json::Value::String(name)) = profile_changes.remove("name") {
+    user_info.name = name;
+  }
+  if let serde_json::Value::Object(profile) = &mut user_info.data {
+    profile.extend(profile_changes);
+  }
+
+  let timestamp = Utc::now().naive_utc();
+
+  // Returning users are matched on the provider's subject, never on the email it reports
+  let identity_exists = identities::table
+    .filter(identities::provider_id.eq(social_oauth.provider_id.clone()))
+    .filter(identities::provider_user_id.eq(user_info.subject.clone()))
+    .first::<Identity>(&mut conn)
     .optional();
 
-    let user_id = if let Ok(Some(user)) = user_exists {
-      user.id
-    } else {
+  let identity = match identity_exists {
+    Ok(identity) => identity,
+    Err(e) => {
+      let error_response = serde_json::json!({
+        "status": "fail",
+        "message": format!("Identity could not be loaded: {}", e),
+      });
+      return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+    }
+  };
+
+  let user_id = if let Some(identity) = identity {
+    let statement = diesel::update(identities::table)
+      .filter(identities::id.eq(identity.id))
+      .set(&IdentitySignInUpdate {
+        email: user_info.email.clone(),
+        email_verified: user_info.email_verified,
+        identity_data: user_info.data,
+        last_signin_at: timestamp,
+        updated_at: Some(timestamp),
+      })
+      .execute(&mut conn);
+
+    if let Err(e) = statement {
       let error_response = serde_json::json!({
-          "status": "fail",
-          "message": "Invalid email or password"
+        "status": "fail",
+        "message": format!("Identity could not be updated: {}", e),
       });
       return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+    }
+
+    identity.user_id
+  } else {
+    // Only an email the provider vouches for may link to an existing account
+    let verified_email = user_info.email
+      .clone()
+      .filter(|_| user_info.email_verified)
+      .map(|email| email.to_lowercase());
+
+    let user_exists = match &verified_email {
+      Some(email) => user::table
+        .filter(user::email.eq(email))
+        .first::<User>(&mut conn)
+        .optional()
+        .unwrap_or(None),
+      None => None,
+    };
+
+    let linked_to_existing = user_exists.is_some();
+    let user_id = match user_exists {
+      Some(user) => user.id,
+      None => {
+        hooks::run(&data.env, Hook::BeforeRegister, serde_json::json!({
+          "email": verified_email,
+          "name": user_info.name,
+          "method": provider_name,
+          "tenantId": social_oauth.tenant_id,
+        })).await?;
+
+        // Create new user, without an email when the provider could not vouch for one
+        let statement = diesel::insert_into(user::table)
+          .values(&User {
+            id: Ulid::new().to_string(),
+            name: user_info.name.clone(),
+            email: verified_email.clone(),
+            password: None,
+            verified: verified_email.is_some(),
+            disabled_at: None,
+            locale: preferred_locales(None, context.accept_language.as_deref()).into_iter().next(),
+            created_at: timestamp,
+            updated_at: None,
+            deleted_at: None
+          })
+          .get_result::<User>(&mut conn);
+
+        match statement {
+          Ok(user) => {
+            webhook::enqueue(&mut conn, webhook::USER_REGISTERED, serde_json::json!({
+              "userId": user.id,
+              "email": user.email,
+              "name": user.name,
+              "method": provider_name,
+              "tenantId": social_oauth.tenant_id,
+            }));
+            user.id
+          }
+          Err(e) => {
+            let error_response = serde_json::json!({
+              "status": "fail",
+              "message": format!("New user could not be saved to database: {}", e),
+            });
+            return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+          }
+        }
+      }
     };
 
-  if user_id.is_empty() {
-    // Create new user
-    let timestamp = Utc::now().naive_utc();
-    let statement = diesel::insert_into(user::table)
-      .values(&User {
-          id: user_id.clone(),
-          name: name.into(),
-          email: email.into(),
-          password: None,
-          verified: false,
-          created_at: timestamp,
-          updated_at: None,
-          deleted_at: None
+    // Insert identity
+    let statement = diesel::insert_into(identities::table)
+      .values(&Identity {
+        id: Ulid::new().to_string(),
+        user_id: user_id.clone(),
+        provider_id: social_oauth.provider_id.clone(),
+        provider_user_id: user_info.subject,
+        email: user_info.email,
+        email_verified: user_info.email_verified,
+        identity_data: user_info.data,
+        last_signin_at: timestamp,
+        created_at: timestamp,
+        updated_at: None,
+        deleted_at: None
       })
-      .get_result::<User>(&mut conn);
+      .execute(&mut conn);
 
     if let Err(e) = statement {
       let error_response = serde_json::json!({
         "status": "fail",
-        "message": format!("New user could not be saved to database: {}", e),
+        "message": format!("New identity could not be saved to database: {}", e),
       });
-      
       return Err((StatusCode::BAD_REQUEST, Json(error_response)));
     }
-  }
 
-  // Insert identity    
-  let timestamp = Utc::now().naive_utc();
-  let last_signin_at = Utc::now().naive_utc();
-  let statement = diesel::insert_into(identities::table)
-    .values(&Identity {
-      id: Ulid::new().to_string().into(),
-      user_id: user_id.clone().into(),
-      provider_id: social_oauth.provider_id.into(),
-      identity_data: obj.into(),
-      last_signin_at: last_signin_at.into(),
-      created_at: timestamp,
-      updated_at: None,
-      deleted_at: None
-    })
-    .execute(&mut conn);
+    record_user_event(&mut conn, &context, "identity.linked", Some(&user_id), OUTCOME_SUCCESS, serde_json::json!({ "provider": provider_name }));
+    // A new account linking its first identity is not news to anyone
+    if linked_to_existing {
+      security_notice::notify(&data, &mut conn, &user_id, social_oauth.tenant_id.clone(), &context, SecurityNotice::IdentityLinked { provider: provider_name.to_string() }).await;
+    }
 
-  if let Err(e) = statement {
-    let error_response = serde_json::json!({
-      "status": "fail",
-      "message": format!("New identity could not be saved to database: {}", e),
-    });
-    
-    return Err((StatusCode::BAD_REQUEST, Json(error_response)));
-  }
+    user_id
+  };
+
+  // Accounts without an email are sent on to collect and verify one
+  let needs_email = user::table
+    .filter(user::id.eq(user_id.clone()))
+    .first::<User>(&mut conn)
+    .map(|user| user.email.is_none())
+    .unwrap_or(false);
 
   // Generate tokens
-  let access_token_details = generate_paseto_token(
-    user_id.clone(),
-    data.env.access_token_max_age,
-    &data.env.auth_key,
-  ).unwrap();
-
-  let refresh_token_details = generate_paseto_token(
-    user_id.clone(),
-    data.env.refresh_token_max_age,
-    &data.env.auth_key,
-  ).unwrap();
-
-  // Save tokens
-  // Access token
-  let expires = DateTime::<Utc>::from_timestamp(access_token_details.expires_in.unwrap(), 0)
-    .map(|dt| dt.naive_utc())
-    .unwrap_or_else(|| {
-      // Handle invalid timestamps
-      DateTime::<Utc>::from_timestamp(0, 0)
-        .unwrap()
-        .naive_utc()
-    });
-  let timestamp = Utc::now().naive_utc();
-  let statement = diesel::insert_into(tokens::table)
-    .values(&Token {
-      id: Ulid::new().to_string(),
-      user_id: user_id.clone().into(),
-      expires,
-      blacklisted: false,
-      token: access_token_details.token.clone().unwrap(),
-      token_uuid: access_token_details.token_uuid.to_string(),
-      created_at: timestamp,
-      updated_at: None,
-      deleted_at: None
-    })
-    .execute(&mut conn);
-  
-  if let Err(e) = statement {
-    let error_message = format!("Failed to save access token: validation error\nDetails: {:?}", e);
-    let error_response = serde_json::json!({
-        "status": "fail",
-        "message": error_message
-    });
-    return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+  // Recorded in the tokens for OIDC auth_time and amr
+  let mut auth_claims = session_claims(Utc::now().timestamp(), "fed");
+  auth_claims.extend(idp_claims(&provider_name));
+
+  // Started on a tenant, the session acts in it when the user belongs there
+  let tenant_id = social_oauth.tenant_id
+    .as_deref()
+    .filter(|tenant_id| find_membership(&mut conn, tenant_id, &user_id).is_some());
+  if let Some(tenant_id) = tenant_id {
+    auth_claims.extend(tenant_claims(tenant_id));
   }
+  let env = tenant_config(&mut conn, &data.env, tenant_id);
+
+  hooks::run(&env, Hook::BeforeLogin, serde_json::json!({
+    "userId": user_id,
+    "method": "fed",
+    "provider": provider_name,
+    "tenantId": tenant_id,
+  })).await.inspect_err(|(_, Json(error))| {
+    record_user_event(&mut conn, &context, "oauth.login", Some(&user_id), OUTCOME_FAILURE, serde_json::json!({ "provider": provider_name, "reason": error["message"] }));
+  })?;
 
-  // Refresh Token
-  let expires = DateTime::<Utc>::from_timestamp(access_token_details.expires_in.unwrap(), 0)
-    .map(|dt| dt.naive_utc())
-    .unwrap_or_else(|| {
-      // Handle invalid timestamps
-      DateTime::<Utc>::from_timestamp(0, 0)
-        .unwrap()
-        .naive_utc()
-    });
-  let timestamp = Utc::now().naive_utc();
-  let statement = diesel::insert_into(tokens::table)
-    .values(&Token {
-      id: Ulid::new().to_string(),
-      user_id: user_id.into(),
-      expires,
-      blacklisted: false,
-      token: refresh_token_details.token.clone().unwrap(),
-      token_uuid: refresh_token_details.token_uuid.to_string(),
-      created_at: timestamp,
-      updated_at: None,
-      deleted_at: None
-    })
-    .execute(&mut conn);
-  
-  if let Err(e) = statement {
-    let error_message = format!("Failed to save refresh token: validation error\nDetails: {:?}", e);
-    let error_response = serde_json::json!({
-        "status": "fail",
-        "message": error_message
-    });
-    return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+  let (access_token_details, refresh_token_details) = issue_session_tokens(&mut conn, &env, &user_id, &auth_claims).await
+    .inspect_err(|(_, Json(error))| {
+      record_user_event(&mut conn, &context, "oauth.login", Some(&user_id), OUTCOME_FAILURE, serde_json::json!({ "provider": provider_name, "reason": error["message"] }));
+    })?;
+  let unrecognized = security_notice::is_unrecognized_sign_in(&mut conn, &user_id, &context);
+  record_user_event(&mut conn, &context, "oauth.login", Some(&user_id), OUTCOME_SUCCESS, serde_json::json!({ "provider": provider_name, "method": "fed", "tenantId": tenant_id }));
+  webhook::enqueue(&mut conn, webhook::USER_LOGIN, serde_json::json!({ "userId": user_id, "method": "fed", "provider": provider_name, "tenantId": tenant_id }));
+  if unrecognized {
+    security_notice::notify(&data, &mut conn, &user_id, tenant_id.map(|tenant_id| tenant_id.to_string()), &context, SecurityNotice::NewSignIn { method: provider_name.to_string() }).await;
   }
 
   // Set cookies
@@ -310,7 +461,7 @@ pub async fn callback_handler(
   )
     .path("/")
     .secure(true)
-    .max_age(time::Duration::seconds(parse_duration(&data.env.access_token_expires_in).unwrap_or(900)))
+    .max_age(time::Duration::seconds(parse_duration(&env.access_token_expires_in).unwrap_or(900)))
     .same_site(SameSite::Strict)
     .http_only(true);
 
@@ -319,13 +470,29 @@ pub async fn callback_handler(
   )
     .path("/")
     .secure(true)
-    .max_age(time::Duration::seconds(parse_duration(&data.env.refresh_token_expires_in).unwrap_or(900)))
+    .max_age(time::Duration::seconds(parse_duration(&env.refresh_token_expires_in).unwrap_or(900)))
     .same_site(SameSite::Strict)
     .http_only(true);
 
-  let redirect = Redirect::temporary(&social_oauth.redirect_to);
+  let state_cookie = Cookie::build((oauth_state_cookie_name(&params.state), ""))
+    .path("/")
+    .secure(true)
+    .max_age(time::Duration::minutes(-1))
+    .same_site(SameSite::Lax)
+    .http_only(true);
+
+  let mut redirect_to = safe_redirect_url(&data.env, &social_oauth.redirect_to);
+  if needs_email {
+    redirect_to = append_query_param(&redirect_to, "collect_email", "true");
+  }
+
+  let redirect = Redirect::temporary(&redirect_to);
   let mut response = redirect.into_response();
 
+  response.headers_mut().append(
+    header::SET_COOKIE,
+    state_cookie.to_string().parse().unwrap(),
+  );
   response.headers_mut().append(
     header::SET_COOKIE,
     access_cookie.to_string().parse().unwrap(),
@@ -5,37 +5,38 @@ use axum::{
 use anyhow::Result;
 use serde_json::json;
 
-use super::model::OAuthProvider;
+use super::model::{OAuthProvider, OAuthUserInfo};
 
 pub trait OAuthProfileProvider {
   fn provider(&self) -> &'static OAuthProvider;
   
   // Provider-specific URL
-  fn profile_url(&self) -> &'static str;
+  fn profile_url(&self) -> &str;
   
   // Provider-specific headers
   fn additional_headers(&self) -> Vec<(String, String)> {
     Vec::new() // Default empty implementation
   }
 
-  // Add this new method for providers that need additional requests
+  // Add this new method for providers that need additional requests.
+  // Returns the primary verified email, if the provider has one.
   async fn fetch_additional_data(
     &self, 
     _client: &reqwest::Client, 
     _access_token: &str
-  ) -> Result<String, (StatusCode, Json<serde_json::Value>)> {
-    Ok("".into()) // Default implementation returns null
+  ) -> Result<Option<String>, (StatusCode, Json<serde_json::Value>)> {
+    Ok(None) // Default implementation returns null
   }
   
   // Provider-specific parsing
   fn extract_user_info(&self, response_json: serde_json::Value, bytes: &[u8]) 
-    -> Result<(String, String, serde_json::Value), (StatusCode, Json<serde_json::Value>)>;
+    -> Result<OAuthUserInfo, (StatusCode, Json<serde_json::Value>)>;
 }
 
 pub async fn handle_oauth_provider<P: OAuthProfileProvider>(
   provider: &P,
   access_token: &str,
-) -> Result<(String, String, serde_json::Value), (StatusCode, Json<serde_json::Value>)> {
+) -> Result<OAuthUserInfo, (StatusCode, Json<serde_json::Value>)> {
   let mut request = reqwest::Client::new()
     .get(provider.profile_url())
     .header(header::AUTHORIZATION, format!("Bearer {}", access_token));
@@ -64,14 +65,16 @@ pub async fn handle_oauth_provider<P: OAuthProfileProvider>(
   })?;  
 
   // Extract user info using provider-specific implementation
-  let (mut email, name, obj) = provider.extract_user_info(response_json, &bytes)?;
+  let mut user_info = provider.extract_user_info(response_json, &bytes)?;
 
   // Fetch additional data if needed
-  if provider.provider() == &OAuthProvider::Github {
-    email = provider.fetch_additional_data(&reqwest::Client::new(), access_token).await.unwrap();
+  if matches!(provider.provider(), OAuthProvider::Bitbucket | OAuthProvider::Github) {
+    let email = provider.fetch_additional_data(&reqwest::Client::new(), access_token).await?;
+    user_info.email_verified = email.is_some();
+    user_info.email = email;
   }
   
-  Ok((email, name, obj))
+  Ok(user_info)
 }
 
 pub fn handle_error(e: reqwest::Error) -> (StatusCode, Json<serde_json::Value>) {
@@ -0,0 +1,139 @@
+use crate::{config::Config, social_handlers::model::OAuthUserInfo};
+use axum::{
+  http::StatusCode,
+  Json,
+};
+use anyhow::Result;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{Duration, Utc};
+use p256::{
+  ecdsa::{signature::Signer, Signature, SigningKey},
+  pkcs8::DecodePrivateKey,
+};
+use serde_json::json;
+use serde::{Deserialize, Serialize};
+
+const APPLE_AUDIENCE: &str = "https://appleid.apple.com";
+
+// Apple allows a client secret to live for at most six months
+const APPLE_CLIENT_SECRET_TTL_DAYS: i64 = 150;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AppleIdTokenClaims {
+  pub iss: String,
+  pub sub: String,
+  pub aud: String,
+  pub email: Option<String>,
+  // Apple sends these as either booleans or "true"/"false" strings
+  pub email_verified: Option<serde_json::Value>,
+  pub is_private_email: Option<serde_json::Value>,
+  pub nonce: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AppleUserName {
+  #[serde(rename = "firstName")]
+  pub first_name: Option<String>,
+  #[serde(rename = "lastName")]
+  pub last_name: Option<String>,
+}
+
+// Posted alongside the code, only on the first authorization
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AppleUser {
+  pub name: Option<AppleUserName>,
+  pub email: Option<String>,
+}
+
+fn claim_is_true(value: &Option<serde_json::Value>) -> bool {
+  match value {
+    Some(serde_json::Value::Bool(flag)) => *flag,
+    Some(serde_json::Value::String(flag)) => flag == "true",
+    _ => false,
+  }
+}
+
+// Apple expects an ES256 JWT signed with the team's private key in place of a static secret
+pub fn generate_client_secret(config: &Config) -> Result<String, Box<dyn std::error::Error>> {
+  let private_key = config.apple_private_key.replace("\\n", "\n");
+  let signing_key = SigningKey::from_pkcs8_pem(&private_key)?;
+
+  let now = Utc::now();
+  let header = json!({
+    "alg": "ES256",
+    "kid": config.apple_key_id,
+  });
+  let claims = json!({
+    "iss": config.apple_team_id,
+    "iat": now.timestamp(),
+    "exp": (now + Duration::days(APPLE_CLIENT_SECRET_TTL_DAYS)).timestamp(),
+    "aud": APPLE_AUDIENCE,
+    "sub": config.apple_client_id,
+  });
+
+  let signing_input = format!(
+    "{}.{}",
+    URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header)?),
+    URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims)?),
+  );
+  let signature: Signature = signing_key.sign(signing_input.as_bytes());
+
+  Ok(format!("{}.{}", signing_input, URL_SAFE_NO_PAD.encode(signature.to_bytes())))
+}
+
+pub struct AppleProvider;
+
+impl AppleProvider {
+  // Apple has no profile endpoint, everything comes from the id_token and the posted user
+  pub fn extract_user_info(&self, id_token: &str, user: Option<&str>)
+    -> Result<OAuthUserInfo, (StatusCode, Json<serde_json::Value>)> {
+    let parse_error = || (
+      StatusCode::INTERNAL_SERVER_ERROR,
+      Json(json!({
+        "status": "error",
+        "message": "Failed to parse response"
+      }))
+    );
+
+    let payload = id_token.split('.').nth(1).ok_or_else(parse_error)?;
+    let payload = URL_SAFE_NO_PAD.decode(payload.trim_end_matches('=')).map_err(|_| parse_error())?;
+    let claims: AppleIdTokenClaims = serde_json::from_slice(&payload).map_err(|_| parse_error())?;
+
+    let user: Option<AppleUser> = match user {
+      Some(user) => Some(serde_json::from_str(user).map_err(|_| parse_error())?),
+      None => None,
+    };
+
+    // Private relay addresses forward to the user's real inbox, so they are treated like any other
+    let email_verified = claims.email.is_some() && claim_is_true(&claims.email_verified);
+
+    // Apple only sends the name on first authorization, returning users keep the stored name
+    let name = user
+      .as_ref()
+      .and_then(|user| user.name.as_ref())
+      .map(|name| {
+        [name.first_name.as_deref(), name.last_name.as_deref()]
+          .into_iter()
+          .flatten()
+          .collect::<Vec<&str>>()
+          .join(" ")
+      })
+      .unwrap_or_default();
+
+    let data = json!({
+      "sub": claims.sub,
+      "email": claims.email,
+      "email_verified": email_verified,
+      "is_private_email": claim_is_true(&claims.is_private_email),
+      "name": name,
+    });
+
+    Ok(OAuthUserInfo {
+      subject: claims.sub,
+      email: claims.email,
+      email_verified,
+      name,
+      data,
+    })
+  }
+}
@@ -0,0 +1,168 @@
+use crate::social_handlers::{fetcher::{missing_field_error, OAuthProfileProvider}, model::{OAuthProvider, OAuthUserInfo}};
+use axum::{
+  http::StatusCode,
+  Json,
+};
+use anyhow::Result;
+use reqwest::header;
+use serde_json::json;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BitbucketUser {
+  pub uuid: String,
+  pub account_id: Option<String>,
+  pub username: Option<String>,
+  pub nickname: Option<String>,
+  pub display_name: Option<String>,
+  pub created_on: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BitbucketEmail {
+  pub email: String,
+  pub is_primary: bool,
+  pub is_confirmed: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BitbucketEmails {
+  pub values: Vec<BitbucketEmail>,
+}
+
+pub const API_URL: &str = "https://api.bitbucket.org/2.0";
+
+pub struct BitbucketProvider {
+  profile_url: String,
+  emails_url: String,
+}
+
+impl BitbucketProvider {
+  pub fn new(base_url: &str) -> Self {
+    let base_url = base_url.trim_end_matches('/');
+    Self {
+      profile_url: format!("{}/user", base_url),
+      emails_url: format!("{}/user/emails", base_url),
+    }
+  }
+}
+
+impl OAuthProfileProvider for BitbucketProvider {
+  fn provider(&self) -> &'static OAuthProvider {
+    &OAuthProvider::Bitbucket
+  }
+
+  fn profile_url(&self) -> &str {
+    &self.profile_url
+  }
+
+  // The profile has no email, it lives on a separate endpoint
+  async fn fetch_additional_data(
+    &self,
+    client: &reqwest::Client,
+    access_token: &str
+  ) -> Result<Option<String>, (StatusCode, Json<serde_json::Value>)> {
+    let bitbucket_emails = client
+      .get(&self.emails_url)
+      .header(header::AUTHORIZATION, format!("Bearer {}", access_token))
+      .send()
+      .await
+      .map_err(|e| (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({
+          "status": "error",
+          "message": format!("Failed to fetch Bitbucket emails: {}", e)
+        }))
+      ))?
+      .json::<BitbucketEmails>()
+      .await
+      .map_err(|e| (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({
+          "status": "error",
+          "message": format!("Failed to parse Bitbucket emails: {}", e)
+        }))
+      ))?;
+
+    let email = bitbucket_emails
+      .values
+      .iter()
+      .find(|e| e.is_primary && e.is_confirmed)
+      .map(|e| e.email.clone());
+
+    Ok(email)
+  }
+    
+  fn extract_user_info(&self, response_json: serde_json::Value, bytes: &[u8]) 
+    -> Result<OAuthUserInfo, (StatusCode, Json<serde_json::Value>)> {
+    let user: BitbucketUser = serde_json::from_slice(bytes).map_err(|_| (
+      StatusCode::INTERNAL_SERVER_ERROR,
+      Json(json!({
+        "status": "error",
+        "message": "Failed to parse response"
+      }))
+    ))?;
+    
+    let name = user.display_name
+      .or(user.nickname)
+      .ok_or_else(|| missing_field_error("Name"))?;
+
+    // The confirmed email is looked up separately in fetch_additional_data
+    Ok(OAuthUserInfo {
+      subject: user.uuid,
+      email: None,
+      email_verified: false,
+      name,
+      data: response_json,
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use axum::Router;
+  use serde_json::json;
+
+  use super::BitbucketProvider;
+  use crate::social_handlers::{fetcher::handle_oauth_provider, get_users::mock, model::OAuthUserInfo};
+
+  async fn fetch_user(emails: serde_json::Value) -> OAuthUserInfo {
+    let router = Router::new()
+      .route("/user", mock::respond_with(json!({
+        "uuid": "{d301aafa-d676-4ee0-88be-962be7417567}",
+        "display_name": "Ada Lovelace",
+        "nickname": "ada",
+      })))
+      .route("/user/emails", mock::respond_with(emails));
+    let base_url = mock::serve(router).await;
+    handle_oauth_provider(&BitbucketProvider::new(&base_url), mock::ACCESS_TOKEN).await.unwrap()
+  }
+
+  #[tokio::test]
+  async fn confirmed_primary_email_is_verified() {
+    let user = fetch_user(json!({
+      "values": [
+        { "email": "old@example.com", "is_primary": false, "is_confirmed": true },
+        { "email": "ada@example.com", "is_primary": true, "is_confirmed": true },
+      ]
+    })).await;
+
+    assert_eq!(user.subject, "{d301aafa-d676-4ee0-88be-962be7417567}");
+    assert_eq!(user.name, "Ada Lovelace");
+    assert_eq!(user.email.as_deref(), Some("ada@example.com"));
+    assert!(user.email_verified);
+  }
+
+  #[tokio::test]
+  async fn unconfirmed_primary_email_is_not_used() {
+    let user = fetch_user(json!({
+      "values": [
+        { "email": "ada@example.com", "is_primary": true, "is_confirmed": false },
+        { "email": "old@example.com", "is_primary": false, "is_confirmed": true },
+      ]
+    })).await;
+
+    assert_eq!(user.email, None);
+    assert!(!user.email_verified);
+  }
+}
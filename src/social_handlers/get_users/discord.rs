@@ -0,0 +1,117 @@
+use crate::social_handlers::{fetcher::OAuthProfileProvider, model::{OAuthProvider, OAuthUserInfo}};
+use axum::{
+  http::StatusCode,
+  Json,
+};
+use anyhow::Result;
+use serde_json::json;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DiscordUser {
+  pub id: String,
+  pub username: String,
+  pub global_name: Option<String>,
+  pub discriminator: Option<String>,
+  pub avatar: Option<String>,
+  pub locale: Option<String>,
+  pub mfa_enabled: Option<bool>,
+  pub email: Option<String>,
+  pub verified: Option<bool>,
+}
+
+pub const API_URL: &str = "https://discord.com/api";
+
+pub struct DiscordProvider {
+  profile_url: String,
+}
+
+impl DiscordProvider {
+  pub fn new(base_url: &str) -> Self {
+    Self {
+      profile_url: format!("{}/users/@me", base_url.trim_end_matches('/')),
+    }
+  }
+}
+
+impl OAuthProfileProvider for DiscordProvider {
+  fn provider(&self) -> &'static OAuthProvider {
+    &OAuthProvider::Discord
+  }
+
+  fn profile_url(&self) -> &str {
+    &self.profile_url
+  }
+    
+  fn extract_user_info(&self, response_json: serde_json::Value, bytes: &[u8]) 
+    -> Result<OAuthUserInfo, (StatusCode, Json<serde_json::Value>)> {
+    let user: DiscordUser = serde_json::from_slice(bytes).map_err(|_| (
+      StatusCode::INTERNAL_SERVER_ERROR,
+      Json(json!({
+        "status": "error",
+        "message": "Failed to parse response"
+      }))
+    ))?;
+
+    let name = user.global_name.unwrap_or(user.username);
+    
+    Ok(OAuthUserInfo {
+      subject: user.id,
+      email_verified: user.email.is_some() && user.verified == Some(true),
+      email: user.email,
+      name,
+      data: response_json,
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use axum::Router;
+  use serde_json::json;
+
+  use super::DiscordProvider;
+  use crate::social_handlers::{fetcher::handle_oauth_provider, get_users::mock, model::OAuthUserInfo};
+
+  async fn fetch_user(user: serde_json::Value) -> OAuthUserInfo {
+    let base_url = mock::serve(Router::new().route("/users/@me", mock::respond_with(user))).await;
+    handle_oauth_provider(&DiscordProvider::new(&base_url), mock::ACCESS_TOKEN).await.unwrap()
+  }
+
+  #[tokio::test]
+  async fn verified_email_is_verified() {
+    let user = fetch_user(json!({
+      "id": "80351110224678912",
+      "username": "ada",
+      "global_name": "Ada",
+      "email": "ada@example.com",
+      "verified": true,
+    })).await;
+
+    assert_eq!(user.subject, "80351110224678912");
+    assert_eq!(user.name, "Ada");
+    assert_eq!(user.email.as_deref(), Some("ada@example.com"));
+    assert!(user.email_verified);
+  }
+
+  #[tokio::test]
+  async fn unverified_email_is_not_verified() {
+    let user = fetch_user(json!({
+      "id": "80351110224678912",
+      "username": "ada",
+      "email": "ada@example.com",
+      "verified": false,
+    })).await;
+
+    assert_eq!(user.name, "ada");
+    assert!(!user.email_verified);
+  }
+
+  #[tokio::test]
+  async fn missing_email_scope_leaves_email_empty() {
+    let user = fetch_user(json!({ "id": "80351110224678912", "username": "ada" })).await;
+
+    assert_eq!(user.email, None);
+    assert!(!user.email_verified);
+  }
+}
@@ -1,4 +1,4 @@
-use crate::social_handlers::{fetcher::{missing_field_error, OAuthProfileProvider}, model::OAuthProvider};
+use crate::social_handlers::{fetcher::{missing_field_error, OAuthProfileProvider}, model::{OAuthProvider, OAuthUserInfo}};
 use axum::{
   http::StatusCode,
   Json,
@@ -86,7 +86,7 @@ impl OAuthProfileProvider for FacebookProvider {
   }
   
   fn extract_user_info(&self, response_json: serde_json::Value, bytes: &[u8]) 
-    -> Result<(String, String, serde_json::Value), (StatusCode, Json<serde_json::Value>)> {
+    -> Result<OAuthUserInfo, (StatusCode, Json<serde_json::Value>)> {
     let user: FacebookUser = serde_json::from_slice(bytes).map_err(|_| (
       StatusCode::INTERNAL_SERVER_ERROR,
       Json(json!({
@@ -97,8 +97,14 @@ impl OAuthProfileProvider for FacebookProvider {
     
     let name = user.name
       .ok_or_else(|| missing_field_error("Name"))?;
-    let email = format!("{}@facebook.com", name.to_lowercase());
-    
-    Ok((email, name, response_json))
+
+    // Facebook omits the email unless it has been confirmed
+    Ok(OAuthUserInfo {
+      subject: user.id,
+      email_verified: user.email.is_some(),
+      email: user.email,
+      name,
+      data: response_json,
+    })
   }
 }
\ No newline at end of file
@@ -1,4 +1,4 @@
-use crate::social_handlers::{fetcher::{missing_field_error, OAuthProfileProvider}, model::OAuthProvider};
+use crate::social_handlers::{fetcher::OAuthProfileProvider, model::{OAuthProvider, OAuthUserInfo}};
 use axum::{
   http::StatusCode,
   Json,
@@ -75,7 +75,7 @@ impl OAuthProfileProvider for GithubProvider {
     &self,
     client: &reqwest::Client,
     access_token: &str
-) -> Result<String, (StatusCode, Json<serde_json::Value>)> {
+) -> Result<Option<String>, (StatusCode, Json<serde_json::Value>)> {
     let github_emails = client
       .get("https://api.github.com/user/emails")
       .header(header::AUTHORIZATION, format!("Bearer {}", access_token))
@@ -102,15 +102,13 @@ impl OAuthProfileProvider for GithubProvider {
     let email = github_emails
       .iter()
       .find(|e| e.primary && e.verified)
-      .ok_or_else(|| missing_field_error("Email"))?
-      .email
-      .clone();
+      .map(|e| e.email.clone());
 
     Ok(email)
   }
 
   fn extract_user_info(&self, response_json: serde_json::Value, bytes: &[u8]) 
-    -> Result<(String, String, serde_json::Value), (StatusCode, Json<serde_json::Value>)> {
+    -> Result<OAuthUserInfo, (StatusCode, Json<serde_json::Value>)> {
     let user: GitHubUser = serde_json::from_slice(bytes).map_err(|_| (
       StatusCode::INTERNAL_SERVER_ERROR,
       Json(json!({
@@ -120,8 +118,15 @@ impl OAuthProfileProvider for GithubProvider {
     ))?;      
     
     let name = user.name
-      .ok_or_else(|| missing_field_error("Name"))?;
-    
-    Ok(("".into(), name, response_json))
+      .unwrap_or(user.login);
+
+    // The verified email is looked up separately in fetch_additional_data
+    Ok(OAuthUserInfo {
+      subject: user.id.to_string(),
+      email: None,
+      email_verified: false,
+      name,
+      data: response_json,
+    })
   }
 }
\ No newline at end of file
@@ -0,0 +1,119 @@
+use crate::social_handlers::{fetcher::OAuthProfileProvider, model::{OAuthProvider, OAuthUserInfo}};
+use axum::{
+  http::StatusCode,
+  Json,
+};
+use anyhow::Result;
+use serde_json::json;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GitlabUser {
+  pub id: i64,
+  pub username: String,
+  pub name: Option<String>,
+  pub state: Option<String>,
+  pub avatar_url: Option<String>,
+  pub web_url: Option<String>,
+  pub email: Option<String>,
+  // Only set once the primary email has been confirmed
+  pub confirmed_at: Option<String>,
+  pub created_at: Option<String>,
+}
+
+// Works against gitlab.com or a self-hosted instance
+pub struct GitlabProvider {
+  profile_url: String,
+}
+
+impl GitlabProvider {
+  pub fn new(base_url: &str) -> Self {
+    Self {
+      profile_url: format!("{}/api/v4/user", base_url.trim_end_matches('/')),
+    }
+  }
+}
+
+impl OAuthProfileProvider for GitlabProvider {
+  fn provider(&self) -> &'static OAuthProvider {
+    &OAuthProvider::Gitlab
+  }
+
+  fn profile_url(&self) -> &str {
+    &self.profile_url
+  }
+    
+  fn extract_user_info(&self, response_json: serde_json::Value, bytes: &[u8]) 
+    -> Result<OAuthUserInfo, (StatusCode, Json<serde_json::Value>)> {
+    let user: GitlabUser = serde_json::from_slice(bytes).map_err(|_| (
+      StatusCode::INTERNAL_SERVER_ERROR,
+      Json(json!({
+        "status": "error",
+        "message": "Failed to parse response"
+      }))
+    ))?;
+
+    let name = user.name.unwrap_or(user.username);
+    
+    Ok(OAuthUserInfo {
+      subject: user.id.to_string(),
+      email_verified: user.email.is_some() && user.confirmed_at.is_some(),
+      email: user.email,
+      name,
+      data: response_json,
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use axum::Router;
+  use serde_json::json;
+
+  use super::GitlabProvider;
+  use crate::social_handlers::{fetcher::handle_oauth_provider, get_users::mock, model::OAuthUserInfo};
+
+  async fn fetch_user(user: serde_json::Value) -> OAuthUserInfo {
+    let base_url = mock::serve(Router::new().route("/api/v4/user", mock::respond_with(user))).await;
+    handle_oauth_provider(&GitlabProvider::new(&base_url), mock::ACCESS_TOKEN).await.unwrap()
+  }
+
+  #[tokio::test]
+  async fn confirmed_email_is_verified() {
+    let user = fetch_user(json!({
+      "id": 42,
+      "username": "ada",
+      "name": "Ada Lovelace",
+      "email": "ada@example.com",
+      "confirmed_at": "2024-01-01T00:00:00Z",
+    })).await;
+
+    assert_eq!(user.subject, "42");
+    assert_eq!(user.name, "Ada Lovelace");
+    assert_eq!(user.email.as_deref(), Some("ada@example.com"));
+    assert!(user.email_verified);
+  }
+
+  #[tokio::test]
+  async fn unconfirmed_email_is_not_verified() {
+    let user = fetch_user(json!({
+      "id": 42,
+      "username": "ada",
+      "email": "ada@example.com",
+      "confirmed_at": null,
+    })).await;
+
+    assert_eq!(user.name, "ada");
+    assert!(!user.email_verified);
+  }
+
+  #[tokio::test]
+  async fn trailing_slash_on_base_url_is_ignored() {
+    let base_url = mock::serve(Router::new().route("/api/v4/user", mock::respond_with(json!({ "id": 1, "username": "ada" })))).await;
+    let user = handle_oauth_provider(&GitlabProvider::new(&format!("{}/", base_url)), mock::ACCESS_TOKEN).await.unwrap();
+
+    assert_eq!(user.subject, "1");
+    assert_eq!(user.email, None);
+    assert!(!user.email_verified);
+  }
+}
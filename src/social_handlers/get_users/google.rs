@@ -1,4 +1,4 @@
-use crate::social_handlers::{fetcher::{missing_field_error, OAuthProfileProvider}, model::OAuthProvider};
+use crate::social_handlers::{fetcher::{missing_field_error, OAuthProfileProvider}, model::{OAuthProvider, OAuthUserInfo}};
 use axum::{
   http::StatusCode,
   Json,
@@ -9,6 +9,8 @@ use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GoogleUser {
+  pub sub: String,
+  pub name: Option<String>,
   pub email: Option<String>,
   pub email_verified: Option<bool>,
   pub family_name: Option<String>,
@@ -30,7 +32,7 @@ impl OAuthProfileProvider for GoogleProvider {
   }
     
   fn extract_user_info(&self, response_json: serde_json::Value, bytes: &[u8]) 
-    -> Result<(String, String, serde_json::Value), (StatusCode, Json<serde_json::Value>)> {
+    -> Result<OAuthUserInfo, (StatusCode, Json<serde_json::Value>)> {
     let user: GoogleUser = serde_json::from_slice(bytes).map_err(|_| (
       StatusCode::INTERNAL_SERVER_ERROR,
       Json(json!({
@@ -39,9 +41,16 @@ impl OAuthProfileProvider for GoogleProvider {
       }))
     ))?;
     
-    let email = user.given_name.ok_or_else(|| missing_field_error("Email"))?;
-    let name = user.email.ok_or_else(|| missing_field_error("Name"))?;
+    let name = user.name
+      .or(user.given_name)
+      .ok_or_else(|| missing_field_error("Name"))?;
     
-    Ok((email, name, response_json))
+    Ok(OAuthUserInfo {
+      subject: user.sub,
+      email_verified: user.email.is_some() && user.email_verified == Some(true),
+      email: user.email,
+      name,
+      data: response_json,
+    })
   }
 }
\ No newline at end of file
@@ -1,4 +1,4 @@
-use crate::social_handlers::{fetcher::{missing_field_error, OAuthProfileProvider}, model::OAuthProvider};
+use crate::social_handlers::{fetcher::{missing_field_error, OAuthProfileProvider}, model::{OAuthProvider, OAuthUserInfo}};
 use axum::{
   http::StatusCode,
   Json,
@@ -46,7 +46,7 @@ impl OAuthProfileProvider for InstagramProvider {
   }
     
   fn extract_user_info(&self, response_json: serde_json::Value, bytes: &[u8]) 
-    -> Result<(String, String, serde_json::Value), (StatusCode, Json<serde_json::Value>)> {
+    -> Result<OAuthUserInfo, (StatusCode, Json<serde_json::Value>)> {
     let user: InstagramUser = serde_json::from_slice(bytes).map_err(|_| (
       StatusCode::INTERNAL_SERVER_ERROR,
       Json(json!({
@@ -55,10 +55,17 @@ impl OAuthProfileProvider for InstagramProvider {
       }))
     ))?;
     
-    let username = user.username.ok_or_else(|| missing_field_error("Email"))?;
-    let email = format!("{}@instagram.com", username.to_lowercase()); 
-    let name = user.full_name.ok_or_else(|| missing_field_error("Name"))?;
-    
-    Ok((email, name, response_json))
+    let name = user.full_name
+      .or(user.username)
+      .ok_or_else(|| missing_field_error("Name"))?;
+
+    // Instagram never shares an email address
+    Ok(OAuthUserInfo {
+      subject: user.id,
+      email: None,
+      email_verified: false,
+      name,
+      data: response_json,
+    })
   }
 }
\ No newline at end of file
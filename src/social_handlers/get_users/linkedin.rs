@@ -1,4 +1,4 @@
-use crate::social_handlers::{fetcher::{missing_field_error, OAuthProfileProvider}, model::OAuthProvider};
+use crate::social_handlers::{fetcher::{missing_field_error, OAuthProfileProvider}, model::{OAuthProvider, OAuthUserInfo}};
 use axum::{
   http::StatusCode,
   Json,
@@ -37,7 +37,7 @@ impl OAuthProfileProvider for LinkedInProvider {
   }
     
   fn extract_user_info(&self, response_json: serde_json::Value, bytes: &[u8]) 
-    -> Result<(String, String, serde_json::Value), (StatusCode, Json<serde_json::Value>)> {
+    -> Result<OAuthUserInfo, (StatusCode, Json<serde_json::Value>)> {
     let user: LinkedInUser = serde_json::from_slice(bytes).map_err(|_| (
       StatusCode::INTERNAL_SERVER_ERROR,
       Json(json!({
@@ -46,9 +46,15 @@ impl OAuthProfileProvider for LinkedInProvider {
       }))
     ))?;
     
-    let email = user.email.ok_or_else(|| missing_field_error("Email"))?;
+    let subject = user.sub.ok_or_else(|| missing_field_error("Id"))?;
     let name = user.name.ok_or_else(|| missing_field_error("Name"))?;
     
-    Ok((email, name, response_json))
+    Ok(OAuthUserInfo {
+      subject,
+      email_verified: user.email.is_some() && user.email_verified,
+      email: user.email,
+      name,
+      data: response_json,
+    })
   }
 }
\ No newline at end of file
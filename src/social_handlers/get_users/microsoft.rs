@@ -1,4 +1,4 @@
-use crate::social_handlers::{fetcher::{missing_field_error, OAuthProfileProvider}, model::OAuthProvider};
+use crate::social_handlers::{fetcher::{missing_field_error, OAuthProfileProvider}, model::{OAuthProvider, OAuthUserInfo}};
 use axum::{
   http::StatusCode,
   Json,
@@ -42,7 +42,7 @@ impl OAuthProfileProvider for MicrosoftProvider {
   }
     
   fn extract_user_info(&self, response_json: serde_json::Value, bytes: &[u8]) 
-    -> Result<(String, String, serde_json::Value), (StatusCode, Json<serde_json::Value>)> {
+    -> Result<OAuthUserInfo, (StatusCode, Json<serde_json::Value>)> {
     let user: MicrosoftUser = serde_json::from_slice(bytes).map_err(|_| (
       StatusCode::INTERNAL_SERVER_ERROR,
       Json(json!({
@@ -51,9 +51,15 @@ impl OAuthProfileProvider for MicrosoftProvider {
       }))
     ))?;
     
-    let email = user.mail.ok_or_else(|| missing_field_error("Email"))?;
     let name = user.display_name.ok_or_else(|| missing_field_error("Name"))?;
-    
-    Ok((email, name, response_json))
+
+    // Graph's mail attribute is editable by tenant admins and never verified
+    Ok(OAuthUserInfo {
+      subject: user.id,
+      email: user.mail,
+      email_verified: false,
+      name,
+      data: response_json,
+    })
   }
 }
\ No newline at end of file
@@ -0,0 +1,24 @@
+use axum::{
+  http::{header, HeaderMap, StatusCode},
+  routing::{get, MethodRouter},
+  Json,
+};
+use serde_json::json;
+
+pub use crate::test_server::serve;
+
+// The token the mock APIs accept, anything else is answered like a provider would
+pub const ACCESS_TOKEN: &str = "mock-access-token";
+
+pub fn respond_with(body: serde_json::Value) -> MethodRouter {
+  get(move |headers: HeaderMap| {
+    let body = body.clone();
+    async move {
+      let authorization = headers.get(header::AUTHORIZATION).and_then(|value| value.to_str().ok());
+      if authorization != Some(format!("Bearer {}", ACCESS_TOKEN).as_str()) {
+        return (StatusCode::UNAUTHORIZED, Json(json!({ "message": "401 Unauthorized" })));
+      }
+      (StatusCode::OK, Json(body))
+    }
+  })
+}
@@ -1,11 +1,18 @@
 pub mod amazon;
+pub mod apple;
+pub mod bitbucket;
+pub mod discord;
 pub mod facebook;
 pub mod google;
 pub mod github;
+pub mod gitlab;
 pub mod instagram;
 pub mod linkedin;
 pub mod microsoft;
+#[cfg(test)]
+pub mod mock;
 pub mod reddit;
+pub mod slack;
 pub mod tiktok;
 pub mod twitch;
 pub mod twitter;
\ No newline at end of file
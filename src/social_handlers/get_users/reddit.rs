@@ -1,4 +1,4 @@
-use crate::social_handlers::{fetcher::{missing_field_error, OAuthProfileProvider}, model::OAuthProvider};
+use crate::social_handlers::{fetcher::{missing_field_error, OAuthProfileProvider}, model::{OAuthProvider, OAuthUserInfo}};
 use axum::{
   http::StatusCode,
   Json,
@@ -41,7 +41,7 @@ impl OAuthProfileProvider for RedditProvider {
   }
   
   fn extract_user_info(&self, response_json: serde_json::Value, bytes: &[u8]) 
-    -> Result<(String, String, serde_json::Value), (StatusCode, Json<serde_json::Value>)> {
+    -> Result<OAuthUserInfo, (StatusCode, Json<serde_json::Value>)> {
     let user: RedditUser = serde_json::from_slice(bytes).map_err(|_| (
       StatusCode::INTERNAL_SERVER_ERROR,
       Json(json!({
@@ -50,10 +50,17 @@ impl OAuthProfileProvider for RedditProvider {
       }))
     ))?;
     
+    let subject = user.id.ok_or_else(|| missing_field_error("Id"))?;
     let name = user.name
       .ok_or_else(|| missing_field_error("Name"))?;
-    let email = format!("{}@reddit.com", name.to_lowercase());
-    
-    Ok((email, name, response_json))
+
+    // Reddit never shares an email address
+    Ok(OAuthUserInfo {
+      subject,
+      email: None,
+      email_verified: false,
+      name,
+      data: response_json,
+    })
   }
 }
\ No newline at end of file
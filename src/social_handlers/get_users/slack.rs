@@ -0,0 +1,133 @@
+use crate::social_handlers::{fetcher::{missing_field_error, OAuthProfileProvider}, model::{OAuthProvider, OAuthUserInfo}};
+use axum::{
+  http::StatusCode,
+  Json,
+};
+use anyhow::Result;
+use serde_json::json;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SlackUser {
+  pub ok: bool,
+  pub sub: Option<String>,
+  pub email: Option<String>,
+  pub email_verified: Option<bool>,
+  pub name: Option<String>,
+  pub given_name: Option<String>,
+  pub family_name: Option<String>,
+  pub picture: Option<String>,
+  pub locale: Option<String>,
+  #[serde(rename = "https://slack.com/team_id")]
+  pub team_id: Option<String>,
+  #[serde(rename = "https://slack.com/team_name")]
+  pub team_name: Option<String>,
+}
+
+pub const API_URL: &str = "https://slack.com/api";
+
+pub struct SlackProvider {
+  profile_url: String,
+}
+
+impl SlackProvider {
+  pub fn new(base_url: &str) -> Self {
+    Self {
+      profile_url: format!("{}/openid.connect.userInfo", base_url.trim_end_matches('/')),
+    }
+  }
+}
+
+impl OAuthProfileProvider for SlackProvider {
+  fn provider(&self) -> &'static OAuthProvider {
+    &OAuthProvider::Slack
+  }
+
+  fn profile_url(&self) -> &str {
+    &self.profile_url
+  }
+    
+  fn extract_user_info(&self, response_json: serde_json::Value, bytes: &[u8]) 
+    -> Result<OAuthUserInfo, (StatusCode, Json<serde_json::Value>)> {
+    let user: SlackUser = serde_json::from_slice(bytes).map_err(|_| (
+      StatusCode::INTERNAL_SERVER_ERROR,
+      Json(json!({
+        "status": "error",
+        "message": "Failed to parse response"
+      }))
+    ))?;
+
+    // Slack answers errors with 200 and ok: false
+    if !user.ok {
+      return Err((
+        StatusCode::BAD_REQUEST,
+        Json(json!({
+          "status": "fail",
+          "message": "Slack rejected the user info request"
+        }))
+      ));
+    }
+
+    let subject = user.sub.ok_or_else(|| missing_field_error("Id"))?;
+    let name = user.name.ok_or_else(|| missing_field_error("Name"))?;
+    
+    Ok(OAuthUserInfo {
+      subject,
+      email_verified: user.email.is_some() && user.email_verified == Some(true),
+      email: user.email,
+      name,
+      data: response_json,
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use axum::{http::StatusCode, Router};
+  use serde_json::json;
+
+  use super::SlackProvider;
+  use crate::social_handlers::{fetcher::handle_oauth_provider, get_users::mock, model::OAuthUserInfo};
+
+  async fn fetch_user(user: serde_json::Value) -> Result<OAuthUserInfo, StatusCode> {
+    let base_url = mock::serve(Router::new().route("/openid.connect.userInfo", mock::respond_with(user))).await;
+    handle_oauth_provider(&SlackProvider::new(&base_url), mock::ACCESS_TOKEN).await.map_err(|(status, _)| status)
+  }
+
+  #[tokio::test]
+  async fn verified_email_is_verified() {
+    let user = fetch_user(json!({
+      "ok": true,
+      "sub": "U0R7JM",
+      "name": "Ada Lovelace",
+      "email": "ada@example.com",
+      "email_verified": true,
+      "https://slack.com/team_id": "T0R7GR",
+    })).await.unwrap();
+
+    assert_eq!(user.subject, "U0R7JM");
+    assert_eq!(user.name, "Ada Lovelace");
+    assert_eq!(user.email.as_deref(), Some("ada@example.com"));
+    assert!(user.email_verified);
+  }
+
+  #[tokio::test]
+  async fn unverified_email_is_not_verified() {
+    let user = fetch_user(json!({
+      "ok": true,
+      "sub": "U0R7JM",
+      "name": "Ada Lovelace",
+      "email": "ada@example.com",
+      "email_verified": false,
+    })).await.unwrap();
+
+    assert!(!user.email_verified);
+  }
+
+  #[tokio::test]
+  async fn error_answer_is_rejected() {
+    let result = fetch_user(json!({ "ok": false, "error": "invalid_auth" })).await;
+
+    assert_eq!(result.err(), Some(StatusCode::BAD_REQUEST));
+  }
+}
@@ -1,4 +1,4 @@
-use crate::social_handlers::{fetcher::{missing_field_error, OAuthProfileProvider}, model::OAuthProvider};
+use crate::social_handlers::{fetcher::{missing_field_error, OAuthProfileProvider}, model::{OAuthProvider, OAuthUserInfo}};
 use axum::{
   http::StatusCode,
   Json,
@@ -46,18 +46,28 @@ impl OAuthProfileProvider for TwitchProvider {
   }
     
   fn extract_user_info(&self, response_json: serde_json::Value, bytes: &[u8]) 
-    -> Result<(String, String, serde_json::Value), (StatusCode, Json<serde_json::Value>)> {
-    let user: TwitchUser = serde_json::from_slice(bytes).map_err(|_| (
+    -> Result<OAuthUserInfo, (StatusCode, Json<serde_json::Value>)> {
+    let user: TwitchUser = serde_json::from_slice::<TwitchUserResponse>(bytes).map_err(|_| (
       StatusCode::INTERNAL_SERVER_ERROR,
       Json(json!({
         "status": "error",
         "message": "Failed to parse response"
       }))
-    ))?;
+    ))?
+      .data
+      .into_iter()
+      .next()
+      .ok_or_else(|| missing_field_error("User"))?;
     
-    let email = user.email.ok_or_else(|| missing_field_error("Email"))?;
     let name = user.display_name.ok_or_else(|| missing_field_error("Name"))?;
-    
-    Ok((email, name, response_json))
+
+    // Twitch only exposes the email once it has been verified
+    Ok(OAuthUserInfo {
+      subject: user.id,
+      email_verified: user.email.is_some(),
+      email: user.email,
+      name,
+      data: response_json,
+    })
   }
 }
\ No newline at end of file
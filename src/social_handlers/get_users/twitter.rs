@@ -1,4 +1,4 @@
-use crate::social_handlers::{fetcher::{missing_field_error, OAuthProfileProvider}, model::OAuthProvider};
+use crate::social_handlers::{fetcher::{missing_field_error, OAuthProfileProvider}, model::{OAuthProvider, OAuthUserInfo}};
 use axum::{
   http::StatusCode,
   Json,
@@ -39,19 +39,26 @@ impl OAuthProfileProvider for TwitterProvider {
   }
     
   fn extract_user_info(&self, response_json: serde_json::Value, bytes: &[u8]) 
-    -> Result<(String, String, serde_json::Value), (StatusCode, Json<serde_json::Value>)> {
-    let user: TwitterUser = serde_json::from_slice(bytes).map_err(|_| (
+    -> Result<OAuthUserInfo, (StatusCode, Json<serde_json::Value>)> {
+    let user: TwitterUser = serde_json::from_slice::<TwitterMeResponse>(bytes).map_err(|_| (
       StatusCode::INTERNAL_SERVER_ERROR,
       Json(json!({
         "status": "error",
         "message": "Failed to parse response"
       }))
-    ))?;
+    ))?.data;
     
-    let username = user.username.ok_or_else(|| missing_field_error("Email"))?;
-    let email = format!("{}@twitter.com", username.to_lowercase()); 
-    let name = user.name.ok_or_else(|| missing_field_error("Name"))?;
-    
-    Ok((email, name, response_json))
+    let name = user.name
+      .or(user.username)
+      .ok_or_else(|| missing_field_error("Name"))?;
+
+    // Twitter does not share an email address, it is collected separately
+    Ok(OAuthUserInfo {
+      subject: user.id,
+      email: None,
+      email_verified: false,
+      name,
+      data: response_json,
+    })
   }
 }
\ No newline at end of file
@@ -1,16 +1,67 @@
+use oauth2::{
+  basic::{BasicErrorResponse, BasicRevocationErrorResponse, BasicTokenIntrospectionResponse, BasicTokenType},
+  Client, ExtraTokenFields, StandardRevocableToken, StandardTokenResponse,
+};
 use serde::{Deserialize, Serialize};
 
+// Cookie binding the OAuth state to the browser that started the flow
+pub const OAUTH_STATE_COOKIE: &str = "oauth_state";
+
+// One cookie per pending state, so a second sign-in tab does not break the first
+pub fn oauth_state_cookie_name(state: &str) -> String {
+  format!("{}_{}", OAUTH_STATE_COOKIE, state)
+}
+
+// How long a pending OAuth state stays valid
+pub const OAUTH_STATE_TTL_MINUTES: i64 = 10;
+
 #[derive(Debug, Serialize, Deserialize, Clone, Hash, Eq, PartialEq)]
 pub enum OAuthProvider {
   Amazon,
+  Apple,
+  Bitbucket,
+  Discord,
   Facebook,
   Github,
+  Gitlab,
   Google,
   Instagram,
   LinkedIn,
   Microsoft,
   Reddit,
+  Slack,
   Tiktok,
   Twitch,
   Twitter
-}
\ No newline at end of file
+}
+
+// Profile details normalized across providers
+#[derive(Debug)]
+pub struct OAuthUserInfo {
+  // The provider's stable user id, used to find the identity on later sign-ins
+  pub subject: String,
+  pub email: Option<String>,
+  // Only true when the provider vouches that the address belongs to the user
+  pub email_verified: bool,
+  pub name: String,
+  pub data: serde_json::Value,
+}
+
+// OIDC providers return an id_token alongside the access token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IdTokenFields {
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub id_token: Option<String>,
+}
+
+impl ExtraTokenFields for IdTokenFields {}
+
+pub type OAuthTokenResponse = StandardTokenResponse<IdTokenFields, BasicTokenType>;
+
+pub type OAuthClient = Client<
+  BasicErrorResponse,
+  OAuthTokenResponse,
+  BasicTokenIntrospectionResponse,
+  StandardRevocableToken,
+  BasicRevocationErrorResponse,
+>;
@@ -6,11 +6,12 @@ use axum::{
     Json,
 };
 use anyhow::Result;
+use axum_extra::extract::cookie::{Cookie, SameSite};
 use diesel::{ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl};
 use serde_json::json;
 use ulid::Ulid;
 use chrono::{Duration, Utc};
-use crate::{model::OAuthSchema, schema::{social_auth, social_provider, SocialAuth, SocialProvider}, AppState};
+use crate::{model::OAuthSchema, redirect::validate_redirect, schema::{social_auth, social_provider, SocialAuth, SocialProvider}, smtp::generate_random_string, tenant::{resolve_tenant_id, tenant_config}, utils::hash_token, AppState};
 use oauth2::{
   basic::BasicClient,
   AuthUrl,
@@ -23,19 +24,24 @@ use oauth2::{
   TokenUrl,
 };
 
-use super::model::OAuthProvider;
+use super::{get_users::apple::generate_client_secret, model::{oauth_state_cookie_name, OAuthProvider, OAUTH_STATE_TTL_MINUTES}};
 
 impl OAuthProvider {
   pub fn from_str(provider: &str) -> Option<Self> {
     match provider.to_lowercase().as_str() {
       "amazon" => Some(Self::Amazon),
+      "apple" => Some(Self::Apple),
+      "bitbucket" => Some(Self::Bitbucket),
+      "discord" => Some(Self::Discord),
       "facebook" => Some(Self::Facebook),
       "github" => Some(Self::Github),
+      "gitlab" => Some(Self::Gitlab),
       "google" => Some(Self::Google),
       "instagram" => Some(Self::Instagram),
       "linkedin" => Some(Self::LinkedIn),
       "microsoft" => Some(Self::Microsoft),
       "reddit" => Some(Self::Reddit),
+      "slack" => Some(Self::Slack),
       "tiktok" => Some(Self::Tiktok),
       "twitch" => Some(Self::Twitch),
       "twitter" => Some(Self::Twitter),
@@ -43,48 +49,76 @@ impl OAuthProvider {
     }
   }
 
-  pub fn auth_url(&self) -> &'static str {
+  pub fn auth_url(&self, app_state: &AppState) -> String {
     match self {
-      Self::Amazon => "https://www.amazon.com/ap/oa",
-      Self::Facebook => "https://www.facebook.com/v12.0/dialog/oauth",
-      Self::Github => "https://github.com/login/oauth/authorize",
-      Self::Google => "https://accounts.google.com/o/oauth2/v2/auth",
-      Self::Instagram => "https://api.instagram.com/oauth/authorize",
-      Self::LinkedIn => "https://www.linkedin.com/oauth/v2/authorization",
-      Self::Microsoft => "https://login.microsoftonline.com/common/oauth2/v2.0/authorize",
-      Self::Reddit => "https://www.reddit.com/api/v1/authorize",
-      Self::Tiktok => "https://open.tiktokapis.com/v2/oauth/authorize",
-      Self::Twitch => "https://id.twitch.tv/oauth2/authorize",
-      Self::Twitter => "https://x.com/i/oauth2/authorize",
+      Self::Amazon => "https://www.amazon.com/ap/oa".into(),
+      Self::Apple => "https://appleid.apple.com/auth/authorize".into(),
+      Self::Bitbucket => "https://bitbucket.org/site/oauth2/authorize".into(),
+      Self::Discord => "https://discord.com/oauth2/authorize".into(),
+      Self::Facebook => "https://www.facebook.com/v12.0/dialog/oauth".into(),
+      Self::Github => "https://github.com/login/oauth/authorize".into(),
+      Self::Gitlab => format!("{}/oauth/authorize", app_state.env.gitlab_base_url),
+      Self::Google => "https://accounts.google.com/o/oauth2/v2/auth".into(),
+      Self::Instagram => "https://api.instagram.com/oauth/authorize".into(),
+      Self::LinkedIn => "https://www.linkedin.com/oauth/v2/authorization".into(),
+      Self::Microsoft => "https://login.microsoftonline.com/common/oauth2/v2.0/authorize".into(),
+      Self::Reddit => "https://www.reddit.com/api/v1/authorize".into(),
+      Self::Slack => "https://slack.com/openid/connect/authorize".into(),
+      Self::Tiktok => "https://open.tiktokapis.com/v2/oauth/authorize".into(),
+      Self::Twitch => "https://id.twitch.tv/oauth2/authorize".into(),
+      Self::Twitter => "https://x.com/i/oauth2/authorize".into(),
     }
   }
 
-  pub fn token_url(&self) -> &'static str {
+  pub fn token_url(&self, app_state: &AppState) -> String {
     match self {
-      Self::Amazon => "https://api.amazon.com/auth/o2/token",
-      Self::Facebook => "https://graph.facebook.com/v12.0/oauth/access_token",
-      Self::Github => "https://github.com/login/oauth/access_token",
-      Self::Google => "https://www.googleapis.com/oauth2/v3/token",
-      Self::Instagram => "https://api.instagram.com/oauth/access_token",
-      Self::LinkedIn => "https://www.linkedin.com/oauth/v2/accessToken",
-      Self::Microsoft => "https://login.microsoftonline.com/common/oauth2/v2.0/token",
-      Self::Reddit => "https://www.reddit.com/api/v1/access_token",
-      Self::Tiktok => "https://open.tiktokapis.com/v2/oauth/token",
-      Self::Twitch => "https://id.twitch.tv/oauth2/token",
-      Self::Twitter => "https://api.x.com/2/oauth2/token",
+      Self::Amazon => "https://api.amazon.com/auth/o2/token".into(),
+      Self::Apple => "https://appleid.apple.com/auth/token".into(),
+      Self::Bitbucket => "https://bitbucket.org/site/oauth2/access_token".into(),
+      Self::Discord => "https://discord.com/api/oauth2/token".into(),
+      Self::Facebook => "https://graph.facebook.com/v12.0/oauth/access_token".into(),
+      Self::Github => "https://github.com/login/oauth/access_token".into(),
+      Self::Gitlab => format!("{}/oauth/token", app_state.env.gitlab_base_url),
+      Self::Google => "https://www.googleapis.com/oauth2/v3/token".into(),
+      Self::Instagram => "https://api.instagram.com/oauth/access_token".into(),
+      Self::LinkedIn => "https://www.linkedin.com/oauth/v2/accessToken".into(),
+      Self::Microsoft => "https://login.microsoftonline.com/common/oauth2/v2.0/token".into(),
+      Self::Reddit => "https://www.reddit.com/api/v1/access_token".into(),
+      Self::Slack => "https://slack.com/api/openid.connect.token".into(),
+      Self::Tiktok => "https://open.tiktokapis.com/v2/oauth/token".into(),
+      Self::Twitch => "https://id.twitch.tv/oauth2/token".into(),
+      Self::Twitter => "https://api.x.com/2/oauth2/token".into(),
     }
   }
 
+  // Providers that accept an S256 code challenge on the authorization request
+  pub fn supports_pkce(&self) -> bool {
+    match self {
+      Self::Amazon | Self::Facebook | Self::Github | Self::Gitlab | Self::Google | Self::Microsoft | Self::Tiktok | Self::Twitter => true,
+      Self::Apple | Self::Bitbucket | Self::Discord | Self::Instagram | Self::LinkedIn | Self::Reddit | Self::Slack | Self::Twitch => false,
+    }
+  }
+
+  // Providers that issue an OpenID Connect id_token carrying our nonce
+  pub fn is_oidc(&self) -> bool {
+    matches!(self, Self::Apple | Self::Google | Self::LinkedIn | Self::Microsoft | Self::Slack | Self::Twitch)
+  }
+
   pub fn client_id(&self, app_state: &AppState) -> String {
     match self {
       Self::Amazon => app_state.env.amazon_client_id.clone(),
+      Self::Apple => app_state.env.apple_client_id.clone(),
+      Self::Bitbucket => app_state.env.bitbucket_client_id.clone(),
+      Self::Discord => app_state.env.discord_client_id.clone(),
       Self::Facebook => app_state.env.facebook_client_id.clone(),
       Self::Github => app_state.env.github_client_id.clone(),
+      Self::Gitlab => app_state.env.gitlab_client_id.clone(),
       Self::Google => app_state.env.google_client_id.clone(),
       Self::Instagram => app_state.env.instagram_client_id.clone(),
       Self::LinkedIn => app_state.env.linkedin_client_id.clone(),
       Self::Microsoft => app_state.env.microsoft_client_id.clone(),
       Self::Reddit => app_state.env.reddit_client_id.clone(),
+      Self::Slack => app_state.env.slack_client_id.clone(),
       Self::Tiktok => app_state.env.tiktok_client_id.clone(),
       Self::Twitch => app_state.env.twitch_client_id.clone(),
       Self::Twitter => app_state.env.twitter_client_id.clone(),
@@ -94,13 +128,21 @@ impl OAuthProvider {
   pub fn client_secret(&self, app_state: &AppState) -> String {
     match self {
       Self::Amazon => app_state.env.amazon_client_secret.clone(),
+      Self::Apple => generate_client_secret(&app_state.env).unwrap_or_else(|e| {
+        tracing::error!("Failed to generate Apple client secret: {}", e);
+        String::new()
+      }),
+      Self::Bitbucket => app_state.env.bitbucket_client_secret.clone(),
+      Self::Discord => app_state.env.discord_client_secret.clone(),
       Self::Facebook => app_state.env.facebook_client_secret.clone(),
       Self::Github => app_state.env.github_client_secret.clone(),
+      Self::Gitlab => app_state.env.gitlab_client_secret.clone(),
       Self::Google => app_state.env.google_client_secret.clone(),
       Self::Instagram => app_state.env.instagram_client_secret.clone(),
       Self::LinkedIn => app_state.env.linkedin_client_secret.clone(),
       Self::Microsoft => app_state.env.microsoft_client_secret.clone(),
       Self::Reddit => app_state.env.reddit_client_secret.clone(),
+      Self::Slack => app_state.env.slack_client_secret.clone(),
       Self::Tiktok => app_state.env.tiktok_client_secret.clone(),
       Self::Twitch => app_state.env.twitch_client_secret.clone(),
       Self::Twitter => app_state.env.twitter_client_secret.clone(),
@@ -111,13 +153,18 @@ impl OAuthProvider {
     let base_url = &app_state.env.server_url;
     match self {
       Self::Amazon => format!("{}{}", base_url, app_state.env.amazon_redirect_url),
+      Self::Apple => format!("{}{}", gen_https_base_url(base_url.to_string()), app_state.env.apple_redirect_url),
+      Self::Bitbucket => format!("{}{}", base_url, app_state.env.bitbucket_redirect_url),
+      Self::Discord => format!("{}{}", base_url, app_state.env.discord_redirect_url),
       Self::Facebook => format!("{}{}", gen_https_base_url(base_url.to_string()), app_state.env.facebook_redirect_url),
       Self::Github => format!("{}{}", base_url, app_state.env.github_redirect_url),
+      Self::Gitlab => format!("{}{}", base_url, app_state.env.gitlab_redirect_url),
       Self::Google => format!("{}{}", base_url, app_state.env.google_redirect_url),
       Self::Instagram => format!("{}{}", base_url, app_state.env.instagram_redirect_url),
       Self::LinkedIn => format!("{}{}", base_url, app_state.env.linkedin_redirect_url),
       Self::Microsoft => format!("{}{}", base_url, app_state.env.microsoft_redirect_url),
       Self::Reddit => format!("{}{}", base_url, app_state.env.reddit_redirect_url),
+      Self::Slack => format!("{}{}", gen_https_base_url(base_url.to_string()), app_state.env.slack_redirect_url),
       Self::Tiktok => format!("{}{}", base_url, app_state.env.tiktok_redirect_url),
       Self::Twitch => format!("{}{}", gen_https_base_url(base_url.to_string()), app_state.env.twitch_redirect_url),
       Self::Twitter => format!("{}{}", base_url, app_state.env.twitter_redirect_url),
@@ -131,10 +178,22 @@ fn gen_https_base_url(base_url: String) -> String {
 
 pub async fn url_handler(
   State(data): State<Arc<AppState>>,
+  request_headers: HeaderMap,
   Json(body): Json<OAuthSchema>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
   let mut conn = data.db_pool.get().expect("Failed to get connection from pool");
 
+  let tenant_id = resolve_tenant_id(&mut conn, &request_headers, body.tenant_id.as_deref());
+  let env = tenant_config(&mut conn, &data.env, tenant_id.as_deref());
+
+  if !env.provider_enabled(&body.provider) {
+    let error_response = json!({
+      "status": "fail",
+      "message": "Provider is not enabled"
+    });
+    return Err((StatusCode::FORBIDDEN, Json(error_response)));
+  }
+
   let provider = match OAuthProvider::from_str(&body.provider.clone()) {
     Some(p) => p,
     None => {
@@ -149,6 +208,8 @@ pub async fn url_handler(
   let scopes = body.scopes.to_owned();
   let callback_url = body.callback_url.to_owned();
 
+  validate_redirect(&data.env, &callback_url)?;
+
   let provider_exists = social_provider::table
     .filter(social_provider::name.eq(body.provider))
     .first::<SocialProvider>(&mut conn)
@@ -166,9 +227,9 @@ pub async fn url_handler(
 
   let client_id = ClientId::new(provider.client_id(&data));
   let client_secret = ClientSecret::new(provider.client_secret(&data));
-  let auth_url = AuthUrl::new(provider.auth_url().to_string())
+  let auth_url = AuthUrl::new(provider.auth_url(&data))
     .expect("Invalid authorization endpoint URL");
-  let token_url = TokenUrl::new(provider.token_url().to_string())
+  let token_url = TokenUrl::new(provider.token_url(&data))
     .expect("Invalid token endpoint URL");
 
   let client = BasicClient::new(client_id)
@@ -183,7 +244,7 @@ pub async fn url_handler(
   let auth_builder = client.authorize_url(CsrfToken::new_random);    
   let mut pkce_verifier = String::new();
 
-  let auth_builder = if provider == OAuthProvider::Twitter {
+  let auth_builder = if provider.supports_pkce() {
     let (pkce_challenge, verifier) = PkceCodeChallenge::new_random_sha256();
       
     pkce_verifier = verifier.secret().to_string();
@@ -191,15 +252,47 @@ pub async fn url_handler(
   } else {
     auth_builder
   };
+
+  let mut nonce = None;
+
+  let auth_builder = if provider.is_oidc() {
+    let value = generate_random_string();
+    nonce = Some(value.clone());
+    auth_builder.add_extra_param("nonce", value)
+  } else {
+    auth_builder
+  };
+
+  let mut scopes = scopes
+    .split(',')
+    .map(|scope| scope.trim().to_string())
+    .filter(|scope| !scope.is_empty())
+    .collect::<Vec<String>>();
+
+  // The nonce only comes back inside an id_token, which requires the openid scope.
+  // Apple always returns an id_token and rejects the openid scope.
+  if provider.is_oidc() && provider != OAuthProvider::Apple && !scopes.iter().any(|scope| scope == "openid") {
+    scopes.push("openid".to_string());
+  }
   
-  let auth_builder = scopes.split(',').fold(auth_builder, |builder, scope| {
-    builder.add_scope(Scope::new(scope.to_string()))
+  let auth_builder = scopes.into_iter().fold(auth_builder, |builder, scope| {
+    builder.add_scope(Scope::new(scope))
   });
 
+  // Apple posts the code back as a form when name or email is requested
+  let auth_builder = if provider == OAuthProvider::Apple {
+    auth_builder.add_extra_param("response_mode", "form_post")
+  } else {
+    auth_builder
+  };
+
   let (auth_url, csrf_token) = auth_builder.url();
 
+  // Bind the state to this browser so a callback from another browser is rejected
+  let browser_binding = generate_random_string();
+
   // Store CSRF token in database for verification
-  let expires = (Utc::now() + Duration::days(10)).naive_utc();
+  let expires = (Utc::now() + Duration::minutes(OAUTH_STATE_TTL_MINUTES)).naive_utc();
   let timestamp = Utc::now().naive_utc();
   let statement = diesel::insert_into(social_auth::table)
     .values(&SocialAuth {
@@ -207,7 +300,10 @@ pub async fn url_handler(
       provider_id: provider_id.into(),
       csrf: csrf_token.secret().to_string().into(),
       pkce_verifier: pkce_verifier.into(),
+      nonce,
+      browser_binding: hash_token(&browser_binding),
       redirect_to: callback_url.into(),
+      tenant_id,
       expires,
       created_at: timestamp,
       updated_at: None,
@@ -218,15 +314,31 @@ pub async fn url_handler(
   if let Err(e) = statement {
     let error_response = serde_json::json!({
         "status": "fail",
-        "message": format!("forgot password code not saved to database: validation error\nDetails: {:?}", e)
+        "message": format!("oauth state not saved to database: validation error\nDetails: {:?}", e)
     });
     return Err((StatusCode::BAD_REQUEST, Json(error_response)));
   }
 
+  // Lax so the cookie survives the top-level redirect back from the provider,
+  // None for Apple since its callback is a cross-site form POST
+  let same_site = if provider == OAuthProvider::Apple {
+    SameSite::None
+  } else {
+    SameSite::Lax
+  };
+
+  let state_cookie = Cookie::build((oauth_state_cookie_name(csrf_token.secret()), browser_binding))
+    .path("/")
+    .secure(true)
+    .max_age(time::Duration::minutes(OAUTH_STATE_TTL_MINUTES))
+    .same_site(same_site)
+    .http_only(true);
+
   let mut response = Response::new(json!({ "url": auth_url.as_str().to_string() }).to_string());
 
   let mut headers = HeaderMap::new();
   headers.append(header::CONTENT_TYPE, "application/json".parse().unwrap());
+  headers.append(header::SET_COOKIE, state_cookie.to_string().parse().unwrap());
   response.headers_mut().extend(headers);
 
   Ok(response)
@@ -1,90 +1,246 @@
 use handlebars::Handlebars;
-use std::path::PathBuf;
-use std::fs;
+use std::{fs, path::{Path, PathBuf}, sync::{Arc, RwLock}, time::{Duration, SystemTime}};
+
+// Defaults compiled into the binary, a file at the same path under EMAIL_TEMPLATE_DIR replaces one
+const EMBEDDED: [(&str, &str); 18] = [
+  ("email_verification/_.html.hbs", include_str!("templates/email_verification/_.html.hbs")),
+  ("email_verification/_.txt.hbs", include_str!("templates/email_verification/_.txt.hbs")),
+  ("magic_link/_.html.hbs", include_str!("templates/magic_link/_.html.hbs")),
+  ("magic_link/_.txt.hbs", include_str!("templates/magic_link/_.txt.hbs")),
+  ("password_reset/_.html.hbs", include_str!("templates/password_reset/_.html.hbs")),
+  ("password_reset/_.txt.hbs", include_str!("templates/password_reset/_.txt.hbs")),
+  ("new_sign_in/_.html.hbs", include_str!("templates/new_sign_in/_.html.hbs")),
+  ("new_sign_in/_.txt.hbs", include_str!("templates/new_sign_in/_.txt.hbs")),
+  ("password_changed/_.html.hbs", include_str!("templates/password_changed/_.html.hbs")),
+  ("password_changed/_.txt.hbs", include_str!("templates/password_changed/_.txt.hbs")),
+  ("email_changed/_.html.hbs", include_str!("templates/email_changed/_.html.hbs")),
+  ("email_changed/_.txt.hbs", include_str!("templates/email_changed/_.txt.hbs")),
+  ("identity_linked/_.html.hbs", include_str!("templates/identity_linked/_.html.hbs")),
+  ("identity_linked/_.txt.hbs", include_str!("templates/identity_linked/_.txt.hbs")),
+  ("api_key_created/_.html.hbs", include_str!("templates/api_key_created/_.html.hbs")),
+  ("api_key_created/_.txt.hbs", include_str!("templates/api_key_created/_.txt.hbs")),
+  ("layouts/base.hbs", include_str!("templates/layouts/base.hbs")),
+  ("partials/styles.hbs", include_str!("templates/partials/styles.hbs")),
+];
+
+const DEFAULT_VARIANT: &str = "_";
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
 
 #[derive(Debug)]
 pub struct EmailTemplate {
   pub html: String,
   pub text: String,
+  // From an optional subject.hbs, otherwise the caller's subject is kept
+  pub subject: Option<String>,
+}
+
+// The same templates twice, HTML bodies are escaped while text bodies and subjects are not
+struct Registries {
+  html: Handlebars<'static>,
+  plain: Handlebars<'static>,
 }
 
+// Templates are compiled once, a reload swaps the whole registry so renders never see half of one
 pub struct TemplateEngine {
-  template_dir: PathBuf,
-  handlebars: Handlebars<'static>,
+  template_dir: Option<PathBuf>,
+  handlebars: RwLock<Arc<Registries>>,
+}
+
+// "magic_link/de.html.hbs" is registered as the template "magic_link/de.html", files in layouts/ and
+// partials/ become partials named after the file
+fn register(handlebars: &mut Handlebars<'static>, path: &str, source: &str) -> Result<(), Box<dyn std::error::Error>> {
+  let name = path.trim_end_matches(".hbs");
+  match name.split_once('/') {
+    Some(("layouts", partial)) | Some(("partials", partial)) => handlebars.register_partial(partial, source)?,
+    _ => handlebars.register_template_string(name, source)?,
+  }
+  Ok(())
+}
+
+// Every *.hbs file one folder deep, as "folder/file.hbs"
+fn template_files(dir: &Path) -> std::io::Result<Vec<(String, PathBuf)>> {
+  let mut files = vec![];
+  for folder in fs::read_dir(dir)? {
+    let folder = folder?.path();
+    if !folder.is_dir() {
+      continue;
+    }
+    for file in fs::read_dir(&folder)? {
+      let file = file?.path();
+      if file.extension().is_some_and(|ext| ext == "hbs") {
+        let folder_name = folder.file_name().unwrap_or_default().to_string_lossy();
+        let file_name = file.file_name().unwrap_or_default().to_string_lossy();
+        files.push((format!("{}/{}", folder_name, file_name), file));
+      }
+    }
+  }
+  Ok(files)
+}
+
+fn compile(template_dir: Option<&Path>) -> Result<Registries, Box<dyn std::error::Error>> {
+  let mut handlebars = Handlebars::new();
+
+  for (path, source) in EMBEDDED {
+    register(&mut handlebars, path, source)?;
+  }
+
+  if let Some(dir) = template_dir {
+    for (path, file) in template_files(dir)? {
+      register(&mut handlebars, &path, &fs::read_to_string(file)?)?;
+    }
+  }
+
+  let mut plain = handlebars.clone();
+  plain.register_escape_fn(handlebars::no_escape);
+  Ok(Registries { html: handlebars, plain })
+}
+
+// Changes whenever a template is added, removed or saved
+fn fingerprint(dir: &Path) -> Vec<(String, Option<SystemTime>)> {
+  let mut files = template_files(dir)
+    .unwrap_or_default()
+    .into_iter()
+    .map(|(path, file)| (path, fs::metadata(file).and_then(|meta| meta.modified()).ok()))
+    .collect::<Vec<_>>();
+  files.sort();
+  files
+}
+
+// "pt_BR" becomes "pt-br", None for anything that is not a language tag
+pub fn normalize_locale(locale: &str) -> Option<String> {
+  let locale = locale.trim().to_lowercase().replace('_', "-");
+  let valid = !locale.is_empty()
+    && locale.len() <= 35
+    && locale.split('-').all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric()));
+  valid.then_some(locale)
+}
+
+// Ordered locale preferences, the user's saved locale first, then the Accept-Language entries by weight
+pub fn preferred_locales(user_locale: Option<&str>, accept_language: Option<&str>) -> Vec<String> {
+  let mut weighted = accept_language
+    .unwrap_or_default()
+    .split(',')
+    .filter_map(|entry| {
+      let mut parts = entry.split(';');
+      let tag = normalize_locale(parts.next()?)?;
+      let weight = parts
+        .find_map(|param| param.trim().strip_prefix("q="))
+        .and_then(|q| q.parse::<f32>().ok())
+        .unwrap_or(1.0);
+      (weight > 0.0).then_some((tag, weight))
+    })
+    .collect::<Vec<_>>();
+  weighted.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+  let mut locales = user_locale.and_then(normalize_locale).into_iter().collect::<Vec<_>>();
+  for (tag, _) in weighted {
+    if !locales.contains(&tag) {
+      locales.push(tag);
+    }
+  }
+  locales
 }
 
 impl TemplateEngine {
-  pub fn new(template_dir: PathBuf) -> Result<Self, Box<dyn std::error::Error>> {
-    let mut handlebars = Handlebars::new();
-    // Enable directory support for partials
-    handlebars.set_dev_mode(true);
-    
+  pub fn new(template_dir: Option<PathBuf>) -> Result<Self, Box<dyn std::error::Error>> {
+    let handlebars = compile(template_dir.as_deref())?;
+
     Ok(TemplateEngine {
       template_dir,
-      handlebars,
+      handlebars: RwLock::new(Arc::new(handlebars)),
     })
   }
 
-  pub fn load_template(&mut self, template_name: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let html_path = self.template_dir.join(template_name).join("_.html.hbs");
-    let text_path = self.template_dir.join(template_name).join("_.txt.hbs");
-
-    // Register HTML and text templates with different names
-    self.handlebars.register_template_file(
-      &format!("{}_html", template_name),
-      &html_path
-    )?;
-    self.handlebars.register_template_file(
-      &format!("{}_text", template_name),
-      &text_path
-    )?;
-
-    // Register partials
-    let partials_dir = self.template_dir.join("partials");
-    if partials_dir.exists() {
-      for entry in fs::read_dir(partials_dir)? {
-        let entry = entry?;
-        let path = entry.path();
-        if let Some(name) = path.file_stem() {
-          // Register as partial instead of regular template
-          self.handlebars.register_partial(
-              name.to_str().unwrap(),
-              fs::read_to_string(path.clone())?.as_str()
-          )?;
-        }
+  // A template that no longer compiles is logged and the previous ones stay in use
+  pub fn reload(&self) {
+    match compile(self.template_dir.as_deref()) {
+      Ok(handlebars) => {
+        *self.handlebars.write().unwrap() = Arc::new(handlebars);
+        tracing::info!("Reloaded email templates");
       }
+      Err(e) => tracing::error!("Failed to reload email templates: {}", e),
     }
+  }
+
+  // Polls the template directory for changes, meant for development
+  pub fn spawn_watcher(self: Arc<Self>) {
+    let Some(dir) = self.template_dir.clone() else {
+      return;
+    };
 
-    // Register layouts
-    let layouts_dir = self.template_dir.join("layouts");
-    if layouts_dir.exists() {
-      for entry in fs::read_dir(layouts_dir)? {
-        let entry = entry?;
-        let path = entry.path();
-        if let Some(name) = path.file_stem() {
-          // Register layouts as partials
-          self.handlebars.register_partial(
-              name.to_str().unwrap(),
-              fs::read_to_string(path.clone())?.as_str()
-          )?;
+    tokio::spawn(async move {
+      let mut last = fingerprint(&dir);
+      let mut interval = tokio::time::interval(WATCH_INTERVAL);
+      loop {
+        interval.tick().await;
+        let current = fingerprint(&dir);
+        if current != last {
+          last = current;
+          self.reload();
         }
       }
-    }
+    });
+  }
 
-    Ok(())
+  // Uses the first locale with an HTML variant, "pt-br" falls back to "pt" before the next locale
+  fn variant(handlebars: &Handlebars<'static>, template_name: &str, locales: &[String]) -> String {
+    locales
+      .iter()
+      .flat_map(|locale| {
+        let language = locale.split(['-', '_']).next().unwrap_or_default().to_string();
+        [locale.clone(), language]
+      })
+      .find(|variant| handlebars.has_template(&format!("{}/{}.html", template_name, variant)))
+      .unwrap_or_else(|| DEFAULT_VARIANT.to_string())
   }
 
-  pub fn render(&self, template_name: &str, params: &serde_json::Value) -> Result<EmailTemplate, Box<dyn std::error::Error>> {
-    let html = self.handlebars.render(
-      &format!("{}_html", template_name),
-      params
-    )?;
+  pub fn render(
+    &self,
+    template_name: &str,
+    locales: &[String],
+    params: &serde_json::Value,
+  ) -> Result<EmailTemplate, Box<dyn std::error::Error>> {
+    let registries = self.handlebars.read().unwrap().clone();
+    let (handlebars, plain) = (&registries.html, &registries.plain);
+    let variant = Self::variant(handlebars, template_name, locales);
+
+    // Text and subject fall back to the default variant when a locale only translated the HTML
+    let name = |kind: &str| {
+      [variant.as_str(), DEFAULT_VARIANT]
+        .into_iter()
+        .map(|variant| format!("{}/{}.{}", template_name, variant, kind))
+        .find(|name| plain.has_template(name))
+    };
 
-    let text = self.handlebars.render(
-      &format!("{}_text", template_name),
-      params
-    )?;
+    let html = handlebars.render(&format!("{}/{}.html", template_name, variant), params)?;
+    let text = match name("txt") {
+      Some(name) => plain.render(&name, params)?,
+      None => return Err(format!("{} has no text template", template_name).into()),
+    };
+    let subject = match name("subject") {
+      Some(name) => Some(plain.render(&name, params)?.trim().to_string()),
+      None => None,
+    };
 
-    Ok(EmailTemplate { html, text })
+    Ok(EmailTemplate { html, text, subject })
   }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn escapes_only_the_html_body() {
+    let engine = TemplateEngine::new(None).unwrap();
+    let params = serde_json::json!({
+      "ConfirmationURL": "https://example.com/verify",
+      "Code": "a&b",
+      "RedirectTo": "https://example.com/?x=1&y=2",
+    });
+    let email = engine.render("magic_link", &[], &params).unwrap();
+
+    assert!(email.html.contains("code=a&amp;b"));
+    assert!(email.text.contains("https://example.com/verify?code=a&b"));
+  }
+}
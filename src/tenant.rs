@@ -0,0 +1,147 @@
+use axum::http::{header, HeaderMap};
+use diesel::prelude::*;
+
+use crate::{
+  config::Config,
+  schema::{tenant_members, tenant_settings, tenants, Tenant, TenantMember, TenantSettings},
+};
+
+pub const TENANT_OWNER: &str = "owner";
+pub const TENANT_ADMIN: &str = "admin";
+pub const TENANT_MEMBER: &str = "member";
+
+pub fn is_tenant_role(role: &str) -> bool {
+  [TENANT_OWNER, TENANT_ADMIN, TENANT_MEMBER].contains(&role)
+}
+
+pub fn can_manage(role: &str) -> bool {
+  role == TENANT_OWNER || role == TENANT_ADMIN
+}
+
+pub fn find_tenant(conn: &mut PgConnection, tenant_id: &str) -> Option<Tenant> {
+  tenants::table
+    .filter(tenants::id.eq(tenant_id))
+    .filter(tenants::deleted_at.is_null())
+    .first::<Tenant>(conn)
+    .optional()
+    .unwrap_or(None)
+}
+
+// A membership only counts while the tenant itself still exists
+pub fn find_membership(conn: &mut PgConnection, tenant_id: &str, user_id: &str) -> Option<TenantMember> {
+  find_tenant(conn, tenant_id)?;
+
+  tenant_members::table
+    .filter(tenant_members::tenant_id.eq(tenant_id))
+    .filter(tenant_members::user_id.eq(user_id))
+    .filter(tenant_members::deleted_at.is_null())
+    .first::<TenantMember>(conn)
+    .optional()
+    .unwrap_or(None)
+}
+
+// The active tenant travels in both session tokens so it survives a refresh
+pub fn tenant_claims(tenant_id: &str) -> Vec<(&'static str, String)> {
+  vec![("tenant_id", tenant_id.to_string())]
+}
+
+pub fn find_settings(conn: &mut PgConnection, tenant_id: &str) -> Option<TenantSettings> {
+  tenant_settings::table
+    .filter(tenant_settings::tenant_id.eq(tenant_id))
+    .filter(tenant_settings::deleted_at.is_null())
+    .first::<TenantSettings>(conn)
+    .optional()
+    .unwrap_or(None)
+}
+
+// An explicit tenantId wins, then the X-Tenant-Id header, then a tenant claiming the request host
+pub fn resolve_tenant_id(conn: &mut PgConnection, headers: &HeaderMap, explicit: Option<&str>) -> Option<String> {
+  let header_tenant = headers
+    .get("x-tenant-id")
+    .and_then(|value| value.to_str().ok());
+
+  if let Some(tenant_id) = explicit.or(header_tenant).filter(|id| !id.is_empty()) {
+    return find_tenant(conn, tenant_id).map(|tenant| tenant.id);
+  }
+
+  let host = headers
+    .get(header::HOST)
+    .and_then(|value| value.to_str().ok())
+    .map(|host| host.split(':').next().unwrap_or_default().to_lowercase())?;
+
+  let tenant_id = tenant_settings::table
+    .filter(tenant_settings::host.eq(host))
+    .filter(tenant_settings::deleted_at.is_null())
+    .select(tenant_settings::tenant_id)
+    .first::<String>(conn)
+    .optional()
+    .unwrap_or(None)?;
+
+  find_tenant(conn, &tenant_id).map(|tenant| tenant.id)
+}
+
+// Whether a session signed in with `amr`, through `idp` when federated, meets a config's sign-in
+// policy. Checked again whenever a session comes to act in a tenant, not only at sign-in
+pub fn check_sign_in_policy(config: &Config, amr: Option<&str>, idp: Option<&str>) -> Result<(), &'static str> {
+  let provider = match amr {
+    Some("pwd") => Some("password"),
+    Some("otp") => Some("magiclink"),
+    Some("fed") => idp,
+    _ => None,
+  };
+  let enabled = match provider {
+    Some(provider) => config.provider_enabled(provider),
+    None => config.enabled_providers.is_empty(),
+  };
+  if !enabled {
+    return Err("This sign-in method is not enabled for this tenant");
+  }
+
+  // There is no second factor to step up to yet, so no session can satisfy the requirement
+  if config.mfa_required {
+    return Err("Multi-factor authentication is required");
+  }
+
+  Ok(())
+}
+
+// The global Config with a tenant's overrides applied, or the global Config as is without a tenant
+pub fn tenant_config(conn: &mut PgConnection, env: &Config, tenant_id: Option<&str>) -> Config {
+  let mut config = env.clone();
+  let Some(settings) = tenant_id.and_then(|tenant_id| find_settings(conn, tenant_id)) else {
+    return config;
+  };
+
+  if let Some(providers) = settings.enabled_providers {
+    config.enabled_providers = providers.iter().map(|provider| provider.to_lowercase()).collect();
+  }
+  if let Some(min_length) = settings.password_min_length {
+    config.password_min_length = min_length.max(0) as usize;
+  }
+  if let Some(require_digit) = settings.password_require_digit {
+    config.password_require_digit = require_digit;
+  }
+  if let Some(require_symbol) = settings.password_require_symbol {
+    config.password_require_symbol = require_symbol;
+  }
+  if let Some(mfa_required) = settings.mfa_required {
+    config.mfa_required = mfa_required;
+  }
+  // Lifetimes are in minutes, the cookie max age follows the token
+  if let Some(max_age) = settings.access_token_max_age {
+    config.access_token_max_age = max_age;
+    config.access_token_expires_in = format!("{}m", max_age);
+  }
+  if let Some(max_age) = settings.refresh_token_max_age {
+    config.refresh_token_max_age = max_age;
+    config.refresh_token_expires_in = format!("{}m", max_age);
+  }
+  if let Some(from) = settings.mailer_from {
+    config.mailer_from = from;
+  }
+  if let Some(from_name) = settings.mailer_from_name {
+    config.mailer_from_name = from_name;
+  }
+
+  config
+}
@@ -0,0 +1,9 @@
+use axum::Router;
+
+// Serves the routes on a free local port, standing in for an outside API. Returns its base URL
+pub async fn serve(router: Router) -> String {
+  let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+  let address = listener.local_addr().unwrap();
+  tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+  format!("http://{}", address)
+}
@@ -4,8 +4,12 @@ use diesel::prelude::*;
 use std::error::Error;
 use std::time::{SystemTime, UNIX_EPOCH};
 use std::sync::Arc;
+use ulid::Ulid;
 use uuid::Uuid;
-use crate::schema::{tokens, Token};
+use crate::config::Config;
+use crate::hooks;
+use crate::rbac;
+use crate::schema::{oauth_refresh_tokens, tokens, user, OAuthRefreshTokenRevoke, Token, User};
 use crate::AppState;
 
 // (Make sure to import your Paseto types and builder—this example assumes you’re using v4 local tokens.)
@@ -18,6 +22,23 @@ pub struct TokenDetails {
   pub token_uuid: Uuid,
   pub expires_in: Option<i64>,
   pub token: Option<String>,
+  // When and how the session was authenticated, kept across refreshes
+  pub auth_time: Option<i64>,
+  pub amr: Option<String>,
+  // The provider behind a federated sign-in
+  pub idp: Option<String>,
+  // Only set on tokens issued to OAuth clients
+  pub client_id: Option<String>,
+  pub scope: Option<String>,
+  // "client" when the subject is a service client rather than a user
+  pub principal: Option<String>,
+  // Space separated, only on first-party access tokens
+  pub roles: Option<String>,
+  pub permissions: Option<String>,
+  // The tenant the session is currently acting in
+  pub tenant_id: Option<String>,
+  // The admin behind an impersonation session
+  pub act: Option<String>,
 }
 
 #[derive(Debug)]
@@ -32,10 +53,22 @@ pub struct TokenClaims {
   pub nbf: i64,
 }
 
+// Claims recording how a user signed in, e.g. "pwd", "otp" or "fed"
+pub fn session_claims(auth_time: i64, amr: &str) -> Vec<(&'static str, String)> {
+  vec![("auth_time", auth_time.to_string()), ("amr", amr.to_string())]
+}
+
+// Kept next to amr so a tenant's enabled providers can be checked again after sign-in
+pub fn idp_claims(provider: &str) -> Vec<(&'static str, String)> {
+  vec![("idp", provider.to_string())]
+}
+
+// Custom claims carry extras such as the session's auth_time or an OAuth grant's client and scope
 pub fn generate_paseto_token(
   user_id: String,
   ttl: i64,
   secret: &str,
+  custom_claims: &[(&str, String)],
 ) -> Result<TokenDetails, Box<dyn Error>> {
   let private_key = Key::<64>::try_from(&secret[..]).unwrap();
   let pk: &[u8] = private_key.as_slice();
@@ -58,22 +91,206 @@ pub fn generate_paseto_token(
   let iat_datetime: DateTime<Utc> = DateTime::<Utc>::from(UNIX_EPOCH + std::time::Duration::from_secs(now as u64));
   let nbf_datetime: DateTime<Utc> = DateTime::<Utc>::from(UNIX_EPOCH + std::time::Duration::from_secs(now as u64));
 
-  let token = PasetoBuilder::<V4, Public>::default()
+  let mut builder = PasetoBuilder::<V4, Public>::default();
+  builder
     .set_claim(SubjectClaim::from(claims.sub.as_str()))
     .set_claim(CustomClaim::try_from(("token_uuid", claims.token_uuid.clone())).unwrap())
     .set_claim(ExpirationClaim::try_from(exp_datetime.to_rfc3339()).unwrap())
     .set_claim(IssuedAtClaim::try_from(iat_datetime.to_rfc3339()).unwrap())
-    .set_claim(NotBeforeClaim::try_from(nbf_datetime.to_rfc3339()).unwrap())
-    .build(&private_key)?;
+    .set_claim(NotBeforeClaim::try_from(nbf_datetime.to_rfc3339()).unwrap());
+
+  for (name, value) in custom_claims {
+    builder.set_claim(CustomClaim::try_from((*name, value.clone()))?);
+  }
+
+  let token = builder.build(&private_key)?;
+
+  let custom_claim = |claim: &str| custom_claims
+    .iter()
+    .find(|(name, _)| *name == claim)
+    .map(|(_, value)| value.clone());
 
   Ok(TokenDetails {
     user_id,
     token_uuid,
     expires_in: Some(exp),
     token: Some(token),
+    auth_time: custom_claim("auth_time").and_then(|value| value.parse().ok()),
+    amr: custom_claim("amr"),
+    idp: custom_claim("idp"),
+    client_id: custom_claim("client_id"),
+    scope: custom_claim("scope"),
+    principal: custom_claim("principal"),
+    roles: custom_claim("roles"),
+    permissions: custom_claim("permissions"),
+    tenant_id: custom_claim("tenant_id"),
+    act: custom_claim("act"),
+  })
+}
+
+// Saves a token so it can later be found and blacklisted
+fn save_token(conn: &mut PgConnection, user_id: &str, token_details: &TokenDetails) -> QueryResult<usize> {
+  let expires = DateTime::<Utc>::from_timestamp(token_details.expires_in.unwrap_or_default(), 0)
+    .map(|dt| dt.naive_utc())
+    .unwrap_or_else(|| {
+      // Handle invalid timestamps
+      DateTime::<Utc>::from_timestamp(0, 0)
+        .unwrap()
+        .naive_utc()
+    });
+
+  diesel::insert_into(tokens::table)
+    .values(&Token {
+      id: Ulid::new().to_string(),
+      user_id: user_id.to_string(),
+      expires,
+      blacklisted: false,
+      token: token_details.token.clone().unwrap_or_default(),
+      token_uuid: token_details.token_uuid.to_string(),
+      created_at: Utc::now().naive_utc(),
+      updated_at: None,
+      deleted_at: None
+    })
+    .execute(conn)
+}
+
+// Logging out deletes the stored token, so a token that verifies but is gone no longer counts
+pub fn is_token_active(conn: &mut PgConnection, token_uuid: &Uuid) -> bool {
+  tokens::table
+    .filter(tokens::token_uuid.eq(token_uuid.to_string()))
+    .filter(tokens::blacklisted.eq(false))
+    .count()
+    .get_result::<i64>(conn)
+    .map(|count| count > 0)
+    .unwrap_or(false)
+}
+
+pub fn delete_token(conn: &mut PgConnection, token_uuid: &Uuid) -> QueryResult<usize> {
+  diesel::delete(tokens::table)
+    .filter(tokens::token_uuid.eq(token_uuid.to_string()))
+    .execute(conn)
+}
+
+// Signs a user out everywhere, including refresh tokens held by OAuth clients
+pub fn revoke_user_tokens(conn: &mut PgConnection, user_id: &str) -> QueryResult<usize> {
+  conn.transaction(|conn| {
+    let revoked = diesel::delete(tokens::table)
+      .filter(tokens::user_id.eq(user_id))
+      .execute(conn)?;
+
+    diesel::update(oauth_refresh_tokens::table)
+      .filter(oauth_refresh_tokens::user_id.eq(user_id))
+      .filter(oauth_refresh_tokens::revoked.eq(false))
+      .set(&OAuthRefreshTokenRevoke {
+        revoked: true,
+        updated_at: Some(Utc::now().naive_utc()),
+      })
+      .execute(conn)?;
+
+    Ok(revoked)
   })
 }
 
+// Disabled and deleted accounts cannot start new sessions
+pub fn is_user_active(user: &User) -> bool {
+  user.disabled_at.is_none() && user.deleted_at.is_none()
+}
+
+// The access/refresh pair of a first-party session, both saved to the tokens table. Lifetimes come
+// from env, which is the tenant's config when the session acts in a tenant
+pub async fn issue_session_tokens(
+  conn: &mut PgConnection,
+  env: &Config,
+  user_id: &str,
+  auth_claims: &[(&str, String)],
+) -> Result<(TokenDetails, TokenDetails), (StatusCode, Json<serde_json::Value>)> {
+  let active = user::table
+    .filter(user::id.eq(user_id))
+    .first::<User>(conn)
+    .map(|user| is_user_active(&user))
+    .unwrap_or(false);
+
+  if !active {
+    let error_response = serde_json::json!({
+      "status": "fail",
+      "message": "This account is disabled"
+    });
+    return Err((StatusCode::FORBIDDEN, Json(error_response)));
+  }
+
+  let mut access_claims = auth_claims.to_vec();
+  access_claims.extend(rbac::access_claims(conn, user_id));
+
+  let hook_claims = hooks::access_token_claims(env, user_id, &access_claims).await?;
+  access_claims.extend(hook_claims.iter().map(|(name, value)| (name.as_str(), value.clone())));
+
+  let access_token_details = generate_paseto_token(
+    user_id.to_string(),
+    env.access_token_max_age,
+    &env.auth_key,
+    &access_claims,
+  ).unwrap();
+
+  let refresh_token_details = generate_paseto_token(
+    user_id.to_string(),
+    env.refresh_token_max_age,
+    &env.auth_key,
+    auth_claims,
+  ).unwrap();
+
+  if let Err(e) = save_token(conn, user_id, &access_token_details) {
+    let error_message = format!("Failed to save access token: validation error\nDetails: {:?}", e);
+    let error_response = serde_json::json!({
+        "status": "fail",
+        "message": error_message
+    });
+    return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+  }
+
+  if let Err(e) = save_token(conn, user_id, &refresh_token_details) {
+    let error_message = format!("Failed to save refresh token: validation error\nDetails: {:?}", e);
+    let error_response = serde_json::json!({
+      "status": "fail",
+      "message": error_message
+    });
+    return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+  }
+
+  Ok((access_token_details, refresh_token_details))
+}
+
+// A short-lived access token acting as the user on the admin's behalf. It carries no roles or
+// permissions and comes without a refresh token
+pub fn issue_impersonation_token(
+  conn: &mut PgConnection,
+  env: &Config,
+  user_id: &str,
+  actor_id: &str,
+) -> Result<TokenDetails, (StatusCode, Json<serde_json::Value>)> {
+  let claims = [
+    ("auth_time", Utc::now().timestamp().to_string()),
+    ("amr", "imp".to_string()),
+    ("act", actor_id.to_string()),
+  ];
+
+  let token_details = generate_paseto_token(
+    user_id.to_string(),
+    env.impersonation_max_age,
+    &env.auth_key,
+    &claims,
+  ).unwrap();
+
+  if let Err(e) = save_token(conn, user_id, &token_details) {
+    let error_response = serde_json::json!({
+      "status": "fail",
+      "message": format!("Failed to save impersonation token: {}", e)
+    });
+    return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+  }
+
+  Ok(token_details)
+}
+
 pub fn verify_paseto_token(
   secret: &str,
   token: &str,
@@ -178,11 +395,25 @@ pub fn verify_paseto_token(
     }
   }
 
+  let custom_claim = |claim: &str| claims.get(claim)
+    .and_then(|v| v.as_str())
+    .map(|v| v.to_string());
+
   Ok(TokenDetails {
     token: None,
     token_uuid: Uuid::parse_str(token_uuid)?,
     user_id: sub,
     expires_in: None,
+    auth_time: custom_claim("auth_time").and_then(|value| value.parse().ok()),
+    amr: custom_claim("amr"),
+    idp: custom_claim("idp"),
+    client_id: custom_claim("client_id"),
+    scope: custom_claim("scope"),
+    principal: custom_claim("principal"),
+    roles: custom_claim("roles"),
+    permissions: custom_claim("permissions"),
+    tenant_id: custom_claim("tenant_id"),
+    act: custom_claim("act"),
   })
 }
 
@@ -1,8 +1,10 @@
-use std::sync::Arc;
-use axum::{extract::State, response::IntoResponse, Json};
+use std::{net::{IpAddr, SocketAddr}, sync::Arc};
+use axum::{body::Body, extract::{ConnectInfo, State}, http::{Extensions, HeaderMap, Request}, response::IntoResponse, Json};
 use chrono::Utc;
 use diesel::{ExpressionMethods, RunQueryDsl};
 use reqwest::StatusCode;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use sha2::{Digest, Sha256};
 use crate::{schema::{email_confirmation, EmailConfirmationFlowUpdate}, AppState};
 
 pub fn parse_duration(duration_str: &str) -> Result<i64, Box<dyn std::error::Error>> {
@@ -46,3 +48,103 @@ pub async fn update_confirm_code(
   Ok(())
 }
 
+pub fn hash_token(value: &str) -> String {
+  URL_SAFE_NO_PAD.encode(Sha256::digest(value.as_bytes()))
+}
+
+// Makes LIKE take `value` literally, wildcards and the escape character included
+pub fn escape_like(value: &str) -> String {
+  value
+    .replace('\\', "\\\\")
+    .replace('%', "\\%")
+    .replace('_', "\\_")
+}
+
+// Proxies whose X-Forwarded-For is believed, added to every request as an extension
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies(pub Vec<IpAddr>);
+
+pub fn client_ip(req: &Request<Body>) -> Option<String> {
+  forwarded_ip(req.headers(), req.extensions())
+}
+
+// The peer address, or behind a trusted proxy the right-most X-Forwarded-For hop that is not one of
+// our proxies. Hops left of that were written by the client and could say anything
+pub fn forwarded_ip(headers: &HeaderMap, extensions: &Extensions) -> Option<String> {
+  let peer = extensions
+    .get::<ConnectInfo<SocketAddr>>()
+    .map(|ConnectInfo(addr)| addr.ip())?;
+  let trusted = extensions
+    .get::<TrustedProxies>()
+    .map(|TrustedProxies(proxies)| proxies.as_slice())
+    .unwrap_or_default();
+
+  if !trusted.contains(&peer) {
+    return Some(peer.to_string());
+  }
+
+  let hops = headers
+    .get_all("x-forwarded-for")
+    .iter()
+    .filter_map(|value| value.to_str().ok())
+    .flat_map(|value| value.split(','))
+    .map(|hop| hop.trim().parse::<IpAddr>().ok())
+    .collect::<Vec<_>>();
+
+  let mut client = peer;
+  for hop in hops.into_iter().rev() {
+    match hop {
+      Some(hop) if trusted.contains(&hop) => client = hop,
+      Some(hop) => return Some(hop.to_string()),
+      // An unreadable hop means the chain cannot be followed further
+      None => break,
+    }
+  }
+  Some(client.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn request(peer: &str, trusted: &[&str], forwarded_for: Option<&str>) -> (HeaderMap, Extensions) {
+    let mut headers = HeaderMap::new();
+    if let Some(forwarded_for) = forwarded_for {
+      headers.insert("x-forwarded-for", forwarded_for.parse().unwrap());
+    }
+    let mut extensions = Extensions::new();
+    extensions.insert(ConnectInfo(format!("{}:443", peer).parse::<SocketAddr>().unwrap()));
+    extensions.insert(TrustedProxies(trusted.iter().map(|ip| ip.parse().unwrap()).collect()));
+    (headers, extensions)
+  }
+
+  #[test]
+  fn escape_like_escapes_wildcards() {
+    assert_eq!(escape_like("ann"), "ann");
+    assert_eq!(escape_like("a_b%c\\d"), "a\\_b\\%c\\\\d");
+  }
+
+  #[test]
+  fn ignores_forwarded_for_from_untrusted_peers() {
+    let (headers, extensions) = request("203.0.113.9", &[], Some("198.51.100.1"));
+    assert_eq!(forwarded_ip(&headers, &extensions).as_deref(), Some("203.0.113.9"));
+  }
+
+  #[test]
+  fn takes_the_right_most_untrusted_hop() {
+    let (headers, extensions) = request("10.0.0.1", &["10.0.0.1", "10.0.0.2"], Some("1.2.3.4, 198.51.100.1, 10.0.0.2"));
+    assert_eq!(forwarded_ip(&headers, &extensions).as_deref(), Some("198.51.100.1"));
+  }
+
+  #[test]
+  fn stops_at_an_unreadable_hop() {
+    let (headers, extensions) = request("10.0.0.1", &["10.0.0.1"], Some("198.51.100.1, not-an-ip"));
+    assert_eq!(forwarded_ip(&headers, &extensions).as_deref(), Some("10.0.0.1"));
+  }
+
+  #[test]
+  fn uses_the_peer_without_forwarded_for() {
+    let (headers, extensions) = request("10.0.0.1", &["10.0.0.1"], None);
+    assert_eq!(forwarded_ip(&headers, &extensions).as_deref(), Some("10.0.0.1"));
+  }
+}
@@ -0,0 +1,252 @@
+use std::time::{Duration as StdDuration, Instant};
+
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use ulid::Ulid;
+
+use crate::{
+  schema::{webhook_deliveries, webhook_outbox, webhooks, Webhook, WebhookDelivery, WebhookMessage, WebhookMessageClaim, WebhookMessageResult},
+  smtp::generate_random_string, DbPool
+};
+
+pub const USER_REGISTERED: &str = "user.registered";
+pub const USER_EMAIL_VERIFIED: &str = "user.email_verified";
+pub const USER_LOGIN: &str = "user.login";
+pub const USER_PASSWORD_CHANGED: &str = "user.password_changed";
+pub const USER_DELETED: &str = "user.deleted";
+
+// Subscribing to "*" receives every event
+pub const EVENT_TYPES: [&str; 5] = [USER_REGISTERED, USER_EMAIL_VERIFIED, USER_LOGIN, USER_PASSWORD_CHANGED, USER_DELETED];
+pub const ALL_EVENTS: &str = "*";
+
+pub const STATUS_PENDING: &str = "pending";
+pub const STATUS_DELIVERED: &str = "delivered";
+pub const STATUS_FAILED: &str = "failed";
+
+pub const SIGNATURE_HEADER: &str = "X-Heimdall-Signature";
+pub const EVENT_HEADER: &str = "X-Heimdall-Event";
+pub const DELIVERY_HEADER: &str = "X-Heimdall-Delivery";
+
+const POLL_INTERVAL: StdDuration = StdDuration::from_secs(5);
+const REQUEST_TIMEOUT: StdDuration = StdDuration::from_secs(10);
+const BATCH_SIZE: i64 = 20;
+// Outlasts a batch in which every request runs into the timeout, so another dispatcher never picks
+// up a message that is still being sent
+const CLAIM_SECONDS: i64 = BATCH_SIZE * REQUEST_TIMEOUT.as_secs() as i64 + 60;
+const FIRST_RETRY_SECONDS: i64 = 30;
+const MAX_RETRY_SECONDS: i64 = 6 * 60 * 60;
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub fn is_event_type(event_type: &str) -> bool {
+  event_type == ALL_EVENTS || EVENT_TYPES.contains(&event_type)
+}
+
+pub fn generate_secret() -> String {
+  format!("whsec_{}", generate_random_string())
+}
+
+// Receivers recompute this over "{timestamp}.{body}" and compare, the timestamp lets them refuse replays
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+  let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+  mac.update(format!("{}.{}", timestamp, body).as_bytes());
+  format!("t={},v1={}", timestamp, hex::encode(mac.finalize().into_bytes()))
+}
+
+// Writes one outbox message per subscribed webhook, delivery happens in the background
+pub fn enqueue(conn: &mut PgConnection, event_type: &str, data: serde_json::Value) {
+  let subscribed = webhooks::table
+    .filter(webhooks::active.eq(true))
+    .filter(webhooks::deleted_at.is_null())
+    .filter(webhooks::event_types.overlaps_with(vec![event_type.to_string(), ALL_EVENTS.to_string()]))
+    .load::<Webhook>(conn);
+
+  let subscribed = match subscribed {
+    Ok(subscribed) => subscribed,
+    Err(e) => {
+      tracing::error!("Failed to load webhooks for {}: {}", event_type, e);
+      return;
+    }
+  };
+
+  if subscribed.is_empty() {
+    return;
+  }
+
+  // Every subscriber sees the same event id, so receivers can de-duplicate across redeliveries
+  let timestamp = Utc::now().naive_utc();
+  let payload = serde_json::json!({
+    "id": Ulid::new().to_string(),
+    "type": event_type,
+    "createdAt": timestamp,
+    "data": data,
+  });
+
+  let messages = subscribed.iter().map(|webhook| WebhookMessage {
+    id: Ulid::new().to_string(),
+    webhook_id: webhook.id.clone(),
+    event_type: event_type.to_string(),
+    payload: payload.clone(),
+    status: STATUS_PENDING.into(),
+    attempts: 0,
+    next_attempt_at: timestamp,
+    last_error: None,
+    created_at: timestamp,
+    updated_at: None,
+  }).collect::<Vec<_>>();
+
+  let statement = diesel::insert_into(webhook_outbox::table)
+    .values(&messages)
+    .execute(conn);
+
+  if let Err(e) = statement {
+    tracing::error!("Failed to enqueue webhook event {}: {}", event_type, e);
+  }
+}
+
+// 30s, 1m, 2m, 4m... capped at six hours
+fn retry_delay(attempts: i32) -> Duration {
+  let exponent = attempts.clamp(1, 20) as u32 - 1;
+  Duration::seconds(FIRST_RETRY_SECONDS.saturating_mul(2_i64.pow(exponent)).min(MAX_RETRY_SECONDS))
+}
+
+// Skips rows another dispatcher has locked, then pushes them back so they are not picked up twice
+fn claim_due(conn: &mut PgConnection) -> QueryResult<Vec<WebhookMessage>> {
+  conn.transaction(|conn| {
+    let now = Utc::now().naive_utc();
+    let due = webhook_outbox::table
+      .filter(webhook_outbox::status.eq(STATUS_PENDING))
+      .filter(webhook_outbox::next_attempt_at.le(now))
+      .order(webhook_outbox::next_attempt_at.asc())
+      .limit(BATCH_SIZE)
+      .for_update()
+      .skip_locked()
+      .load::<WebhookMessage>(conn)?;
+
+    diesel::update(webhook_outbox::table)
+      .filter(webhook_outbox::id.eq_any(due.iter().map(|message| message.id.clone()).collect::<Vec<_>>()))
+      .set(&WebhookMessageClaim {
+        next_attempt_at: now + Duration::seconds(CLAIM_SECONDS),
+      })
+      .execute(conn)?;
+
+    Ok(due)
+  })
+}
+
+async fn send(client: &reqwest::Client, webhook: &Webhook, message: &WebhookMessage) -> (Option<i32>, Option<String>) {
+  let body = message.payload.to_string();
+  let signature = sign(&webhook.secret, Utc::now().timestamp(), &body);
+
+  let response = client
+    .post(&webhook.url)
+    .header(reqwest::header::CONTENT_TYPE, "application/json")
+    .header(SIGNATURE_HEADER, signature)
+    .header(EVENT_HEADER, &message.event_type)
+    .header(DELIVERY_HEADER, &message.id)
+    .body(body)
+    .send()
+    .await;
+
+  match response {
+    Ok(response) if response.status().is_success() => (Some(response.status().as_u16() as i32), None),
+    Ok(response) => (Some(response.status().as_u16() as i32), Some(format!("Receiver responded with {}", response.status()))),
+    Err(e) => (None, Some(e.to_string())),
+  }
+}
+
+fn record_attempt(
+  conn: &mut PgConnection,
+  message: &WebhookMessage,
+  status_code: Option<i32>,
+  error: Option<String>,
+  duration_ms: i64,
+  max_attempts: i32,
+) {
+  let now = Utc::now().naive_utc();
+  let attempts = message.attempts + 1;
+
+  let _ = diesel::insert_into(webhook_deliveries::table)
+    .values(&WebhookDelivery {
+      id: Ulid::new().to_string(),
+      webhook_id: message.webhook_id.clone(),
+      message_id: message.id.clone(),
+      event_type: message.event_type.clone(),
+      attempt: attempts,
+      status_code,
+      error: error.clone(),
+      duration_ms,
+      created_at: now,
+    })
+    .execute(conn)
+    .inspect_err(|e| tracing::error!("Failed to log webhook delivery {}: {}", message.id, e));
+
+  let (status, next_attempt_at): (&str, NaiveDateTime) = match &error {
+    None => (STATUS_DELIVERED, now),
+    Some(_) if attempts >= max_attempts => (STATUS_FAILED, now),
+    Some(_) => (STATUS_PENDING, now + retry_delay(attempts)),
+  };
+
+  let _ = diesel::update(webhook_outbox::table)
+    .filter(webhook_outbox::id.eq(message.id.clone()))
+    .set(&WebhookMessageResult {
+      status: status.into(),
+      attempts,
+      next_attempt_at,
+      last_error: error,
+      updated_at: Some(now),
+    })
+    .execute(conn)
+    .inspect_err(|e| tracing::error!("Failed to update webhook message {}: {}", message.id, e));
+}
+
+async fn dispatch_due(pool: &DbPool, client: &reqwest::Client, max_attempts: i32) -> Result<(), String> {
+  let mut conn = pool.get().map_err(|e| e.to_string())?;
+  let due = claim_due(&mut conn).map_err(|e| e.to_string())?;
+
+  for message in due {
+    let webhook = webhooks::table
+      .filter(webhooks::id.eq(message.webhook_id.clone()))
+      .first::<Webhook>(&mut conn)
+      .optional()
+      .map_err(|e| e.to_string())?;
+
+    // Messages for removed or paused webhooks fail straight away, an admin can redeliver them later
+    let webhook = match webhook {
+      Some(webhook) if webhook.active && webhook.deleted_at.is_none() => webhook,
+      _ => {
+        record_attempt(&mut conn, &message, None, Some("Webhook is disabled".into()), 0, 0);
+        continue;
+      }
+    };
+
+    let started = Instant::now();
+    let (status_code, error) = send(client, &webhook, &message).await;
+    let duration_ms = started.elapsed().as_millis() as i64;
+
+    record_attempt(&mut conn, &message, status_code, error, duration_ms, max_attempts);
+  }
+
+  Ok(())
+}
+
+// Runs for the life of the server, draining the outbox every few seconds
+pub fn spawn_dispatcher(pool: DbPool, max_attempts: i32) {
+  let client = reqwest::Client::builder()
+    .user_agent("Heimdall-Webhooks/1.0")
+    .timeout(REQUEST_TIMEOUT)
+    .build()
+    .expect("Failed to create HTTP client");
+
+  tokio::spawn(async move {
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    loop {
+      interval.tick().await;
+      if let Err(e) = dispatch_due(&pool, &client, max_attempts).await {
+        tracing::error!("Failed to dispatch webhooks: {}", e);
+      }
+    }
+  });
+}